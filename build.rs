@@ -0,0 +1,30 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+fn main() {
+    #[cfg(feature = "capi")]
+    generate_c_header();
+}
+
+/// Generates the C header for the `capi` bindings to `$OUT_DIR/spl_tool.h`.
+///
+/// The build never writes to the source tree, the checked-in `include/spl_tool.h` is updated by
+/// `scripts/gen-header.sh`.
+#[cfg(feature = "capi")]
+fn generate_c_header() {
+    let crate_dir =
+        std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR is set by cargo");
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let config = cbindgen::Config::from_file(format!("{crate_dir}/cbindgen.toml"))
+        .expect("valid cbindgen.toml");
+
+    cbindgen::Builder::new()
+        .with_crate(crate_dir.as_str())
+        .with_config(config)
+        .generate()
+        .expect("unable to generate C bindings")
+        .write_to_file(format!("{out_dir}/spl_tool.h"));
+}
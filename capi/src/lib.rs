@@ -0,0 +1,8 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+//! C shared (`libspl_tool_capi.so`) and static (`libspl_tool_capi.a`) libraries for the
+//! `spl_tool` C API.
+//!
+//! The bindings live in the `capi` module of `spl_tool`, the C header is `include/spl_tool.h`.
+
+pub use spl_tool::capi::*;
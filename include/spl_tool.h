@@ -0,0 +1,414 @@
+/* SPDX-License-Identifier: GPL-2.0+ */
+
+#ifndef SPL_TOOL_H
+#define SPL_TOOL_H
+
+/* Generated by cbindgen from the `capi` feature, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Return code indicating success.
+#define SPL_OK 0
+
+// Return code for [Error::InvalidHeaderLen].
+#define SPL_ERR_INVALID_HEADER_LEN -1
+
+// Return code for [Error::InvalidSplLen].
+#define SPL_ERR_INVALID_SPL_LEN -2
+
+// Return code for [Error::InvalidSlice].
+#define SPL_ERR_INVALID_SLICE -3
+
+// Return code for [Error::InvalidHeaderFile].
+#define SPL_ERR_INVALID_HEADER_FILE -4
+
+// Return code for [Error::InvalidSplFile].
+#define SPL_ERR_INVALID_SPL_FILE -5
+
+// Return code for [Error::InvalidConfigFile].
+#define SPL_ERR_INVALID_CONFIG_FILE -6
+
+// Return code for [Error::InvalidConfigLine].
+#define SPL_ERR_INVALID_CONFIG_LINE -7
+
+// Return code for [Error::InvalidConfigValue].
+#define SPL_ERR_INVALID_CONFIG_VALUE -8
+
+// Return code for [Error::InvalidManifestFile].
+#define SPL_ERR_INVALID_MANIFEST_FILE -9
+
+// Return code for [Error::InvalidSignAlg].
+#define SPL_ERR_INVALID_SIGN_ALG -10
+
+// Return code for [Error::InvalidSigningKey].
+#define SPL_ERR_INVALID_SIGNING_KEY -11
+
+// Return code for [Error::InvalidSignature].
+#define SPL_ERR_INVALID_SIGNATURE -12
+
+// Return code for [Error::InvalidKeyHash].
+#define SPL_ERR_INVALID_KEY_HASH -13
+
+// Return code for [Error::InvalidCrc].
+#define SPL_ERR_INVALID_CRC -14
+
+// Return code for [Error::NullPointer].
+#define SPL_ERR_NULL_POINTER -15
+
+// Return code for [Error::RequiresCliFeature].
+#define SPL_ERR_REQUIRES_CLI_FEATURE -16
+
+// Return code for [Error::InvalidFlashAlign].
+#define SPL_ERR_INVALID_FLASH_ALIGN -17
+
+// Return code for [Error::FlashErase].
+#define SPL_ERR_FLASH_ERASE -18
+
+// Return code for [Error::FlashWrite].
+#define SPL_ERR_FLASH_WRITE -19
+
+// Return code for [Error::FlashRead].
+#define SPL_ERR_FLASH_READ -20
+
+// Return code for [Error::FlashVerify].
+#define SPL_ERR_FLASH_VERIFY -21
+
+// Return code for [Error::InvalidSofs].
+#define SPL_ERR_INVALID_SOFS -22
+
+// Return code for [Error::InvalidElf].
+#define SPL_ERR_INVALID_ELF -23
+
+// Return code for [Error::InvalidElfAddr].
+#define SPL_ERR_INVALID_ELF_ADDR -24
+
+// Return code for [Error::InvalidHexRecord].
+#define SPL_ERR_INVALID_HEX_RECORD -25
+
+// Return code for [Error::InvalidEnvSize].
+#define SPL_ERR_INVALID_ENV_SIZE -26
+
+// Return code for [Error::InvalidEnvVar].
+#define SPL_ERR_INVALID_ENV_VAR -27
+
+// Return code for [Error::InvalidUimageMagic].
+#define SPL_ERR_INVALID_UIMAGE_MAGIC -28
+
+// Return code for [Error::InvalidUimageType].
+#define SPL_ERR_INVALID_UIMAGE_TYPE -29
+
+// Return code for [Error::InvalidUimageComp].
+#define SPL_ERR_INVALID_UIMAGE_COMP -30
+
+// Return code for [Error::InvalidFit].
+#define SPL_ERR_INVALID_FIT -31
+
+// Return code for [Error::InvalidFitHash].
+#define SPL_ERR_INVALID_FIT_HASH -32
+
+// Return code for [Error::InvalidGpt].
+#define SPL_ERR_INVALID_GPT -33
+
+// Return code for [Error::DiskRead].
+#define SPL_ERR_DISK_READ -34
+
+// Return code for [Error::DiskWrite].
+#define SPL_ERR_DISK_WRITE -35
+
+// Return code for [Error::InvalidFixRecord].
+#define SPL_ERR_INVALID_FIX_RECORD -36
+
+// Return code for [Error::InvalidSparseImage].
+#define SPL_ERR_INVALID_SPARSE_IMAGE -37
+
+// Return code for [Error::UndefinedEnvVar].
+#define SPL_ERR_UNDEFINED_ENV_VAR -38
+
+// Return code for [Error::InvalidFlashLayout].
+#define SPL_ERR_INVALID_FLASH_LAYOUT -39
+
+// Return code for [Error::InvalidResl].
+#define SPL_ERR_INVALID_RESL -40
+
+// Return code for [Error::InvalidUimageLen].
+#define SPL_ERR_INVALID_UIMAGE_LEN -41
+
+// CRC-32 polynomial used for the SPL image checksum.
+#define SPL_CRC32_POLY 79764919
+
+// Reflected form of the standard (IEEE 802.3) CRC-32 polynomial.
+#define CRC32_IEEE_POLY 3988292384
+
+// Start address of the JH7110 on-chip SRAM the bootrom loads the SPL into.
+#define JH7110_SRAM_BASE 134217728
+
+// Length of the JH7110 on-chip SRAM.
+#define JH7110_SRAM_LEN 2097152
+
+// Magic number of a flattened device tree, and FIT image.
+#define FDT_MAGIC 3490578157
+
+// Length of the flattened device tree header.
+#define FDT_HEADER_LEN 40
+
+// Default size of the VisionFive2 SPI flash (16 MiB).
+#define DEF_FLASH_SIZE 16777216
+
+// Length of an erase sector of the VisionFive2 SPI flash.
+#define FLASH_SECTOR_LEN 4096
+
+// Value of an erased flash byte.
+#define FLASH_ERASED 255
+
+// Default maximum length of an SPL copy region (the VisionFive2 `spl` MTD partition).
+#define DEF_SPL_REGION_LEN 524288
+
+// Minimum length of a GPT header (UEFI revision 1.0).
+#define GPT_HEADER_LEN 92
+
+// Minimum length of a GPT partition entry.
+#define GPT_ENTRY_LEN 128
+
+// Maximum length of the partition entry array accepted by [GptDisk].
+#define GPT_MAX_ENTRIES_LEN 1048576
+
+// Default logical sector (LBA) length of a disk image.
+#define DEF_SECTOR_LEN 512
+
+// Maximum logical sector length supported by [GptDisk] (4Kn disks).
+#define MAX_SECTOR_LEN 4096
+
+// Length of the MBR at the start of LBA 0, regardless of the sector length.
+#define MBR_LEN 512
+
+// Offset of the first partition record in the MBR.
+#define MBR_PART_OFFSET 446
+
+// Partition type of the protective MBR record covering the GPT disk.
+#define MBR_TYPE_PROTECTIVE 238
+
+// Offset of the backup SPL address written by the JH7110 `--fix-imghdr` workaround.
+//
+// The value lands in the MBR boot code, which is ignored on GPT disks.
+#define JH7110_FIX_BOFS_OFFSET 4
+
+// Offset of the invalid SPL CRC ([CRC_FAILED]) written by the JH7110 `--fix-imghdr` workaround.
+//
+// With 512-byte sectors, the value lands in the reserved tail of the primary GPT header sector,
+// after the header CRC range. With larger sectors, it lands in the unused tail of LBA 0.
+#define JH7110_FIX_CRCS_OFFSET 656
+
+// Maximum length of the data in a single HEX/SREC record.
+#define HEX_RECORD_MAX_LEN 255
+
+// Length of the data written to each HEX/SREC record.
+#define HEX_LINE_LEN 16
+
+// Length of the stack buffer used to stream data to and from flash.
+//
+// The `READ_SIZE` and `WRITE_SIZE` of the flash must evenly divide this length.
+#define FLASH_CHUNK_LEN 256
+
+#if defined(SPL_TOOL_SECURE_BOOT)
+// Length of the public key hash field in a signed U-Boot SPL header.
+#define SPL_KEY_HASH_LEN 32
+#endif
+
+#if defined(SPL_TOOL_SECURE_BOOT)
+// Length of the public key field in a signed U-Boot SPL header.
+#define SPL_PUB_KEY_LEN 256
+#endif
+
+#if defined(SPL_TOOL_SECURE_BOOT)
+// Length of the signature field in a signed U-Boot SPL header.
+#define SPL_SIGNATURE_LEN 256
+#endif
+
+#if defined(SPL_TOOL_SECURE_BOOT)
+// Length of a P-256 public key coordinate.
+#define P256_COORD_LEN 32
+#endif
+
+#if defined(SPL_TOOL_SECURE_BOOT)
+// Length of an RSA-2048 modulus.
+#define RSA2048_LEN 256
+#endif
+
+// Length of a SHA-256 digest in bytes.
+#define SHA256_LEN 32
+
+// Magic number of an Android sparse image header.
+#define SPARSE_HEADER_MAGIC 3978755898
+
+// Major version of the Android sparse image format.
+#define SPARSE_MAJOR_VERSION 1
+
+// Minor version of the Android sparse image format.
+#define SPARSE_MINOR_VERSION 0
+
+// Length of an Android sparse image header.
+#define SPARSE_HEADER_LEN 28
+
+// Length of an Android sparse image chunk header.
+#define SPARSE_CHUNK_HEADER_LEN 12
+
+// Default block length of bmap files and Android sparse images.
+#define DEF_SPARSE_BLOCK_LEN 4096
+
+// Default value of the offset of SPL header: `64+256+256 = 0x240`
+#define DEF_SOFS 576
+
+// Default SPL version ID.
+#define DEF_VERS 16843009
+
+// Default value of `SBL_BAK_OFFSET`.
+#define DEF_BACKUP 2097152
+
+// Default offset of the U-Boot FIT image in the VisionFive2 SPI flash.
+#define DEF_FIT_OFFSET 1048576
+
+// Default value for the offset from `HDR` to `SPL_IMAGE`.
+#define DEF_RESL 1024
+
+// Value indicating a failed CRC32 calculation/check.
+#define CRC_FAILED 1515870810
+
+// Default size for an U-Boot SPL header.
+//
+// Currently set to `1 KiB` (including reserved padding).
+#define SPL_HEADER_LEN 1024
+
+// Upper bound (exclusive) of the length of an U-Boot SPL image.
+//
+// See [spl_check_len] for the check used on creation and validation.
+#define MAX_SPL_LEN 180048
+
+// Length of the stack buffer used to stream the SPL payload for the CRC32 check.
+#define HEALTH_CHUNK_LEN 256
+
+// Default alignment of the offsets checked for SPL headers (one disk sector).
+#define DEF_SCAN_ALIGN 512
+
+// Default offset of the U-Boot environment in the VisionFive2 SPI flash.
+#define DEF_ENV_OFFSET 983040
+
+// Default size of the U-Boot environment region in the VisionFive2 SPI flash.
+#define DEF_ENV_SIZE 65536
+
+// Length of the CRC32 at the start of a U-Boot environment blob.
+#define ENV_CRC_LEN 4
+
+// Flag byte of the active copy of a redundant U-Boot environment.
+#define ENV_REDUND_ACTIVE 1
+
+// Default byte used to pad the data area, the erased flash value also used by `mkenvimage`.
+#define ENV_PAD_BYTE 255
+
+// Magic number of a legacy U-Boot image (uImage) header.
+#define UIMAGE_MAGIC 654645590
+
+// Length of a legacy uImage header.
+#define UIMAGE_HEADER_LEN 64
+
+// Length of the image name in a legacy uImage header.
+#define UIMAGE_NAME_LEN 32
+
+// Operating system ID for Linux (`IH_OS_LINUX`).
+#define UIMAGE_OS_LINUX 5
+
+// Operating system ID for U-Boot firmware (`IH_OS_U_BOOT`).
+#define UIMAGE_OS_U_BOOT 17
+
+// Architecture ID for RISC-V (`IH_ARCH_RISCV`).
+#define UIMAGE_ARCH_RISCV 26
+
+// Represents the U-Boot header for the SPL binary.
+//
+// All `u32` end up little endian in output header.
+//
+// Reserved regions are kept verbatim, so parsing and serializing a header does not clobber
+// foreign data (e.g. a GPT). The experimental `secure-boot` feature models a signed header in
+// the reserved region after `bofs`.
+typedef struct UbootSplHeader {
+  uint32_t sofs;
+  uint32_t bofs;
+  uint8_t zro2[636];
+  uint32_t vers;
+  uint32_t fsiz;
+  uint32_t resl;
+  uint32_t crcs;
+  uint8_t zro3[364];
+} UbootSplHeader;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Initializes an SPL header with the default values.
+//
+// # Safety
+//
+// `header` must be null, or point to memory valid for writing an [UbootSplHeader].
+int spl_header_init(struct UbootSplHeader *header);
+
+// Creates an SPL header for the `u-boot-spl.bin` payload.
+//
+// A `bofs` or `vers` of zero keeps the default value.
+//
+// # Safety
+//
+// `payload` must be valid for reading `payload_len` bytes, and `header` must point to memory
+// valid for writing an [UbootSplHeader].
+int spl_header_create(const uint8_t *payload,
+                      size_t payload_len,
+                      uint32_t bofs,
+                      uint32_t vers,
+                      struct UbootSplHeader *header);
+
+// Parses an SPL header from a buffer of at least `SPL_HEADER_LEN` bytes.
+//
+// # Safety
+//
+// `buf` must be valid for reading `buf_len` bytes, and `header` must point to memory valid
+// for writing an [UbootSplHeader].
+int spl_header_parse(const uint8_t *buf, size_t buf_len, struct UbootSplHeader *header);
+
+// Serializes an SPL header to a buffer of at least `SPL_HEADER_LEN` bytes.
+//
+// # Safety
+//
+// `header` must point to a valid [UbootSplHeader], and `buf` must be valid for writing
+// `buf_len` bytes.
+int spl_header_serialize(const struct UbootSplHeader *header, uint8_t *buf, size_t buf_len);
+
+// Verifies the size and CRC32 of the `u-boot-spl.bin` payload against an SPL header.
+//
+// # Safety
+//
+// `header` must point to a valid [UbootSplHeader], and `payload` must be valid for reading
+// `payload_len` bytes.
+int spl_header_verify(const struct UbootSplHeader *header,
+                      const uint8_t *payload,
+                      size_t payload_len);
+
+// Calculates the SPL CRC32 of a buffer, as stored in the header `crcs` field.
+//
+// Returns zero for a null `data` pointer.
+//
+// # Safety
+//
+// `data` must be valid for reading `data_len` bytes.
+uint32_t spl_crc32_calc(const uint8_t *data, size_t data_len);
+
+// Gets a static, NUL-terminated description of a return code.
+const char *spl_strerror(int code);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SPL_TOOL_H */
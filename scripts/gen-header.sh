@@ -0,0 +1,22 @@
+#!/bin/sh
+# SPDX-License-Identifier: GPL-2.0+
+#
+# Regenerates the checked-in C header `include/spl_tool.h` for the `capi` feature.
+#
+# The build script generates the header into its `OUT_DIR`, this copies it into the source tree.
+
+set -eu
+
+cd "$(dirname "$0")/.."
+
+out_dir=$(cargo build --features capi --message-format=json |
+    sed -n 's/.*"reason":"build-script-executed","package_id":"[^"]*spl_tool[@#:][^"]*".*"out_dir":"\([^"]*\)".*/\1/p' |
+    tail -n 1)
+
+if [ -z "$out_dir" ] || [ ! -f "$out_dir/spl_tool.h" ]; then
+    echo "error: generated spl_tool.h not found" >&2
+    exit 1
+fi
+
+cp "$out_dir/spl_tool.h" include/spl_tool.h
+echo "include/spl_tool.h updated"
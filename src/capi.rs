@@ -0,0 +1,341 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+//! C ABI bindings for the library.
+//!
+//! All functions return `SPL_OK` on success, or the negated [Error::code] on failure.
+//! The C header is `include/spl_tool.h`, regenerated by `scripts/gen-header.sh`. The `spl_tool_capi`
+//! crate builds the bindings into C shared and static libraries.
+
+use core::ffi::{c_char, c_int};
+use core::slice;
+
+use super::{spl_crc32, Error, Result, UbootSplHeader, SPL_HEADER_LEN};
+
+/// Return code indicating success.
+pub const SPL_OK: c_int = 0;
+/// Return code for [Error::InvalidHeaderLen].
+pub const SPL_ERR_INVALID_HEADER_LEN: c_int = -1;
+/// Return code for [Error::InvalidSplLen].
+pub const SPL_ERR_INVALID_SPL_LEN: c_int = -2;
+/// Return code for [Error::InvalidSlice].
+pub const SPL_ERR_INVALID_SLICE: c_int = -3;
+/// Return code for [Error::InvalidHeaderFile].
+pub const SPL_ERR_INVALID_HEADER_FILE: c_int = -4;
+/// Return code for [Error::InvalidSplFile].
+pub const SPL_ERR_INVALID_SPL_FILE: c_int = -5;
+/// Return code for [Error::InvalidConfigFile].
+pub const SPL_ERR_INVALID_CONFIG_FILE: c_int = -6;
+/// Return code for [Error::InvalidConfigLine].
+pub const SPL_ERR_INVALID_CONFIG_LINE: c_int = -7;
+/// Return code for [Error::InvalidConfigValue].
+pub const SPL_ERR_INVALID_CONFIG_VALUE: c_int = -8;
+/// Return code for [Error::InvalidManifestFile].
+pub const SPL_ERR_INVALID_MANIFEST_FILE: c_int = -9;
+/// Return code for [Error::InvalidSignAlg].
+pub const SPL_ERR_INVALID_SIGN_ALG: c_int = -10;
+/// Return code for [Error::InvalidSigningKey].
+pub const SPL_ERR_INVALID_SIGNING_KEY: c_int = -11;
+/// Return code for [Error::InvalidSignature].
+pub const SPL_ERR_INVALID_SIGNATURE: c_int = -12;
+/// Return code for [Error::InvalidKeyHash].
+pub const SPL_ERR_INVALID_KEY_HASH: c_int = -13;
+/// Return code for [Error::InvalidCrc].
+pub const SPL_ERR_INVALID_CRC: c_int = -14;
+/// Return code for [Error::NullPointer].
+pub const SPL_ERR_NULL_POINTER: c_int = -15;
+/// Return code for [Error::RequiresCliFeature].
+pub const SPL_ERR_REQUIRES_CLI_FEATURE: c_int = -16;
+/// Return code for [Error::InvalidFlashAlign].
+pub const SPL_ERR_INVALID_FLASH_ALIGN: c_int = -17;
+/// Return code for [Error::FlashErase].
+pub const SPL_ERR_FLASH_ERASE: c_int = -18;
+/// Return code for [Error::FlashWrite].
+pub const SPL_ERR_FLASH_WRITE: c_int = -19;
+/// Return code for [Error::FlashRead].
+pub const SPL_ERR_FLASH_READ: c_int = -20;
+/// Return code for [Error::FlashVerify].
+pub const SPL_ERR_FLASH_VERIFY: c_int = -21;
+/// Return code for [Error::InvalidSofs].
+pub const SPL_ERR_INVALID_SOFS: c_int = -22;
+/// Return code for [Error::InvalidElf].
+pub const SPL_ERR_INVALID_ELF: c_int = -23;
+/// Return code for [Error::InvalidElfAddr].
+pub const SPL_ERR_INVALID_ELF_ADDR: c_int = -24;
+/// Return code for [Error::InvalidHexRecord].
+pub const SPL_ERR_INVALID_HEX_RECORD: c_int = -25;
+/// Return code for [Error::InvalidEnvSize].
+pub const SPL_ERR_INVALID_ENV_SIZE: c_int = -26;
+/// Return code for [Error::InvalidEnvVar].
+pub const SPL_ERR_INVALID_ENV_VAR: c_int = -27;
+/// Return code for [Error::InvalidUimageMagic].
+pub const SPL_ERR_INVALID_UIMAGE_MAGIC: c_int = -28;
+/// Return code for [Error::InvalidUimageType].
+pub const SPL_ERR_INVALID_UIMAGE_TYPE: c_int = -29;
+/// Return code for [Error::InvalidUimageComp].
+pub const SPL_ERR_INVALID_UIMAGE_COMP: c_int = -30;
+/// Return code for [Error::InvalidFit].
+pub const SPL_ERR_INVALID_FIT: c_int = -31;
+/// Return code for [Error::InvalidFitHash].
+pub const SPL_ERR_INVALID_FIT_HASH: c_int = -32;
+/// Return code for [Error::InvalidGpt].
+pub const SPL_ERR_INVALID_GPT: c_int = -33;
+/// Return code for [Error::DiskRead].
+pub const SPL_ERR_DISK_READ: c_int = -34;
+/// Return code for [Error::DiskWrite].
+pub const SPL_ERR_DISK_WRITE: c_int = -35;
+/// Return code for [Error::InvalidFixRecord].
+pub const SPL_ERR_INVALID_FIX_RECORD: c_int = -36;
+/// Return code for [Error::InvalidSparseImage].
+pub const SPL_ERR_INVALID_SPARSE_IMAGE: c_int = -37;
+/// Return code for [Error::UndefinedEnvVar].
+pub const SPL_ERR_UNDEFINED_ENV_VAR: c_int = -38;
+/// Return code for [Error::InvalidFlashLayout].
+pub const SPL_ERR_INVALID_FLASH_LAYOUT: c_int = -39;
+/// Return code for [Error::InvalidResl].
+pub const SPL_ERR_INVALID_RESL: c_int = -40;
+/// Return code for [Error::InvalidUimageLen].
+pub const SPL_ERR_INVALID_UIMAGE_LEN: c_int = -41;
+
+/// Initializes an SPL header with the default values.
+///
+/// # Safety
+///
+/// `header` must be null, or point to memory valid for writing an [UbootSplHeader].
+#[no_mangle]
+pub unsafe extern "C" fn spl_header_init(header: *mut UbootSplHeader) -> c_int {
+    if header.is_null() {
+        return SPL_ERR_NULL_POINTER;
+    }
+
+    header.write(UbootSplHeader::new());
+
+    SPL_OK
+}
+
+/// Creates an SPL header for the `u-boot-spl.bin` payload.
+///
+/// A `bofs` or `vers` of zero keeps the default value.
+///
+/// # Safety
+///
+/// `payload` must be valid for reading `payload_len` bytes, and `header` must point to memory
+/// valid for writing an [UbootSplHeader].
+#[no_mangle]
+pub unsafe extern "C" fn spl_header_create(
+    payload: *const u8,
+    payload_len: usize,
+    bofs: u32,
+    vers: u32,
+    header: *mut UbootSplHeader,
+) -> c_int {
+    ret_code((|| {
+        let payload = as_slice(payload, payload_len)?;
+        let mut hdr = UbootSplHeader::new();
+
+        if bofs != 0 {
+            hdr.set_bofs(bofs);
+        }
+        if vers != 0 {
+            hdr.set_vers(vers);
+        }
+        hdr.set_payload(payload)?;
+
+        as_mut(header)?.write(hdr);
+        Ok(())
+    })())
+}
+
+/// Parses an SPL header from a buffer of at least `SPL_HEADER_LEN` bytes.
+///
+/// # Safety
+///
+/// `buf` must be valid for reading `buf_len` bytes, and `header` must point to memory valid
+/// for writing an [UbootSplHeader].
+#[no_mangle]
+pub unsafe extern "C" fn spl_header_parse(
+    buf: *const u8,
+    buf_len: usize,
+    header: *mut UbootSplHeader,
+) -> c_int {
+    ret_code((|| {
+        let hdr = UbootSplHeader::try_from(as_slice(buf, buf_len)?)?;
+        as_mut(header)?.write(hdr);
+        Ok(())
+    })())
+}
+
+/// Serializes an SPL header to a buffer of at least `SPL_HEADER_LEN` bytes.
+///
+/// # Safety
+///
+/// `header` must point to a valid [UbootSplHeader], and `buf` must be valid for writing
+/// `buf_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn spl_header_serialize(
+    header: *const UbootSplHeader,
+    buf: *mut u8,
+    buf_len: usize,
+) -> c_int {
+    ret_code((|| {
+        let hdr = header.as_ref().ok_or(Error::NullPointer)?;
+        if buf.is_null() {
+            Err(Error::NullPointer)
+        } else if buf_len < SPL_HEADER_LEN {
+            Err(Error::InvalidHeaderLen((buf_len, SPL_HEADER_LEN)))
+        } else {
+            let hdr_bytes: [u8; SPL_HEADER_LEN] = hdr.into();
+            slice::from_raw_parts_mut(buf, SPL_HEADER_LEN).copy_from_slice(hdr_bytes.as_ref());
+            Ok(())
+        }
+    })())
+}
+
+/// Verifies the size and CRC32 of the `u-boot-spl.bin` payload against an SPL header.
+///
+/// # Safety
+///
+/// `header` must point to a valid [UbootSplHeader], and `payload` must be valid for reading
+/// `payload_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn spl_header_verify(
+    header: *const UbootSplHeader,
+    payload: *const u8,
+    payload_len: usize,
+) -> c_int {
+    ret_code((|| {
+        header
+            .as_ref()
+            .ok_or(Error::NullPointer)?
+            .verify_payload(as_slice(payload, payload_len)?)
+    })())
+}
+
+/// Calculates the SPL CRC32 of a buffer, as stored in the header `crcs` field.
+///
+/// Returns zero for a null `data` pointer.
+///
+/// # Safety
+///
+/// `data` must be valid for reading `data_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn spl_crc32_calc(data: *const u8, data_len: usize) -> u32 {
+    as_slice(data, data_len).map(spl_crc32).unwrap_or(0)
+}
+
+/// Gets a static, NUL-terminated description of a return code.
+#[no_mangle]
+pub extern "C" fn spl_strerror(code: c_int) -> *const c_char {
+    let msg = match code {
+        SPL_OK => c"success",
+        _ => Error::code_c_description(code.saturating_neg()).unwrap_or(c"unknown error"),
+    };
+
+    msg.as_ptr()
+}
+
+fn ret_code(res: Result<()>) -> c_int {
+    match res {
+        Ok(()) => SPL_OK,
+        Err(err) => -err.code(),
+    }
+}
+
+unsafe fn as_slice<'a>(ptr: *const u8, len: usize) -> Result<&'a [u8]> {
+    if ptr.is_null() {
+        Err(Error::NullPointer)
+    } else {
+        Ok(slice::from_raw_parts(ptr, len))
+    }
+}
+
+unsafe fn as_mut<'a>(
+    ptr: *mut UbootSplHeader,
+) -> Result<&'a mut core::mem::MaybeUninit<UbootSplHeader>> {
+    ptr.cast::<core::mem::MaybeUninit<UbootSplHeader>>()
+        .as_mut()
+        .ok_or(Error::NullPointer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::ffi::CStr;
+
+    #[test]
+    fn test_error_codes() {
+        for (code, err) in [
+            (SPL_ERR_INVALID_HEADER_LEN, Error::InvalidHeaderLen((0, 0))),
+            (SPL_ERR_INVALID_SPL_LEN, Error::InvalidSplLen((0, 0))),
+            (SPL_ERR_INVALID_HEADER_FILE, Error::InvalidHeaderFile),
+            (SPL_ERR_INVALID_SPL_FILE, Error::InvalidSplFile),
+            (SPL_ERR_INVALID_CONFIG_FILE, Error::InvalidConfigFile),
+            (SPL_ERR_INVALID_CONFIG_LINE, Error::InvalidConfigLine(0)),
+            (SPL_ERR_INVALID_CONFIG_VALUE, Error::InvalidConfigValue(0)),
+            (SPL_ERR_INVALID_MANIFEST_FILE, Error::InvalidManifestFile),
+            (SPL_ERR_INVALID_SIGN_ALG, Error::InvalidSignAlg(0)),
+            (SPL_ERR_INVALID_SIGNING_KEY, Error::InvalidSigningKey),
+            (SPL_ERR_INVALID_SIGNATURE, Error::InvalidSignature),
+            (SPL_ERR_INVALID_KEY_HASH, Error::InvalidKeyHash),
+            (SPL_ERR_INVALID_CRC, Error::InvalidCrc((0, 0))),
+            (SPL_ERR_NULL_POINTER, Error::NullPointer),
+            (SPL_ERR_REQUIRES_CLI_FEATURE, Error::RequiresCliFeature),
+            (SPL_ERR_INVALID_FLASH_ALIGN, Error::InvalidFlashAlign(0)),
+            (SPL_ERR_FLASH_ERASE, Error::FlashErase(0)),
+            (SPL_ERR_FLASH_WRITE, Error::FlashWrite(0)),
+            (SPL_ERR_FLASH_READ, Error::FlashRead(0)),
+            (SPL_ERR_FLASH_VERIFY, Error::FlashVerify(0)),
+            (SPL_ERR_INVALID_SOFS, Error::InvalidSofs(0)),
+            (SPL_ERR_INVALID_ELF, Error::InvalidElf),
+            (SPL_ERR_INVALID_ELF_ADDR, Error::InvalidElfAddr(0)),
+            (SPL_ERR_INVALID_HEX_RECORD, Error::InvalidHexRecord(0)),
+            (SPL_ERR_INVALID_ENV_SIZE, Error::InvalidEnvSize((0, 0))),
+            (SPL_ERR_INVALID_ENV_VAR, Error::InvalidEnvVar(0)),
+            (SPL_ERR_INVALID_UIMAGE_MAGIC, Error::InvalidUimageMagic(0)),
+            (SPL_ERR_INVALID_UIMAGE_TYPE, Error::InvalidUimageType(0)),
+            (SPL_ERR_INVALID_UIMAGE_COMP, Error::InvalidUimageComp(0)),
+            (SPL_ERR_INVALID_FIT, Error::InvalidFit),
+            (SPL_ERR_INVALID_FIT_HASH, Error::InvalidFitHash),
+            (SPL_ERR_INVALID_GPT, Error::InvalidGpt),
+            (SPL_ERR_DISK_READ, Error::DiskRead(0)),
+            (SPL_ERR_DISK_WRITE, Error::DiskWrite(0)),
+            (SPL_ERR_INVALID_FIX_RECORD, Error::InvalidFixRecord),
+            (SPL_ERR_INVALID_SPARSE_IMAGE, Error::InvalidSparseImage),
+            (SPL_ERR_UNDEFINED_ENV_VAR, Error::UndefinedEnvVar),
+            (SPL_ERR_INVALID_FLASH_LAYOUT, Error::InvalidFlashLayout(0)),
+            (SPL_ERR_INVALID_RESL, Error::InvalidResl(0)),
+            (SPL_ERR_INVALID_UIMAGE_LEN, Error::InvalidUimageLen((0, 0))),
+        ] {
+            assert_eq!(code, -err.code(), "{err:?}");
+
+            let msg = unsafe { CStr::from_ptr(spl_strerror(code)) };
+            assert_eq!(msg.to_str().unwrap(), err.description());
+        }
+
+        let err = <[u8; 4]>::try_from([0u8; 1].as_ref()).unwrap_err();
+        assert_eq!(SPL_ERR_INVALID_SLICE, -Error::InvalidSlice(err).code());
+    }
+
+    #[test]
+    fn test_strerror() {
+        let msg = |code| unsafe { CStr::from_ptr(spl_strerror(code)) };
+
+        assert_eq!(msg(SPL_OK), c"success");
+        assert_eq!(msg(SPL_ERR_INVALID_CRC), c"invalid CRC");
+        assert_eq!(msg(-1000), c"unknown error");
+        assert_eq!(msg(1), c"unknown error");
+        assert_eq!(msg(c_int::MIN), c"unknown error");
+    }
+
+    #[test]
+    fn test_header_create() {
+        let payload = [0x13u8, 0x00, 0x00, 0x00];
+        let mut header = UbootSplHeader::default();
+
+        let ret = unsafe { spl_header_create(payload.as_ptr(), payload.len(), 0, 0, &mut header) };
+        assert_eq!(ret, SPL_OK);
+        assert!(header.verify_payload(payload.as_ref()).is_ok());
+
+        let ret = unsafe { spl_header_create(core::ptr::null(), 0, 0, 0, &mut header) };
+        assert_eq!(ret, SPL_ERR_NULL_POINTER);
+    }
+}
@@ -0,0 +1,395 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use spl_tool::{
+    is_erased, Error, FitImage, FlashLayout, FlashRegion, FlashRegionKind, IoContext,
+    SplCopyStatus, UbootEnv, DEF_BACKUP, DEF_ENV_OFFSET, DEF_ENV_SIZE, DEF_FIT_OFFSET,
+    FLASH_SECTOR_LEN,
+};
+
+use super::info::copy_status;
+use super::output::write_atomic;
+use super::parse_num;
+use super::Result;
+
+/// Layout of the SPI flash dump to analyze.
+#[derive(clap::Args, Debug)]
+pub struct AnalyzeArgs {
+    /// Raw SPI flash dump (binary, HEX or SREC)
+    pub image: String,
+    /// Directory the extracted components are written to [default: <IMAGE>.extract]
+    #[arg(short = 'o', long = "output-dir")]
+    pub output_dir: Option<String>,
+    /// Offset of the backup SPL copy, used when the primary header is unusable
+    #[arg(short = 'b', long = "bofs", default_value_t = DEF_BACKUP, value_parser = parse_num)]
+    pub bofs: u32,
+    /// Offset of the U-Boot environment region
+    #[arg(long = "env-offset", default_value_t = DEF_ENV_OFFSET, value_parser = parse_num)]
+    pub env_offset: u32,
+    /// Size of the U-Boot environment region
+    #[arg(long = "env-size", default_value_t = DEF_ENV_SIZE as u32, value_parser = parse_num)]
+    pub env_size: u32,
+    /// Offset of the U-Boot FIT image
+    #[arg(long = "fit-offset", default_value_t = DEF_FIT_OFFSET, value_parser = parse_num)]
+    pub fit_offset: u32,
+}
+
+impl AnalyzeArgs {
+    fn layout(&self) -> FlashLayout {
+        FlashLayout::new()
+            .with_bofs(self.bofs)
+            .with_env_offset(self.env_offset)
+            .with_env_size(self.env_size)
+            .with_fit_offset(self.fit_offset)
+    }
+}
+
+/// Identifies, checks and extracts the boot components of a raw SPI flash dump.
+///
+/// The primary and backup SPL copies, the U-Boot environment, and the U-Boot FIT image are each
+/// written to their own file, and a map of the used and free flash regions is printed.
+///
+/// Every component is extracted before an error is returned for the first corrupt one.
+pub fn spl_analyze(args: &AnalyzeArgs) -> Result<()> {
+    let name = args.image.as_str();
+    let img = super::image::read_image(name)?;
+
+    let outdir = args
+        .output_dir
+        .as_deref()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(format!("{name}.extract")));
+    fs::create_dir_all(&outdir).io_context(Error::InvalidHeaderFile, &outdir)?;
+
+    println!("{name}: {} bytes", img.len());
+
+    let mut layout = args.layout();
+    let mut regions = Vec::new();
+    let mut res = Ok(());
+
+    let primary = check_spl(&img, 0, FlashRegionKind::PrimarySpl, &outdir);
+    if let Some(hdr_bofs) = primary.bofs.filter(|&b| b != 0 && b != layout.bofs()) {
+        log::debug!("Using bofs {hdr_bofs:#x} from the primary SPL header");
+        layout.set_bofs(hdr_bofs);
+    }
+    collect(&mut regions, &mut res, primary.region, primary.res);
+
+    let backup = check_spl(&img, layout.bofs(), FlashRegionKind::BackupSpl, &outdir);
+    collect(&mut regions, &mut res, backup.region, backup.res);
+
+    let (region, env_res) = check_env(&img, &layout, &outdir);
+    collect(&mut regions, &mut res, region, env_res);
+
+    let (region, fit_res) = check_fit(&img, &layout, &outdir);
+    collect(&mut regions, &mut res, region, fit_res);
+
+    println!("Region map:");
+    print_map(&img, &mut regions);
+
+    println!("Components written to {}", outdir.display());
+
+    res
+}
+
+struct SplCheck {
+    region: Option<FlashRegion>,
+    bofs: Option<u32>,
+    res: Result<()>,
+}
+
+fn check_spl(img: &[u8], offset: u32, kind: FlashRegionKind, outdir: &Path) -> SplCheck {
+    if offset as usize >= img.len() {
+        println!("{kind} @ {offset:#x}: outside of the flash dump");
+        return SplCheck {
+            region: None,
+            bofs: None,
+            res: Ok(()),
+        };
+    }
+
+    let (status, header) = copy_status(img, offset);
+    println!("{kind} @ {offset:#x}: {status}");
+
+    let res = match status {
+        SplCopyStatus::Ok | SplCopyStatus::Blank => Ok(()),
+        SplCopyStatus::InvalidHeader(err) | SplCopyStatus::ReadError(err) => Err(err.into()),
+        SplCopyStatus::CrcMismatch { actual, expected } => {
+            Err(Error::InvalidCrc((actual, expected)).into())
+        }
+    };
+
+    let Some(hdr) = header else {
+        return SplCheck {
+            region: None,
+            bofs: None,
+            res,
+        };
+    };
+
+    let start = offset as usize;
+    let end = start
+        .saturating_add(hdr.resl() as usize)
+        .saturating_add(hdr.fsiz() as usize)
+        .min(img.len());
+    println!(
+        "  fsiz: {}, resl: {:#x}, bofs: {:#x}",
+        hdr.fsiz(),
+        hdr.resl(),
+        hdr.bofs()
+    );
+
+    let file = match kind {
+        FlashRegionKind::BackupSpl => "backup-spl.bin",
+        _ => "primary-spl.bin",
+    };
+
+    SplCheck {
+        region: Some(FlashRegion::new(start as u32, end as u32, kind)),
+        bofs: Some(hdr.bofs()),
+        res: res.and(extract(outdir, file, &img[start..end])),
+    }
+}
+
+fn check_env(img: &[u8], layout: &FlashLayout, outdir: &Path) -> (Option<FlashRegion>, Result<()>) {
+    let start = layout.env_offset() as usize;
+    let end = start.saturating_add(layout.env_size() as usize);
+    let kind = FlashRegionKind::Env;
+
+    let Some(blob) = img.get(start..end) else {
+        println!("{kind} @ {start:#x}: outside of the flash dump");
+        return (None, Ok(()));
+    };
+
+    if is_erased(blob) {
+        println!("{kind} @ {start:#x}: blank");
+        return (None, Ok(()));
+    }
+
+    let region = Some(FlashRegion::new(start as u32, end as u32, kind));
+
+    let env = match UbootEnv::parse(blob, false).or_else(|_| UbootEnv::parse(blob, true)) {
+        Ok(env) => env,
+        Err(err) => {
+            println!("{kind} @ {start:#x}: {err}");
+            return (
+                region,
+                extract(outdir, "env.bin", blob).and(Err(err.into())),
+            );
+        }
+    };
+
+    let mut text = String::new();
+    let mut count = 0usize;
+    for var in env.vars() {
+        match var {
+            Ok((key, val)) => {
+                // writing to a `String` is infallible
+                let _ = writeln!(text, "{key}={val}");
+                count += 1;
+            }
+            Err(err) => {
+                println!("{kind} @ {start:#x}: {err}");
+                return (
+                    region,
+                    extract(outdir, "env.bin", blob).and(Err(err.into())),
+                );
+            }
+        }
+    }
+
+    match env.flags() {
+        Some(flags) => println!("{kind} @ {start:#x}: OK, {count} variable(s), flags: {flags}"),
+        None => println!("{kind} @ {start:#x}: OK, {count} variable(s)"),
+    }
+
+    let res = extract(outdir, "env.bin", blob).and(extract(outdir, "env.txt", text.as_bytes()));
+
+    (region, res)
+}
+
+fn check_fit(img: &[u8], layout: &FlashLayout, outdir: &Path) -> (Option<FlashRegion>, Result<()>) {
+    let start = layout.fit_offset() as usize;
+    let kind = FlashRegionKind::Fit;
+
+    let Some(data) = img.get(start..).filter(|d| !d.is_empty()) else {
+        println!("{kind} @ {start:#x}: outside of the flash dump");
+        return (None, Ok(()));
+    };
+
+    if data.len() >= 4 && is_erased(&data[..4]) {
+        println!("{kind} @ {start:#x}: blank");
+        return (None, Ok(()));
+    }
+
+    let fit = match FitImage::parse(data) {
+        Ok(fit) => fit,
+        Err(err) => {
+            println!("{kind} @ {start:#x}: {err}");
+            return (None, Err(err.into()));
+        }
+    };
+
+    let (len, res) = match fit.verify() {
+        Ok(summary) => {
+            println!(
+                "{kind} @ {start:#x}: OK, {} image(s), {} hash(es) checked, {} skipped",
+                summary.images, summary.hashes, summary.skipped
+            );
+            (summary.len, Ok(()))
+        }
+        Err(err) => {
+            println!("{kind} @ {start:#x}: {err}");
+            (fit.totalsize(), Err(err.into()))
+        }
+    };
+
+    let end = start.saturating_add(len).min(img.len());
+    let region = Some(FlashRegion::new(start as u32, end as u32, kind));
+
+    (
+        region,
+        res.and(extract(outdir, "u-boot.itb", &img[start..end])),
+    )
+}
+
+fn collect(
+    regions: &mut Vec<FlashRegion>,
+    res: &mut Result<()>,
+    region: Option<FlashRegion>,
+    check: Result<()>,
+) {
+    regions.extend(region);
+    if res.is_ok() {
+        *res = check;
+    }
+}
+
+fn extract(outdir: &Path, file: &str, data: &[u8]) -> Result<()> {
+    let path = outdir.join(file);
+    write_atomic(&path, data).io_context(Error::InvalidHeaderFile, &path)?;
+
+    log::info!("Extracted {} bytes to {}", data.len(), path.display());
+
+    Ok(())
+}
+
+fn print_map(img: &[u8], regions: &mut [FlashRegion]) {
+    regions.sort_by_key(|r| (r.start, r.end));
+
+    let mut pos = 0usize;
+    for (idx, region) in regions.iter().enumerate() {
+        let start = region.start as usize;
+        if start > pos {
+            print_gaps(img, pos, start);
+        }
+
+        print_region(region);
+        for other in regions[..idx].iter().filter(|o| o.overlaps(region)) {
+            println!("  ^ overlaps {}", other.kind);
+        }
+
+        pos = pos.max(region.end as usize);
+    }
+
+    if pos < img.len() {
+        print_gaps(img, pos, img.len());
+    }
+}
+
+// classifies the unclaimed range per erase sector, merging adjacent sectors of the same kind
+fn print_gaps(img: &[u8], start: usize, end: usize) {
+    let mut gap: Option<FlashRegion> = None;
+    let mut pos = start;
+
+    while pos < end {
+        let next = (pos / FLASH_SECTOR_LEN + 1)
+            .saturating_mul(FLASH_SECTOR_LEN)
+            .min(end);
+        let kind = if is_erased(&img[pos..next]) {
+            FlashRegionKind::Free
+        } else {
+            FlashRegionKind::Data
+        };
+
+        match gap.as_mut() {
+            Some(g) if g.kind == kind => g.end = next as u32,
+            _ => {
+                if let Some(g) = gap.as_ref() {
+                    print_region(g);
+                }
+                gap = Some(FlashRegion::new(pos as u32, next as u32, kind));
+            }
+        }
+
+        pos = next;
+    }
+
+    if let Some(g) = gap.as_ref() {
+        print_region(g);
+    }
+}
+
+fn print_region(region: &FlashRegion) {
+    println!(
+        "  {:#010x}-{:#010x} {:>10} {}",
+        region.start,
+        region.end,
+        region.len(),
+        region.kind
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use spl_tool::crc32_ieee;
+
+    use super::*;
+
+    const ENV_SIZE: usize = 0x100;
+
+    fn env_blob(data: &[u8]) -> Vec<u8> {
+        let mut blob = vec![0u8; ENV_SIZE];
+        blob[4..4 + data.len()].copy_from_slice(data);
+        let crc = crc32_ieee(&blob[4..]);
+        blob[..4].copy_from_slice(crc.to_le_bytes().as_ref());
+        blob
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("spl_tool-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_check_env_extracts_on_error() {
+        let dir = temp_dir("analyze-env");
+        let layout = FlashLayout::new()
+            .with_env_offset(0)
+            .with_env_size(ENV_SIZE as u32);
+
+        let blob = env_blob(b"bootdelay=2\0novalue\0\0");
+        let (region, res) = check_env(blob.as_ref(), &layout, &dir);
+
+        assert!(region.is_some());
+        assert!(matches!(res.unwrap_err().error(), Error::InvalidEnvVar(1)));
+        assert_eq!(fs::read(dir.join("env.bin")).unwrap(), blob);
+        assert!(!dir.join("env.txt").exists());
+
+        let blob = env_blob(b"bootdelay=2\0\0");
+        let (_, res) = check_env(blob.as_ref(), &layout, &dir);
+
+        assert!(res.is_ok());
+        assert_eq!(fs::read(dir.join("env.bin")).unwrap(), blob);
+        assert_eq!(
+            fs::read_to_string(dir.join("env.txt")).unwrap(),
+            "bootdelay=2\n"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
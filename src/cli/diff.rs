@@ -0,0 +1,62 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use spl_tool::SplDiff;
+
+use super::image::read_image;
+use super::Result;
+
+/// Prints the field-level and payload differences between two headered SPL images.
+pub fn spl_diff(old: &str, new: &str) -> Result<()> {
+    let old_img = read_image(old)?;
+    let new_img = read_image(new)?;
+
+    let diff = SplDiff::new(old_img.as_ref(), new_img.as_ref())?;
+
+    if diff.is_empty() {
+        println!("{old} and {new} are identical");
+        return Ok(());
+    }
+
+    println!("--- {old}");
+    println!("+++ {new}");
+
+    println!("header:");
+    let mut fields = diff.fields().peekable();
+    if fields.peek().is_none() {
+        println!("  (no changes)");
+    }
+    for field in fields {
+        println!(
+            "  {:#05x} {}: {} -> {}",
+            field.field.offset(),
+            field.field,
+            field.old,
+            field.new
+        );
+    }
+
+    let old_len = diff.old_payload().len();
+    let new_len = diff.new_payload().len();
+    println!(
+        "payload: {old_len} -> {new_len} bytes ({:+})",
+        diff.size_delta()
+    );
+
+    match diff.first_diff() {
+        Some(offset) => {
+            println!("  first difference at {offset:#x}");
+            println!("  changed regions:");
+            for range in diff.changed_ranges() {
+                println!(
+                    "    {:#x}..{:#x} ({} bytes)",
+                    range.start,
+                    range.end,
+                    range.len()
+                );
+            }
+        }
+        None => println!("  (no changes)"),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,173 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use std::{fs, io};
+
+use spl_tool::{
+    uboot_env_create, Error, ErrorContext, IoContext, UbootEnv, UbootEnvText, DEF_ENV_SIZE,
+    ENV_PAD_BYTE, ENV_REDUND_ACTIVE,
+};
+
+use super::output::write_atomic;
+use super::parse_num;
+use super::Result;
+
+/// Location of a U-Boot environment in a blob or flash dump.
+#[derive(clap::Args, Debug)]
+pub struct EnvRegion {
+    /// U-Boot environment blob, or flash dump with `--offset`
+    pub image: String,
+    /// Offset of the environment in the image, e.g. 0xf0000 for a VisionFive2 SPI flash dump
+    #[arg(short = 'o', long = "offset", default_value = "0", value_parser = parse_num)]
+    pub offset: u32,
+    /// Size of the environment region
+    #[arg(short = 's', long = "size", default_value_t = DEF_ENV_SIZE as u32, value_parser = parse_num)]
+    pub size: u32,
+    /// The environment has a redundant-env flag byte
+    #[arg(short = 'r', long = "redundant", default_value = "false")]
+    pub redundant: bool,
+    /// Byte used to pad the data area when writing, like `mkenvimage -p`
+    #[arg(short = 'p', long = "pad", default_value_t = ENV_PAD_BYTE, value_parser = parse_byte)]
+    pub pad: u8,
+}
+
+impl EnvRegion {
+    fn range(&self) -> std::ops::Range<usize> {
+        let start = self.offset as usize;
+        start..start + self.size as usize
+    }
+}
+
+/// Prints the variables in the U-Boot environment, or only the values of `keys`.
+pub fn env_get(region: &EnvRegion, keys: &[String]) -> Result<()> {
+    let img = read_file(region.image.as_str())?;
+    let env = parse_env(region, img.as_ref())?;
+
+    if keys.is_empty() {
+        for var in env.vars() {
+            let (key, val) = var?;
+            println!("{key}={val}");
+        }
+        return Ok(());
+    }
+
+    for key in keys.iter() {
+        match env.get(key.as_str()) {
+            Some(val) => println!("{key}={val}"),
+            None => {
+                log::error!("U-Boot env variable {key} is not defined");
+                return Err(Error::UndefinedEnvVar.into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Sets a variable in the U-Boot environment, or deletes it without a value.
+pub fn env_set(region: &EnvRegion, key: &str, val: Option<&str>) -> Result<()> {
+    let mut img = read_file(region.image.as_str())?;
+
+    let (flags, mut vars) = {
+        let env = parse_env(region, img.as_ref())?;
+        let vars = env
+            .vars()
+            .map(|var| var.map(|(k, v)| (k.to_owned(), v.to_owned())))
+            .collect::<spl_tool::Result<Vec<_>>>()?;
+        (env.flags(), vars)
+    };
+
+    match (vars.iter().position(|(k, _)| k == key), val) {
+        (Some(idx), Some(val)) => vars[idx].1 = val.to_owned(),
+        (Some(idx), None) => {
+            vars.remove(idx);
+        }
+        (None, Some(val)) => vars.push((key.to_owned(), val.to_owned())),
+        (None, None) => log::warn!("U-Boot env variable {key} is not defined"),
+    }
+
+    write_env(
+        region,
+        &mut img,
+        flags.map(|f| f.wrapping_add(1)),
+        vars.iter().map(|(k, v)| (k.as_str(), v.as_str())),
+    )
+}
+
+/// Replaces the U-Boot environment with the variables from a `mkenvimage` text file.
+///
+/// The image is created, or extended to fit the region, if needed.
+pub fn env_import(region: &EnvRegion, text: &str) -> Result<()> {
+    let text = fs::read_to_string(text).io_context(Error::InvalidConfigFile, text)?;
+
+    // only a missing image is created, any other error must not overwrite it
+    let name = region.image.as_str();
+    let mut img = match fs::read(name) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        res => res.io_context(Error::InvalidSplFile, name)?,
+    };
+
+    let flags = if region.redundant {
+        let prev = img
+            .get(region.range())
+            .and_then(|blob| UbootEnv::parse(blob, true).ok())
+            .and_then(|env| env.flags());
+        Some(prev.map_or(ENV_REDUND_ACTIVE, |f| f.wrapping_add(1)))
+    } else {
+        None
+    };
+
+    let vars = UbootEnvText::new(text.as_str())
+        .collect::<spl_tool::Result<Vec<_>>>()
+        .inspect_err(|err| log::error!("Error parsing U-Boot env text file: {err}"))?;
+
+    write_env(region, &mut img, flags, vars)
+}
+
+fn parse_env<'a>(region: &EnvRegion, img: &'a [u8]) -> Result<UbootEnv<'a>> {
+    let name = region.image.as_str();
+    let blob = img.get(region.range()).ok_or_else(|| {
+        log::error!(
+            "{name} is too short for a U-Boot env region at {:#x}, size: {:#x}",
+            region.offset,
+            region.size
+        );
+        Error::InvalidEnvSize((region.range().end, img.len()))
+    })?;
+
+    UbootEnv::parse(blob, region.redundant)
+        .inspect_err(|err| log::error!("Error parsing U-Boot env in {name}: {err}"))
+        .path_context(name)
+}
+
+fn write_env<'v, I>(region: &EnvRegion, img: &mut Vec<u8>, flags: Option<u8>, vars: I) -> Result<()>
+where
+    I: IntoIterator<Item = (&'v str, &'v str)>,
+{
+    let name = region.image.as_str();
+    let range = region.range();
+
+    if img.len() < range.end {
+        img.resize(range.end, 0xff);
+    }
+
+    let len = uboot_env_create(&mut img[range], flags, region.pad, vars)
+        .inspect_err(|err| log::error!("Error creating U-Boot env: {err}"))?;
+
+    write_atomic(name, img).io_context(Error::InvalidSplFile, name)?;
+
+    log::info!(
+        "U-Boot env written to {name} at {:#x} successfully ({len} of {} bytes used).",
+        region.offset,
+        region.size
+    );
+
+    Ok(())
+}
+
+fn read_file(name: &str) -> Result<Vec<u8>> {
+    fs::read(name).io_context(Error::InvalidSplFile, name)
+}
+
+fn parse_byte(val: &str) -> std::result::Result<u8, String> {
+    parse_num(val).and_then(|num| u8::try_from(num).map_err(|_| format!("invalid byte: {val}")))
+}
@@ -0,0 +1,564 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::ops::Range;
+
+use spl_tool::{
+    gpt_sector_len, BlockKind, DiskIo, Error, GptHeader, IoContext, Sha256, SparseChunkHeader,
+    SparseHeader, DEF_SPARSE_BLOCK_LEN, MBR_LEN, SPARSE_CHUNK_HEADER_LEN, SPARSE_HEADER_LEN,
+};
+
+use super::gpt::FileDisk;
+use super::output::{write_atomic, AtomicFile};
+use super::write::parse_block_len;
+use super::Result;
+
+// placeholder for the bmap file checksum, while the checksum itself is calculated
+const BMAP_CHECKSUM_ZERO: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Disk image to export, and the bmap and/or Android sparse outputs.
+#[derive(clap::Args, Debug)]
+#[command(group(clap::ArgGroup::new("outputs").required(true).multiple(true).args(["bmap", "sparse"])))]
+pub struct ExportArgs {
+    /// Generated disk image, e.g. with the SPL, FIT and rootfs partitions
+    pub image: String,
+    /// Output block map file (bmaptool XML format)
+    #[arg(long = "bmap")]
+    pub bmap: Option<String>,
+    /// Output Android sparse image
+    #[arg(long = "sparse")]
+    pub sparse: Option<String>,
+    /// Block size, a multiple of 4096 bytes
+    #[arg(long = "block-size", default_value_t = DEF_SPARSE_BLOCK_LEN as u32, value_parser = parse_block_len)]
+    pub block_size: u32,
+    /// Leave zero blocks out of the outputs, except for the partition tables.
+    ///
+    /// The target's previous contents survive in the skipped blocks, only use this for targets
+    /// that are known to be zeroed (e.g. after a discard).
+    #[arg(long = "skip-zeros")]
+    pub skip_zeros: bool,
+}
+
+// consecutive blocks of the same kind
+#[derive(Clone, Copy, Debug)]
+struct BlockRun {
+    kind: BlockKind,
+    start: u64,
+    count: u64,
+}
+
+/// Writes a block map file and/or an Android sparse image of a disk image.
+///
+/// Like `bmaptool create`, only the holes of a sparse image file are left out of both outputs.
+/// Zero blocks are written as zero fills, so that no stale data (e.g. GPT partition entries of a
+/// previous layout) survives on the target. With `--skip-zeros`, zero blocks are left out as well,
+/// except for the MBR and both GPT copies.
+pub fn spl_export(args: &ExportArgs) -> Result<()> {
+    let name = args.image.as_str();
+    let block_len = args.block_size as usize;
+
+    let mut img = FileDisk::open(name, false)?;
+    let runs = block_runs(&mut img, block_len, args.skip_zeros)?;
+
+    let mapped = runs
+        .iter()
+        .filter(|r| r.kind.is_mapped())
+        .map(|r| r.count)
+        .sum::<u64>();
+    let total = runs.last().map(|r| r.start + r.count).unwrap_or(0);
+    log::info!("{name}: {mapped} of {total} {block_len}-byte blocks mapped");
+
+    if let Some(bmap) = args.bmap.as_deref() {
+        let text = bmap_text(&mut img, &runs, block_len)?;
+        write_atomic(bmap, text).io_context(Error::InvalidHeaderFile, bmap)?;
+        log::info!("Block map written to {bmap} successfully.");
+    }
+
+    if let Some(sparse) = args.sparse.as_deref() {
+        write_sparse(&mut img, &runs, block_len, sparse)?;
+        log::info!("Android sparse image written to {sparse} successfully.");
+    }
+
+    Ok(())
+}
+
+fn block_runs(img: &mut FileDisk, block_len: usize, skip_zeros: bool) -> Result<Vec<BlockRun>> {
+    let len = img.disk_len();
+    let allocated = img.data_ranges();
+    let tables = partition_tables(img);
+    let mut runs: Vec<BlockRun> = Vec::new();
+    let mut buf = vec![0u8; block_len];
+    let mut block = 0u64;
+
+    while block * (block_len as u64) < len {
+        let start = block * block_len as u64;
+        let range = start..(start + block_len as u64).min(len);
+        let pinned = overlaps(&tables, &range);
+        let hole = allocated
+            .as_deref()
+            .is_some_and(|allocated| !overlaps(allocated, &range));
+
+        let kind = if hole && !pinned {
+            BlockKind::Hole
+        } else {
+            match BlockKind::classify(read_block(img, block, &mut buf)?) {
+                BlockKind::Zero if skip_zeros && !pinned => BlockKind::Hole,
+                kind => kind,
+            }
+        };
+
+        match runs.last_mut() {
+            Some(run) if run.kind == kind => run.count += 1,
+            _ => runs.push(BlockRun {
+                kind,
+                start: block,
+                count: 1,
+            }),
+        }
+
+        block += 1;
+    }
+
+    Ok(runs)
+}
+
+// sorted byte ranges of the MBR, and of both GPT copies with their partition entry arrays
+fn partition_tables(img: &mut FileDisk) -> Vec<Range<u64>> {
+    let len = img.disk_len();
+    let mbr = len.min(MBR_LEN as u64);
+
+    let Some(sector_len) = gpt_sector_len(img) else {
+        return std::iter::once(0..mbr).collect();
+    };
+
+    let mut sector = vec![0u8; sector_len];
+    let header = img
+        .read_at(sector_len as u64, &mut sector)
+        .and_then(|_| GptHeader::parse(&sector));
+
+    match header {
+        Ok(header) => {
+            let sector_len = sector_len as u64;
+            let first = header
+                .first_usable_lba()
+                .saturating_mul(sector_len)
+                .clamp(mbr, len);
+            let last = header
+                .last_usable_lba()
+                .saturating_add(1)
+                .saturating_mul(sector_len)
+                .clamp(first, len);
+
+            [0..first, last..len]
+                .into_iter()
+                .filter(|r| !r.is_empty())
+                .collect()
+        }
+        Err(err) => {
+            // without a valid primary header, only the signature sector is known
+            log::warn!("Invalid primary GPT header: {err}");
+            std::iter::once(0..len.min(2 * sector_len as u64)).collect()
+        }
+    }
+}
+
+// gets whether `range` overlaps any of the sorted, non-overlapping `ranges`
+fn overlaps(ranges: &[Range<u64>], range: &Range<u64>) -> bool {
+    let idx = ranges.partition_point(|r| r.end <= range.start);
+    ranges.get(idx).is_some_and(|r| r.start < range.end)
+}
+
+// reads a block of the image, the last block is truncated at the end of the image
+fn read_block<'a>(img: &mut FileDisk, block: u64, buf: &'a mut [u8]) -> Result<&'a [u8]> {
+    let offset = block * buf.len() as u64;
+    let len = (img.disk_len() - offset).min(buf.len() as u64) as usize;
+
+    img.read_at(offset, &mut buf[..len])?;
+
+    Ok(&buf[..len])
+}
+
+fn bmap_text(img: &mut FileDisk, runs: &[BlockRun], block_len: usize) -> Result<String> {
+    // bmap ranges cover all consecutive mapped blocks, regardless of the block contents
+    let mut ranges: Vec<(u64, u64)> = Vec::new();
+    for run in runs.iter().filter(|r| r.kind.is_mapped()) {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == run.start => *end += run.count,
+            _ => ranges.push((run.start, run.start + run.count - 1)),
+        }
+    }
+
+    let blocks = runs.last().map(|r| r.start + r.count).unwrap_or(0);
+    let mapped = ranges.iter().map(|(s, e)| e - s + 1).sum::<u64>();
+
+    // writing to a `String` is infallible
+    let mut res = String::new();
+    let _ = writeln!(res, "<?xml version=\"1.0\" ?>");
+    let _ = writeln!(res, "<bmap version=\"2.0\">");
+    let _ = writeln!(res, "    <ImageSize> {} </ImageSize>", img.disk_len());
+    let _ = writeln!(res, "    <BlockSize> {block_len} </BlockSize>");
+    let _ = writeln!(res, "    <BlocksCount> {blocks} </BlocksCount>");
+    let _ = writeln!(res, "    <MappedBlocksCount> {mapped} </MappedBlocksCount>");
+    let _ = writeln!(res, "    <ChecksumType> sha256 </ChecksumType>");
+    let _ = writeln!(
+        res,
+        "    <BmapFileChecksum> {BMAP_CHECKSUM_ZERO} </BmapFileChecksum>"
+    );
+    let _ = writeln!(res, "    <BlockMap>");
+
+    let mut buf = vec![0u8; block_len];
+    for &(start, end) in ranges.iter() {
+        let mut hasher = Sha256::new();
+        for block in start..=end {
+            hasher.update(read_block(img, block, &mut buf)?);
+        }
+        let digest = hex_digest(hasher.finalize().as_ref());
+
+        if start == end {
+            let _ = writeln!(res, "        <Range chksum=\"{digest}\"> {start} </Range>");
+        } else {
+            let _ = writeln!(
+                res,
+                "        <Range chksum=\"{digest}\"> {start}-{end} </Range>"
+            );
+        }
+    }
+
+    let _ = writeln!(res, "    </BlockMap>");
+    let _ = writeln!(res, "</bmap>");
+
+    // the file checksum is calculated with the checksum field zeroed
+    let checksum = hex_digest(
+        Sha256::new()
+            .with_update(res.as_bytes())
+            .finalize()
+            .as_ref(),
+    );
+
+    Ok(res.replacen(BMAP_CHECKSUM_ZERO, checksum.as_str(), 1))
+}
+
+fn write_sparse(img: &mut FileDisk, runs: &[BlockRun], block_len: usize, path: &str) -> Result<()> {
+    // raw chunks are split, so that the chunk length fits the 32-bit `total_sz` field
+    let max_raw = ((u32::MAX as usize - SPARSE_CHUNK_HEADER_LEN) / block_len) as u64;
+    let chunks = runs
+        .iter()
+        .flat_map(|run| {
+            let max = if run.kind == BlockKind::Data {
+                max_raw
+            } else {
+                u32::MAX as u64
+            };
+            (0..run.count.div_ceil(max)).map(move |idx| BlockRun {
+                kind: run.kind,
+                start: run.start + idx * max,
+                count: (run.count - idx * max).min(max),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let total_blocks = runs.last().map(|r| r.start + r.count).unwrap_or(0);
+    let (total_blocks, total_chunks) =
+        match (u32::try_from(total_blocks), u32::try_from(chunks.len())) {
+            (Ok(blocks), Ok(chunks)) => (blocks, chunks),
+            _ => {
+                log::error!("Image is too large for an Android sparse image");
+                return Err(Error::InvalidSparseImage.into());
+            }
+        };
+
+    if !img.disk_len().is_multiple_of(block_len as u64) {
+        log::warn!("Image length is not a multiple of {block_len}, the last block is zero-padded");
+    }
+
+    let mut out = AtomicFile::create(path).io_context(Error::InvalidSparseImage, path)?;
+
+    let header: [u8; SPARSE_HEADER_LEN] =
+        SparseHeader::new(block_len as u32, total_blocks, total_chunks).into();
+    out.write_all(header.as_ref())
+        .io_context(Error::InvalidSparseImage, path)?;
+
+    let mut buf = vec![0u8; block_len];
+    for chunk in chunks.iter() {
+        let chunk_type = chunk.kind.chunk_type();
+        let hdr: [u8; SPARSE_CHUNK_HEADER_LEN] =
+            SparseChunkHeader::new(chunk_type, chunk.count as u32, block_len as u32)?.into();
+        out.write_all(hdr.as_ref())
+            .io_context(Error::InvalidSparseImage, path)?;
+
+        match chunk.kind {
+            BlockKind::Hole => (),
+            BlockKind::Zero => out
+                .write_all(0u32.to_le_bytes().as_ref())
+                .io_context(Error::InvalidSparseImage, path)?,
+            BlockKind::Fill(val) => out
+                .write_all(val.to_le_bytes().as_ref())
+                .io_context(Error::InvalidSparseImage, path)?,
+            BlockKind::Data => {
+                for block in chunk.start..chunk.start + chunk.count {
+                    let len = read_block(img, block, &mut buf)?.len();
+                    buf[len..].fill(0);
+                    out.write_all(buf.as_ref())
+                        .io_context(Error::InvalidSparseImage, path)?;
+                }
+            }
+        }
+    }
+
+    out.commit().io_context(Error::InvalidSparseImage, path)
+}
+
+fn hex_digest(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use spl_tool::{
+        crc32_ieee, GptDisk, SparseChunkType, GPT_ENTRY_LEN, GPT_SIGNATURE, MBR_PART_OFFSET,
+        MBR_SIGNATURE, MBR_TYPE_PROTECTIVE, SPARSE_HEADER_MAGIC,
+    };
+
+    use super::*;
+
+    const BLOCK_LEN: usize = DEF_SPARSE_BLOCK_LEN;
+    const SECTOR_LEN: usize = 512;
+    const SECTORS: usize = 512;
+    const ENTRIES_SECTORS: usize = 32;
+    // block of the rootfs data, and a zero block of the rootfs partition
+    const DATA_BLOCK: usize = 10;
+    const ZERO_BLOCK: usize = 20;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("spl_tool-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn le32(buf: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+    }
+
+    // writes both GPT headers, with a partition entry array holding a single partition
+    fn write_gpt(disk: &mut FileDisk) {
+        let last_lba = SECTORS as u64 - 1;
+        let mut entries = vec![0u8; ENTRIES_SECTORS * SECTOR_LEN];
+        entries[..32].fill(0x11);
+        entries[32..40].copy_from_slice(34u64.to_le_bytes().as_ref());
+        entries[40..48].copy_from_slice((last_lba - 33).to_le_bytes().as_ref());
+
+        let mut mbr = [0u8; MBR_LEN];
+        mbr[MBR_PART_OFFSET + 4] = MBR_TYPE_PROTECTIVE;
+        mbr[510..].copy_from_slice(MBR_SIGNATURE.as_ref());
+        disk.write_at(0, mbr.as_ref()).unwrap();
+
+        for (my_lba, alternate_lba, entries_lba) in [(1, last_lba, 2), (last_lba, 1, last_lba - 32)]
+        {
+            let mut hdr = [0u8; SECTOR_LEN];
+            hdr[..8].copy_from_slice(GPT_SIGNATURE.as_ref());
+            hdr[8..12].copy_from_slice(0x1_0000u32.to_le_bytes().as_ref());
+            hdr[12..16].copy_from_slice(92u32.to_le_bytes().as_ref());
+            hdr[24..32].copy_from_slice(u64::to_le_bytes(my_lba).as_ref());
+            hdr[32..40].copy_from_slice(u64::to_le_bytes(alternate_lba).as_ref());
+            hdr[40..48].copy_from_slice(34u64.to_le_bytes().as_ref());
+            hdr[48..56].copy_from_slice((last_lba - 33).to_le_bytes().as_ref());
+            hdr[56..72].fill(0x5a);
+            hdr[72..80].copy_from_slice(u64::to_le_bytes(entries_lba).as_ref());
+            hdr[80..84].copy_from_slice(128u32.to_le_bytes().as_ref());
+            hdr[84..88].copy_from_slice((GPT_ENTRY_LEN as u32).to_le_bytes().as_ref());
+            hdr[88..92].copy_from_slice(crc32_ieee(entries.as_ref()).to_le_bytes().as_ref());
+            let crc = crc32_ieee(&hdr[..92]);
+            hdr[16..20].copy_from_slice(crc.to_le_bytes().as_ref());
+
+            disk.write_at(my_lba * SECTOR_LEN as u64, hdr.as_ref())
+                .unwrap();
+            disk.write_at(entries_lba * SECTOR_LEN as u64, &entries[..GPT_ENTRY_LEN])
+                .unwrap();
+        }
+    }
+
+    // creates a GPT disk image, either fully allocated or as a sparse file where only the written
+    // sectors are allocated
+    fn gpt_image(path: &Path, allocated: bool) -> Vec<u8> {
+        let len = SECTORS * SECTOR_LEN;
+        fs::write(path, if allocated { vec![0u8; len] } else { vec![] }).unwrap();
+
+        let mut disk = FileDisk::open(path, true).unwrap();
+        disk.set_len(len as u64).unwrap();
+        write_gpt(&mut disk);
+        disk.write_at((DATA_BLOCK * BLOCK_LEN) as u64, b"rootfs")
+            .unwrap();
+        disk.sync_all().unwrap();
+
+        fs::read(path).unwrap()
+    }
+
+    fn export(dir: &Path, image: &Path, skip_zeros: bool) -> (String, Vec<u8>) {
+        let bmap = dir.join("disk.img.bmap");
+        let sparse = dir.join("disk.simg");
+
+        spl_export(&ExportArgs {
+            image: image.to_str().unwrap().into(),
+            bmap: Some(bmap.to_str().unwrap().into()),
+            sparse: Some(sparse.to_str().unwrap().into()),
+            block_size: BLOCK_LEN as u32,
+            skip_zeros,
+        })
+        .unwrap();
+
+        (fs::read_to_string(bmap).unwrap(), fs::read(sparse).unwrap())
+    }
+
+    // copies the mapped blocks onto the target, like `bmaptool copy`
+    fn apply_bmap(bmap: &str, image: &[u8], target: &mut [u8]) -> usize {
+        let mut mapped = 0;
+        for line in bmap.lines().filter(|l| l.contains("<Range")) {
+            let range = line.split('>').nth(1).unwrap();
+            let range = range.split('<').next().unwrap().trim();
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            let start = start.parse::<usize>().unwrap() * BLOCK_LEN;
+            let end = (end.parse::<usize>().unwrap() + 1) * BLOCK_LEN;
+
+            target[start..end].copy_from_slice(&image[start..end]);
+            mapped += (end - start) / BLOCK_LEN;
+        }
+        mapped
+    }
+
+    // expands the sparse image onto the target, like `fastboot flash`
+    fn apply_sparse(sparse: &[u8], target: &mut [u8]) {
+        assert_eq!(le32(sparse, 0x00), SPARSE_HEADER_MAGIC);
+        assert_eq!(le32(sparse, 0x0c) as usize, BLOCK_LEN);
+        assert_eq!(le32(sparse, 0x10) as usize, target.len() / BLOCK_LEN);
+
+        let mut pos = SPARSE_HEADER_LEN;
+        let mut offset = 0;
+        for _ in 0..le32(sparse, 0x14) {
+            let hdr = &sparse[pos..pos + SPARSE_CHUNK_HEADER_LEN];
+            let chunk_type =
+                SparseChunkType::try_from(u16::from_le_bytes([hdr[0], hdr[1]])).unwrap();
+            let len = le32(hdr, 4) as usize * BLOCK_LEN;
+            let total = le32(hdr, 8) as usize;
+            let data = &sparse[pos + SPARSE_CHUNK_HEADER_LEN..pos + total];
+            let dst = &mut target[offset..offset + len];
+
+            match chunk_type {
+                SparseChunkType::Raw => dst.copy_from_slice(data),
+                SparseChunkType::Fill => dst
+                    .chunks_exact_mut(4)
+                    .for_each(|w| w.copy_from_slice(data)),
+                SparseChunkType::DontCare => (),
+                SparseChunkType::Crc32 => panic!("unexpected CRC32 chunk"),
+            }
+
+            pos += total;
+            offset += len;
+        }
+
+        assert_eq!(pos, sparse.len());
+        assert_eq!(offset, target.len());
+    }
+
+    fn gpt_ok(disk: &mut [u8]) -> bool {
+        GptDisk::new(disk)
+            .with_sector_len(SECTOR_LEN)
+            .check()
+            .is_ok()
+    }
+
+    #[test]
+    fn test_zero_entries_dirty_target() {
+        let dir = temp_dir("export-dirty");
+        let path = dir.join("disk.img");
+        let mut image = gpt_image(&path, true);
+        assert!(gpt_ok(&mut image));
+
+        let (bmap, sparse) = export(&dir, &path, false);
+
+        // the stale bytes of the previous contents must not survive in the zeroed blocks
+        let mut target = vec![0xa5u8; image.len()];
+        apply_sparse(&sparse, &mut target);
+        assert!(target == image);
+        assert!(gpt_ok(&mut target));
+
+        let mut target = vec![0xa5u8; image.len()];
+        let mapped = apply_bmap(&bmap, &image, &mut target);
+        assert!(target[..ZERO_BLOCK * BLOCK_LEN] == image[..ZERO_BLOCK * BLOCK_LEN]);
+        assert!(gpt_ok(&mut target));
+        assert!(
+            bmap.contains(format!("<MappedBlocksCount> {mapped} </MappedBlocksCount>").as_str())
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_skip_zeros_maps_partition_tables() {
+        let dir = temp_dir("export-skip-zeros");
+        let path = dir.join("disk.img");
+        let image = gpt_image(&path, true);
+
+        let (bmap, sparse) = export(&dir, &path, true);
+
+        let mut target = vec![0xa5u8; image.len()];
+        apply_sparse(&sparse, &mut target);
+
+        // the GPT copies, and their zeroed entry slots, are written
+        let tables = 34 * SECTOR_LEN;
+        assert!(target[..tables] == image[..tables]);
+        let backup = image.len() - 33 * SECTOR_LEN;
+        assert!(target[backup..] == image[backup..]);
+        assert!(gpt_ok(&mut target));
+
+        // other zero blocks are skipped
+        let data = DATA_BLOCK * BLOCK_LEN;
+        assert!(target[data..data + BLOCK_LEN] == image[data..data + BLOCK_LEN]);
+        let zero = ZERO_BLOCK * BLOCK_LEN;
+        assert!(target[zero..zero + BLOCK_LEN].iter().all(|&b| b == 0xa5));
+
+        let mut target = vec![0xa5u8; image.len()];
+        apply_bmap(&bmap, &image, &mut target);
+        assert!(gpt_ok(&mut target));
+        assert!(target[zero..zero + BLOCK_LEN].iter().all(|&b| b == 0xa5));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_holes_unmapped() {
+        let dir = temp_dir("export-holes");
+        let path = dir.join("disk.img");
+        let image = gpt_image(&path, false);
+
+        let holes = FileDisk::open(&path, false)
+            .unwrap()
+            .data_ranges()
+            .is_some_and(|ranges| {
+                ranges.iter().map(|r| r.end - r.start).sum::<u64>() < image.len() as u64
+            });
+
+        let (bmap, sparse) = export(&dir, &path, false);
+
+        let mut target = vec![0xa5u8; image.len()];
+        apply_sparse(&sparse, &mut target);
+        // the partition tables are mapped, even when the entry arrays are holes
+        assert!(gpt_ok(&mut target));
+
+        let zero = ZERO_BLOCK * BLOCK_LEN;
+        if holes {
+            assert!(target[zero..zero + BLOCK_LEN].iter().all(|&b| b == 0xa5));
+        } else {
+            assert!(target == image);
+        }
+
+        let mut target = vec![0xa5u8; image.len()];
+        apply_bmap(&bmap, &image, &mut target);
+        assert!(gpt_ok(&mut target));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
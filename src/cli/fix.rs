@@ -0,0 +1,485 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use spl_tool::{
+    jh7110_fix_overlaps_gpt, DiskIo, Error, ErrorContext, GptHeader, HeaderConf, IoContext,
+    UbootSplHeader, CRC_FAILED, DEF_BACKUP, JH7110_FIX_CRCS_OFFSET, MAX_SECTOR_LEN, SPL_HEADER_LEN,
+};
+
+use super::gpt::{disk_sector_len, FileDisk};
+use super::{conf_path, with_suffix, Result};
+
+/// Extension of the sidecar file holding the bytes overwritten by `--fix-imghdr`.
+pub const FIX_RECORD_EXT: &str = "fix-imghdr";
+
+const FIX_RECORD_MAGIC: &str = "# spl_tool fix-imghdr undo record";
+
+/// Represents a run of consecutive bytes changed by `--fix-imghdr`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PatchRun {
+    /// Offset of the run in the image.
+    pub offset: u64,
+    /// Bytes before the change.
+    pub old: Vec<u8>,
+    /// Bytes after the change.
+    pub new: Vec<u8>,
+}
+
+impl fmt::Display for PatchRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:#010x}: {} -> {}",
+            self.offset,
+            hex_bytes(self.old.as_ref(), " "),
+            hex_bytes(self.new.as_ref(), " ")
+        )
+    }
+}
+
+/// Represents the bytes changed by `--fix-imghdr`, saved to a sidecar file to undo the fix.
+///
+/// The text format has a comment header, then one `<offset> <old hex> <new hex>` line per run.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FixRecord {
+    /// Changed byte runs, in offset order.
+    pub runs: Vec<PatchRun>,
+}
+
+impl FixRecord {
+    /// Collects the runs of bytes that differ between `old` and `new`, starting at `offset`.
+    pub fn diff(offset: u64, old: &[u8], new: &[u8]) -> Self {
+        let mut runs: Vec<PatchRun> = Vec::new();
+
+        for (idx, (&o, &n)) in old.iter().zip(new.iter()).enumerate() {
+            if o == n {
+                continue;
+            }
+
+            let pos = offset + idx as u64;
+            match runs.last_mut() {
+                Some(run) if run.offset + run.old.len() as u64 == pos => {
+                    run.old.push(o);
+                    run.new.push(n);
+                }
+                _ => runs.push(PatchRun {
+                    offset: pos,
+                    old: vec![o],
+                    new: vec![n],
+                }),
+            }
+        }
+
+        Self { runs }
+    }
+
+    /// Gets whether no bytes were changed.
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// Serializes the record into its sidecar text format.
+    pub fn to_text(&self, name: &Path) -> String {
+        let mut res = String::new();
+
+        // writing to a `String` is infallible
+        let _ = writeln!(res, "{FIX_RECORD_MAGIC}");
+        let _ = writeln!(res, "# image: {}", name.display());
+        let _ = writeln!(res, "# offset old new");
+        for run in self.runs.iter() {
+            let _ = writeln!(
+                res,
+                "{:#x} {} {}",
+                run.offset,
+                hex_bytes(run.old.as_ref(), ""),
+                hex_bytes(run.new.as_ref(), "")
+            );
+        }
+
+        res
+    }
+
+    /// Parses a record from its sidecar text format.
+    pub fn parse(text: &str) -> Result<Self> {
+        if !text.starts_with(FIX_RECORD_MAGIC) {
+            log::error!("Missing fix-imghdr undo record header");
+            return Err(Error::InvalidFixRecord.into());
+        }
+
+        let mut runs = Vec::new();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let run = parse_run(line).ok_or_else(|| {
+                log::error!("Invalid fix-imghdr undo record line {}: {line}", idx + 1);
+                Error::InvalidFixRecord
+            })?;
+            runs.push(run);
+        }
+
+        Ok(Self { runs })
+    }
+}
+
+/// Gets the default sidecar path of the fix-imghdr undo record for an image.
+pub fn fix_record_path(name: &Path) -> PathBuf {
+    with_suffix(name, format!(".{FIX_RECORD_EXT}").as_str())
+}
+
+/// Applies the JH7110 eMMC boot workaround to the header window of an image.
+///
+/// The original bytes are saved to the `undo_file` sidecar (default: `<image>.fix-imghdr`) before
+/// the image is modified, so the fix can be reverted with [spl_unfix_image]. With `dry_run`, the
+/// planned byte changes are only printed.
+pub fn spl_fix_image_header(
+    conf: &HeaderConf,
+    sector_len: Option<usize>,
+    dry_run: bool,
+    undo_file: Option<&Path>,
+) -> Result<()> {
+    if !conf.fix_image_header() {
+        return Ok(());
+    }
+
+    let path = conf_path(conf)?;
+    let name = path.display();
+
+    let mut disk = FileDisk::open(path, !dry_run)?;
+
+    // The header window is always the first 1 KiB of the image. With 512-byte sectors it
+    // covers the PMBR and the primary GPT header, with larger sectors only LBA 0.
+    let sector_len = disk_sector_len(&mut disk, sector_len);
+    let mut sector = [0u8; MAX_SECTOR_LEN];
+    if disk
+        .read_at(sector_len as u64, &mut sector[..sector_len])
+        .is_ok()
+    {
+        if let Ok(gpt) = GptHeader::parse(&sector[..sector_len]) {
+            if jh7110_fix_overlaps_gpt(sector_len, gpt.header_size()) {
+                log::error!(
+                    "IMG {name}: the fixed CRC at {JH7110_FIX_CRCS_OFFSET:#x} would overwrite the GPT header ({} bytes at {sector_len:#x})",
+                    gpt.header_size()
+                );
+                return Err(Error::InvalidGpt.into());
+            }
+            log::debug!("IMG {name}: GPT with {sector_len}-byte sectors");
+        }
+    }
+
+    // From `spl_tool` C implementation:
+    //
+    // When starting with emmc, bootrom will read 0x0 instead of partition 0. (Known issues).
+    // Read GPT PMBR+Header, then write the backup address at 0x4, and write the wrong CRC
+    // check value at 0x290, so that bootrom CRC check fails and jump to the backup address
+    // to load the real SPL.
+    let mut img_header =
+        UbootSplHeader::read_at(&mut disk, 0).io_context(Error::InvalidSplFile, path)?;
+    // the header keeps the reserved bytes, so these are the original bytes of the window
+    let img_bytes = img_header.to_bytes();
+
+    img_header.set_bofs(if conf.bofs() != 0 {
+        conf.bofs()
+    } else {
+        DEF_BACKUP
+    });
+
+    img_header.set_crcs(CRC_FAILED);
+
+    let hdr_bytes: [u8; SPL_HEADER_LEN] = img_header.into();
+    let record = FixRecord::diff(0, img_bytes.as_ref(), hdr_bytes.as_ref());
+
+    if dry_run {
+        println!(
+            "IMG {name}: planned changes ({} run(s)):",
+            record.runs.len()
+        );
+        for run in record.runs.iter() {
+            println!("  {run}");
+        }
+        return Ok(());
+    }
+
+    if record.is_empty() {
+        log::info!("IMG {name} header is already fixed, nothing to change.");
+        return Ok(());
+    }
+
+    let undo_path = undo_file.map_or_else(|| fix_record_path(path), Path::to_path_buf);
+    write_record(&undo_path, path, &record)?;
+
+    for run in record.runs.iter() {
+        log::info!("IMG {name}: {run}");
+    }
+
+    img_header
+        .write_at(&mut disk, 0)
+        .io_context(Error::DiskWrite(0), path)?;
+    disk.sync_all()?;
+    disk.verify_at(0, &hdr_bytes)?;
+
+    log::info!(
+        "IMG {name} fixed header successfully, undo record: {}",
+        undo_path.display()
+    );
+
+    Ok(())
+}
+
+/// Reverts `--fix-imghdr` using the saved undo record.
+///
+/// The original bytes are only restored when the image still contains exactly the bytes the fix
+/// wrote. With `dry_run`, the planned byte changes are only printed.
+pub fn spl_unfix_image(path: &Path, undo_file: Option<&Path>, dry_run: bool) -> Result<()> {
+    let undo_path = undo_file.map_or_else(|| fix_record_path(path), Path::to_path_buf);
+    let text = fs::read_to_string(&undo_path).io_context(Error::InvalidFixRecord, &undo_path)?;
+    let record = FixRecord::parse(text.as_str()).path_context(&undo_path)?;
+
+    let name = path.display();
+    let mut disk = FileDisk::open(path, !dry_run)?;
+
+    for run in record.runs.iter() {
+        let mut cur = vec![0u8; run.new.len()];
+        disk.read_at(run.offset, cur.as_mut())?;
+
+        if cur != run.new {
+            log::error!(
+                "IMG {name}: bytes at {:#x} are {}, expected {} written by --fix-imghdr",
+                run.offset,
+                hex_bytes(cur.as_ref(), " "),
+                hex_bytes(run.new.as_ref(), " ")
+            );
+            return Err(Error::InvalidFixRecord.into());
+        }
+    }
+
+    if dry_run {
+        println!(
+            "IMG {name}: planned changes ({} run(s)):",
+            record.runs.len()
+        );
+        for run in record.runs.iter() {
+            let undo = PatchRun {
+                offset: run.offset,
+                old: run.new.clone(),
+                new: run.old.clone(),
+            };
+            println!("  {undo}");
+        }
+        return Ok(());
+    }
+
+    for run in record.runs.iter() {
+        disk.write_at(run.offset, run.old.as_ref())?;
+    }
+    disk.sync_all()?;
+    for run in record.runs.iter() {
+        disk.verify_at(run.offset, run.old.as_ref())?;
+    }
+
+    fs::remove_file(&undo_path).io_context(Error::InvalidFixRecord, &undo_path)?;
+
+    log::info!("IMG {name} original header restored successfully.");
+
+    Ok(())
+}
+
+fn write_record(path: &Path, name: &Path, record: &FixRecord) -> Result<()> {
+    // never clobber an existing record, it may hold the only copy of the original bytes
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .io_context(Error::InvalidFixRecord, path)?;
+
+    file.write_all(record.to_text(name).as_bytes())
+        .and_then(|_| file.sync_all())
+        .io_context(Error::InvalidFixRecord, path)
+}
+
+fn parse_run(line: &str) -> Option<PatchRun> {
+    let mut fields = line.split_whitespace();
+
+    let offset = super::parse_offset(fields.next()?).ok()?;
+    let old = parse_hex(fields.next()?)?;
+    let new = parse_hex(fields.next()?)?;
+
+    (fields.next().is_none() && !old.is_empty() && old.len() == new.len()).then_some(PatchRun {
+        offset,
+        old,
+        new,
+    })
+}
+
+fn parse_hex(val: &str) -> Option<Vec<u8>> {
+    if !val.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..val.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(val.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+fn hex_bytes(data: &[u8], sep: &str) -> String {
+    data.iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+
+#[cfg(test)]
+mod tests {
+    use spl_tool::{spl_image, spl_image_len};
+
+    use super::*;
+
+    const PAYLOAD: &[u8] = &[0x13, 0x00, 0x00, 0x00, 0x6f, 0x00, 0x00, 0x00];
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("spl_tool-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = [0, 1, 2, 3, 4, 5, 6, 7];
+        let new = [0, 9, 9, 3, 4, 9, 6, 9];
+        let record = FixRecord::diff(0x10, old.as_ref(), new.as_ref());
+
+        assert_eq!(
+            record.runs,
+            [
+                PatchRun {
+                    offset: 0x11,
+                    old: vec![1, 2],
+                    new: vec![9, 9],
+                },
+                PatchRun {
+                    offset: 0x15,
+                    old: vec![5],
+                    new: vec![9],
+                },
+                PatchRun {
+                    offset: 0x17,
+                    old: vec![7],
+                    new: vec![9],
+                },
+            ]
+        );
+        assert_eq!(record.runs[0].to_string(), "0x00000011: 01 02 -> 09 09");
+        assert!(FixRecord::diff(0, old.as_ref(), old.as_ref()).is_empty());
+    }
+
+    #[test]
+    fn test_text_roundtrip() {
+        let record = FixRecord::diff(0, [0u8, 0, 0xab, 0].as_ref(), [1u8, 2, 0xab, 0xff].as_ref());
+        let text = record.to_text(Path::new("sdcard.img"));
+
+        assert_eq!(
+            text,
+            format!("{FIX_RECORD_MAGIC}\n# image: sdcard.img\n# offset old new\n0x0 0000 0102\n0x3 00 ff\n")
+        );
+        assert_eq!(FixRecord::parse(text.as_str()).unwrap(), record);
+
+        // blank lines, comments and surrounding whitespace are ignored
+        let text = format!("{FIX_RECORD_MAGIC}\n\n  # comment\n  0x290 0a0B0c0D ffffffff  \n");
+        let record = FixRecord::parse(text.as_str()).unwrap();
+        assert_eq!(
+            record.runs,
+            [PatchRun {
+                offset: 0x290,
+                old: vec![0x0a, 0x0b, 0x0c, 0x0d],
+                new: vec![0xff; 4],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        // missing header
+        assert!(FixRecord::parse("0x0 00 01\n").is_err());
+        assert!(FixRecord::parse("").is_err());
+
+        for line in [
+            "0x0 00",
+            "0x0 00 01 02",
+            "0x0 000 001",
+            "0x0 00 0102",
+            "0x0 zz 01",
+            "0x0 é0 01",
+            "zz 00 01",
+        ] {
+            let text = format!("{FIX_RECORD_MAGIC}\n{line}\n");
+            let err = FixRecord::parse(text.as_str()).unwrap_err();
+            assert!(matches!(err.error(), Error::InvalidFixRecord), "{line}");
+        }
+    }
+
+    #[test]
+    fn test_fix_unfix() {
+        let dir = temp_dir("fix");
+        let path = dir.join("sdcard.img");
+        let img: [u8; spl_image_len(PAYLOAD.len())] = spl_image(PAYLOAD);
+        fs::write(&path, img).unwrap();
+
+        let conf = HeaderConf::new()
+            .with_path(&path)
+            .with_bofs(0x20_0000)
+            .with_fix_image_header(true);
+
+        // a dry run changes nothing
+        spl_fix_image_header(&conf, None, true, None).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), img);
+        assert!(!fix_record_path(&path).exists());
+
+        spl_fix_image_header(&conf, None, false, None).unwrap();
+        let fixed = fs::read(&path).unwrap();
+        let header = UbootSplHeader::try_from(&fixed[..SPL_HEADER_LEN]).unwrap();
+        assert_eq!(header.bofs(), 0x20_0000);
+        assert_eq!(header.crcs(), CRC_FAILED);
+
+        let text = fs::read_to_string(fix_record_path(&path)).unwrap();
+        let record = FixRecord::parse(text.as_str()).unwrap();
+        assert_eq!(record, FixRecord::diff(0, img.as_ref(), fixed.as_ref()));
+
+        // fixing again changes nothing, and keeps the original record
+        spl_fix_image_header(&conf, None, false, None).unwrap();
+        assert_eq!(fs::read_to_string(fix_record_path(&path)).unwrap(), text);
+
+        spl_unfix_image(&path, None, false).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), img);
+        assert!(!fix_record_path(&path).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unfix_modified() {
+        let dir = temp_dir("unfix");
+        let path = dir.join("sdcard.img");
+        let img = [0u8; 0x400];
+        fs::write(&path, img).unwrap();
+
+        let record = FixRecord::diff(4, [0u8; 4].as_ref(), [0u8, 0, 0x20, 0].as_ref());
+        write_record(&fix_record_path(&path), &path, &record).unwrap();
+        // an existing record is never overwritten
+        assert!(write_record(&fix_record_path(&path), &path, &record).is_err());
+
+        // the image no longer holds the bytes written by the fix
+        let err = spl_unfix_image(&path, None, false).unwrap_err();
+        assert!(matches!(err.error(), Error::InvalidFixRecord));
+        assert_eq!(fs::read(&path).unwrap(), img);
+        assert!(fix_record_path(&path).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
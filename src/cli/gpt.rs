@@ -0,0 +1,319 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use spl_tool::{
+    gpt_sector_len, is_valid_sector_len, DiskIo, Error, GptCopy, GptCopyCheck, GptDisk, GptReport,
+    GptStatus, IoContext, PathError, CRC_FAILED, DEF_SECTOR_LEN, JH7110_FIX_BOFS_OFFSET,
+    JH7110_FIX_CRCS_OFFSET,
+};
+
+use super::output::lock_file;
+use super::parse_num;
+use super::Result;
+
+/// GPT copy used as the source of a repair.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GptSource {
+    /// Primary copy at LBA 1
+    Primary,
+    /// Backup copy at the last LBA
+    Backup,
+}
+
+impl From<GptSource> for GptCopy {
+    fn from(val: GptSource) -> Self {
+        match val {
+            GptSource::Primary => Self::Primary,
+            GptSource::Backup => Self::Backup,
+        }
+    }
+}
+
+/// Parses a logical sector length command-line argument.
+pub fn parse_sector_len(val: &str) -> std::result::Result<usize, String> {
+    let len = parse_num(val)? as usize;
+    if is_valid_sector_len(len) {
+        Ok(len)
+    } else {
+        Err(format!(
+            "unsupported sector size: {val}, expected 512, 1024, 2048 or 4096"
+        ))
+    }
+}
+
+/// Disk image file or block device accessed through [DiskIo].
+pub struct FileDisk {
+    file: fs::File,
+    path: PathBuf,
+    len: u64,
+}
+
+impl FileDisk {
+    /// Opens a disk image or block device, for writing with `write`.
+    ///
+    /// The disk is locked against concurrent `spl_tool` runs: exclusively for writing, shared for
+    /// reading.
+    pub fn open<P: AsRef<Path>>(name: P, write: bool) -> Result<Self> {
+        let name = name.as_ref();
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .write(write)
+            .open(name)
+            .io_context(Error::InvalidSplFile, name)?;
+
+        lock_file(&file, write).io_context(Error::InvalidSplFile, name)?;
+
+        // block devices report a zero metadata length, seeking to the end works for both
+        let len = file
+            .seek(SeekFrom::End(0))
+            .io_context(Error::InvalidSplFile, name)?;
+
+        Ok(Self {
+            file,
+            path: name.to_path_buf(),
+            len,
+        })
+    }
+
+    /// Truncates or extends a disk image file to `len` bytes.
+    pub fn set_len(&mut self, len: u64) -> Result<()> {
+        self.file
+            .set_len(len)
+            .io_context(Error::DiskWrite(len), &self.path)?;
+        self.len = len;
+        Ok(())
+    }
+
+    /// Flushes all written data to the disk.
+    pub fn sync_all(&self) -> Result<()> {
+        self.file
+            .sync_all()
+            .io_context(Error::DiskWrite(0), &self.path)
+    }
+
+    /// Gets the allocated byte ranges of a sparse disk image file, as reported by `SEEK_DATA` and
+    /// `SEEK_HOLE`.
+    ///
+    /// Returns `None` when holes cannot be detected, e.g. for block devices, or on file systems
+    /// and platforms without `SEEK_HOLE` support. Every byte is then considered allocated.
+    #[cfg(target_os = "linux")]
+    pub fn data_ranges(&self) -> Option<Vec<Range<u64>>> {
+        use std::os::fd::AsRawFd;
+
+        if !self.file.metadata().ok()?.is_file() {
+            return None;
+        }
+
+        let fd = self.file.as_raw_fd();
+        let seek = |pos: u64, whence| {
+            let pos = libc::off_t::try_from(pos).ok()?;
+            // SAFETY: `lseek` only moves the offset of the open descriptor, `read_at` and
+            // `write_at` always seek before accessing the file
+            let res = unsafe { libc::lseek(fd, pos, whence) };
+            match u64::try_from(res) {
+                Ok(res) => Some(Ok(res)),
+                Err(_) => Some(Err(std::io::Error::last_os_error())),
+            }
+        };
+
+        let mut res = Vec::new();
+        let mut pos = 0u64;
+        while pos < self.len {
+            let start = match seek(pos, libc::SEEK_DATA)? {
+                Ok(start) => start,
+                // no data after `pos`, the rest of the file is a hole
+                Err(err) if err.raw_os_error() == Some(libc::ENXIO) => break,
+                Err(err) => {
+                    log::debug!("{}: SEEK_DATA failed: {err}", self.path.display());
+                    return None;
+                }
+            };
+            let end = seek(start, libc::SEEK_HOLE)?
+                .ok()
+                .filter(|&end| end > start)?
+                .min(self.len);
+
+            res.push(start..end);
+            pos = end;
+        }
+
+        Some(res)
+    }
+
+    /// Gets the allocated byte ranges of a sparse disk image file.
+    ///
+    /// Hole detection is only supported on Linux, every byte is considered allocated.
+    #[cfg(not(target_os = "linux"))]
+    pub fn data_ranges(&self) -> Option<Vec<Range<u64>>> {
+        None
+    }
+
+    /// Reads back the bytes at `offset` after a [sync_all](Self::sync_all), and verifies that
+    /// they match the `expected` written bytes.
+    pub fn verify_at(&mut self, offset: u64, expected: &[u8]) -> Result<()> {
+        let mut readback = vec![0u8; expected.len()];
+        self.read_at(offset, readback.as_mut())?;
+
+        match expected
+            .iter()
+            .zip(readback.iter())
+            .position(|(a, b)| a != b)
+        {
+            Some(pos) => {
+                let pos = offset + pos as u64;
+                log::error!("Readback mismatch after writing disk at {pos:#x}");
+                Err(PathError::new(Error::DiskWrite(pos)).with_path(&self.path))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl DiskIo for FileDisk {
+    fn disk_len(&self) -> u64 {
+        self.len
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> spl_tool::Result<()> {
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.read_exact(buf))
+            .map_err(|err| {
+                log::debug!("Error reading disk at {offset:#x}: {err}");
+                Error::DiskRead(offset)
+            })
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> spl_tool::Result<()> {
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.file.write_all(buf))
+            .map_err(|err| {
+                log::error!("Error writing disk at {offset:#x}: {err}");
+                Error::DiskWrite(offset)
+            })
+    }
+}
+
+// byte stream access, e.g. for `UbootSplHeader::read_at`, keeping the underlying I/O errors
+impl Read for FileDisk {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Write for FileDisk {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for FileDisk {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+/// Gets the logical sector length of a disk image, `sector_len` or detected from the position of
+/// the GPT signature, defaulting to [DEF_SECTOR_LEN].
+pub fn disk_sector_len(disk: &mut FileDisk, sector_len: Option<usize>) -> usize {
+    sector_len
+        .or_else(|| gpt_sector_len(disk))
+        .unwrap_or(DEF_SECTOR_LEN)
+}
+
+/// Validates the protective MBR, both GPT copies, and the partition entry CRC32s of a disk image.
+///
+/// Without `sector_len`, the logical sector length is detected from the GPT signature position.
+pub fn gpt_check(name: &str, sector_len: Option<usize>) -> Result<()> {
+    let mut gpt = open_gpt(name, false, sector_len)?;
+    let report = gpt.check();
+
+    print_report(name, gpt.io().disk_len(), &report);
+
+    if report.is_ok() {
+        Ok(())
+    } else {
+        log::error!("{name}: GPT is damaged, try `gpt repair`");
+        Err(Error::InvalidGpt.into())
+    }
+}
+
+/// Rebuilds the damaged or mismatched GPT copy from the intact one.
+pub fn gpt_repair(name: &str, source: Option<GptSource>, sector_len: Option<usize>) -> Result<()> {
+    let mut gpt = open_gpt(name, true, sector_len)?;
+
+    match gpt
+        .repair(source.map(GptCopy::from))
+        .inspect_err(|err| log::error!("{name}: GPT repair failed: {err}"))?
+    {
+        Some(copy) => println!("{name}: rebuilt {copy} GPT"),
+        None => println!("{name}: GPT is intact, nothing to repair"),
+    }
+
+    gpt.io().sync_all()?;
+
+    // read back the written copy from the disk
+    let report = gpt.check();
+    print_report(name, gpt.io().disk_len(), &report);
+
+    if report.is_ok() {
+        Ok(())
+    } else {
+        log::error!("{name}: GPT is still damaged after the repair");
+        Err(Error::InvalidGpt.into())
+    }
+}
+
+fn open_gpt(name: &str, write: bool, sector_len: Option<usize>) -> Result<GptDisk<FileDisk>> {
+    let mut disk = FileDisk::open(name, write)?;
+    let sector_len = disk_sector_len(&mut disk, sector_len);
+
+    Ok(GptDisk::new(disk).with_sector_len(sector_len))
+}
+
+fn print_report(name: &str, len: u64, report: &GptReport) {
+    println!("{name}: {len} bytes, {}-byte sectors", report.sector_len);
+    println!("protective MBR: {}", report.pmbr);
+    print_copy(GptCopy::Primary, &report.primary);
+    print_copy(GptCopy::Backup, &report.backup);
+
+    if report.primary.status.is_ok() && report.backup.status.is_ok() {
+        let copies = if report.copies_match {
+            "match"
+        } else {
+            "MISMATCH"
+        };
+        println!("copies: {copies}");
+    }
+
+    if let Some(bofs) = report.jh7110_fix {
+        println!(
+            "JH7110 --fix-imghdr workaround: bofs {bofs:#x} at {JH7110_FIX_BOFS_OFFSET:#x}, crcs {CRC_FAILED:#x} at {JH7110_FIX_CRCS_OFFSET:#x} (ignored)"
+        );
+    }
+}
+
+fn print_copy(copy: GptCopy, check: &GptCopyCheck) {
+    println!("{copy} GPT @ LBA {}: {}", check.lba, check.status);
+
+    if let (GptStatus::Ok, Some(hdr)) = (check.status, check.header.as_ref()) {
+        println!(
+            "  usable LBAs: {}-{}, entries: {} x {} bytes @ LBA {}, alternate LBA: {}",
+            hdr.first_usable_lba(),
+            hdr.last_usable_lba(),
+            hdr.num_entries(),
+            hdr.entry_size(),
+            hdr.entries_lba(),
+            hdr.alternate_lba()
+        );
+    }
+}
@@ -0,0 +1,175 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use spl_tool::{
+    sha256, Error, HexFormat, HexReader, HexWriter, IoContext, UbootSplHeader, DEF_FLASH_SIZE,
+    SHA256_LEN, SPL_HEADER_LEN,
+};
+
+use super::output::write_atomic;
+use super::{with_suffix, Result};
+
+/// Maximum length of a flash image loaded from an Intel HEX or S-record file.
+///
+/// Records are placed at their absolute address, so the image is limited to the flash size to
+/// bound the allocation for a record at a high address.
+pub const MAX_HEX_IMAGE_LEN: u64 = DEF_FLASH_SIZE as u64;
+
+/// Reads a headered SPL image or flash layout from a raw binary, Intel HEX, or S-record file.
+///
+/// HEX/SREC data is placed at its absolute address, and gaps are filled with erased flash
+/// (`0xff`).
+pub fn read_image(name: &str) -> Result<Vec<u8>> {
+    read_image_format(name).map(|(img, _)| img)
+}
+
+/// Reads an image like [read_image], and returns the detected HEX/SREC format of the file.
+///
+/// The format is `None` for raw binary files.
+pub fn read_image_format(name: &str) -> Result<(Vec<u8>, Option<HexFormat>)> {
+    let data = fs::read(name).io_context(Error::InvalidSplFile, name)?;
+
+    match HexFormat::detect(data.as_ref()) {
+        Some(format) => {
+            log::debug!("Reading {name} as {format}");
+
+            let text = String::from_utf8(data)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+                .io_context(Error::InvalidSplFile, name)?;
+
+            Ok((read_hex(name, text.as_str())?, Some(format)))
+        }
+        None => Ok((data, None)),
+    }
+}
+
+fn read_hex(name: &str, text: &str) -> Result<Vec<u8>> {
+    let mut img = Vec::new();
+
+    for rec in HexReader::new(text) {
+        let rec = rec.inspect_err(|err| log::error!("Error parsing {name}: {err}"))?;
+
+        if rec.end() > MAX_HEX_IMAGE_LEN {
+            log::error!(
+                "Record at {:#x} in {name} exceeds the maximum image length: {MAX_HEX_IMAGE_LEN:#x}",
+                rec.addr()
+            );
+            return Err(Error::InvalidSplFile.into());
+        }
+
+        let start = rec.addr() as usize;
+        let end = rec.end() as usize;
+        if img.len() < end {
+            img.resize(end, 0xff);
+        }
+        img[start..end].copy_from_slice(rec.data());
+    }
+
+    Ok(img)
+}
+
+/// Writes a headered SPL image as Intel HEX or S-record, next to the raw binary image.
+///
+/// The image is written at address `0`. With `flash_layout`, the backup copy is also written at
+/// the `bofs` address from the image header.
+///
+/// Returns the path and SHA-256 digest of the written file.
+pub fn write_hex_image(
+    path: &Path,
+    format: HexFormat,
+    flash_layout: bool,
+) -> Result<(PathBuf, [u8; SHA256_LEN])> {
+    let img = fs::read(path).io_context(Error::InvalidSplFile, path)?;
+    let name = path.display();
+
+    let outpath = match path.extension() {
+        Some(ext) if ext == "out" => path.with_extension(format.extension()),
+        _ => with_suffix(path, format!(".{}", format.extension()).as_str()),
+    };
+
+    let mut writer = HexWriter::new(String::new(), format);
+    writer.write(0, img.as_ref()).map_err(|_| {
+        log::error!("Error writing {format} records for {name}");
+        Error::InvalidHeaderFile
+    })?;
+
+    if flash_layout {
+        let header = UbootSplHeader::try_from(img.as_slice())?;
+        let bofs = header.bofs();
+
+        if (bofs as usize) < img.len().max(SPL_HEADER_LEN) {
+            log::error!(
+                "Backup offset {bofs:#x} overlaps the primary SPL image ({} bytes)",
+                img.len()
+            );
+            return Err(Error::InvalidSplLen((img.len(), bofs as usize)).into());
+        }
+
+        writer.write(bofs, img.as_ref()).map_err(|_| {
+            log::error!("Error writing {format} records for the backup copy at {bofs:#x}");
+            Error::InvalidHeaderFile
+        })?;
+    }
+
+    let text = writer.finish().map_err(|_| {
+        log::error!("Error finishing {format} records for {name}");
+        Error::InvalidHeaderFile
+    })?;
+
+    let digest = sha256(text.as_bytes());
+    write_atomic(&outpath, text).io_context(Error::InvalidHeaderFile, &outpath)?;
+
+    log::info!(
+        "{format} image written to {} successfully.",
+        outpath.display()
+    );
+
+    Ok((outpath, digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_hex_layout() {
+        // gaps between records are erased flash
+        let img = read_hex("t.hex", ":0100000011EE\n:0100030022DA\n:00000001FF\n").unwrap();
+        assert_eq!(img, [0x11, 0xff, 0xff, 0x22]);
+
+        let text = HexWriter::new(String::new(), HexFormat::Srec);
+        let text = text.finish().unwrap();
+        assert!(read_hex("t.srec", text.as_str()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_read_hex_max_len() {
+        // a record ending at the maximum image length is accepted
+        let mut writer = HexWriter::new(String::new(), HexFormat::IntelHex);
+        writer.write(MAX_HEX_IMAGE_LEN as u32 - 1, &[0x55]).unwrap();
+        let text = writer.finish().unwrap();
+        let img = read_hex("t.hex", text.as_str()).unwrap();
+        assert_eq!(img.len() as u64, MAX_HEX_IMAGE_LEN);
+        assert_eq!(img.last(), Some(&0x55));
+
+        // a record past the maximum image length is rejected before allocating
+        let mut writer = HexWriter::new(String::new(), HexFormat::IntelHex);
+        writer.write(MAX_HEX_IMAGE_LEN as u32, &[0x55]).unwrap();
+        let text = writer.finish().unwrap();
+        assert!(read_hex("t.hex", text.as_str()).is_err());
+
+        let mut writer = HexWriter::new(String::new(), HexFormat::IntelHex);
+        writer.write(u32::MAX - 1, &[0x55, 0xaa]).unwrap();
+        let text = writer.finish().unwrap();
+        assert!(read_hex("t.hex", text.as_str()).is_err());
+    }
+
+    #[test]
+    fn test_read_hex_invalid() {
+        let err = read_hex("t.hex", ":0100000011EE\n:0100000011EF\n").unwrap_err();
+        assert!(matches!(err.error(), Error::InvalidHexRecord(2)));
+    }
+}
@@ -0,0 +1,118 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+#[cfg(feature = "secure-boot")]
+use spl_tool::SplSignAlg;
+use spl_tool::{
+    gpt_sector_len, spl_copy_status, Error, SplCopyStatus, UbootSplHeader, CRC_FAILED, DEF_BACKUP,
+};
+
+use super::image::read_image;
+use super::Result;
+
+/// Prints the header fields and the status of the SPL copies in an image or flash layout.
+pub fn spl_info(name: &str) -> Result<()> {
+    let mut img = read_image(name)?;
+    let is_disk = gpt_sector_len(img.as_mut_slice()).is_some();
+
+    println!("{name}: {} bytes", img.len());
+
+    let (primary, header) = copy_status(img.as_ref(), 0);
+    println!("primary @ 0x0: {primary}");
+
+    if let Some(hdr) = header.as_ref() {
+        print_header(hdr, is_disk);
+    }
+
+    match backup_offset(img.as_ref(), header.as_ref()) {
+        Some(bofs) => {
+            let (backup, backup_header) = copy_status(img.as_ref(), bofs);
+            println!("backup @ {bofs:#x}: {backup}");
+
+            if let Some(hdr) = backup_header.as_ref() {
+                print_header(hdr, false);
+            }
+        }
+        None => println!("backup: not present"),
+    }
+
+    Ok(())
+}
+
+/// Verifies the header and payload CRC32 of the SPL copies in an image or flash layout.
+///
+/// The backup copy is only checked when the image extends past its offset.
+pub fn spl_verify(name: &str) -> Result<()> {
+    let img = read_image(name)?;
+
+    let (primary, header) = copy_status(img.as_ref(), 0);
+    check_status(name, "primary", 0, primary)?;
+
+    if let Some(bofs) = backup_offset(img.as_ref(), header.as_ref()) {
+        let (backup, _) = copy_status(img.as_ref(), bofs);
+        check_status(name, "backup", bofs, backup)?;
+    }
+
+    println!("{name}: OK");
+
+    Ok(())
+}
+
+/// Checks the SPL copy at `offset` in an in-memory image or flash dump.
+pub fn copy_status(img: &[u8], offset: u32) -> (SplCopyStatus, Option<UbootSplHeader>) {
+    spl_copy_status(
+        &mut |pos: u32, buf: &mut [u8]| {
+            let start = pos as usize;
+            let src = img
+                .get(start..start.saturating_add(buf.len()))
+                .ok_or(Error::FlashRead(pos))?;
+            buf.copy_from_slice(src);
+            Ok(())
+        },
+        offset,
+    )
+}
+
+fn backup_offset(img: &[u8], header: Option<&UbootSplHeader>) -> Option<u32> {
+    let bofs = header.map(|h| h.bofs()).unwrap_or(DEF_BACKUP);
+    (bofs != 0 && (bofs as usize) < img.len()).then_some(bofs)
+}
+
+fn check_status(name: &str, copy: &str, offset: u32, status: SplCopyStatus) -> Result<()> {
+    match status {
+        SplCopyStatus::Ok => Ok(()),
+        SplCopyStatus::Blank => {
+            log::error!("{name}: {copy} SPL copy at {offset:#x} is blank");
+            Err(Error::InvalidSplFile.into())
+        }
+        SplCopyStatus::InvalidHeader(err) | SplCopyStatus::ReadError(err) => {
+            log::error!("{name}: {copy} SPL copy at {offset:#x}: {status}");
+            Err(err.into())
+        }
+        SplCopyStatus::CrcMismatch { actual, expected } => {
+            log::error!("{name}: {copy} SPL copy at {offset:#x}: {status}");
+            if expected == CRC_FAILED {
+                log::error!("{name}: {copy} SPL header was fixed with --fix-imghdr");
+            }
+            Err(Error::InvalidCrc((actual, expected)).into())
+        }
+    }
+}
+
+// `is_disk`: the header is at the start of a GPT disk image
+#[cfg_attr(not(feature = "secure-boot"), allow(unused_variables))]
+fn print_header(hdr: &UbootSplHeader, is_disk: bool) {
+    println!("  sofs: {:#x}", hdr.sofs());
+    println!("  bofs: {:#x}", hdr.bofs());
+    println!("  vers: {:#x}", hdr.vers());
+    println!("  fsiz: {}", hdr.fsiz());
+    println!("  resl: {:#x}", hdr.resl());
+    println!("  crcs: {:#010x}", hdr.crcs());
+    // the experimental secure-boot fields overlap the protective MBR of a disk image
+    #[cfg(feature = "secure-boot")]
+    if !is_disk && hdr.is_signed() {
+        match SplSignAlg::try_from(hdr.salg()) {
+            Ok(alg) => println!("  salg: {alg:?} (experimental)"),
+            Err(err) => println!("  salg: {err}"),
+        }
+    }
+}
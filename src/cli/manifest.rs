@@ -0,0 +1,277 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use spl_tool::{sha256, Error, HexFormat, IoContext, UbootSplHeader, SHA256_LEN};
+
+use super::output::write_atomic;
+use super::Result;
+
+/// Machine-readable record of a generated SPL image, used for release provenance.
+#[derive(Clone, Debug)]
+pub struct Manifest {
+    input_path: String,
+    input_len: usize,
+    input_sha256: [u8; SHA256_LEN],
+    payload_sha256: [u8; SHA256_LEN],
+    header: UbootSplHeader,
+    output_path: String,
+    output_sha256: [u8; SHA256_LEN],
+    hex_output: Option<HexOutput>,
+}
+
+// Intel HEX or S-record image written next to the raw binary image.
+#[derive(Clone, Debug)]
+struct HexOutput {
+    path: String,
+    format: HexFormat,
+    flash_layout: bool,
+    sha256: [u8; SHA256_LEN],
+}
+
+impl Manifest {
+    /// Creates a new [Manifest] from the input file, the SPL payload, generated header, and
+    /// output image.
+    ///
+    /// The `input` is the file as read from disk, which differs from the `payload` for ELF input.
+    pub fn new(
+        input_path: &Path,
+        input: &[u8],
+        payload: &[u8],
+        header: &UbootSplHeader,
+        output_path: &Path,
+        output: &[u8],
+    ) -> Self {
+        Self {
+            input_path: input_path.to_string_lossy().into_owned(),
+            input_len: input.len(),
+            input_sha256: sha256(input),
+            payload_sha256: sha256(payload),
+            header: *header,
+            output_path: output_path.to_string_lossy().into_owned(),
+            output_sha256: sha256(output),
+            hex_output: None,
+        }
+    }
+
+    /// Sets the Intel HEX or S-record image written from the output image.
+    pub fn set_hex_output(
+        &mut self,
+        path: &Path,
+        format: HexFormat,
+        flash_layout: bool,
+        sha256: [u8; SHA256_LEN],
+    ) {
+        self.hex_output = Some(HexOutput {
+            path: path.to_string_lossy().into_owned(),
+            format,
+            flash_layout,
+            sha256,
+        });
+    }
+
+    /// Serializes the [Manifest] to a JSON string.
+    pub fn to_json(&self) -> String {
+        let hdr = &self.header;
+        let mut res = String::new();
+
+        // writing to a `String` is infallible
+        let _ = write!(
+            res,
+            concat!(
+                "{{\n",
+                "  \"tool\": {{ \"name\": \"{name}\", \"version\": \"{version}\" }},\n",
+                "  \"input\": {{ \"path\": \"{in_path}\", \"size\": {in_len}, \"sha256\": \"{in_sha}\" }},\n",
+                "  \"payload\": {{ \"size\": {fsiz}, \"sha256\": \"{payload_sha}\" }},\n",
+                "  \"header\": {{\n",
+                "    \"sofs\": \"{sofs:#x}\",\n",
+                "    \"bofs\": \"{bofs:#x}\",\n",
+                "    \"vers\": \"{vers:#x}\",\n",
+                "    \"fsiz\": {fsiz},\n",
+                "    \"resl\": \"{resl:#x}\",\n",
+                "    \"crcs\": \"{crcs:#010x}\"\n",
+                "  }},\n",
+            ),
+            name = env!("CARGO_PKG_NAME"),
+            version = env!("CARGO_PKG_VERSION"),
+            in_path = json_escape(self.input_path.as_str()),
+            in_len = self.input_len,
+            in_sha = hex(&self.input_sha256),
+            payload_sha = hex(&self.payload_sha256),
+            sofs = hdr.sofs(),
+            bofs = hdr.bofs(),
+            vers = hdr.vers(),
+            fsiz = hdr.fsiz(),
+            resl = hdr.resl(),
+            crcs = hdr.crcs(),
+        );
+
+        // the secure-boot header layout is experimental, so only record it for signed images
+        #[cfg(feature = "secure-boot")]
+        if hdr.is_signed() {
+            let _ = write!(
+                res,
+                concat!(
+                    "  \"signature\": {{\n",
+                    "    \"experimental\": true,\n",
+                    "    \"salg\": \"{salg:#x}\",\n",
+                    "    \"sexp\": \"{sexp:#x}\",\n",
+                    "    \"khsh\": \"{khsh}\",\n",
+                    "    \"pkey\": \"{pkey}\",\n",
+                    "    \"ssig\": \"{ssig}\"\n",
+                    "  }},\n",
+                ),
+                salg = hdr.salg(),
+                sexp = hdr.sexp(),
+                khsh = hex(hdr.khsh()),
+                pkey = hex(hdr.pkey()),
+                ssig = hex(hdr.ssig()),
+            );
+        }
+
+        let _ = write!(
+            res,
+            concat!(
+                "  \"crc\": \"{crcs:#010x}\",\n",
+                "  \"output\": {{ \"path\": \"{out_path}\", \"sha256\": \"{out_sha}\" }}",
+            ),
+            crcs = hdr.crcs(),
+            out_path = json_escape(self.output_path.as_str()),
+            out_sha = hex(&self.output_sha256),
+        );
+
+        if let Some(hex_out) = self.hex_output.as_ref() {
+            let _ = write!(
+                res,
+                concat!(
+                    ",\n",
+                    "  \"hex_output\": {{ \"path\": \"{path}\", \"format\": \"{format}\", ",
+                    "\"flash_layout\": {flash_layout}, \"sha256\": \"{sha}\" }}",
+                ),
+                path = json_escape(hex_out.path.as_str()),
+                format = hex_out.format,
+                flash_layout = hex_out.flash_layout,
+                sha = hex(&hex_out.sha256),
+            );
+        }
+
+        res.push_str("\n}\n");
+
+        res
+    }
+
+    /// Writes the [Manifest] as JSON to the provided path.
+    pub fn write(&self, path: &str) -> Result<()> {
+        write_atomic(path, self.to_json()).io_context(Error::InvalidManifestFile, path)?;
+
+        log::info!("Manifest written to {path} successfully.");
+
+        Ok(())
+    }
+}
+
+/// Formats a byte buffer as a lower-case hex string.
+pub fn hex(data: &[u8]) -> String {
+    data.iter()
+        .fold(String::with_capacity(data.len() * 2), |mut s, b| {
+            let _ = write!(s, "{b:02x}");
+            s
+        })
+}
+
+fn json_escape(val: &str) -> String {
+    let mut res = String::with_capacity(val.len());
+    for c in val.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(res, "\\u{:04x}", c as u32);
+            }
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_json() {
+        let input = b"\x7fELF input file";
+        let payload = [0x13u8, 0, 0, 0];
+        let header = UbootSplHeader::new()
+            .with_payload(payload.as_ref())
+            .unwrap();
+        let out = [0u8; 8];
+
+        let mut manifest = Manifest::new(
+            Path::new("spl \"1\".elf"),
+            input.as_ref(),
+            payload.as_ref(),
+            &header,
+            Path::new("spl.elf.normal.out"),
+            out.as_ref(),
+        );
+        let json = manifest.to_json();
+
+        assert!(json.contains("\"path\": \"spl \\\"1\\\".elf\""));
+        assert!(json.contains(format!("\"size\": {}", input.len()).as_str()));
+        assert!(json.contains(hex(&sha256(input)).as_str()));
+        assert!(json.contains(hex(&sha256(payload.as_ref())).as_str()));
+        for field in ["sofs", "bofs", "vers", "fsiz", "resl", "crcs"] {
+            assert!(
+                json.contains(format!("\"{field}\": ").as_str()),
+                "missing {field}"
+            );
+        }
+        assert!(!json.contains("\"signature\""));
+        assert!(!json.contains("hex_output"));
+        assert!(json.ends_with("}\n}\n"));
+
+        manifest.set_hex_output(
+            Path::new("spl.elf.normal.hex"),
+            HexFormat::IntelHex,
+            true,
+            sha256(b"hex"),
+        );
+        let json = manifest.to_json();
+
+        assert!(json.contains("\"flash_layout\": true"));
+        assert!(json.contains(hex(&sha256(b"hex")).as_str()));
+    }
+
+    #[cfg(feature = "secure-boot")]
+    #[test]
+    fn test_manifest_json_signed() {
+        let payload = [0x13u8, 0, 0, 0];
+        let key = spl_tool::SplSigningKey::EcdsaP256(
+            p256::ecdsa::SigningKey::from_slice(&[1u8; 32]).unwrap(),
+        );
+        let mut header = UbootSplHeader::new()
+            .with_payload(payload.as_ref())
+            .unwrap();
+        header.sign(&key, payload.as_ref()).unwrap();
+
+        let json = Manifest::new(
+            Path::new("spl.bin"),
+            payload.as_ref(),
+            payload.as_ref(),
+            &header,
+            Path::new("spl.bin.normal.out"),
+            payload.as_ref(),
+        )
+        .to_json();
+
+        assert!(json.contains("\"experimental\": true"));
+        assert!(json.contains("\"salg\": \"0x1\""));
+        assert!(json.contains(hex(header.ssig()).as_str()));
+        assert!(json.ends_with("}\n}\n"));
+    }
+}
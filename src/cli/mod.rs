@@ -0,0 +1,94 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+//! Helpers for the `spl_tool` CLI application.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use spl_tool::{Error, ErrorCategory, HeaderConf, PathError};
+
+pub mod analyze;
+pub mod diff;
+pub mod env;
+pub mod export;
+pub mod fix;
+pub mod gpt;
+pub mod image;
+pub mod info;
+pub mod manifest;
+pub mod output;
+pub mod scan;
+pub mod script;
+#[cfg(feature = "secure-boot")]
+pub mod sign;
+pub mod update;
+pub mod write;
+
+/// Convenience alias for a [`Result`](std::result::Result) type with the path context of the CLI
+/// application.
+pub type Result<T> = std::result::Result<T, PathError>;
+
+/// Gets the process exit code of an [ErrorCategory].
+///
+/// `1` is left for unexpected failures, and `2` for the argument errors reported by `clap`.
+pub const fn exit_code(category: ErrorCategory) -> u8 {
+    match category {
+        ErrorCategory::Header => 3,
+        ErrorCategory::Payload => 4,
+        ErrorCategory::Config => 5,
+        ErrorCategory::Signature => 6,
+        ErrorCategory::Format => 7,
+        ErrorCategory::Io => 8,
+        ErrorCategory::Unsupported => 9,
+    }
+}
+
+/// Prints an error, and the chain of its underlying errors, to `stderr`.
+///
+/// Returns the process exit code of the error [category](PathError::category).
+pub fn report(err: &PathError) -> u8 {
+    use std::error::Error as _;
+
+    eprintln!("error: {err}");
+
+    let mut source = err.source();
+    while let Some(err) = source {
+        eprintln!("  caused by: {err}");
+        source = err.source();
+    }
+
+    exit_code(err.category())
+}
+
+/// Gets the platform path of the SPL image in a [HeaderConf].
+pub fn conf_path<'a>(conf: &HeaderConf<'a>) -> Result<&'a Path> {
+    conf.path().ok_or_else(|| {
+        log::error!("Invalid SPL file path: {}", conf.name());
+        Error::InvalidSplFile.into()
+    })
+}
+
+/// Appends `suffix` to the file name of `path`, keeping non-UTF-8 file names intact.
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut res = OsString::from(path);
+    res.push(suffix);
+    PathBuf::from(res)
+}
+
+/// Parses a hexadecimal (`0x` prefix) or decimal command-line number.
+pub fn parse_num(val: &str) -> std::result::Result<u32, String> {
+    match val.strip_prefix("0x").or_else(|| val.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => val.parse::<u32>(),
+    }
+    .map_err(|_| format!("invalid number: {val}"))
+}
+
+/// Parses a hexadecimal (`0x` prefix) or decimal command-line offset into a large image.
+pub fn parse_offset(val: &str) -> std::result::Result<u64, String> {
+    match val.strip_prefix("0x").or_else(|| val.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => val.parse::<u64>(),
+    }
+    .map_err(|_| format!("invalid offset: {val}"))
+}
@@ -0,0 +1,307 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Takes an advisory lock on an open file, exclusive for writers and shared for readers.
+///
+/// Fails with [io::ErrorKind::WouldBlock] instead of waiting when another `spl_tool` run holds a
+/// conflicting lock. Filesystems without lock support are used unlocked. The lock is released
+/// when the file is closed.
+pub fn lock_file(file: &fs::File, exclusive: bool) -> io::Result<()> {
+    let res = if exclusive {
+        file.try_lock()
+    } else {
+        file.try_lock_shared()
+    };
+
+    match res {
+        Ok(()) => Ok(()),
+        Err(fs::TryLockError::WouldBlock) => Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            "file is locked by another process",
+        )),
+        Err(fs::TryLockError::Error(err)) if err.kind() == io::ErrorKind::Unsupported => {
+            log::debug!("Advisory file locks are not supported: {err}");
+            Ok(())
+        }
+        Err(fs::TryLockError::Error(err)) => Err(err),
+    }
+}
+
+/// File written through a temporary file next to its destination, so that a crash never leaves a
+/// half-written file behind.
+///
+/// Writers of the same destination are serialized by an exclusive lock on its directory, held
+/// from creation until the file is committed or dropped, even when the destination does not exist
+/// yet. No lock file is left behind. An existing destination is locked too, against in-place
+/// writers.
+///
+/// On [commit](Self::commit), the data is synced, and the temporary file atomically replaces the
+/// destination, keeping its permissions. The temporary file is removed when dropped without
+/// committing.
+pub struct AtomicFile {
+    file: io::BufWriter<fs::File>,
+    path: PathBuf,
+    tmp: PathBuf,
+    // locks held for the lifetime of the [AtomicFile], released when the files are closed
+    _lock: Option<fs::File>,
+    target: Option<fs::File>,
+    committed: bool,
+}
+
+impl AtomicFile {
+    /// Creates a new [AtomicFile] replacing the regular file at `path`.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        // directories cannot be opened on every platform, the destination is then locked alone
+        let lock = match fs::File::open(parent_dir(&path)) {
+            Ok(dir) => {
+                lock_file(&dir, true)?;
+                Some(dir)
+            }
+            Err(err) => {
+                log::debug!("Unable to lock the output directory: {err}");
+                None
+            }
+        };
+
+        // also conflict with in-place writers of an existing destination
+        let target = match fs::File::open(&path) {
+            Ok(file) => {
+                if !file.metadata()?.is_file() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "output is not a regular file",
+                    ));
+                }
+                lock_file(&file, true)?;
+                Some(file)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+
+        let tmp = sidecar_path(&path, format!("{}.tmp", process::id()).as_str())?;
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp)?;
+
+        Ok(Self {
+            file: io::BufWriter::new(file),
+            path,
+            tmp,
+            _lock: lock,
+            target,
+            committed: false,
+        })
+    }
+
+    /// Syncs the written data, and atomically replaces the destination.
+    pub fn commit(mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        let file = self.file.get_ref();
+        if let Some(target) = self.target.as_ref() {
+            file.set_permissions(target.metadata()?.permissions())?;
+        }
+        file.sync_all()?;
+
+        fs::rename(&self.tmp, &self.path)?;
+        self.committed = true;
+
+        sync_parent(&self.path);
+
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+/// Writes `data` to `path` so that a crash never leaves a half-written file behind.
+///
+/// Regular files are replaced through an [AtomicFile]. Block devices and other special files
+/// cannot be replaced by a rename, so they are written in place, synced, and read back to verify
+/// the written bytes.
+pub fn write_atomic<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, data: C) -> io::Result<()> {
+    let path = path.as_ref();
+    let data = data.as_ref();
+
+    match fs::metadata(path) {
+        Ok(meta) if !meta.is_file() => return write_special(path, data),
+        Ok(_) => (),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+        Err(err) => return Err(err),
+    }
+
+    let mut file = AtomicFile::create(path)?;
+    file.write_all(data)?;
+    file.commit()
+}
+
+// hidden `.<name>.<ext>` file in the same directory as `path`, so the temporary file is renamed
+// on one filesystem
+fn sidecar_path(path: &Path, ext: &str) -> io::Result<PathBuf> {
+    let name = path.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "output path has no file name")
+    })?;
+
+    let mut sidecar_name = std::ffi::OsString::from(".");
+    sidecar_name.push(name);
+    sidecar_name.push(".");
+    sidecar_name.push(ext);
+
+    Ok(path.with_file_name(sidecar_name))
+}
+
+// directory containing `path`, the current directory for a bare file name
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+// persists the rename, directories cannot be opened for syncing on every platform
+fn sync_parent(path: &Path) {
+    if let Ok(dir) = fs::File::open(parent_dir(path)) {
+        let _ = dir.sync_all();
+    }
+}
+
+fn write_special(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut file = fs::OpenOptions::new().read(true).write(true).open(path)?;
+    lock_file(&file, true)?;
+
+    file.write_all(data)?;
+    file.sync_all()?;
+
+    let mut readback = vec![0u8; data.len()];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(readback.as_mut())?;
+
+    match data.iter().zip(readback.iter()).position(|(a, b)| a != b) {
+        Some(pos) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("readback mismatch at offset {pos:#x}"),
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("spl_tool-{name}-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_concurrent_create() {
+        let dir = temp_dir("output-create");
+        let path = dir.join("boot.scr");
+
+        // creators of a new file are serialized by the directory lock
+        let mut first = AtomicFile::create(&path).unwrap();
+        let err = AtomicFile::create(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        first.write_all(b"first").unwrap();
+        first.commit().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+        // only the destination is left in the directory
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // the lock is released after the rename
+        let mut second = AtomicFile::create(&path).unwrap();
+        assert_eq!(
+            AtomicFile::create(&path).err().unwrap().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        second.write_all(b"second").unwrap();
+        second.commit().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_in_place_writer() {
+        let dir = temp_dir("output-in-place");
+        let path = dir.join("sdcard.img");
+        fs::write(&path, b"old").unwrap();
+
+        let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        lock_file(&file, true).unwrap();
+        assert_eq!(
+            write_atomic(&path, b"new").unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        drop(file);
+
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_drop_uncommitted() {
+        let dir = temp_dir("output-drop");
+        let path = dir.join("u-boot-spl.bin.normal.out");
+        fs::write(&path, b"old").unwrap();
+
+        let mut file = AtomicFile::create(&path).unwrap();
+        file.write_all(b"partial").unwrap();
+        let tmp = file.tmp.clone();
+        assert!(tmp.exists());
+        drop(file);
+
+        assert!(!tmp.exists());
+        assert_eq!(fs::read(&path).unwrap(), b"old");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_keep_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("output-perm");
+        let path = dir.join("flash.bin");
+        fs::write(&path, b"old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
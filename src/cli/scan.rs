@@ -0,0 +1,141 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use spl_tool::{Error, ErrorContext, IoContext, SplCopyStatus, SplScanner, CRC_FAILED};
+
+use super::Result;
+
+/// Length of the read window used to scan large files.
+pub const SCAN_WINDOW_LEN: usize = 0x10_0000;
+
+/// Maximum number of read errors reported before a scan is aborted.
+pub const SCAN_MAX_ERRORS: usize = 64;
+
+/// Scans a large file for plausible SPL headers, and prints the fields of each hit.
+///
+/// The file is read in [SCAN_WINDOW_LEN] chunks, so multi-GB disk images are never loaded in full.
+///
+/// Read errors are reported, and scanning continues at the next aligned offset. The first error
+/// is returned after the scan, which is aborted after [SCAN_MAX_ERRORS] errors.
+pub fn spl_scan(name: &str, align: u64, start: u64) -> Result<()> {
+    let mut file = fs::File::open(name).io_context(Error::InvalidSplFile, name)?;
+
+    // the metadata length is zero for block and MTD devices
+    let len = file
+        .seek(SeekFrom::End(0))
+        .io_context(Error::InvalidSplFile, name)?;
+
+    let mut window = WindowReader::new(file);
+    let scanner = SplScanner::new(|offset, buf: &mut [u8]| window.read(offset, buf), len)
+        .with_align(align)
+        .with_position(start);
+
+    println!("Scanning {name} ({len} bytes) at {align}-byte boundaries");
+
+    let mut hits = 0usize;
+    let mut errors = Vec::new();
+    for hit in scanner {
+        let hit = match hit {
+            Ok(hit) => hit,
+            Err(err) => {
+                log::error!("Error scanning {name}: {err}");
+                errors.push(err);
+                if errors.len() >= SCAN_MAX_ERRORS {
+                    log::error!("Too many errors scanning {name}, aborting");
+                    break;
+                }
+                continue;
+            }
+        };
+        let hdr = &hit.header;
+
+        println!(
+            "{:#012x}: sofs={:#x} bofs={:#x} vers={:#x} fsiz={} resl={:#x} crcs={:#010x} crc: {}",
+            hit.offset,
+            hdr.sofs(),
+            hdr.bofs(),
+            hdr.vers(),
+            hdr.fsiz(),
+            hdr.resl(),
+            hdr.crcs(),
+            hit.status,
+        );
+
+        if let SplCopyStatus::CrcMismatch {
+            expected: CRC_FAILED,
+            ..
+        } = hit.status
+        {
+            println!("{:14}header was fixed with --fix-imghdr", "");
+        }
+
+        hits += 1;
+    }
+
+    println!("Found {hits} SPL header(s)");
+
+    match errors.first() {
+        Some(&err) => {
+            log::error!("{} read error(s) scanning {name}", errors.len());
+            // the first read error of the window is the cause of the first `DiskRead`
+            match (err, window.io_error.take()) {
+                (Error::DiskRead(_), Some(io_err)) => Err(io_err).io_context(err, name),
+                _ => Err(err).path_context(name),
+            }
+        }
+        None => Ok(()),
+    }
+}
+
+struct WindowReader {
+    file: fs::File,
+    start: u64,
+    buf: Vec<u8>,
+    // first I/O error, returned with the first scan error
+    io_error: Option<io::Error>,
+}
+
+impl WindowReader {
+    fn new(file: fs::File) -> Self {
+        Self {
+            file,
+            start: 0,
+            buf: Vec::with_capacity(SCAN_WINDOW_LEN),
+            io_error: None,
+        }
+    }
+
+    fn read(&mut self, offset: u64, out: &mut [u8]) -> spl_tool::Result<()> {
+        let end = offset + out.len() as u64;
+        if offset < self.start || end > self.start + self.buf.len() as u64 {
+            self.fill(offset, out.len().max(SCAN_WINDOW_LEN))?;
+        }
+
+        let pos = (offset - self.start) as usize;
+        let src = self
+            .buf
+            .get(pos..pos + out.len())
+            .ok_or(Error::InvalidSplLen((self.buf.len() - pos, out.len())))?;
+        out.copy_from_slice(src);
+
+        Ok(())
+    }
+
+    fn fill(&mut self, offset: u64, len: usize) -> spl_tool::Result<()> {
+        self.start = offset;
+        self.buf.clear();
+
+        self.file
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| (&mut self.file).take(len as u64).read_to_end(&mut self.buf))
+            .map_err(|err| {
+                log::debug!("Error reading image at {offset:#x}: {err}");
+                self.io_error.get_or_insert(err);
+                Error::DiskRead(offset)
+            })?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,280 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use std::fmt::Write as _;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use spl_tool::{
+    uimage_script_create, uimage_script_len, Error, FlashLayout, FlashRegionKind, IoContext,
+    UimageHeader, DEF_BACKUP, DEF_ENV_OFFSET, DEF_ENV_SIZE, DEF_FIT_FILE, DEF_FIT_OFFSET,
+    DEF_FLASH_SIZE, DEF_SPL_FILE, DEF_SPL_REGION_LEN,
+};
+
+use super::output::write_atomic;
+use super::parse_num;
+use super::Result;
+
+/// Default filename of the generated flashing script image.
+pub const DEF_SCRIPT_FILE: &str = "boot.scr";
+
+/// Layout of the SPI flash update performed by the flashing script.
+#[derive(clap::Args, Debug)]
+pub struct ScriptArgs {
+    /// Headered SPL image filename on the boot partition [default: u-boot-spl.bin.normal.out]
+    #[arg(long = "spl")]
+    pub spl: Option<String>,
+    /// FIT image filename on the boot partition
+    #[arg(long = "fit", default_value = DEF_FIT_FILE)]
+    pub fit: String,
+    /// Only update the SPL copies, and leave the FIT image untouched
+    #[arg(long = "no-fit", default_value = "false")]
+    pub no_fit: bool,
+    /// Offset of the backup SPL copy in the SPI flash
+    #[arg(short = 'b', long = "bofs", default_value_t = DEF_BACKUP, value_parser = parse_num)]
+    pub bofs: u32,
+    /// Offset of the FIT image in the SPI flash
+    #[arg(long = "fit-offset", default_value_t = DEF_FIT_OFFSET, value_parser = parse_num)]
+    pub fit_offset: u32,
+    /// Offset of the U-Boot environment region in the SPI flash
+    #[arg(long = "env-offset", default_value_t = DEF_ENV_OFFSET, value_parser = parse_num)]
+    pub env_offset: u32,
+    /// Size of the U-Boot environment region
+    #[arg(long = "env-size", default_value_t = DEF_ENV_SIZE as u32, value_parser = parse_num)]
+    pub env_size: u32,
+    /// Maximum length of an SPL copy region
+    #[arg(long = "region-len", default_value_t = DEF_SPL_REGION_LEN, value_parser = parse_num)]
+    pub region_len: u32,
+    /// Size of the SPI flash
+    #[arg(long = "flash-size", default_value_t = DEF_FLASH_SIZE, value_parser = parse_num)]
+    pub flash_size: u32,
+    /// Memory address the images are loaded to before flashing
+    #[arg(short = 'a', long = "load-addr", default_value = "${kernel_addr_r}")]
+    pub load_addr: String,
+    /// Output script image
+    #[arg(short = 'o', long = "output", default_value = DEF_SCRIPT_FILE)]
+    pub output: String,
+    /// Also write the plain-text script source
+    #[arg(short = 's', long = "source")]
+    pub source: Option<String>,
+}
+
+impl ScriptArgs {
+    fn layout(&self) -> FlashLayout {
+        FlashLayout::new()
+            .with_bofs(self.bofs)
+            .with_env_offset(self.env_offset)
+            .with_env_size(self.env_size)
+            .with_fit_offset(self.fit_offset)
+            .with_flash_size(self.flash_size)
+            .with_spl_region_len(self.region_len)
+    }
+}
+
+/// Generates a `boot.scr` script image that flashes the SPL (and FIT) images with `sf update`.
+///
+/// The backup SPL copy is written before the primary copy, so an interrupted update still leaves
+/// a bootable SPL. Each image is only written when it loaded successfully, and fits in its flash
+/// region; otherwise the script stops before touching the flash.
+///
+/// The flash offsets are derived from the [FlashLayout], and overlapping layouts are refused.
+pub fn spl_script(args: &ScriptArgs) -> Result<()> {
+    let layout = args.layout();
+    if let Err(err) = layout.validate() {
+        log::error!("Invalid flash layout: {err}");
+        return Err(err.into());
+    }
+
+    let script = script_source(args, &layout);
+    log::debug!("Flashing script:\n{script}");
+
+    if let Some(source) = args.source.as_deref() {
+        write_atomic(source, script.as_str()).io_context(Error::InvalidHeaderFile, source)?;
+    }
+
+    let header = UimageHeader::new()
+        .with_time(build_time())
+        .with_name("spl_tool flash script");
+
+    let mut img = vec![0u8; uimage_script_len(script.len())];
+    uimage_script_create(header, script.as_bytes(), img.as_mut())?;
+
+    let output = args.output.as_str();
+    write_atomic(output, img).io_context(Error::InvalidHeaderFile, output)?;
+
+    log::info!("Flashing script written to {output} successfully.");
+
+    Ok(())
+}
+
+fn script_source(args: &ScriptArgs, layout: &FlashLayout) -> String {
+    let spl = args
+        .spl
+        .clone()
+        .unwrap_or_else(|| format!("{DEF_SPL_FILE}.normal.out"));
+    let addr = args.load_addr.as_str();
+    let load = format!("load ${{devtype}} ${{devnum}}:${{distro_bootpart}} {addr}");
+
+    // both copies are written from the same load, so the SPL must fit in the smaller region
+    let spl_max = [FlashRegionKind::PrimarySpl, FlashRegionKind::BackupSpl]
+        .into_iter()
+        .filter_map(|kind| layout.spl_region(kind))
+        .map(|region| region.len())
+        .min()
+        .unwrap_or(0);
+
+    let mut res = String::new();
+
+    // writing to a `String` is infallible
+    let _ = writeln!(
+        res,
+        "# Generated by {} {}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+    let _ = writeln!(res, "sf probe || exit 1");
+    let _ = writeln!(res, "echo Updating SPL from {spl}");
+    // `${filesize}` keeps its previous value when `load` fails, so only flash after a good load
+    let _ = writeln!(
+        res,
+        "if {load} {spl} && itest ${{filesize}} -le {spl_max:#x}; then"
+    );
+    let _ = writeln!(
+        res,
+        "  sf update {addr} {:#x} ${{filesize}} && sf update {addr} 0x0 ${{filesize}} || exit 1",
+        layout.bofs()
+    );
+    let _ = writeln!(res, "else");
+    let _ = writeln!(
+        res,
+        "  echo Failed to load {spl}, or larger than {spl_max:#x} bytes"
+    );
+    let _ = writeln!(res, "  exit 1");
+    let _ = writeln!(res, "fi");
+
+    if !args.no_fit {
+        let fit = args.fit.as_str();
+        let region = layout.fit_region();
+        let fit_max = region.len();
+
+        let _ = writeln!(res, "echo Updating FIT from {fit}");
+        let _ = writeln!(
+            res,
+            "if {load} {fit} && itest ${{filesize}} -le {fit_max:#x}; then"
+        );
+        let _ = writeln!(
+            res,
+            "  sf update {addr} {:#x} ${{filesize}} || exit 1",
+            region.start
+        );
+        let _ = writeln!(res, "else");
+        let _ = writeln!(
+            res,
+            "  echo Failed to load {fit}, or larger than {fit_max:#x} bytes"
+        );
+        let _ = writeln!(res, "  exit 1");
+        let _ = writeln!(res, "fi");
+    }
+
+    let _ = writeln!(res, "echo SPI flash update complete");
+
+    res
+}
+
+// honors `SOURCE_DATE_EPOCH` for reproducible builds, like `mkimage`
+fn build_time() -> u32 {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|val| val.parse::<u32>().ok())
+        .or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs() as u32)
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: ScriptArgs,
+    }
+
+    fn args(argv: &[&str]) -> ScriptArgs {
+        Cli::parse_from(std::iter::once("script").chain(argv.iter().copied())).args
+    }
+
+    #[test]
+    fn test_script_source() {
+        let args = args(&[]);
+        let script = script_source(&args, &args.layout());
+        let lines: Vec<_> = script.lines().collect();
+
+        assert_eq!(lines[1], "sf probe || exit 1");
+        // every `sf update` runs only after a successful, size-checked `load`
+        let loads: Vec<_> = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| l.starts_with("if load "))
+            .collect();
+        assert_eq!(loads.len(), 2);
+        assert!(loads[0]
+            .1
+            .ends_with("u-boot-spl.bin.normal.out && itest ${filesize} -le 0x80000; then"));
+        assert!(loads[1]
+            .1
+            .ends_with("u-boot.itb && itest ${filesize} -le 0x100000; then"));
+
+        let updates: Vec<_> = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| l.contains("sf update"))
+            .collect();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].0, loads[0].0 + 1);
+        assert_eq!(updates[1].0, loads[1].0 + 1);
+
+        // the backup copy is written before the primary copy
+        assert_eq!(
+            updates[0].1.trim(),
+            "sf update ${kernel_addr_r} 0x200000 ${filesize} && sf update ${kernel_addr_r} 0x0 ${filesize} || exit 1"
+        );
+        assert_eq!(
+            updates[1].1.trim(),
+            "sf update ${kernel_addr_r} 0x100000 ${filesize} || exit 1"
+        );
+        assert_eq!(lines.iter().filter(|l| l.trim() == "exit 1").count(), 2);
+    }
+
+    #[test]
+    fn test_script_source_no_fit() {
+        let args = args(&["--no-fit", "--bofs", "0x40000", "--spl", "spl.bin"]);
+        let script = script_source(&args, &args.layout());
+
+        assert!(!script.contains("u-boot.itb"));
+        // the primary region ends at the backup copy
+        assert!(script.contains("spl.bin && itest ${filesize} -le 0x40000; then"));
+        assert!(script.contains("sf update ${kernel_addr_r} 0x40000 ${filesize}"));
+    }
+
+    #[test]
+    fn test_script_overlap() {
+        for argv in [
+            ["--fit-offset", "0x200000"],
+            ["--fit-offset", "0xf8000"],
+            ["--bofs", "0x1000000"],
+        ] {
+            let mut args = args(&argv);
+            args.output = String::new();
+            assert!(matches!(
+                spl_script(&args).map_err(|e| e.error()),
+                Err(Error::InvalidFlashLayout(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,60 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use std::fs;
+use std::path::Path;
+
+use spl_tool::{
+    Error, ErrorContext, IoContext, SplSigningKey, SplVerifyingKey, UbootSplHeader, SPL_HEADER_LEN,
+};
+
+use super::Result;
+
+/// Loads a PEM-encoded private key used to sign SPL images.
+pub fn load_signing_key(path: &str) -> Result<SplSigningKey> {
+    let pem = fs::read_to_string(path).io_context(Error::InvalidSigningKey, path)?;
+
+    SplSigningKey::from_pem(pem.as_str()).path_context(path)
+}
+
+/// Loads a PEM-encoded public key used to verify SPL images.
+pub fn load_verifying_key(path: &str) -> Result<SplVerifyingKey> {
+    let pem = fs::read_to_string(path).io_context(Error::InvalidSigningKey, path)?;
+
+    SplVerifyingKey::from_pem(pem.as_str()).path_context(path)
+}
+
+/// Verifies the signature of a headered SPL image.
+///
+/// If `pub_key` is provided, the image must be signed by that key. Without it, any image signed
+/// by the key embedded in its own header passes, so a warning is logged.
+pub fn spl_verify_signature(path: &Path, pub_key: Option<&str>) -> Result<()> {
+    let img = fs::read(path).io_context(Error::InvalidSplFile, path)?;
+    let name = path.display();
+
+    let header = UbootSplHeader::try_from(img.as_slice())?;
+
+    let start = header.resl() as usize;
+    let end = start.saturating_add(header.fsiz() as usize);
+    let payload = img.get(start..end).ok_or_else(|| {
+        log::error!("SPL image {name} is truncated, expected at least {end} bytes");
+        Error::InvalidSplLen((img.len().saturating_sub(SPL_HEADER_LEN), end))
+    })?;
+
+    let trusted_key_hash = match pub_key {
+        Some(path) => Some(load_verifying_key(path)?.key_hash()?),
+        None => {
+            log::warn!(
+                "No trusted public key provided, SPL image {name} is only checked against its embedded key"
+            );
+            None
+        }
+    };
+
+    header
+        .verify_signature(payload, trusted_key_hash.as_ref())
+        .inspect_err(|err| log::error!("Error verifying SPL image {name}: {err}"))?;
+
+    log::info!("SPL image {name} signature verified successfully.");
+
+    Ok(())
+}
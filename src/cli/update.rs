@@ -0,0 +1,408 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use spl_tool::{
+    flash_update_region, Error, ErrorContext, FitImage, FlashLayout, FlashRegion, FlashRegionKind,
+    IoContext, SplCopyStatus, SplHeaderMut, DEF_ENV_OFFSET, DEF_ENV_SIZE, DEF_FIT_OFFSET,
+    DEF_SPL_REGION_LEN,
+};
+
+use super::image::{read_image, read_image_format};
+use super::info::copy_status;
+use super::output::write_atomic;
+use super::parse_num;
+use super::Result;
+
+/// SPL copies replaced by a flash update.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SplTarget {
+    /// Primary and backup copies
+    #[default]
+    Both,
+    /// Primary copy at offset `0`
+    Primary,
+    /// Backup copy at `bofs`
+    Backup,
+}
+
+impl SplTarget {
+    fn kinds(self) -> &'static [FlashRegionKind] {
+        match self {
+            Self::Both => &[FlashRegionKind::PrimarySpl, FlashRegionKind::BackupSpl],
+            Self::Primary => &[FlashRegionKind::PrimarySpl],
+            Self::Backup => &[FlashRegionKind::BackupSpl],
+        }
+    }
+}
+
+/// Existing flash image, and the SPL copies to replace.
+#[derive(clap::Args, Debug)]
+pub struct FlashUpdateArgs {
+    /// Existing SPI flash image (binary, HEX or SREC)
+    pub image: String,
+    /// New headered SPL image, e.g. u-boot-spl.bin.normal.out
+    pub spl: String,
+    /// Output flash image [default: update IMAGE in place, raw binary IMAGE only]
+    #[arg(short = 'o', long = "output")]
+    pub output: Option<String>,
+    /// SPL copies to replace
+    #[arg(short = 't', long = "target", value_enum, default_value_t = SplTarget::Both)]
+    pub target: SplTarget,
+    /// Offset of the backup SPL copy, set as bofs in the written copies [default: new SPL bofs]
+    #[arg(short = 'b', long = "bofs", value_parser = parse_num)]
+    pub bofs: Option<u32>,
+    /// Offset of the U-Boot environment region
+    #[arg(long = "env-offset", default_value_t = DEF_ENV_OFFSET, value_parser = parse_num)]
+    pub env_offset: u32,
+    /// Size of the U-Boot environment region
+    #[arg(long = "env-size", default_value_t = DEF_ENV_SIZE as u32, value_parser = parse_num)]
+    pub env_size: u32,
+    /// Offset of the U-Boot FIT image
+    #[arg(long = "fit-offset", default_value_t = DEF_FIT_OFFSET, value_parser = parse_num)]
+    pub fit_offset: u32,
+    /// Maximum length of an SPL copy region
+    #[arg(long = "region-len", default_value_t = DEF_SPL_REGION_LEN, value_parser = parse_num)]
+    pub region_len: u32,
+}
+
+/// Replaces the primary and/or backup SPL copies in an existing flash image.
+///
+/// The rest of each SPL region is padded with erased flash (`0xff`), and every byte outside of
+/// the updated regions, including the U-Boot env and FIT image, is left unchanged. The update is
+/// refused when the new SPL does not fit in a region, or a region overlaps the env or FIT image.
+///
+/// With `--bofs`, the `bofs` of both written copies is set to the backup offset, and a backup-only
+/// update is refused when the primary header points elsewhere.
+///
+/// The output is always a raw binary image, so HEX/SREC images are only updated with `--output`.
+pub fn spl_flash_update(args: &FlashUpdateArgs) -> Result<()> {
+    let name = args.image.as_str();
+    let (old, format) = read_image_format(name)?;
+
+    if let (Some(format), None) = (format, args.output.as_deref()) {
+        log::error!("{name}: refusing to overwrite the {format} image with a raw binary image");
+        log::error!("Use --output to write the updated flash image to a new file");
+        return Err(Error::InvalidSplFile).path_context(name);
+    }
+
+    let spl_name = args.spl.as_str();
+    let spl = read_image(spl_name)?;
+
+    let header = match copy_status(spl.as_ref(), 0) {
+        (SplCopyStatus::Ok, Some(hdr)) => hdr,
+        (status, _) => {
+            log::error!("{spl_name}: new SPL image is not usable: {status}");
+            return Err(Error::InvalidSplFile.into());
+        }
+    };
+    let mut spl = spl[..header.resl() as usize + header.fsiz() as usize].to_vec();
+
+    // the bootrom finds the backup copy through `bofs`, which is not covered by the CRC
+    let bofs = args.bofs.unwrap_or(header.bofs());
+    if bofs != header.bofs() {
+        log::info!(
+            "{spl_name}: setting the header bofs {:#x} to {bofs:#x} in the written SPL copies",
+            header.bofs()
+        );
+        SplHeaderMut::new(spl.as_mut())?.set_bofs(bofs);
+    }
+
+    if !args.target.kinds().contains(&FlashRegionKind::PrimarySpl) {
+        if let (SplCopyStatus::Ok, Some(primary)) = copy_status(old.as_ref(), 0) {
+            if primary.bofs() != bofs {
+                log::error!(
+                    "{name}: primary SPL header points to the backup copy at {:#x}, not {bofs:#x}",
+                    primary.bofs()
+                );
+                log::error!("Update both copies, or set --bofs to the primary header bofs");
+                return Err(Error::InvalidFlashLayout(bofs).into());
+            }
+        }
+    }
+
+    let layout = FlashLayout::new()
+        .with_bofs(bofs)
+        .with_env_offset(args.env_offset)
+        .with_env_size(args.env_size)
+        .with_fit_offset(args.fit_offset)
+        .with_flash_size(old.len().min(u32::MAX as usize) as u32)
+        .with_spl_region_len(args.region_len);
+
+    let protected = protected_regions(old.as_ref(), &layout);
+
+    let mut regions = Vec::new();
+    for &kind in args.target.kinds() {
+        let region = layout.spl_region(kind).ok_or(Error::InvalidSplFile)?;
+
+        if region.is_empty() {
+            log::error!(
+                "{name}: {kind} region at {:#x} is outside of the image",
+                region.start
+            );
+            return Err(Error::FlashWrite(region.start).into());
+        }
+
+        if let Some(other) = protected.iter().find(|p| p.overlaps(&region)) {
+            log::error!(
+                "{name}: {kind} region {:#x}-{:#x} overlaps the {} at {:#x}-{:#x}",
+                region.start,
+                region.end,
+                other.kind,
+                other.start,
+                other.end
+            );
+            return Err(Error::FlashWrite(other.start).into());
+        }
+
+        if spl.len() > region.len() as usize {
+            log::error!(
+                "{spl_name}: new SPL ({} bytes) would cross into the next region at {:#x}",
+                spl.len(),
+                region.end
+            );
+            return Err(Error::InvalidSplLen((spl.len(), region.len() as usize)).into());
+        }
+
+        regions.push(region);
+    }
+
+    let mut img = old.clone();
+    for region in regions.iter() {
+        flash_update_region(img.as_mut(), region, spl.as_ref())?;
+        println!(
+            "{}: {:#010x}-{:#010x} ({} bytes SPL, {} bytes padding)",
+            region.kind,
+            region.start,
+            region.end,
+            spl.len(),
+            region.len() as usize - spl.len()
+        );
+    }
+
+    // the env and FIT must survive byte-for-byte, so check everything outside the SPL regions
+    if let Some(pos) = (0..img.len()).find(|&pos| {
+        img[pos] != old[pos]
+            && !regions
+                .iter()
+                .any(|r| (r.start as usize..r.end as usize).contains(&pos))
+    }) {
+        log::error!("{name}: flash update modified data outside of the SPL regions at {pos:#x}");
+        return Err(Error::FlashVerify(pos as u32).into());
+    }
+
+    let output = args.output.as_deref().unwrap_or(name);
+    write_atomic(output, img).io_context(Error::InvalidHeaderFile, output)?;
+
+    log::info!("Flash image written to {output} successfully.");
+
+    Ok(())
+}
+
+// regions that must not be touched by an SPL update: the env region, and the FIT image extent
+// when a valid FIT header is present
+fn protected_regions(img: &[u8], layout: &FlashLayout) -> Vec<FlashRegion> {
+    let env_end = layout.env_offset().saturating_add(layout.env_size());
+    let mut res = vec![FlashRegion::new(
+        layout.env_offset(),
+        env_end,
+        FlashRegionKind::Env,
+    )];
+
+    let fit_start = layout.fit_offset();
+    if let Some(fit) = img
+        .get(fit_start as usize..)
+        .and_then(|data| FitImage::parse(data).ok())
+    {
+        let len = fit.verify().map(|s| s.len).unwrap_or(fit.totalsize());
+        let end = fit_start.saturating_add(len.min(u32::MAX as usize) as u32);
+        res.push(FlashRegion::new(fit_start, end, FlashRegionKind::Fit));
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use spl_tool::{spl_image, spl_image_len};
+
+    use super::*;
+
+    const PAYLOAD: &[u8] = &[0x13, 0x00, 0x00, 0x00, 0x6f, 0x00, 0x00, 0x00];
+
+    #[test]
+    fn test_refuse_hex_in_place() {
+        let dir = std::env::temp_dir().join(format!("spl_tool-update-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let spl: [u8; spl_image_len(PAYLOAD.len())] = spl_image(PAYLOAD);
+        let spl_path = dir.join("spl.bin.normal.out");
+        std::fs::write(&spl_path, spl).unwrap();
+
+        let image = dir.join("flash.hex");
+        // an 8 KiB flash image, erased up to the last byte
+        let hex = ":011FFF00558C\n:00000001FF\n";
+        std::fs::write(&image, hex).unwrap();
+
+        let mut args = FlashUpdateArgs {
+            image: image.display().to_string(),
+            spl: spl_path.display().to_string(),
+            output: None,
+            target: SplTarget::Both,
+            bofs: None,
+            env_offset: DEF_ENV_OFFSET,
+            env_size: DEF_ENV_SIZE as u32,
+            fit_offset: DEF_FIT_OFFSET,
+            region_len: DEF_SPL_REGION_LEN,
+        };
+
+        let err = spl_flash_update(&args).unwrap_err();
+        assert!(matches!(err.error(), Error::InvalidSplFile));
+        assert_eq!(std::fs::read_to_string(&image).unwrap(), hex);
+
+        // the raw binary output goes to a separate file
+        let output = dir.join("flash.bin");
+        args.output = Some(output.display().to_string());
+        args.bofs = Some(0x1000);
+        args.region_len = 0x1000;
+        spl_flash_update(&args).unwrap();
+        assert_eq!(std::fs::read_to_string(&image).unwrap(), hex);
+
+        // both copies point to the backup at `--bofs`
+        let mut patched = spl;
+        SplHeaderMut::new(patched.as_mut())
+            .unwrap()
+            .set_bofs(0x1000);
+
+        let img = std::fs::read(&output).unwrap();
+        assert_eq!(img.len(), 0x2000);
+        assert_eq!(&img[..spl.len()], patched.as_ref());
+        assert_eq!(&img[0x1000..0x1000 + spl.len()], patched.as_ref());
+        assert_eq!(img[0x1fff], 0xff);
+        assert!(matches!(
+            copy_status(img.as_ref(), 0x1000).0,
+            SplCopyStatus::Ok
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // 16 KiB flash: primary SPL, env, backup SPL and FIT image in 4 KiB regions
+    const FLASH_LEN: usize = 0x4000;
+    const ENV_OFFSET: u32 = 0x1000;
+    const BOFS: u32 = 0x2000;
+    const FIT_OFFSET: u32 = 0x3000;
+    const FIT_LEN: usize = 0x100;
+
+    fn flash_image() -> Vec<u8> {
+        let mut img = vec![0xffu8; FLASH_LEN];
+        img[ENV_OFFSET as usize..BOFS as usize].fill(0xaa);
+
+        // empty FDT header, the FIT extent is its `totalsize`
+        let fit = &mut img[FIT_OFFSET as usize..FIT_OFFSET as usize + FIT_LEN];
+        fit.fill(0x55);
+        for (idx, word) in [0xd00d_feed, FIT_LEN as u32, 40, 40, 40, 17, 16, 0, 0, 0]
+            .into_iter()
+            .enumerate()
+        {
+            fit[idx * 4..idx * 4 + 4].copy_from_slice(u32::to_be_bytes(word).as_ref());
+        }
+
+        img
+    }
+
+    fn update_args(dir: &std::path::Path) -> FlashUpdateArgs {
+        let spl: [u8; spl_image_len(PAYLOAD.len())] = spl_image(PAYLOAD);
+        let spl_path = dir.join("spl.bin.normal.out");
+        std::fs::write(&spl_path, spl).unwrap();
+
+        let image = dir.join("flash.bin");
+        std::fs::write(&image, flash_image()).unwrap();
+
+        FlashUpdateArgs {
+            image: image.display().to_string(),
+            spl: spl_path.display().to_string(),
+            output: None,
+            target: SplTarget::Both,
+            bofs: Some(BOFS),
+            env_offset: ENV_OFFSET,
+            env_size: 0x1000,
+            fit_offset: FIT_OFFSET,
+            region_len: 0x1000,
+        }
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("spl_tool-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_in_place_keeps_env_and_fit() {
+        let dir = temp_dir("update-in-place");
+        let args = update_args(&dir);
+
+        spl_flash_update(&args).unwrap();
+
+        let old = flash_image();
+        let img = std::fs::read(&args.image).unwrap();
+        assert_eq!(img.len(), FLASH_LEN);
+        assert_eq!(
+            &img[ENV_OFFSET as usize..BOFS as usize],
+            &old[ENV_OFFSET as usize..BOFS as usize]
+        );
+        assert_eq!(&img[FIT_OFFSET as usize..], &old[FIT_OFFSET as usize..]);
+
+        for offset in [0, BOFS] {
+            let (status, header) = copy_status(img.as_ref(), offset);
+            assert!(matches!(status, SplCopyStatus::Ok), "{offset:#x}: {status}");
+            assert_eq!(header.unwrap().bofs(), BOFS);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_in_place_refuse_overlap() {
+        let dir = temp_dir("update-overlap");
+        let mut args = update_args(&dir);
+
+        // env region running into the backup region
+        args.env_size = 0x1800;
+        let err = spl_flash_update(&args).unwrap_err();
+        assert!(matches!(err.error(), Error::FlashWrite(ENV_OFFSET)));
+        assert_eq!(std::fs::read(&args.image).unwrap(), flash_image());
+
+        // backup region inside the FIT image
+        args.env_size = 0x1000;
+        args.target = SplTarget::Backup;
+        args.bofs = Some(FIT_OFFSET + 0x80);
+        let err = spl_flash_update(&args).unwrap_err();
+        assert!(matches!(err.error(), Error::FlashWrite(FIT_OFFSET)));
+        assert_eq!(std::fs::read(&args.image).unwrap(), flash_image());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_backup_only_bofs_mismatch() {
+        let dir = temp_dir("update-backup-bofs");
+        let mut args = update_args(&dir);
+
+        spl_flash_update(&args).unwrap();
+        let img = std::fs::read(&args.image).unwrap();
+
+        // the primary header still points to the backup at `BOFS`
+        args.target = SplTarget::Backup;
+        args.bofs = Some(BOFS + 0x800);
+        args.region_len = 0x800;
+        let err = spl_flash_update(&args).unwrap_err();
+        assert!(matches!(err.error(), Error::InvalidFlashLayout(_)));
+        assert_eq!(std::fs::read(&args.image).unwrap(), img);
+
+        args.bofs = Some(BOFS);
+        spl_flash_update(&args).unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
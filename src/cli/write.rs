@@ -0,0 +1,612 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+use spl_tool::{
+    spl_copy_status, DiskIo, Error, IoContext, SplCopyStatus, UbootSplHeader, DEF_SECTOR_LEN,
+    MAX_SECTOR_LEN, SPL_HEADER_LEN,
+};
+
+use super::gpt::{parse_sector_len, FileDisk};
+use super::parse_num;
+use super::Result;
+
+/// Default length of each write to the target (1 MiB).
+pub const DEF_WRITE_BLOCK_LEN: u32 = 0x10_0000;
+
+// mount points of a running system, never written to even with `--force`
+const SYSTEM_MOUNTS: [&str; 7] = ["/", "/boot", "/boot/efi", "/usr", "/var", "/home", "[SWAP]"];
+
+// maximum depth of stacked block devices followed through sysfs, e.g. LVM on LUKS on RAID
+#[cfg(target_os = "linux")]
+const SYSFS_MAX_DEPTH: usize = 8;
+
+/// Image to write, and the target block device or file.
+#[derive(clap::Args, Debug)]
+pub struct WriteArgs {
+    /// Generated disk or SPL image (binary)
+    pub image: String,
+    /// Target block device (e.g. /dev/sdX or /dev/mmcblkX), or file
+    pub target: String,
+    /// Logical sector size of the target, writes are aligned to it
+    #[arg(long = "sector-size", default_value_t = DEF_SECTOR_LEN, value_parser = parse_sector_len)]
+    pub sector_size: usize,
+    /// Length of each write, a multiple of 4096 bytes
+    #[arg(long = "block-size", default_value_t = DEF_WRITE_BLOCK_LEN, value_parser = parse_block_len)]
+    pub block_size: u32,
+    /// Write to a target with mounted or otherwise used partitions, or an unknown block device,
+    /// unless one of the partitions is a system mount
+    #[arg(long = "force", default_value = "false")]
+    pub force: bool,
+}
+
+/// Parses a write block length command-line argument, aligned to every supported sector size.
+pub fn parse_block_len(val: &str) -> std::result::Result<u32, String> {
+    let len = parse_num(val)?;
+    if len != 0 && (len as usize).is_multiple_of(MAX_SECTOR_LEN) {
+        Ok(len)
+    } else {
+        Err(format!(
+            "invalid block size: {val}, expected a multiple of {MAX_SECTOR_LEN}"
+        ))
+    }
+}
+
+/// Streams a disk or SPL image to a block device or file, and verifies the written SPL copies.
+///
+/// The image is written in sector-aligned blocks, the partial last sector is merged with the
+/// target contents. After syncing, the SPL header window at `0`, and each valid SPL copy in the
+/// image (at `0`, and at the `bofs` of the header at `0`), are read back and compared.
+///
+/// File targets are truncated to the image length. Block devices that hold mounted partitions,
+/// directly or through device-mapper and MD devices (e.g. LVM, LUKS or RAID), are refused.
+pub fn spl_write(args: &WriteArgs) -> Result<()> {
+    let name = args.image.as_str();
+    let target = args.target.as_str();
+
+    let sector_len = args.sector_size;
+    let block_len = args.block_size as usize;
+
+    let is_block = check_target(name, target, args.force)?;
+
+    let mut img = FileDisk::open(name, false)?;
+    let mut dst = FileDisk::open(target, true)?;
+
+    let len = img.disk_len();
+    if is_block && len > dst.disk_len() {
+        log::error!(
+            "{target}: image ({len} bytes) is larger than the device ({} bytes)",
+            dst.disk_len()
+        );
+        return Err(Error::DiskWrite(dst.disk_len()).into());
+    }
+
+    write_image(&mut img, &mut dst, sector_len, block_len, is_block)?;
+    if !is_block {
+        // drop the tail of a longer existing file, e.g. a stale backup GPT at its old end
+        dst.set_len(len)?;
+    }
+    dst.sync_all()?;
+
+    let verified = verify_spl(&mut img, &mut dst, block_len)?;
+    log::info!(
+        "Wrote {len} bytes from {name} to {target}, verified {verified} SPL region(s) successfully."
+    );
+
+    Ok(())
+}
+
+fn write_image(
+    img: &mut FileDisk,
+    dst: &mut FileDisk,
+    sector_len: usize,
+    block_len: usize,
+    is_block: bool,
+) -> Result<()> {
+    let len = img.disk_len();
+    let progress = io::stderr().is_terminal();
+    let mut buf = vec![0u8; block_len];
+    let mut pos = 0u64;
+
+    while pos < len {
+        let chunk = (len - pos).min(block_len as u64) as usize;
+        img.read_at(pos, &mut buf[..chunk])?;
+
+        // merge the partial last sector of a block device with its current contents, files are
+        // written exactly to the end of the image
+        let aligned = chunk.next_multiple_of(sector_len);
+        let out_len = if is_block && aligned != chunk {
+            let tail = chunk - chunk % sector_len;
+            let mut sector = vec![0u8; sector_len];
+            dst.read_at(pos + tail as u64, sector.as_mut())?;
+            sector[..chunk - tail].copy_from_slice(&buf[tail..chunk]);
+            buf[tail..aligned].copy_from_slice(sector.as_ref());
+            aligned
+        } else {
+            chunk
+        };
+
+        dst.write_at(pos, &buf[..out_len])?;
+        pos += chunk as u64;
+
+        if progress {
+            eprint!(
+                "\rwritten {} / {} MiB ({}%)",
+                pos >> 20,
+                len >> 20,
+                pos * 100 / len
+            );
+        }
+    }
+
+    if progress {
+        eprintln!();
+    }
+
+    Ok(())
+}
+
+// compares the SPL header window, and each valid SPL copy of the image, with the target
+fn verify_spl(img: &mut FileDisk, dst: &mut FileDisk, block_len: usize) -> Result<usize> {
+    let len = img.disk_len();
+    let mut regions = vec![(0u64, (SPL_HEADER_LEN as u64).min(len))];
+
+    let bofs = UbootSplHeader::read_at(&mut *img, 0)
+        .map(|h| h.bofs())
+        .unwrap_or(0);
+
+    let mut offsets = vec![0u32];
+    if bofs != 0 && (bofs as u64) < len {
+        offsets.push(bofs);
+    }
+
+    for offset in offsets {
+        let read = &mut |pos: u32, buf: &mut [u8]| img.read_at(pos as u64, buf);
+        match spl_copy_status(read, offset) {
+            (SplCopyStatus::Ok, Some(hdr)) => {
+                let copy_len = hdr.resl() as u64 + hdr.fsiz() as u64;
+                regions.push((offset as u64, copy_len));
+            }
+            (status, _) => log::debug!("No SPL copy to verify at {offset:#x}: {status}"),
+        }
+    }
+
+    if regions.len() == 1 {
+        log::warn!("No valid SPL copy found in the image, only the header window was verified");
+    }
+
+    for &(start, len) in regions.iter() {
+        compare_region(img, dst, start, len, block_len)?;
+        log::debug!("Verified {len} bytes at {start:#x}");
+    }
+
+    Ok(regions.len())
+}
+
+fn compare_region(
+    img: &mut FileDisk,
+    dst: &mut FileDisk,
+    start: u64,
+    len: u64,
+    block_len: usize,
+) -> Result<()> {
+    let mut expected = vec![0u8; block_len];
+    let mut pos = start;
+    let end = start + len;
+
+    while pos < end {
+        let chunk = (end - pos).min(block_len as u64) as usize;
+        img.read_at(pos, &mut expected[..chunk])?;
+        dst.verify_at(pos, &expected[..chunk])?;
+        pos += chunk as u64;
+    }
+
+    Ok(())
+}
+
+// refuses to overwrite the image itself, or a disk with mounted partitions, and creates a missing
+// file target
+fn check_target(name: &str, target: &str, force: bool) -> Result<bool> {
+    let meta = match fs::metadata(target) {
+        Ok(meta) => meta,
+        Err(err) if err.kind() == io::ErrorKind::NotFound && !target.starts_with("/dev/") => {
+            fs::File::create(target).io_context(Error::DiskWrite(0), target)?;
+            return Ok(false);
+        }
+        Err(err) => {
+            log::error!("Error opening target {target}: {err}");
+            return Err(Error::DiskWrite(0).into());
+        }
+    };
+
+    if let (Ok(img), Ok(dst)) = (fs::canonicalize(name), fs::canonicalize(target)) {
+        if img == dst {
+            log::error!("{target}: target is the image itself");
+            return Err(Error::DiskWrite(0).into());
+        }
+    }
+
+    if meta.is_file() {
+        return Ok(false);
+    }
+
+    check_mounts(Path::new(target), &meta, force)?;
+
+    Ok(true)
+}
+
+#[cfg(target_os = "linux")]
+fn check_mounts(target: &Path, meta: &fs::Metadata, force: bool) -> Result<()> {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+    if !meta.file_type().is_block_device() {
+        return Ok(());
+    }
+
+    let name = target.display();
+    let unchecked = |reason: &str| {
+        if force {
+            log::warn!("{name}: {reason}, mounts not checked");
+            Ok(())
+        } else {
+            log::error!("{name}: {reason}, use --force to write it anyway");
+            Err(Error::DiskWrite(0).into())
+        }
+    };
+
+    let disks = match sysfs_rdev(meta.rdev()) {
+        Some(dev) => sysfs_disks(&dev),
+        None => return unchecked("unknown block device"),
+    };
+
+    let mountinfo = match fs::read_to_string("/proc/self/mountinfo") {
+        Ok(mountinfo) => mountinfo,
+        Err(err) => return unchecked(format!("error reading mounts: {err}").as_str()),
+    };
+    let mounts = mountinfo.lines().filter_map(parse_mountinfo).map(|info| {
+        // btrfs and other filesystems on anonymous devices report `0:N` as `major:minor`, so
+        // resolve the mount source (e.g. `/dev/sda2`) first
+        let dev = fs::metadata(info.source.as_str())
+            .ok()
+            .filter(|m| m.file_type().is_block_device())
+            .and_then(|m| sysfs_rdev(m.rdev()))
+            .or_else(|| sysfs_path(info.dev.as_str()));
+        (dev, info.mount)
+    });
+
+    let swaps = fs::read_to_string("/proc/swaps").unwrap_or_default();
+    let swaps = swaps.lines().skip(1).filter_map(|line| {
+        let rdev = fs::metadata(unescape_mount(line.split_whitespace().next()?))
+            .ok()
+            .filter(|m| m.file_type().is_block_device())?
+            .rdev();
+        Some((sysfs_rdev(rdev), String::from("[SWAP]")))
+    });
+
+    let mut mounted = false;
+    for (dev, mount) in mounts.chain(swaps) {
+        let on_target = dev
+            .map(|dev| sysfs_disks(&dev))
+            .is_some_and(|devs| devs.iter().any(|d| disks.contains(d)));
+        if !on_target {
+            continue;
+        }
+
+        if SYSTEM_MOUNTS.contains(&mount.as_str()) {
+            log::error!("{name}: refusing to write a system disk, mounted at {mount}");
+            return Err(Error::DiskWrite(0).into());
+        } else if !force {
+            log::error!("{name}: disk is mounted at {mount}, unmount it or use --force");
+            return Err(Error::DiskWrite(0).into());
+        } else {
+            log::warn!("{name}: disk is mounted at {mount}");
+            mounted = true;
+        }
+    }
+
+    // e.g. an open LUKS volume, active LVM volume group, or RAID member that is not mounted
+    let holders: Vec<String> = disks.iter().flat_map(|disk| sysfs_holders(disk)).collect();
+    if !mounted && !holders.is_empty() {
+        let holders = holders.join(", ");
+        if force {
+            log::warn!("{name}: disk is in use by {holders}");
+        } else {
+            log::error!("{name}: disk is in use by {holders}, release it or use --force");
+            return Err(Error::DiskWrite(0).into());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn check_mounts(target: &Path, _meta: &fs::Metadata, _force: bool) -> Result<()> {
+    log::warn!(
+        "{}: mounted partitions are not checked on this platform",
+        target.display()
+    );
+    Ok(())
+}
+
+// mount entry of a `/proc/self/mountinfo` line
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq)]
+struct MountInfo {
+    dev: String,
+    mount: String,
+    source: String,
+}
+
+// mountinfo fields: id, parent id, major:minor, root, mount point, options, optional fields...,
+// then `-` followed by the fstype, mount source and super options
+#[cfg(target_os = "linux")]
+fn parse_mountinfo(line: &str) -> Option<MountInfo> {
+    let mut fields = line.split_whitespace().skip(2);
+    let dev = fields.next()?.to_string();
+    let mount = unescape_mount(fields.nth(1)?);
+    let source = unescape_mount(fields.skip_while(|&f| f != "-").nth(2)?);
+
+    Some(MountInfo { dev, mount, source })
+}
+
+#[cfg(target_os = "linux")]
+fn sysfs_path(dev: &str) -> Option<std::path::PathBuf> {
+    fs::canonicalize(format!("/sys/dev/block/{dev}")).ok()
+}
+
+// sysfs directory of a block device number
+#[cfg(target_os = "linux")]
+fn sysfs_rdev(rdev: u64) -> Option<std::path::PathBuf> {
+    let major = ((rdev >> 32) & 0xffff_f000) | ((rdev >> 8) & 0xfff);
+    let minor = ((rdev >> 12) & 0xffff_ff00) | (rdev & 0xff);
+
+    sysfs_path(format!("{major}:{minor}").as_str())
+}
+
+// sysfs directories of the whole disks backing a block device, following the `slaves` of
+// device-mapper and MD devices (e.g. LVM, LUKS or RAID) down to the physical disks
+#[cfg(target_os = "linux")]
+fn sysfs_disks(dev: &Path) -> Vec<std::path::PathBuf> {
+    let mut res = Vec::new();
+    collect_disks(dev, 0, &mut res);
+    res
+}
+
+#[cfg(target_os = "linux")]
+fn collect_disks(dev: &Path, depth: usize, res: &mut Vec<std::path::PathBuf>) {
+    let slaves = sysfs_links(&dev.join("slaves"));
+
+    if slaves.is_empty() || depth >= SYSFS_MAX_DEPTH {
+        let disk = sysfs_parent_disk(dev);
+        if !res.contains(&disk) {
+            res.push(disk);
+        }
+    } else {
+        for slave in slaves {
+            collect_disks(&slave, depth + 1, res);
+        }
+    }
+}
+
+// names of the devices stacked on a whole disk, or on one of its partitions
+#[cfg(target_os = "linux")]
+fn sysfs_holders(disk: &Path) -> Vec<String> {
+    let parts = fs::read_dir(disk)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|dev| dev.join("partition").exists());
+
+    std::iter::once(disk.to_path_buf())
+        .chain(parts)
+        .flat_map(|dev| sysfs_links(&dev.join("holders")))
+        .filter_map(|holder| Some(holder.file_name()?.to_string_lossy().into_owned()))
+        .collect()
+}
+
+// resolved targets of the device links in a sysfs `slaves` or `holders` directory
+#[cfg(target_os = "linux")]
+fn sysfs_links(dir: &Path) -> Vec<std::path::PathBuf> {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| fs::canonicalize(entry.ok()?.path()).ok())
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn sysfs_parent_disk(dev: &Path) -> std::path::PathBuf {
+    match dev.parent() {
+        Some(disk) if dev.join("partition").exists() => disk.to_path_buf(),
+        _ => dev.to_path_buf(),
+    }
+}
+
+// mount paths escape spaces, tabs, newlines and backslashes as octal `\NNN`
+#[cfg(target_os = "linux")]
+fn unescape_mount(val: &str) -> String {
+    let mut res = String::with_capacity(val.len());
+    let mut rest = val;
+
+    while let Some(idx) = rest.find('\\') {
+        res.push_str(&rest[..idx]);
+        match rest
+            .get(idx + 1..idx + 4)
+            .and_then(|oct| u8::from_str_radix(oct, 8).ok())
+        {
+            Some(b) => {
+                res.push(b as char);
+                rest = &rest[idx + 4..];
+            }
+            None => {
+                res.push('\\');
+                rest = &rest[idx + 1..];
+            }
+        }
+    }
+    res.push_str(rest);
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use spl_tool::{spl_image, spl_image_len};
+
+    use super::*;
+
+    const PAYLOAD: &[u8] = &[0x13, 0x00, 0x00, 0x00, 0x6f, 0x00, 0x00, 0x00];
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("spl_tool-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn args(image: &Path, target: &Path) -> WriteArgs {
+        WriteArgs {
+            image: image.display().to_string(),
+            target: target.display().to_string(),
+            sector_size: DEF_SECTOR_LEN,
+            block_size: DEF_WRITE_BLOCK_LEN,
+            force: false,
+        }
+    }
+
+    #[test]
+    fn test_write_truncates_file() {
+        let dir = temp_dir("write-truncate");
+        let image = dir.join("sdcard.img");
+        let target = dir.join("target.img");
+
+        let spl: [u8; spl_image_len(PAYLOAD.len())] = spl_image(PAYLOAD);
+        let mut img = vec![0u8; 0x1000];
+        img[..spl.len()].copy_from_slice(spl.as_ref());
+        fs::write(&image, &img).unwrap();
+
+        // a longer target, e.g. with a stale backup GPT at its end
+        fs::write(&target, vec![0xa5u8; 0x4000]).unwrap();
+        spl_write(&args(&image, &target)).unwrap();
+        assert_eq!(fs::read(&target).unwrap(), img);
+
+        // a missing target is created
+        fs::remove_file(&target).unwrap();
+        spl_write(&args(&image, &target)).unwrap();
+        assert_eq!(fs::read(&target).unwrap(), img);
+
+        // the image is never its own target
+        let err = spl_write(&args(&image, &image)).unwrap_err();
+        assert!(matches!(err.error(), Error::DiskWrite(0)));
+        assert_eq!(fs::read(&image).unwrap(), img);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sysfs_stacked_devices() {
+        use std::os::unix::fs::symlink;
+
+        let sys = temp_dir("write-sysfs");
+        let dev = |path: &str| {
+            let dev = sys.join(path);
+            fs::create_dir_all(&dev).unwrap();
+            dev
+        };
+        let part = |path: &str| {
+            let part = dev(path);
+            fs::write(part.join("partition"), "1\n").unwrap();
+            part
+        };
+        let link = |from: &Path, dir: &str, to: &Path| {
+            let dir = from.join(dir);
+            fs::create_dir_all(&dir).unwrap();
+            symlink(to, dir.join(to.file_name().unwrap())).unwrap();
+        };
+
+        // LVM on LUKS on sda2, and a RAID1 of sdb and sdc
+        let sda = dev("sda");
+        let sda1 = part("sda/sda1");
+        let sda2 = part("sda/sda2");
+        let crypt = dev("dm-0");
+        let lvm = dev("dm-1");
+        let (sdb, sdc, md) = (dev("sdb"), dev("sdc"), dev("md0"));
+        link(&crypt, "slaves", &sda2);
+        link(&sda2, "holders", &crypt);
+        link(&lvm, "slaves", &crypt);
+        link(&crypt, "holders", &lvm);
+        link(&md, "slaves", &sdb);
+        link(&md, "slaves", &sdc);
+        link(&sdb, "holders", &md);
+
+        let canon = |path: &Path| fs::canonicalize(path).unwrap();
+        let sda = canon(&sda);
+
+        for dev in [sda.clone(), canon(&sda1), canon(&lvm)] {
+            assert_eq!(sysfs_disks(&dev), std::slice::from_ref(&sda));
+        }
+
+        let mut raid = sysfs_disks(&canon(&md));
+        raid.sort();
+        assert_eq!(raid, [canon(&sdb), canon(&sdc)]);
+
+        assert_eq!(sysfs_holders(&sda), ["dm-0"]);
+        assert_eq!(sysfs_holders(&canon(&sdb)), ["md0"]);
+        assert!(sysfs_holders(&canon(&sdc)).is_empty());
+
+        // a cycle of device links ends at the maximum depth
+        let (loop0, loop1) = (dev("dm-2"), dev("dm-3"));
+        link(&loop0, "slaves", &loop1);
+        link(&loop1, "slaves", &loop0);
+        assert_eq!(sysfs_disks(&canon(&loop0)).len(), 1);
+
+        fs::remove_dir_all(&sys).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_mountinfo() {
+        // btrfs subvolume, with an anonymous device number
+        assert_eq!(
+            parse_mountinfo(
+                "61 1 0:33 /root / rw,relatime shared:1 - btrfs /dev/sda2 rw,subvol=/root"
+            ),
+            Some(MountInfo {
+                dev: "0:33".into(),
+                mount: "/".into(),
+                source: "/dev/sda2".into(),
+            })
+        );
+        // no optional fields, escaped mount point and source
+        assert_eq!(
+            parse_mountinfo(
+                "90 61 179:1 / /media/SD\\040card rw - vfat /dev/disk/by-label/SD\\040card rw"
+            ),
+            Some(MountInfo {
+                dev: "179:1".into(),
+                mount: "/media/SD card".into(),
+                source: "/dev/disk/by-label/SD card".into(),
+            })
+        );
+        // several optional fields
+        assert_eq!(
+            parse_mountinfo("25 1 8:1 / /boot rw shared:7 master:2 - ext4 /dev/sda1 rw")
+                .map(|info| info.source),
+            Some("/dev/sda1".into())
+        );
+        assert_eq!(parse_mountinfo("25 1 8:1 / /boot rw shared:7"), None);
+        assert_eq!(parse_mountinfo("25 1 8:1"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_unescape_mount() {
+        assert_eq!(unescape_mount("/media/SD\\040card"), "/media/SD card");
+        assert_eq!(unescape_mount("/a\\011b\\134c"), "/a\tb\\c");
+        assert_eq!(unescape_mount("/trailing\\"), "/trailing\\");
+        assert_eq!(unescape_mount("/bad\\9zz"), "/bad\\9zz");
+    }
+}
@@ -1,5 +1,8 @@
 // SPDX-License-Identifier: GPL-2.0+
 
+/// CRC-32 polynomial used for the SPL image checksum.
+pub const SPL_CRC32_POLY: u32 = 0x04c1_1db7;
+
 /// Reverses the bits in the CRC-32 calculation.
 ///
 /// From the `spl_tool` C implementation: <https://github.com/starfive-tech/Tools/blob/master/spl_tool/crc32.c>
@@ -20,19 +23,24 @@ pub const fn crc32_reverse(mut x: u32) -> u32 {
 /// - `data`: byte buffer to calculate the checksum.
 ///
 /// From the `spl_tool` C implementation: <https://github.com/starfive-tech/Tools/blob/master/spl_tool/crc32.c>
-pub fn crc32(iv: u32, sv: u32, data: &[u8]) -> u32 {
+pub const fn crc32(iv: u32, sv: u32, data: &[u8]) -> u32 {
     let mut crc = iv;
+    let mut i = 0usize;
 
-    for &byte in data.iter() {
-        let mut sum = crc32_reverse(byte as u32);
-        for x in 0..8 {
+    // `while` loops keep the function usable in `const` context
+    while i < data.len() {
+        let mut sum = crc32_reverse(data[i] as u32);
+        let mut x = 0u32;
+        while x < 8 {
             sum <<= (x != 0) as u32;
             crc = if ((crc ^ sum) & 0x80000000) != 0 {
                 (crc << 1) ^ sv
             } else {
                 crc << 1
             };
+            x += 1;
         }
+        i += 1;
     }
 
     crc
@@ -44,3 +52,42 @@ pub fn crc32(iv: u32, sv: u32, data: &[u8]) -> u32 {
 pub const fn crc32_final(iv: u32) -> u32 {
     crc32_reverse(iv ^ !0u32)
 }
+
+/// Calculate the final CRC-32 value of an SPL image, as stored in the header `crcs` field.
+pub const fn spl_crc32(data: &[u8]) -> u32 {
+    crc32_final(crc32(!0, SPL_CRC32_POLY, data))
+}
+
+/// Reflected form of the standard (IEEE 802.3) CRC-32 polynomial.
+pub const CRC32_IEEE_POLY: u32 = 0xedb8_8320;
+
+/// Updates a standard reflected CRC-32 state over the provided data buffer.
+///
+/// Start with a state of `!0`, and invert the final state, or use [crc32_ieee].
+pub const fn crc32_ieee_update(mut crc: u32, data: &[u8]) -> u32 {
+    let mut i = 0usize;
+
+    while i < data.len() {
+        crc ^= data[i] as u32;
+        let mut x = 0u32;
+        while x < 8 {
+            crc = if (crc & 1) != 0 {
+                (crc >> 1) ^ CRC32_IEEE_POLY
+            } else {
+                crc >> 1
+            };
+            x += 1;
+        }
+        i += 1;
+    }
+
+    crc
+}
+
+/// Calculate the standard reflected CRC-32 value, as used by zlib and the U-Boot environment.
+///
+/// The value is the same as [spl_crc32], which reaches it by reversing the bits of the input and
+/// output around the non-reflected polynomial.
+pub const fn crc32_ieee(data: &[u8]) -> u32 {
+    !crc32_ieee_update(!0, data)
+}
@@ -0,0 +1,600 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use core::ops::Range;
+
+use super::{Error, Result};
+
+/// ELF identification magic bytes.
+pub const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+/// Start address of the JH7110 on-chip SRAM the bootrom loads the SPL into.
+pub const JH7110_SRAM_BASE: u64 = 0x0800_0000;
+/// Length of the JH7110 on-chip SRAM.
+pub const JH7110_SRAM_LEN: u64 = 0x20_0000;
+
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const PT_LOAD: u32 = 1;
+const SHT_NOBITS: u32 = 8;
+const SHF_ALLOC: u64 = 0x2;
+
+const ELF32_EHDR_LEN: usize = 0x34;
+const ELF64_EHDR_LEN: usize = 0x40;
+const ELF32_PHDR_LEN: usize = 0x20;
+const ELF64_PHDR_LEN: usize = 0x38;
+const ELF32_SHDR_LEN: usize = 0x28;
+const ELF64_SHDR_LEN: usize = 0x40;
+
+/// Gets whether the data starts with the ELF magic bytes.
+pub fn is_elf(data: &[u8]) -> bool {
+    data.starts_with(ELF_MAGIC.as_ref())
+}
+
+/// Represents a loadable region of an ELF file, either a `PT_LOAD` segment or an allocated section.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ElfSegment {
+    /// Offset of the contents in the ELF file.
+    pub offset: u64,
+    /// Physical (load) address of the contents.
+    pub paddr: u64,
+    /// Length of the contents in the ELF file.
+    pub filesz: u64,
+    /// Length in memory, including zero-initialized data.
+    pub memsz: u64,
+}
+
+impl ElfSegment {
+    /// Gets the load address range of the contents.
+    pub const fn file_range(&self) -> Range<u64> {
+        self.paddr..self.paddr.saturating_add(self.filesz)
+    }
+
+    /// Gets the load address range in memory.
+    pub const fn mem_range(&self) -> Range<u64> {
+        self.paddr..self.paddr.saturating_add(self.memsz)
+    }
+}
+
+/// Represents a little-endian ELF32 or ELF64 SPL image, e.g. `u-boot-spl`.
+#[derive(Clone, Copy, Debug)]
+pub struct SplElf<'a> {
+    data: &'a [u8],
+    is_64: bool,
+    entry: u64,
+    phoff: usize,
+    phentsize: usize,
+    phnum: usize,
+    shoff: usize,
+    shentsize: usize,
+    shnum: usize,
+}
+
+impl<'a> SplElf<'a> {
+    /// Parses the ELF header and program header table location.
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        if !is_elf(data) || data.len() < ELF32_EHDR_LEN {
+            return Err(Error::InvalidElf);
+        }
+
+        let is_64 = match data[4] {
+            ELFCLASS32 => false,
+            ELFCLASS64 => true,
+            _ => return Err(Error::InvalidElf),
+        };
+
+        if data[5] != ELFDATA2LSB {
+            return Err(Error::InvalidElf);
+        }
+
+        let (entry, phoff, shoff, phentsize, phnum, shentsize, shnum) = if is_64 {
+            if data.len() < ELF64_EHDR_LEN {
+                return Err(Error::InvalidElf);
+            }
+            (
+                read_u64(data, 0x18)?,
+                read_u64(data, 0x20)?,
+                read_u64(data, 0x28)?,
+                read_u16(data, 0x36)?,
+                read_u16(data, 0x38)?,
+                read_u16(data, 0x3a)?,
+                read_u16(data, 0x3c)?,
+            )
+        } else {
+            (
+                read_u32(data, 0x18)? as u64,
+                read_u32(data, 0x1c)? as u64,
+                read_u32(data, 0x20)? as u64,
+                read_u16(data, 0x2a)?,
+                read_u16(data, 0x2c)?,
+                read_u16(data, 0x2e)?,
+                read_u16(data, 0x30)?,
+            )
+        };
+
+        let (min_phentsize, min_shentsize) = if is_64 {
+            (ELF64_PHDR_LEN, ELF64_SHDR_LEN)
+        } else {
+            (ELF32_PHDR_LEN, ELF32_SHDR_LEN)
+        };
+
+        let phoff = table_offset(data, phoff, phentsize, phnum, min_phentsize)?;
+        let shoff = table_offset(data, shoff, shentsize, shnum, min_shentsize)?;
+
+        Ok(Self {
+            data,
+            is_64,
+            entry,
+            phoff,
+            phentsize: phentsize as usize,
+            phnum: phnum as usize,
+            shoff,
+            shentsize: shentsize as usize,
+            shnum: shnum as usize,
+        })
+    }
+
+    /// Gets whether the ELF file is 64-bit.
+    pub const fn is_64(&self) -> bool {
+        self.is_64
+    }
+
+    /// Gets the entry point address.
+    pub const fn entry(&self) -> u64 {
+        self.entry
+    }
+
+    /// Gets an iterator over the `PT_LOAD` segments with contents in the file.
+    pub fn segments(&self) -> impl Iterator<Item = Result<ElfSegment>> + '_ {
+        (0..self.phnum).filter_map(move |i| self.segment(i).transpose())
+    }
+
+    /// Gets an iterator over the allocated sections with contents in the file.
+    ///
+    /// The load address of each section is taken from the `PT_LOAD` segment containing it.
+    pub fn sections(&self) -> impl Iterator<Item = Result<ElfSegment>> + '_ {
+        (0..self.shnum).filter_map(move |i| self.section(i).transpose())
+    }
+
+    /// Gets the load address range covered by the flattened binary.
+    pub fn load_range(&self) -> Result<Range<u64>> {
+        let mut res: Option<Range<u64>> = None;
+
+        for region in self.contents() {
+            let region = region?.file_range();
+            res = Some(match res {
+                Some(r) => r.start.min(region.start)..r.end.max(region.end),
+                None => region,
+            });
+        }
+
+        res.ok_or(Error::InvalidElf)
+    }
+
+    /// Flattens the loadable contents into a raw binary, like `objcopy -O binary`.
+    ///
+    /// Allocated sections are used when the file has a section header table, and `PT_LOAD`
+    /// segments otherwise. The binary starts at the lowest load address, and gaps are zero-filled.
+    /// Zero-initialized data past the last segment contents is not included.
+    ///
+    /// Returns the length of the flattened binary written to `out`.
+    pub fn flatten(&self, out: &mut [u8]) -> Result<usize> {
+        let range = self.load_range()?;
+        let len = usize::try_from(range.end - range.start).map_err(|_| Error::InvalidElf)?;
+
+        if len > out.len() {
+            return Err(Error::InvalidSplLen((len, out.len())));
+        }
+
+        out[..len].fill(0);
+
+        for region in self.contents() {
+            let region = region?;
+            // region bounds were checked against the file when parsing the program/section header
+            let src = &self.data[region.offset as usize..(region.offset + region.filesz) as usize];
+            let dst = (region.paddr - range.start) as usize;
+
+            out[dst..dst + src.len()].copy_from_slice(src);
+        }
+
+        Ok(len)
+    }
+
+    /// Checks that the entry point and the load address range fall in the JH7110 SRAM.
+    pub fn check_sram(&self) -> Result<()> {
+        let sram = JH7110_SRAM_BASE..JH7110_SRAM_BASE + JH7110_SRAM_LEN;
+
+        if !sram.contains(&self.entry) {
+            return Err(Error::InvalidElfAddr(self.entry));
+        }
+
+        let range = self.load_range()?;
+        if !sram.contains(&range.start) {
+            Err(Error::InvalidElfAddr(range.start))
+        } else if range.end > sram.end {
+            Err(Error::InvalidElfAddr(range.end))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn contents(&self) -> impl Iterator<Item = Result<ElfSegment>> + '_ {
+        let use_sections = self.shnum != 0;
+
+        self.sections()
+            .filter(move |_| use_sections)
+            .chain(self.segments().filter(move |_| !use_sections))
+    }
+
+    fn segment(&self, idx: usize) -> Result<Option<ElfSegment>> {
+        let ph = self.phoff + idx * self.phentsize;
+
+        if read_u32(self.data, ph)? != PT_LOAD {
+            return Ok(None);
+        }
+
+        let seg = if self.is_64 {
+            ElfSegment {
+                offset: read_u64(self.data, ph + 0x08)?,
+                paddr: read_u64(self.data, ph + 0x18)?,
+                filesz: read_u64(self.data, ph + 0x20)?,
+                memsz: read_u64(self.data, ph + 0x28)?,
+            }
+        } else {
+            ElfSegment {
+                offset: read_u32(self.data, ph + 0x04)? as u64,
+                paddr: read_u32(self.data, ph + 0x0c)? as u64,
+                filesz: read_u32(self.data, ph + 0x10)? as u64,
+                memsz: read_u32(self.data, ph + 0x14)? as u64,
+            }
+        };
+
+        if seg.filesz == 0 {
+            return Ok(None);
+        }
+
+        match seg.offset.checked_add(seg.filesz) {
+            Some(end)
+                if end <= self.data.len() as u64
+                    && seg.paddr.checked_add(seg.filesz.max(seg.memsz)).is_some() =>
+            {
+                Ok(Some(seg))
+            }
+            _ => Err(Error::InvalidElf),
+        }
+    }
+
+    fn section(&self, idx: usize) -> Result<Option<ElfSegment>> {
+        let sh = self.shoff + idx * self.shentsize;
+
+        let (sh_type, flags, addr, offset, size) = if self.is_64 {
+            (
+                read_u32(self.data, sh + 0x04)?,
+                read_u64(self.data, sh + 0x08)?,
+                read_u64(self.data, sh + 0x10)?,
+                read_u64(self.data, sh + 0x18)?,
+                read_u64(self.data, sh + 0x20)?,
+            )
+        } else {
+            (
+                read_u32(self.data, sh + 0x04)?,
+                read_u32(self.data, sh + 0x08)? as u64,
+                read_u32(self.data, sh + 0x0c)? as u64,
+                read_u32(self.data, sh + 0x10)? as u64,
+                read_u32(self.data, sh + 0x14)? as u64,
+            )
+        };
+
+        if flags & SHF_ALLOC == 0 || sh_type == SHT_NOBITS || size == 0 {
+            return Ok(None);
+        }
+
+        // translate the section address to the load address of the containing segment
+        let mut paddr = addr;
+        for seg in self.segments() {
+            let seg = seg?;
+            if (seg.offset..seg.offset + seg.filesz).contains(&offset) {
+                paddr = seg.paddr + (offset - seg.offset);
+                break;
+            }
+        }
+
+        match offset.checked_add(size) {
+            Some(end) if end <= self.data.len() as u64 && paddr.checked_add(size).is_some() => {
+                Ok(Some(ElfSegment {
+                    offset,
+                    paddr,
+                    filesz: size,
+                    memsz: size,
+                }))
+            }
+            _ => Err(Error::InvalidElf),
+        }
+    }
+}
+
+fn table_offset(
+    data: &[u8],
+    off: u64,
+    entsize: u16,
+    num: u16,
+    min_entsize: usize,
+) -> Result<usize> {
+    let off = usize::try_from(off).map_err(|_| Error::InvalidElf)?;
+    let end = (entsize as usize)
+        .checked_mul(num as usize)
+        .and_then(|len| off.checked_add(len))
+        .ok_or(Error::InvalidElf)?;
+
+    if (num != 0 && (entsize as usize) < min_entsize) || end > data.len() {
+        Err(Error::InvalidElf)
+    } else {
+        Ok(off)
+    }
+}
+
+fn read_u16(data: &[u8], idx: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes(
+        data.get(idx..idx + 2)
+            .ok_or(Error::InvalidElf)?
+            .try_into()?,
+    ))
+}
+
+fn read_u32(data: &[u8], idx: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(
+        data.get(idx..idx + 4)
+            .ok_or(Error::InvalidElf)?
+            .try_into()?,
+    ))
+}
+
+fn read_u64(data: &[u8], idx: usize) -> Result<u64> {
+    Ok(u64::from_le_bytes(
+        data.get(idx..idx + 8)
+            .ok_or(Error::InvalidElf)?
+            .try_into()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+
+    const BASE: u64 = JH7110_SRAM_BASE;
+
+    // (offset, paddr, contents, memsz)
+    type Seg<'a> = (u64, u64, &'a [u8], u64);
+    // (type, flags, addr, offset, size)
+    type Sec = (u32, u64, u64, u64, u64);
+
+    fn put(buf: &mut Vec<u8>, off: usize, val: &[u8]) {
+        if buf.len() < off + val.len() {
+            buf.resize(off + val.len(), 0);
+        }
+        buf[off..off + val.len()].copy_from_slice(val);
+    }
+
+    fn elf64(entry: u64, segs: &[Seg], secs: &[Sec]) -> Vec<u8> {
+        let phoff = ELF64_EHDR_LEN;
+        let shoff = phoff + segs.len() * ELF64_PHDR_LEN;
+        let mut buf = std::vec![0u8; ELF64_EHDR_LEN];
+
+        put(&mut buf, 0, ELF_MAGIC.as_ref());
+        put(&mut buf, 4, &[ELFCLASS64, ELFDATA2LSB, 1]);
+        put(&mut buf, 0x18, &entry.to_le_bytes());
+        put(&mut buf, 0x20, &(phoff as u64).to_le_bytes());
+        let shoff_val = if secs.is_empty() { 0 } else { shoff as u64 };
+        put(&mut buf, 0x28, &shoff_val.to_le_bytes());
+        put(&mut buf, 0x36, &(ELF64_PHDR_LEN as u16).to_le_bytes());
+        put(&mut buf, 0x38, &(segs.len() as u16).to_le_bytes());
+        put(&mut buf, 0x3a, &(ELF64_SHDR_LEN as u16).to_le_bytes());
+        put(&mut buf, 0x3c, &(secs.len() as u16).to_le_bytes());
+
+        for (i, (offset, paddr, data, memsz)) in segs.iter().enumerate() {
+            let ph = phoff + i * ELF64_PHDR_LEN;
+            put(&mut buf, ph, &PT_LOAD.to_le_bytes());
+            put(&mut buf, ph + 0x08, &offset.to_le_bytes());
+            put(&mut buf, ph + 0x10, &paddr.to_le_bytes());
+            put(&mut buf, ph + 0x18, &paddr.to_le_bytes());
+            put(&mut buf, ph + 0x20, &(data.len() as u64).to_le_bytes());
+            put(&mut buf, ph + 0x28, &memsz.to_le_bytes());
+        }
+
+        for (i, (sh_type, flags, addr, offset, size)) in secs.iter().enumerate() {
+            let sh = shoff + i * ELF64_SHDR_LEN;
+            put(&mut buf, sh + 0x04, &sh_type.to_le_bytes());
+            put(&mut buf, sh + 0x08, &flags.to_le_bytes());
+            put(&mut buf, sh + 0x10, &addr.to_le_bytes());
+            put(&mut buf, sh + 0x18, &offset.to_le_bytes());
+            put(&mut buf, sh + 0x20, &size.to_le_bytes());
+        }
+
+        for (offset, _, data, _) in segs {
+            put(&mut buf, *offset as usize, data);
+        }
+
+        buf
+    }
+
+    fn elf32(entry: u32, offset: u32, paddr: u32, data: &[u8]) -> Vec<u8> {
+        let phoff = ELF32_EHDR_LEN as u32;
+        let mut buf = Vec::new();
+
+        put(&mut buf, 0, ELF_MAGIC.as_ref());
+        put(&mut buf, 4, &[ELFCLASS32, ELFDATA2LSB, 1]);
+        put(&mut buf, 0x18, &entry.to_le_bytes());
+        put(&mut buf, 0x1c, &phoff.to_le_bytes());
+        put(&mut buf, 0x2a, &(ELF32_PHDR_LEN as u16).to_le_bytes());
+        put(&mut buf, 0x2c, &1u16.to_le_bytes());
+
+        let ph = phoff as usize;
+        put(&mut buf, ph, &PT_LOAD.to_le_bytes());
+        put(&mut buf, ph + 0x04, &offset.to_le_bytes());
+        put(&mut buf, ph + 0x0c, &paddr.to_le_bytes());
+        put(&mut buf, ph + 0x10, &(data.len() as u32).to_le_bytes());
+        put(&mut buf, ph + 0x14, &(data.len() as u32).to_le_bytes());
+        put(&mut buf, offset as usize, data);
+
+        buf
+    }
+
+    #[test]
+    fn test_flatten_segments() {
+        let img = elf64(
+            BASE,
+            &[
+                (0x100, BASE, b"text", 4),
+                (0x200, BASE + 0x10, b"data", 0x100),
+            ],
+            &[],
+        );
+        let elf = SplElf::parse(img.as_ref()).unwrap();
+
+        assert!(elf.is_64());
+        assert_eq!(elf.entry(), BASE);
+        assert_eq!(elf.segments().count(), 2);
+        // zero-initialized data past the contents is not included
+        assert_eq!(elf.load_range().unwrap(), BASE..BASE + 0x14);
+        assert!(elf.check_sram().is_ok());
+
+        let mut out = [0xffu8; 0x20];
+        assert_eq!(elf.flatten(out.as_mut()).unwrap(), 0x14);
+        assert_eq!(&out[..4], b"text");
+        assert_eq!(&out[4..0x10], [0u8; 12].as_ref());
+        assert_eq!(&out[0x10..0x14], b"data");
+
+        let mut out = [0u8; 0x13];
+        assert!(matches!(
+            elf.flatten(out.as_mut()),
+            Err(Error::InvalidSplLen((0x14, 0x13)))
+        ));
+    }
+
+    #[test]
+    fn test_flatten_sections() {
+        // the section table takes precedence, and `.bss` (NOBITS) is skipped
+        let img = elf64(
+            BASE,
+            &[(0x200, BASE, b"textdata", 8)],
+            &[
+                (1, SHF_ALLOC, BASE, 0x200, 4),
+                (SHT_NOBITS, SHF_ALLOC, BASE + 8, 0x208, 0x100),
+                // non-allocated sections, e.g. debug info, are skipped
+                (1, 0, 0, 0x204, 4),
+            ],
+        );
+        let elf = SplElf::parse(img.as_ref()).unwrap();
+
+        assert_eq!(elf.sections().count(), 1);
+        assert_eq!(elf.load_range().unwrap(), BASE..BASE + 4);
+
+        let mut out = [0u8; 8];
+        assert_eq!(elf.flatten(out.as_mut()).unwrap(), 4);
+        assert_eq!(&out[..4], b"text");
+    }
+
+    #[test]
+    fn test_elf32() {
+        let img = elf32(BASE as u32, 0x80, BASE as u32, b"spl!");
+        let elf = SplElf::parse(img.as_ref()).unwrap();
+
+        assert!(!elf.is_64());
+        let mut out = [0u8; 4];
+        assert_eq!(elf.flatten(out.as_mut()).unwrap(), 4);
+        assert_eq!(&out, b"spl!");
+    }
+
+    #[test]
+    fn test_check_sram() {
+        let img = elf64(0x8000_0000, &[(0x100, BASE, b"text", 4)], &[]);
+        let elf = SplElf::parse(img.as_ref()).unwrap();
+        assert!(matches!(
+            elf.check_sram(),
+            Err(Error::InvalidElfAddr(0x8000_0000))
+        ));
+
+        let end = BASE + JH7110_SRAM_LEN;
+        let img = elf64(BASE, &[(0x100, end - 2, b"text", 4)], &[]);
+        let elf = SplElf::parse(img.as_ref()).unwrap();
+        assert!(matches!(elf.check_sram(), Err(Error::InvalidElfAddr(a)) if a == end + 2));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let img = elf64(BASE, &[(0x100, BASE, b"text", 4)], &[]);
+
+        assert!(!is_elf(b"\x7fELG"));
+        assert!(matches!(SplElf::parse(b"\x7fELF"), Err(Error::InvalidElf)));
+
+        let mut bad = img.clone();
+        bad[4] = 3;
+        assert!(matches!(
+            SplElf::parse(bad.as_ref()),
+            Err(Error::InvalidElf)
+        ));
+
+        // big-endian
+        let mut bad = img.clone();
+        bad[5] = 2;
+        assert!(matches!(
+            SplElf::parse(bad.as_ref()),
+            Err(Error::InvalidElf)
+        ));
+
+        // program header table past the end of the file
+        let mut bad = img.clone();
+        bad[0x20..0x28].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(
+            SplElf::parse(bad.as_ref()),
+            Err(Error::InvalidElf)
+        ));
+
+        // program header entries too small
+        let mut bad = img.clone();
+        bad[0x36..0x38].copy_from_slice(&4u16.to_le_bytes());
+        assert!(matches!(
+            SplElf::parse(bad.as_ref()),
+            Err(Error::InvalidElf)
+        ));
+
+        // segment contents past the end of the file
+        let mut bad = img.clone();
+        let ph = ELF64_EHDR_LEN;
+        bad[ph + 0x20..ph + 0x28].copy_from_slice(&u64::MAX.to_le_bytes());
+        let elf = SplElf::parse(bad.as_ref()).unwrap();
+        assert!(matches!(elf.load_range(), Err(Error::InvalidElf)));
+
+        // load address overflow
+        let mut bad = img.clone();
+        bad[ph + 0x18..ph + 0x20].copy_from_slice(&(u64::MAX - 1).to_le_bytes());
+        let elf = SplElf::parse(bad.as_ref()).unwrap();
+        assert!(matches!(elf.load_range(), Err(Error::InvalidElf)));
+
+        // no loadable contents
+        let img = elf64(BASE, &[], &[]);
+        let elf = SplElf::parse(img.as_ref()).unwrap();
+        assert!(matches!(elf.load_range(), Err(Error::InvalidElf)));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let img = elf64(
+            BASE,
+            &[(0x200, BASE, b"textdata", 8)],
+            &[(1, SHF_ALLOC, BASE, 0x200, 8)],
+        );
+        let mut out = [0u8; 16];
+
+        // truncated files are rejected without panicking
+        for len in 0..img.len() {
+            if let Ok(elf) = SplElf::parse(&img[..len]) {
+                assert!(elf.flatten(out.as_mut()).is_err(), "len {len}");
+            }
+        }
+        assert!(SplElf::parse(img.as_ref())
+            .and_then(|elf| elf.flatten(out.as_mut()))
+            .is_ok());
+    }
+}
@@ -2,9 +2,17 @@
 
 use core::fmt;
 
+use super::{SplField, DEF_SOFS, MAX_SPL_LEN, SPL_HEADER_LEN, UIMAGE_MAGIC};
+
 /// Convenience alias for a [`Result`](core::result::Result) type for the library.
 pub type Result<T> = core::result::Result<T, Error>;
 
+/// Represents the library errors.
+///
+/// Errors are `Copy` and allocation-free for `no_std` targets. The structured detail carried by
+/// a variant is available from [detail](Self::detail), and its broad category from
+/// [category](Self::category). With the `std` feature, [Error] implements
+/// `std::error::Error`, and `PathError` adds the path and the underlying I/O error.
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub enum Error {
@@ -13,7 +21,358 @@ pub enum Error {
     InvalidSlice(core::array::TryFromSliceError),
     InvalidHeaderFile,
     InvalidSplFile,
+    InvalidConfigFile,
+    InvalidConfigLine(usize),
+    InvalidConfigValue(usize),
+    InvalidManifestFile,
+    InvalidSignAlg(u32),
+    InvalidSigningKey,
+    InvalidSignature,
+    InvalidKeyHash,
+    InvalidCrc((u32, u32)),
+    NullPointer,
     RequiresCliFeature,
+    InvalidFlashAlign(u32),
+    FlashErase(u32),
+    FlashWrite(u32),
+    FlashRead(u32),
+    FlashVerify(u32),
+    InvalidSofs(u32),
+    InvalidElf,
+    InvalidElfAddr(u64),
+    InvalidHexRecord(usize),
+    InvalidEnvSize((usize, usize)),
+    InvalidEnvVar(usize),
+    InvalidUimageMagic(u32),
+    InvalidUimageType(u8),
+    InvalidUimageComp(u8),
+    InvalidFit,
+    InvalidFitHash,
+    InvalidGpt,
+    DiskRead(u64),
+    DiskWrite(u64),
+    InvalidFixRecord,
+    InvalidSparseImage,
+    UndefinedEnvVar,
+    InvalidFlashLayout(u32),
+    InvalidResl(u32),
+    InvalidUimageLen((usize, usize)),
+}
+
+// Table of the stable [Error] codes and their static descriptions.
+//
+// Generates [Error::code], [Error::description] and [Error::code_description] from a single
+// entry per variant. Codes are never reused for a different variant.
+macro_rules! error_codes {
+    ($($variant:ident = $code:literal => $desc:literal,)+) => {
+        impl Error {
+            /// Gets the stable integer code for the [Error].
+            ///
+            /// Codes are positive, and never reused for a different variant.
+            pub const fn code(&self) -> i32 {
+                match self {
+                    $(Self::$variant { .. } => $code,)+
+                }
+            }
+
+            /// Gets the static description of the [Error], without the variant data.
+            pub const fn description(&self) -> &'static str {
+                match self {
+                    $(Self::$variant { .. } => $desc,)+
+                }
+            }
+
+            /// Gets the static description for an [Error] code, if the code is known.
+            pub const fn code_description(code: i32) -> Option<&'static str> {
+                match code {
+                    $($code => Some($desc),)+
+                    _ => None,
+                }
+            }
+
+            /// Gets the static, NUL-terminated description for an [Error] code.
+            #[cfg(feature = "capi")]
+            pub(crate) const fn code_c_description(code: i32) -> Option<&'static core::ffi::CStr> {
+                match code {
+                    $($code => Some(c_str(concat!($desc, "\0"))),)+
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    InvalidHeaderLen = 1 => "invalid header length",
+    InvalidSplLen = 2 => "invalid SPL length",
+    InvalidSlice = 3 => "invalid slice to array conversion",
+    InvalidHeaderFile = 4 => "invalid SPL header file",
+    InvalidSplFile = 5 => "invalid SPL file",
+    InvalidConfigFile = 6 => "invalid SBL config file",
+    InvalidConfigLine = 7 => "invalid SBL config line",
+    InvalidConfigValue = 8 => "invalid SBL config value",
+    InvalidManifestFile = 9 => "invalid manifest file",
+    InvalidSignAlg = 10 => "invalid signature algorithm",
+    InvalidSigningKey = 11 => "invalid signing key",
+    InvalidSignature = 12 => "invalid SPL image signature",
+    InvalidKeyHash = 13 => "invalid SPL public key hash",
+    InvalidCrc = 14 => "invalid CRC",
+    NullPointer = 15 => "invalid null pointer argument",
+    RequiresCliFeature = 16 => "the CLI application requires the `cli` feature",
+    InvalidFlashAlign = 17 => "invalid flash alignment",
+    FlashErase = 18 => "error erasing flash",
+    FlashWrite = 19 => "error writing flash",
+    FlashRead = 20 => "error reading flash",
+    FlashVerify = 21 => "flash verification failed",
+    InvalidSofs = 22 => "invalid SPL header offset",
+    InvalidElf = 23 => "invalid ELF file",
+    InvalidElfAddr = 24 => "invalid ELF address",
+    InvalidHexRecord = 25 => "invalid Intel HEX/S-record",
+    InvalidEnvSize = 26 => "invalid U-Boot env length",
+    InvalidEnvVar = 27 => "invalid U-Boot env variable",
+    InvalidUimageMagic = 28 => "invalid uImage magic",
+    InvalidUimageType = 29 => "invalid uImage type",
+    InvalidUimageComp = 30 => "invalid uImage compression",
+    InvalidFit = 31 => "invalid FIT image",
+    InvalidFitHash = 32 => "invalid FIT image hash",
+    InvalidGpt = 33 => "invalid GPT",
+    DiskRead = 34 => "error reading disk",
+    DiskWrite = 35 => "error writing disk",
+    InvalidFixRecord = 36 => "invalid fix-imghdr undo record",
+    InvalidSparseImage = 37 => "invalid Android sparse image",
+    UndefinedEnvVar = 38 => "undefined U-Boot env variable",
+    InvalidFlashLayout = 39 => "invalid flash layout",
+    InvalidResl = 40 => "invalid SPL image offset",
+    InvalidUimageLen = 41 => "invalid uImage data length",
+}
+
+#[cfg(feature = "capi")]
+const fn c_str(val: &'static str) -> &'static core::ffi::CStr {
+    match core::ffi::CStr::from_bytes_with_nul(val.as_bytes()) {
+        Ok(val) => val,
+        Err(_) => panic!("error descriptions are NUL-terminated without interior NUL"),
+    }
+}
+
+impl Error {
+    /// Gets the broad [ErrorCategory] of the [Error].
+    pub const fn category(&self) -> ErrorCategory {
+        match self {
+            Self::InvalidHeaderLen(_)
+            | Self::InvalidSlice(_)
+            | Self::InvalidSofs(_)
+            | Self::InvalidResl(_) => ErrorCategory::Header,
+            Self::InvalidSplLen(_) | Self::InvalidCrc(_) => ErrorCategory::Payload,
+            Self::InvalidConfigFile
+            | Self::InvalidConfigLine(_)
+            | Self::InvalidConfigValue(_)
+            | Self::InvalidFlashAlign(_)
+            | Self::NullPointer
+            | Self::UndefinedEnvVar
+            | Self::InvalidFlashLayout(_) => ErrorCategory::Config,
+            Self::InvalidSignAlg(_)
+            | Self::InvalidSigningKey
+            | Self::InvalidSignature
+            | Self::InvalidKeyHash => ErrorCategory::Signature,
+            Self::InvalidElf
+            | Self::InvalidElfAddr(_)
+            | Self::InvalidHexRecord(_)
+            | Self::InvalidEnvSize(_)
+            | Self::InvalidEnvVar(_)
+            | Self::InvalidUimageMagic(_)
+            | Self::InvalidUimageType(_)
+            | Self::InvalidUimageComp(_)
+            | Self::InvalidUimageLen(_)
+            | Self::InvalidFit
+            | Self::InvalidFitHash
+            | Self::InvalidGpt
+            | Self::InvalidFixRecord
+            | Self::InvalidSparseImage => ErrorCategory::Format,
+            Self::InvalidHeaderFile
+            | Self::InvalidSplFile
+            | Self::InvalidManifestFile
+            | Self::FlashErase(_)
+            | Self::FlashWrite(_)
+            | Self::FlashRead(_)
+            | Self::FlashVerify(_)
+            | Self::DiskRead(_)
+            | Self::DiskWrite(_) => ErrorCategory::Io,
+            Self::RequiresCliFeature => ErrorCategory::Unsupported,
+        }
+    }
+
+    /// Gets the structured [ErrorDetail] carried by the [Error].
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use spl_tool::{Error, SplField, UbootSplHeader};
+    ///
+    /// let header = UbootSplHeader::new().with_sofs(0x100);
+    /// let detail = header.validate().unwrap_err().detail();
+    ///
+    /// assert_eq!(detail.field, Some(SplField::Sofs));
+    /// assert_eq!(detail.actual, Some(0x100));
+    /// assert_eq!(detail.expected, Some(0x240));
+    /// ```
+    pub const fn detail(&self) -> ErrorDetail {
+        let detail = ErrorDetail::new();
+
+        match *self {
+            Self::InvalidHeaderLen((actual, expected))
+            | Self::InvalidSplLen((actual, expected))
+            | Self::InvalidUimageLen((actual, expected))
+            | Self::InvalidEnvSize((actual, expected)) => detail
+                .with_actual(actual as u64)
+                .with_expected(expected as u64),
+            Self::InvalidConfigLine(line)
+            | Self::InvalidConfigValue(line)
+            | Self::InvalidHexRecord(line) => detail.with_line(line),
+            #[cfg(feature = "secure-boot")]
+            Self::InvalidSignAlg(alg) => detail.with_field(SplField::Salg).with_actual(alg as u64),
+            #[cfg(not(feature = "secure-boot"))]
+            Self::InvalidSignAlg(alg) => detail.with_actual(alg as u64),
+            #[cfg(feature = "secure-boot")]
+            Self::InvalidSignature => detail.with_field(SplField::Ssig),
+            #[cfg(feature = "secure-boot")]
+            Self::InvalidKeyHash => detail.with_field(SplField::Khsh),
+            // also returned by the U-Boot env, uImage, FIT and GPT checks, so no SPL header field
+            Self::InvalidCrc((actual, expected)) => detail
+                .with_actual(actual as u64)
+                .with_expected(expected as u64),
+            Self::InvalidSofs(sofs) => detail
+                .with_field(SplField::Sofs)
+                .with_actual(sofs as u64)
+                .with_expected(DEF_SOFS as u64),
+            Self::InvalidResl(resl) => detail
+                .with_field(SplField::Resl)
+                .with_actual(resl as u64)
+                .with_range(SPL_HEADER_LEN as u64, MAX_SPL_LEN as u64),
+            Self::InvalidFlashAlign(val) => detail.with_actual(val as u64),
+            Self::FlashErase(offset)
+            | Self::FlashWrite(offset)
+            | Self::FlashRead(offset)
+            | Self::FlashVerify(offset)
+            | Self::InvalidFlashLayout(offset) => detail.with_offset(offset as u64),
+            Self::InvalidElfAddr(addr) => detail.with_actual(addr),
+            Self::InvalidEnvVar(idx) => detail.with_actual(idx as u64),
+            Self::InvalidUimageMagic(magic) => detail
+                .with_actual(magic as u64)
+                .with_expected(UIMAGE_MAGIC as u64),
+            Self::InvalidUimageType(val) | Self::InvalidUimageComp(val) => {
+                detail.with_actual(val as u64)
+            }
+            Self::DiskRead(offset) | Self::DiskWrite(offset) => detail.with_offset(offset),
+            _ => detail,
+        }
+    }
+}
+
+/// Represents the broad category of an [Error], e.g. to select a process exit code.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ErrorCategory {
+    /// Invalid SPL header.
+    Header,
+    /// Invalid SPL payload, e.g. a length or CRC32 mismatch.
+    Payload,
+    /// Invalid configuration or argument.
+    Config,
+    /// Invalid secure boot signature or key.
+    Signature,
+    /// Invalid input format, e.g. ELF, HEX, FIT or GPT.
+    Format,
+    /// Error accessing a file, flash or disk.
+    Io,
+    /// Functionality not available in this build.
+    Unsupported,
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Header => write!(f, "header"),
+            Self::Payload => write!(f, "payload"),
+            Self::Config => write!(f, "config"),
+            Self::Signature => write!(f, "signature"),
+            Self::Format => write!(f, "format"),
+            Self::Io => write!(f, "I/O"),
+            Self::Unsupported => write!(f, "unsupported"),
+        }
+    }
+}
+
+/// Represents the structured detail carried by an [Error].
+///
+/// Each item is `None` when the [Error] does not carry it.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ErrorDetail {
+    /// SPL header field the error refers to.
+    pub field: Option<SplField>,
+    /// Offset in the file, flash or disk.
+    pub offset: Option<u64>,
+    /// Line number in a text input.
+    pub line: Option<usize>,
+    /// Expected value.
+    pub expected: Option<u64>,
+    /// Lower bound of the valid range.
+    pub min: Option<u64>,
+    /// Upper bound of the valid range.
+    pub max: Option<u64>,
+    /// Actual value.
+    pub actual: Option<u64>,
+}
+
+impl ErrorDetail {
+    /// Creates a new, empty [ErrorDetail].
+    pub const fn new() -> Self {
+        Self {
+            field: None,
+            offset: None,
+            line: None,
+            expected: None,
+            min: None,
+            max: None,
+            actual: None,
+        }
+    }
+
+    /// Builder function that sets the SPL header field.
+    pub const fn with_field(mut self, val: SplField) -> Self {
+        self.field = Some(val);
+        self
+    }
+
+    /// Builder function that sets the offset.
+    pub const fn with_offset(mut self, val: u64) -> Self {
+        self.offset = Some(val);
+        self
+    }
+
+    /// Builder function that sets the line number.
+    pub const fn with_line(mut self, val: usize) -> Self {
+        self.line = Some(val);
+        self
+    }
+
+    /// Builder function that sets the expected value.
+    pub const fn with_expected(mut self, val: u64) -> Self {
+        self.expected = Some(val);
+        self
+    }
+
+    /// Builder function that sets the bounds of the valid range.
+    pub const fn with_range(mut self, min: u64, max: u64) -> Self {
+        self.min = Some(min);
+        self.max = Some(max);
+        self
+    }
+
+    /// Builder function that sets the actual value.
+    pub const fn with_actual(mut self, val: u64) -> Self {
+        self.actual = Some(val);
+        self
+    }
 }
 
 impl From<core::array::TryFromSliceError> for Error {
@@ -28,15 +387,102 @@ impl fmt::Display for Error {
             Self::InvalidHeaderLen((inv_len, exp_len)) => {
                 write!(f, "invalid header len: {inv_len}, expected: {exp_len}")
             }
-            Self::InvalidSplLen((inv_len, max_len)) => {
-                write!(f, "invalid SPL len: {inv_len}, max: {max_len}")
+            Self::InvalidSplLen((inv_len, exp_len)) => {
+                write!(f, "invalid SPL len: {inv_len}, expected: {exp_len}")
             }
             Self::InvalidSlice(err) => write!(f, "invalid slice to array conversion: {err}"),
             Self::InvalidHeaderFile => {
                 write!(f, "invalid SPL header file, ensure the path is valid")
             }
             Self::InvalidSplFile => write!(f, "invalid SPL file, ensure the path is valid"),
+            Self::InvalidConfigFile => {
+                write!(f, "invalid SBL config file, ensure the path is valid")
+            }
+            Self::InvalidConfigLine(line) => {
+                write!(f, "invalid SBL config line: {line}, expected: KEY=VALUE")
+            }
+            Self::InvalidConfigValue(line) => write!(f, "invalid SBL config value on line: {line}"),
+            Self::InvalidManifestFile => {
+                write!(f, "invalid manifest file, ensure the path is valid")
+            }
+            Self::InvalidSignAlg(alg) => write!(f, "invalid signature algorithm: {alg}"),
+            Self::InvalidSigningKey => {
+                write!(f, "invalid signing key, expected ECDSA P-256 or RSA-2048")
+            }
+            Self::InvalidSignature => write!(f, "invalid SPL image signature"),
+            Self::InvalidKeyHash => write!(f, "invalid SPL public key hash"),
+            Self::InvalidCrc((inv_crc, exp_crc)) => {
+                write!(f, "invalid CRC: {inv_crc:#010x}, expected: {exp_crc:#010x}")
+            }
+            Self::NullPointer => write!(f, "invalid null pointer argument"),
             Self::RequiresCliFeature => write!(f, "the CLI application requires the `cli` feature"),
+            Self::InvalidFlashAlign(val) => write!(f, "invalid flash alignment: {val:#x}"),
+            Self::FlashErase(offset) => write!(f, "error erasing flash at offset: {offset:#x}"),
+            Self::FlashWrite(offset) => write!(f, "error writing flash at offset: {offset:#x}"),
+            Self::FlashRead(offset) => write!(f, "error reading flash at offset: {offset:#x}"),
+            Self::FlashVerify(offset) => {
+                write!(f, "flash verification failed at offset: {offset:#x}")
+            }
+            Self::InvalidSofs(sofs) => {
+                write!(
+                    f,
+                    "invalid SPL header offset: {sofs:#x}, expected: {DEF_SOFS:#x}"
+                )
+            }
+            Self::InvalidResl(resl) => {
+                write!(
+                    f,
+                    "invalid SPL image offset (resl): {resl:#x}, expected: {SPL_HEADER_LEN:#x} to {MAX_SPL_LEN:#x}"
+                )
+            }
+            Self::InvalidElf => write!(f, "invalid ELF file, expected a little-endian ELF32/ELF64"),
+            Self::InvalidElfAddr(addr) => {
+                write!(
+                    f,
+                    "invalid ELF address: {addr:#x}, outside of the JH7110 SRAM"
+                )
+            }
+            Self::InvalidHexRecord(line) => {
+                write!(f, "invalid Intel HEX/S-record on line: {line}")
+            }
+            Self::InvalidEnvSize((inv_len, size)) => {
+                write!(f, "invalid U-Boot env len: {inv_len}, region size: {size}")
+            }
+            Self::InvalidEnvVar(idx) => write!(f, "invalid U-Boot env variable: {idx}"),
+            Self::UndefinedEnvVar => write!(f, "undefined U-Boot env variable"),
+            Self::InvalidFlashLayout(offset) => {
+                write!(f, "invalid flash layout at offset: {offset:#x}")
+            }
+            Self::InvalidUimageMagic(magic) => {
+                write!(
+                    f,
+                    "invalid uImage magic: {magic:#010x}, expected: {UIMAGE_MAGIC:#010x}"
+                )
+            }
+            Self::InvalidUimageType(ty) => write!(f, "invalid uImage type: {ty}"),
+            Self::InvalidUimageComp(comp) => write!(f, "invalid uImage compression: {comp}"),
+            Self::InvalidUimageLen((inv_len, exp_len)) => {
+                write!(f, "invalid uImage data len: {inv_len}, expected: {exp_len}")
+            }
+            Self::InvalidFit => write!(f, "invalid FIT image"),
+            Self::InvalidFitHash => write!(f, "invalid FIT image hash"),
+            Self::InvalidGpt => write!(f, "invalid GPT"),
+            Self::DiskRead(offset) => write!(f, "error reading disk at offset: {offset:#x}"),
+            Self::DiskWrite(offset) => write!(f, "error writing disk at offset: {offset:#x}"),
+            Self::InvalidFixRecord => write!(f, "invalid fix-imghdr undo record"),
+            Self::InvalidSparseImage => write!(f, "invalid Android sparse image"),
+        }
+    }
+}
+
+// I/O errors are chained by `PathError`, the only error value carried by a variant is the slice
+// conversion error
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidSlice(err) => Some(err),
+            _ => None,
         }
     }
 }
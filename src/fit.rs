@@ -0,0 +1,616 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use super::{crc32_ieee, sha256, Error, Result, SHA256_LEN};
+
+/// Magic number of a flattened device tree, and FIT image.
+pub const FDT_MAGIC: u32 = 0xd00d_feed;
+/// Length of the flattened device tree header.
+pub const FDT_HEADER_LEN: usize = 40;
+
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+// maximum node depth of a FIT image walked by [FitImage::verify]
+const FIT_MAX_DEPTH: usize = 16;
+
+/// Represents the result of verifying a FIT image.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FitSummary {
+    /// Number of component images in the `/images` node.
+    pub images: usize,
+    /// Number of `crc32` and `sha256` hashes that were checked.
+    pub hashes: usize,
+    /// Number of hashes with an unsupported algorithm that were skipped.
+    pub skipped: usize,
+    /// Length of the FIT image, including external data.
+    pub len: usize,
+}
+
+/// Represents a Flattened Image Tree (FIT) image, e.g. `u-boot.itb`.
+#[derive(Clone, Copy, Debug)]
+pub struct FitImage<'a> {
+    data: &'a [u8],
+    totalsize: usize,
+    off_struct: usize,
+    size_struct: usize,
+    off_strings: usize,
+    size_strings: usize,
+}
+
+impl<'a> FitImage<'a> {
+    /// Parses the FIT (flattened device tree) header.
+    ///
+    /// `data` may extend past the end of the image, e.g. a flash dump starting at the FIT offset.
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        let word = |idx: usize| read_be32(data, idx).map(|w| w as usize);
+
+        if read_be32(data, 0)? != FDT_MAGIC {
+            return Err(Error::InvalidFit);
+        }
+
+        let totalsize = word(4)?;
+        let off_struct = word(8)?;
+        let off_strings = word(12)?;
+        let version = word(20)?;
+        let size_strings = word(32)?;
+        let size_struct = word(36)?;
+
+        let in_bounds =
+            |off: usize, len: usize| off.checked_add(len).is_some_and(|end| end <= totalsize);
+
+        if version < 17
+            || totalsize < FDT_HEADER_LEN
+            || totalsize > data.len()
+            || !in_bounds(off_struct, size_struct)
+            || !in_bounds(off_strings, size_strings)
+        {
+            return Err(Error::InvalidFit);
+        }
+
+        Ok(Self {
+            data,
+            totalsize,
+            off_struct,
+            size_struct,
+            off_strings,
+            size_strings,
+        })
+    }
+
+    /// Gets the length of the device tree blob, excluding external data.
+    pub const fn totalsize(&self) -> usize {
+        self.totalsize
+    }
+
+    /// Walks the `/images` node, and verifies the `crc32` and `sha256` hash nodes of each image.
+    ///
+    /// Image data may be embedded (`data`), or external (`data-offset`/`data-position` with
+    /// `data-size`). Returns [Error::InvalidCrc] for a `crc32` mismatch, and
+    /// [Error::InvalidFitHash] for a `sha256` mismatch.
+    pub fn verify(&self) -> Result<FitSummary> {
+        let mut res = FitSummary {
+            len: self.totalsize,
+            ..Default::default()
+        };
+
+        let structs = &self.data[self.off_struct..self.off_struct + self.size_struct];
+        let ext_base = self.totalsize.next_multiple_of(4);
+
+        let mut names: [&[u8]; FIT_MAX_DEPTH] = [&[]; FIT_MAX_DEPTH];
+        let mut depth = 0usize;
+        let mut image = ImageData::default();
+        let mut hash = HashNode::default();
+        let mut pos = 0usize;
+
+        loop {
+            let token = read_be32(structs, pos)?;
+            pos += 4;
+
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = read_str(structs, pos)?;
+                    pos += (name.len() + 1).next_multiple_of(4);
+
+                    if depth >= FIT_MAX_DEPTH {
+                        return Err(Error::InvalidFit);
+                    }
+                    names[depth] = name;
+                    depth += 1;
+
+                    if self.in_image(&names, depth) {
+                        res.images += 1;
+                        image = ImageData::default();
+                    } else if self.in_hash(&names, depth) {
+                        hash = HashNode::default();
+                    }
+                }
+                FDT_END_NODE => {
+                    if depth == 0 {
+                        return Err(Error::InvalidFit);
+                    }
+
+                    if self.in_hash(&names, depth) {
+                        let data = image.data(self.data, ext_base)?;
+                        res.len = res.len.max(image.end(ext_base));
+
+                        match hash.algo {
+                            b"crc32" => {
+                                let expected = read_be32(hash.value, 0)?;
+                                let actual = crc32_ieee(data);
+                                if actual != expected || hash.value.len() != 4 {
+                                    return Err(Error::InvalidCrc((actual, expected)));
+                                }
+                                res.hashes += 1;
+                            }
+                            b"sha256" => {
+                                if hash.value.len() != SHA256_LEN
+                                    || sha256(data).as_ref() != hash.value
+                                {
+                                    return Err(Error::InvalidFitHash);
+                                }
+                                res.hashes += 1;
+                            }
+                            _ => res.skipped += 1,
+                        }
+                    } else if self.in_image(&names, depth) {
+                        res.len = res.len.max(image.end(ext_base));
+                    }
+
+                    depth -= 1;
+                }
+                FDT_PROP => {
+                    let len = read_be32(structs, pos)? as usize;
+                    let name = read_str(self.strings()?, read_be32(structs, pos + 4)? as usize)?;
+                    let value = (pos + 8)
+                        .checked_add(len)
+                        .and_then(|end| structs.get(pos + 8..end))
+                        .ok_or(Error::InvalidFit)?;
+                    pos += 8 + len.next_multiple_of(4);
+
+                    if self.in_image(&names, depth) {
+                        match name {
+                            b"data" => image.embedded = Some(value),
+                            b"data-offset" => image.offset = Some(read_be32(value, 0)? as usize),
+                            b"data-position" => {
+                                image.position = Some(read_be32(value, 0)? as usize)
+                            }
+                            b"data-size" => image.size = Some(read_be32(value, 0)? as usize),
+                            _ => (),
+                        }
+                    } else if self.in_hash(&names, depth) {
+                        match name {
+                            b"algo" => hash.algo = value.strip_suffix(&[0]).unwrap_or(value),
+                            b"value" => hash.value = value,
+                            _ => (),
+                        }
+                    }
+                }
+                FDT_NOP => (),
+                FDT_END => break,
+                _ => return Err(Error::InvalidFit),
+            }
+        }
+
+        if depth != 0 {
+            Err(Error::InvalidFit)
+        } else {
+            Ok(res)
+        }
+    }
+
+    fn strings(&self) -> Result<&'a [u8]> {
+        self.data
+            .get(self.off_strings..self.off_strings + self.size_strings)
+            .ok_or(Error::InvalidFit)
+    }
+
+    // `/images/<image>`, with the root node at depth 1
+    fn in_image(&self, names: &[&[u8]], depth: usize) -> bool {
+        depth == 3 && names[1] == b"images"
+    }
+
+    // `/images/<image>/hash*`
+    fn in_hash(&self, names: &[&[u8]], depth: usize) -> bool {
+        depth == 4 && names[1] == b"images" && names[3].starts_with(b"hash")
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct ImageData<'a> {
+    embedded: Option<&'a [u8]>,
+    offset: Option<usize>,
+    position: Option<usize>,
+    size: Option<usize>,
+}
+
+impl<'a> ImageData<'a> {
+    fn data(&self, fit: &'a [u8], ext_base: usize) -> Result<&'a [u8]> {
+        if let Some(data) = self.embedded {
+            return Ok(data);
+        }
+
+        let start = self.start(ext_base).ok_or(Error::InvalidFit)?;
+        let size = self.size.ok_or(Error::InvalidFit)?;
+
+        fit.get(start..start.saturating_add(size))
+            .ok_or(Error::InvalidFit)
+    }
+
+    fn start(&self, ext_base: usize) -> Option<usize> {
+        self.position
+            .or_else(|| self.offset.map(|off| ext_base.saturating_add(off)))
+    }
+
+    fn end(&self, ext_base: usize) -> usize {
+        match (self.embedded, self.start(ext_base), self.size) {
+            (None, Some(start), Some(size)) => start.saturating_add(size),
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct HashNode<'a> {
+    algo: &'a [u8],
+    value: &'a [u8],
+}
+
+fn read_be32(data: &[u8], idx: usize) -> Result<u32> {
+    data.get(idx..idx.saturating_add(4))
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(Error::InvalidFit)
+}
+
+fn read_str(data: &[u8], idx: usize) -> Result<&[u8]> {
+    let rest = data.get(idx..).ok_or(Error::InvalidFit)?;
+    let len = rest.iter().position(|&b| b == 0).ok_or(Error::InvalidFit)?;
+    Ok(&rest[..len])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+
+    const UBOOT: &[u8] = b"u-boot proper and device tree";
+    const OPENSBI: &[u8] = b"opensbi firmware";
+
+    fn pad(buf: &mut Vec<u8>) {
+        buf.resize(buf.len().next_multiple_of(4), 0);
+    }
+
+    // minimal FIT builder, laid out like `mkimage -f` output
+    #[derive(Default)]
+    struct FitBuilder {
+        structs: Vec<u8>,
+        strings: Vec<u8>,
+        external: Vec<u8>,
+    }
+
+    impl FitBuilder {
+        fn token(&mut self, token: u32) -> &mut Self {
+            self.structs.extend_from_slice(token.to_be_bytes().as_ref());
+            self
+        }
+
+        fn begin(&mut self, name: &str) -> &mut Self {
+            self.token(FDT_BEGIN_NODE);
+            self.structs.extend_from_slice(name.as_bytes());
+            self.structs.push(0);
+            pad(&mut self.structs);
+            self
+        }
+
+        fn end(&mut self) -> &mut Self {
+            self.token(FDT_END_NODE)
+        }
+
+        fn prop(&mut self, name: &str, value: &[u8]) -> &mut Self {
+            let name_off = self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+
+            self.token(FDT_PROP);
+            self.structs
+                .extend_from_slice((value.len() as u32).to_be_bytes().as_ref());
+            self.structs
+                .extend_from_slice(name_off.to_be_bytes().as_ref());
+            self.structs.extend_from_slice(value);
+            pad(&mut self.structs);
+            self
+        }
+
+        fn image(&mut self, name: &str, data: &[u8], external: bool) -> &mut Self {
+            self.begin(name);
+            if external {
+                self.prop(
+                    "data-offset",
+                    (self.external.len() as u32).to_be_bytes().as_ref(),
+                )
+                .prop("data-size", (data.len() as u32).to_be_bytes().as_ref());
+                self.external.extend_from_slice(data);
+                pad(&mut self.external);
+            } else {
+                self.prop("data", data);
+            }
+            self.begin("hash-1")
+                .prop("algo", b"crc32\0")
+                .prop("value", crc32_ieee(data).to_be_bytes().as_ref())
+                .end()
+                .begin("hash-2")
+                .prop("algo", b"sha256\0")
+                .prop("value", sha256(data).as_ref())
+                .end()
+                .begin("hash-3")
+                .prop("algo", b"md5\0")
+                .prop("value", [0; 16].as_ref())
+                .end()
+                .end()
+        }
+
+        fn fit(external: bool) -> Self {
+            let mut res = Self::default();
+            res.begin("")
+                .prop("description", b"test\0")
+                .begin("images")
+                .image("uboot", UBOOT, external)
+                .image("opensbi", OPENSBI, external)
+                .end()
+                .begin("configurations")
+                .end()
+                .end()
+                .token(FDT_END);
+            res
+        }
+
+        fn build(&self) -> Vec<u8> {
+            let off_struct = FDT_HEADER_LEN + 16;
+            let off_strings = off_struct + self.structs.len();
+            let totalsize = off_strings + self.strings.len();
+
+            let mut res = Vec::new();
+            for word in [
+                FDT_MAGIC,
+                totalsize as u32,
+                off_struct as u32,
+                off_strings as u32,
+                FDT_HEADER_LEN as u32,
+                17,
+                16,
+                0,
+                self.strings.len() as u32,
+                self.structs.len() as u32,
+            ] {
+                res.extend_from_slice(word.to_be_bytes().as_ref());
+            }
+            // empty memory reservation map
+            res.extend_from_slice([0; 16].as_ref());
+            res.extend_from_slice(self.structs.as_ref());
+            res.extend_from_slice(self.strings.as_ref());
+
+            if !self.external.is_empty() {
+                pad(&mut res);
+                res.extend_from_slice(self.external.as_ref());
+            }
+
+            res
+        }
+    }
+
+    fn find(haystack: &[u8], needle: &[u8]) -> usize {
+        haystack
+            .windows(needle.len())
+            .position(|w| w == needle)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_verify_embedded() {
+        let img = FitBuilder::fit(false).build();
+        let fit = FitImage::parse(img.as_ref()).unwrap();
+
+        assert_eq!(fit.totalsize(), img.len());
+        assert_eq!(
+            fit.verify().unwrap(),
+            FitSummary {
+                images: 2,
+                hashes: 4,
+                skipped: 2,
+                len: img.len(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_verify_external() {
+        let mut img = FitBuilder::fit(true).build();
+        // trailing flash contents after the image
+        let len = img.len();
+        img.extend_from_slice([0xff; 64].as_ref());
+
+        let fit = FitImage::parse(img.as_ref()).unwrap();
+        let summary = fit.verify().unwrap();
+
+        assert!(fit.totalsize() < len);
+        assert_eq!(summary.hashes, 4);
+        // the external data of the last image ends at its (unpadded) size
+        assert_eq!(
+            summary.len,
+            len - (OPENSBI.len().next_multiple_of(4) - OPENSBI.len())
+        );
+    }
+
+    #[test]
+    fn test_verify_bad_hash() {
+        let img = FitBuilder::fit(true).build();
+
+        let mut bad = img.clone();
+        let pos = img.len() - OPENSBI.len().next_multiple_of(4);
+        bad[pos] ^= 0xff;
+        // the `crc32` hash is checked first
+        assert!(matches!(
+            FitImage::parse(bad.as_ref()).unwrap().verify(),
+            Err(Error::InvalidCrc(_))
+        ));
+
+        let mut bad = img.clone();
+        let pos = find(img.as_ref(), sha256(UBOOT).as_ref());
+        bad[pos] ^= 0xff;
+        assert!(matches!(
+            FitImage::parse(bad.as_ref()).unwrap().verify(),
+            Err(Error::InvalidFitHash)
+        ));
+
+        // external data past the end of the buffer
+        let short = &img[..img.len() - 4];
+        assert!(matches!(
+            FitImage::parse(short).unwrap().verify(),
+            Err(Error::InvalidFit)
+        ));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let img = FitBuilder::fit(false).build();
+
+        let set = |idx: usize, val: u32| {
+            let mut res = img.clone();
+            res[idx..idx + 4].copy_from_slice(val.to_be_bytes().as_ref());
+            res
+        };
+
+        for bad in [
+            set(0, 0xedfe_0dd0),
+            // totalsize past the end of the data, and shorter than the header
+            set(4, img.len() as u32 + 1),
+            set(4, FDT_HEADER_LEN as u32 - 1),
+            // structure block past `totalsize`, and with an overflowing end
+            set(36, img.len() as u32),
+            set(8, u32::MAX),
+            // strings block past `totalsize`
+            set(32, img.len() as u32),
+            // version too old
+            set(20, 16),
+        ] {
+            assert!(matches!(
+                FitImage::parse(bad.as_ref()),
+                Err(Error::InvalidFit)
+            ));
+        }
+
+        for len in 0..FDT_HEADER_LEN {
+            assert!(matches!(
+                FitImage::parse(&img[..len]),
+                Err(Error::InvalidFit)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_verify_invalid_structure() {
+        let verify = |builder: &FitBuilder| {
+            let img = builder.build();
+            FitImage::parse(img.as_ref()).and_then(|fit| fit.verify())
+        };
+
+        // missing `FDT_END`
+        let mut builder = FitBuilder::default();
+        builder.begin("").end();
+        assert!(matches!(verify(&builder), Err(Error::InvalidFit)));
+
+        // unbalanced `FDT_END_NODE`
+        let mut builder = FitBuilder::default();
+        builder.begin("").end().end().token(FDT_END);
+        assert!(matches!(verify(&builder), Err(Error::InvalidFit)));
+
+        // unterminated node
+        let mut builder = FitBuilder::default();
+        builder.begin("").token(FDT_END);
+        assert!(matches!(verify(&builder), Err(Error::InvalidFit)));
+
+        // unknown token
+        let mut builder = FitBuilder::default();
+        builder.begin("").token(0x10).end().token(FDT_END);
+        assert!(matches!(verify(&builder), Err(Error::InvalidFit)));
+
+        // nodes nested deeper than [FIT_MAX_DEPTH]
+        let mut builder = FitBuilder::default();
+        for _ in 0..=FIT_MAX_DEPTH {
+            builder.begin("n");
+        }
+        assert!(matches!(verify(&builder), Err(Error::InvalidFit)));
+
+        // `FDT_NOP` is skipped
+        let mut builder = FitBuilder::default();
+        builder.begin("").token(FDT_NOP).end().token(FDT_END);
+        assert!(verify(&builder).is_ok());
+    }
+
+    #[test]
+    fn test_verify_invalid_props() {
+        let img = FitBuilder::fit(false).build();
+        let hdr_len = |img: &[u8]| read_be32(img, 8).unwrap() as usize;
+        let prop =
+            hdr_len(img.as_ref()) + find(&img[hdr_len(img.as_ref())..], [0, 0, 0, 3].as_ref());
+
+        // property length past the end of the structure block
+        for len in [0x1000, u32::MAX] {
+            let mut bad = img.clone();
+            bad[prop + 4..prop + 8].copy_from_slice(len.to_be_bytes().as_ref());
+            assert!(matches!(
+                FitImage::parse(bad.as_ref()).unwrap().verify(),
+                Err(Error::InvalidFit)
+            ));
+        }
+
+        // property name offset past the end of the strings block
+        let mut bad = img.clone();
+        bad[prop + 8..prop + 12].copy_from_slice(u32::MAX.to_be_bytes().as_ref());
+        assert!(matches!(
+            FitImage::parse(bad.as_ref()).unwrap().verify(),
+            Err(Error::InvalidFit)
+        ));
+
+        // external image without `data-size`
+        let mut builder = FitBuilder::default();
+        builder
+            .begin("")
+            .begin("images")
+            .begin("uboot")
+            .prop("data-offset", [0; 4].as_ref())
+            .begin("hash-1")
+            .prop("algo", b"crc32\0")
+            .prop("value", [0; 4].as_ref())
+            .end()
+            .end()
+            .end()
+            .end()
+            .token(FDT_END);
+        let img = builder.build();
+        assert!(matches!(
+            FitImage::parse(img.as_ref()).unwrap().verify(),
+            Err(Error::InvalidFit)
+        ));
+    }
+
+    #[test]
+    fn test_no_panic() {
+        let img = FitBuilder::fit(true).build();
+
+        // truncated and corrupted images must be rejected, never panic
+        for len in 0..img.len() {
+            let _ = FitImage::parse(&img[..len]).and_then(|fit| fit.verify());
+        }
+        for idx in 0..img.len() {
+            for val in [0x00, 0xff, 0x80, 0x03] {
+                let mut bad = img.clone();
+                bad[idx] = val;
+                let _ = FitImage::parse(bad.as_ref()).and_then(|fit| fit.verify());
+            }
+        }
+    }
+}
@@ -0,0 +1,419 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use core::fmt;
+
+use super::{Error, Result, DEF_BACKUP, DEF_ENV_OFFSET, DEF_ENV_SIZE, DEF_FIT_OFFSET};
+
+/// Default size of the VisionFive2 SPI flash (16 MiB).
+pub const DEF_FLASH_SIZE: u32 = 0x100_0000;
+/// Length of an erase sector of the VisionFive2 SPI flash.
+pub const FLASH_SECTOR_LEN: usize = 0x1000;
+/// Value of an erased flash byte.
+pub const FLASH_ERASED: u8 = 0xff;
+/// Default maximum length of an SPL copy region (the VisionFive2 `spl` MTD partition).
+pub const DEF_SPL_REGION_LEN: u32 = 0x8_0000;
+
+/// Represents the location of the boot components in the SPI flash.
+///
+/// Defaults to the VisionFive2 layout: primary SPL at `0`, backup SPL at [DEF_BACKUP], U-Boot env
+/// at [DEF_ENV_OFFSET], and the U-Boot FIT image at [DEF_FIT_OFFSET].
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FlashLayout {
+    bofs: u32,
+    env_offset: u32,
+    env_size: u32,
+    fit_offset: u32,
+    flash_size: u32,
+    spl_region_len: u32,
+}
+
+impl FlashLayout {
+    /// Creates a new [FlashLayout] with the VisionFive2 defaults.
+    pub const fn new() -> Self {
+        Self {
+            bofs: DEF_BACKUP,
+            env_offset: DEF_ENV_OFFSET,
+            env_size: DEF_ENV_SIZE as u32,
+            fit_offset: DEF_FIT_OFFSET,
+            flash_size: DEF_FLASH_SIZE,
+            spl_region_len: DEF_SPL_REGION_LEN,
+        }
+    }
+
+    /// Gets the offset of the backup SPL copy.
+    pub const fn bofs(&self) -> u32 {
+        self.bofs
+    }
+
+    /// Sets the offset of the backup SPL copy.
+    pub const fn set_bofs(&mut self, val: u32) {
+        self.bofs = val;
+    }
+
+    /// Builder function that sets the offset of the backup SPL copy.
+    pub const fn with_bofs(mut self, val: u32) -> Self {
+        self.set_bofs(val);
+        self
+    }
+
+    /// Gets the offset of the U-Boot environment region.
+    pub const fn env_offset(&self) -> u32 {
+        self.env_offset
+    }
+
+    /// Sets the offset of the U-Boot environment region.
+    pub const fn set_env_offset(&mut self, val: u32) {
+        self.env_offset = val;
+    }
+
+    /// Builder function that sets the offset of the U-Boot environment region.
+    pub const fn with_env_offset(mut self, val: u32) -> Self {
+        self.set_env_offset(val);
+        self
+    }
+
+    /// Gets the size of the U-Boot environment region.
+    pub const fn env_size(&self) -> u32 {
+        self.env_size
+    }
+
+    /// Sets the size of the U-Boot environment region.
+    pub const fn set_env_size(&mut self, val: u32) {
+        self.env_size = val;
+    }
+
+    /// Builder function that sets the size of the U-Boot environment region.
+    pub const fn with_env_size(mut self, val: u32) -> Self {
+        self.set_env_size(val);
+        self
+    }
+
+    /// Gets the offset of the U-Boot FIT image.
+    pub const fn fit_offset(&self) -> u32 {
+        self.fit_offset
+    }
+
+    /// Sets the offset of the U-Boot FIT image.
+    pub const fn set_fit_offset(&mut self, val: u32) {
+        self.fit_offset = val;
+    }
+
+    /// Builder function that sets the offset of the U-Boot FIT image.
+    pub const fn with_fit_offset(mut self, val: u32) -> Self {
+        self.set_fit_offset(val);
+        self
+    }
+
+    /// Gets the size of the flash.
+    pub const fn flash_size(&self) -> u32 {
+        self.flash_size
+    }
+
+    /// Sets the size of the flash.
+    pub const fn set_flash_size(&mut self, val: u32) {
+        self.flash_size = val;
+    }
+
+    /// Builder function that sets the size of the flash.
+    pub const fn with_flash_size(mut self, val: u32) -> Self {
+        self.set_flash_size(val);
+        self
+    }
+
+    /// Gets the maximum length of an SPL copy region.
+    pub const fn spl_region_len(&self) -> u32 {
+        self.spl_region_len
+    }
+
+    /// Sets the maximum length of an SPL copy region.
+    pub const fn set_spl_region_len(&mut self, val: u32) {
+        self.spl_region_len = val;
+    }
+
+    /// Builder function that sets the maximum length of an SPL copy region.
+    pub const fn with_spl_region_len(mut self, val: u32) -> Self {
+        self.set_spl_region_len(val);
+        self
+    }
+
+    /// Gets the flash region reserved for an SPL copy.
+    ///
+    /// The region starts at `0` for [FlashRegionKind::PrimarySpl], or at `bofs` for
+    /// [FlashRegionKind::BackupSpl], and ends at the first of: the maximum SPL region length, the
+    /// start of the next component, or the end of the flash.
+    ///
+    /// Returns `None` for non-SPL region kinds.
+    pub const fn spl_region(&self, kind: FlashRegionKind) -> Option<FlashRegion> {
+        let start = match kind {
+            FlashRegionKind::PrimarySpl => 0,
+            FlashRegionKind::BackupSpl => self.bofs,
+            _ => return None,
+        };
+
+        let end = self.region_end(start, start.saturating_add(self.spl_region_len));
+
+        Some(FlashRegion::new(start, end, kind))
+    }
+
+    /// Gets the flash region reserved for the U-Boot FIT image.
+    ///
+    /// The region starts at `fit_offset`, and ends at the first of: the start of the next
+    /// component, or the end of the flash.
+    pub const fn fit_region(&self) -> FlashRegion {
+        let end = self.region_end(self.fit_offset, self.flash_size);
+
+        FlashRegion::new(self.fit_offset, end, FlashRegionKind::Fit)
+    }
+
+    /// Validates that the components fit in the flash, and do not overlap each other.
+    ///
+    /// Every component must start at a distinct offset inside the flash, and the env region must
+    /// neither contain the start of another component, nor extend past the end of the flash.
+    ///
+    /// Returns [Error::InvalidFlashLayout] with the first conflicting offset.
+    pub const fn validate(&self) -> Result<()> {
+        let starts = [0, self.bofs, self.env_offset, self.fit_offset];
+        let env_end = self.env_offset.saturating_add(self.env_size);
+
+        if env_end > self.flash_size {
+            return Err(Error::InvalidFlashLayout(env_end));
+        }
+
+        let mut idx = 0;
+        while idx < starts.len() {
+            let start = starts[idx];
+            if start >= self.flash_size || (start > self.env_offset && start < env_end) {
+                return Err(Error::InvalidFlashLayout(start));
+            }
+
+            let mut other = idx + 1;
+            while other < starts.len() {
+                if starts[other] == start {
+                    return Err(Error::InvalidFlashLayout(start));
+                }
+                other += 1;
+            }
+
+            idx += 1;
+        }
+
+        Ok(())
+    }
+
+    // end of the region starting at `start`: the first of `limit`, the start of the next
+    // component, or the end of the flash
+    const fn region_end(&self, start: u32, limit: u32) -> u32 {
+        let mut end = limit;
+        if self.flash_size < end {
+            end = self.flash_size;
+        }
+
+        let bounds = [self.bofs, self.env_offset, self.fit_offset];
+        let mut idx = 0;
+        while idx < bounds.len() {
+            if bounds[idx] > start && bounds[idx] < end {
+                end = bounds[idx];
+            }
+            idx += 1;
+        }
+
+        end
+    }
+}
+
+impl Default for FlashLayout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Represents the contents of a region of the flash.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FlashRegionKind {
+    PrimarySpl,
+    BackupSpl,
+    Env,
+    Fit,
+    /// Data not belonging to a known component.
+    Data,
+    /// Erased flash.
+    Free,
+}
+
+impl fmt::Display for FlashRegionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PrimarySpl => write!(f, "primary SPL"),
+            Self::BackupSpl => write!(f, "backup SPL"),
+            Self::Env => write!(f, "U-Boot env"),
+            Self::Fit => write!(f, "U-Boot FIT"),
+            Self::Data => write!(f, "unknown data"),
+            Self::Free => write!(f, "free"),
+        }
+    }
+}
+
+/// Represents a region of the flash.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FlashRegion {
+    /// Start offset of the region.
+    pub start: u32,
+    /// End offset (exclusive) of the region.
+    pub end: u32,
+    /// Contents of the region.
+    pub kind: FlashRegionKind,
+}
+
+impl FlashRegion {
+    /// Creates a new [FlashRegion].
+    pub const fn new(start: u32, end: u32, kind: FlashRegionKind) -> Self {
+        Self { start, end, kind }
+    }
+
+    /// Gets the length of the region.
+    pub const fn len(&self) -> u32 {
+        self.end.saturating_sub(self.start)
+    }
+
+    /// Gets whether the region is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.end <= self.start
+    }
+
+    /// Gets whether the region overlaps another region.
+    pub const fn overlaps(&self, other: &Self) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
+/// Gets whether the data is erased flash (all [FLASH_ERASED]).
+pub fn is_erased(data: &[u8]) -> bool {
+    data.iter().all(|&b| b == FLASH_ERASED)
+}
+
+/// Replaces the contents of a flash region with `data`, and pads the rest of the region with
+/// [FLASH_ERASED].
+///
+/// Bytes outside of `region` are left untouched. Returns [Error::InvalidSplLen] when `data` does
+/// not fit in the region, and [Error::FlashWrite] when the region extends past the end of `flash`.
+pub fn flash_update_region(flash: &mut [u8], region: &FlashRegion, data: &[u8]) -> Result<()> {
+    let len = region.len() as usize;
+    if data.len() > len {
+        return Err(Error::InvalidSplLen((data.len(), len)));
+    }
+
+    let start = region.start as usize;
+    let dst = flash
+        .get_mut(start..start + len)
+        .ok_or(Error::FlashWrite(region.end))?;
+
+    dst[..data.len()].copy_from_slice(data);
+    dst[data.len()..].fill(FLASH_ERASED);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_regions() {
+        let layout = FlashLayout::new();
+
+        assert!(layout.validate().is_ok());
+        assert_eq!(
+            layout.spl_region(FlashRegionKind::PrimarySpl),
+            Some(FlashRegion::new(0, 0x8_0000, FlashRegionKind::PrimarySpl))
+        );
+        assert_eq!(
+            layout.spl_region(FlashRegionKind::BackupSpl),
+            Some(FlashRegion::new(
+                0x20_0000,
+                0x28_0000,
+                FlashRegionKind::BackupSpl
+            ))
+        );
+        assert_eq!(layout.spl_region(FlashRegionKind::Env), None);
+        // the FIT image ends at the backup SPL copy
+        assert_eq!(
+            layout.fit_region(),
+            FlashRegion::new(0x10_0000, 0x20_0000, FlashRegionKind::Fit)
+        );
+    }
+
+    #[test]
+    fn test_clipped_regions() {
+        let layout = FlashLayout::new()
+            .with_bofs(0x4_0000)
+            .with_fit_offset(0x30_0000)
+            .with_flash_size(0x38_0000);
+
+        assert!(layout.validate().is_ok());
+        assert_eq!(
+            layout
+                .spl_region(FlashRegionKind::PrimarySpl)
+                .map(|r| r.end),
+            Some(0x4_0000)
+        );
+        assert_eq!(
+            layout.spl_region(FlashRegionKind::BackupSpl).map(|r| r.end),
+            Some(0xc_0000)
+        );
+        assert_eq!(layout.fit_region().end, 0x38_0000);
+    }
+
+    #[test]
+    fn test_validate_invalid() {
+        let layout = FlashLayout::new();
+
+        for (layout, offset) in [
+            // FIT and backup SPL at the same offset
+            (layout.with_fit_offset(DEF_BACKUP), DEF_BACKUP),
+            // FIT inside the env region
+            (
+                layout.with_fit_offset(DEF_ENV_OFFSET + 0x8000),
+                DEF_ENV_OFFSET + 0x8000,
+            ),
+            // backup SPL over the primary copy
+            (layout.with_bofs(0), 0),
+            // backup SPL past the end of the flash
+            (layout.with_flash_size(DEF_BACKUP), DEF_BACKUP),
+            // env region past the end of the flash
+            (layout.with_env_size(u32::MAX), u32::MAX),
+        ] {
+            assert!(
+                matches!(layout.validate(), Err(Error::InvalidFlashLayout(o)) if o == offset),
+                "{layout:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_flash_update_region() {
+        let mut flash = [0u8; 16];
+        let region = FlashRegion::new(4, 12, FlashRegionKind::PrimarySpl);
+
+        flash_update_region(flash.as_mut(), &region, [1, 2, 3].as_ref()).unwrap();
+        assert_eq!(
+            flash,
+            [0, 0, 0, 0, 1, 2, 3, 0xff, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0]
+        );
+        assert!(!is_erased(&flash[4..12]));
+        assert!(is_erased(&flash[7..12]));
+
+        assert!(matches!(
+            flash_update_region(flash.as_mut(), &region, [0; 9].as_ref()),
+            Err(Error::InvalidSplLen((9, 8)))
+        ));
+
+        let region = FlashRegion::new(12, 20, FlashRegionKind::BackupSpl);
+        assert!(matches!(
+            flash_update_region(flash.as_mut(), &region, [0; 1].as_ref()),
+            Err(Error::FlashWrite(20))
+        ));
+    }
+}
@@ -0,0 +1,1096 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use core::fmt;
+
+use super::{crc32_ieee_update, Error, Result, CRC_FAILED};
+
+/// Signature at the start of a GPT header.
+pub const GPT_SIGNATURE: [u8; 8] = *b"EFI PART";
+/// Minimum length of a GPT header (UEFI revision 1.0).
+pub const GPT_HEADER_LEN: usize = 92;
+/// Minimum length of a GPT partition entry.
+pub const GPT_ENTRY_LEN: usize = 128;
+/// Maximum length of the partition entry array accepted by [GptDisk].
+pub const GPT_MAX_ENTRIES_LEN: u64 = 0x10_0000;
+/// Default logical sector (LBA) length of a disk image.
+pub const DEF_SECTOR_LEN: usize = 512;
+/// Maximum logical sector length supported by [GptDisk] (4Kn disks).
+pub const MAX_SECTOR_LEN: usize = 4096;
+/// Logical sector lengths probed by [gpt_sector_len], in order.
+pub const SECTOR_LENS: [usize; 4] = [512, 1024, 2048, 4096];
+/// Length of the MBR at the start of LBA 0, regardless of the sector length.
+pub const MBR_LEN: usize = 512;
+/// Offset of the first partition record in the MBR.
+pub const MBR_PART_OFFSET: usize = 446;
+/// Partition type of the protective MBR record covering the GPT disk.
+pub const MBR_TYPE_PROTECTIVE: u8 = 0xee;
+/// Boot signature at the end of the MBR.
+pub const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+/// Offset of the backup SPL address written by the JH7110 `--fix-imghdr` workaround.
+///
+/// The value lands in the MBR boot code, which is ignored on GPT disks.
+pub const JH7110_FIX_BOFS_OFFSET: usize = 0x4;
+/// Offset of the invalid SPL CRC ([CRC_FAILED]) written by the JH7110 `--fix-imghdr` workaround.
+///
+/// With 512-byte sectors, the value lands in the reserved tail of the primary GPT header sector,
+/// after the header CRC range. With larger sectors, it lands in the unused tail of LBA 0.
+pub const JH7110_FIX_CRCS_OFFSET: usize = 0x290;
+
+/// Gets whether `len` is a logical sector length supported by [GptDisk].
+pub const fn is_valid_sector_len(len: usize) -> bool {
+    len.is_power_of_two() && len >= DEF_SECTOR_LEN && len <= MAX_SECTOR_LEN
+}
+
+/// Detects the logical sector length of a disk image from the position of the primary GPT
+/// signature (LBA 1).
+///
+/// Returns `None` when no GPT signature is found at any of the [SECTOR_LENS].
+pub fn gpt_sector_len<D: DiskIo + ?Sized>(io: &mut D) -> Option<usize> {
+    SECTOR_LENS.into_iter().find(|&len| {
+        let mut sig = [0u8; GPT_SIGNATURE.len()];
+        io.read_at(len as u64, sig.as_mut()).is_ok() && sig == GPT_SIGNATURE
+    })
+}
+
+/// Gets whether the JH7110 `--fix-imghdr` CRC at [JH7110_FIX_CRCS_OFFSET] would overwrite the
+/// checksummed part of the primary GPT header, for a disk with `sector_len` sectors.
+pub const fn jh7110_fix_overlaps_gpt(sector_len: usize, header_size: u32) -> bool {
+    JH7110_FIX_CRCS_OFFSET + 4 > sector_len
+        && JH7110_FIX_CRCS_OFFSET < sector_len + header_size as usize
+}
+
+/// Random access to a disk image or block device.
+pub trait DiskIo {
+    /// Gets the length of the disk in bytes.
+    fn disk_len(&self) -> u64;
+    /// Reads `buf.len()` bytes at `offset`.
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<()>;
+    /// Writes `buf` at `offset`.
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<()>;
+}
+
+impl DiskIo for [u8] {
+    fn disk_len(&self) -> u64 {
+        self.len() as u64
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let start = usize::try_from(offset).map_err(|_| Error::DiskRead(offset))?;
+        let src = self
+            .get(start..start.saturating_add(buf.len()))
+            .ok_or(Error::DiskRead(offset))?;
+        buf.copy_from_slice(src);
+        Ok(())
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<()> {
+        let start = usize::try_from(offset).map_err(|_| Error::DiskWrite(offset))?;
+        let dst = self
+            .get_mut(start..start.saturating_add(buf.len()))
+            .ok_or(Error::DiskWrite(offset))?;
+        dst.copy_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl<T: DiskIo + ?Sized> DiskIo for &mut T {
+    fn disk_len(&self) -> u64 {
+        (**self).disk_len()
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<()> {
+        (**self).read_at(offset, buf)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<()> {
+        (**self).write_at(offset, buf)
+    }
+}
+
+/// Represents the fields of a GPT header.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GptHeader {
+    revision: u32,
+    header_size: u32,
+    header_crc: u32,
+    my_lba: u64,
+    alternate_lba: u64,
+    first_usable_lba: u64,
+    last_usable_lba: u64,
+    disk_guid: [u8; 16],
+    entries_lba: u64,
+    num_entries: u32,
+    entry_size: u32,
+    entries_crc: u32,
+}
+
+impl GptHeader {
+    /// Parses a GPT header from the start of its sector, and verifies the header CRC32.
+    ///
+    /// Only the first `header_size` bytes are covered by the CRC, the rest of the sector is
+    /// ignored.
+    pub fn parse(sector: &[u8]) -> Result<Self> {
+        if sector.len() < GPT_HEADER_LEN || sector[..8] != GPT_SIGNATURE {
+            return Err(Error::InvalidGpt);
+        }
+
+        let le32 = |idx: usize| {
+            u32::from_le_bytes([
+                sector[idx],
+                sector[idx + 1],
+                sector[idx + 2],
+                sector[idx + 3],
+            ])
+        };
+        let le64 = |idx: usize| (le32(idx) as u64) | ((le32(idx + 4) as u64) << 32);
+
+        let header_size = le32(12);
+        let size = header_size as usize;
+        if size < GPT_HEADER_LEN || size > sector.len() {
+            return Err(Error::InvalidGpt);
+        }
+
+        let header_crc = le32(16);
+        let crc = header_crc32(&sector[..size]);
+        if crc != header_crc {
+            return Err(Error::InvalidCrc((crc, header_crc)));
+        }
+
+        let mut disk_guid = [0u8; 16];
+        disk_guid.copy_from_slice(&sector[56..72]);
+
+        let hdr = Self {
+            revision: le32(8),
+            header_size,
+            header_crc,
+            my_lba: le64(24),
+            alternate_lba: le64(32),
+            first_usable_lba: le64(40),
+            last_usable_lba: le64(48),
+            disk_guid,
+            entries_lba: le64(72),
+            num_entries: le32(80),
+            entry_size: le32(84),
+            entries_crc: le32(88),
+        };
+
+        let entry_size = hdr.entry_size as usize;
+        if entry_size < GPT_ENTRY_LEN
+            || !entry_size.is_multiple_of(8)
+            || hdr.entries_len() > GPT_MAX_ENTRIES_LEN
+            || hdr.first_usable_lba > hdr.last_usable_lba.saturating_add(1)
+        {
+            return Err(Error::InvalidGpt);
+        }
+
+        Ok(hdr)
+    }
+
+    /// Writes the header into the start of its sector, and calculates the header CRC32.
+    ///
+    /// Only the first `header_size` bytes of `sector` are modified.
+    pub fn write_bytes(&self, sector: &mut [u8]) -> Result<()> {
+        let size = self.header_size as usize;
+        let out = sector.get_mut(..size).ok_or(Error::InvalidGpt)?;
+
+        out.fill(0);
+        out[..8].copy_from_slice(GPT_SIGNATURE.as_ref());
+        out[8..12].copy_from_slice(self.revision.to_le_bytes().as_ref());
+        out[12..16].copy_from_slice(self.header_size.to_le_bytes().as_ref());
+        out[24..32].copy_from_slice(self.my_lba.to_le_bytes().as_ref());
+        out[32..40].copy_from_slice(self.alternate_lba.to_le_bytes().as_ref());
+        out[40..48].copy_from_slice(self.first_usable_lba.to_le_bytes().as_ref());
+        out[48..56].copy_from_slice(self.last_usable_lba.to_le_bytes().as_ref());
+        out[56..72].copy_from_slice(self.disk_guid.as_ref());
+        out[72..80].copy_from_slice(self.entries_lba.to_le_bytes().as_ref());
+        out[80..84].copy_from_slice(self.num_entries.to_le_bytes().as_ref());
+        out[84..88].copy_from_slice(self.entry_size.to_le_bytes().as_ref());
+        out[88..92].copy_from_slice(self.entries_crc.to_le_bytes().as_ref());
+
+        let crc = header_crc32(out);
+        out[16..20].copy_from_slice(crc.to_le_bytes().as_ref());
+
+        Ok(())
+    }
+
+    /// Gets the GPT revision.
+    pub const fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    /// Gets the length of the header covered by the header CRC32.
+    pub const fn header_size(&self) -> u32 {
+        self.header_size
+    }
+
+    /// Gets the header CRC32.
+    pub const fn header_crc(&self) -> u32 {
+        self.header_crc
+    }
+
+    /// Gets the LBA of this header copy.
+    pub const fn my_lba(&self) -> u64 {
+        self.my_lba
+    }
+
+    /// Gets the LBA of the other header copy.
+    pub const fn alternate_lba(&self) -> u64 {
+        self.alternate_lba
+    }
+
+    /// Gets the first LBA usable by partitions.
+    pub const fn first_usable_lba(&self) -> u64 {
+        self.first_usable_lba
+    }
+
+    /// Gets the last LBA usable by partitions.
+    pub const fn last_usable_lba(&self) -> u64 {
+        self.last_usable_lba
+    }
+
+    /// Gets the disk GUID.
+    pub const fn disk_guid(&self) -> [u8; 16] {
+        self.disk_guid
+    }
+
+    /// Gets the first LBA of the partition entry array.
+    pub const fn entries_lba(&self) -> u64 {
+        self.entries_lba
+    }
+
+    /// Gets the number of partition entries.
+    pub const fn num_entries(&self) -> u32 {
+        self.num_entries
+    }
+
+    /// Gets the length of a partition entry.
+    pub const fn entry_size(&self) -> u32 {
+        self.entry_size
+    }
+
+    /// Gets the CRC32 of the partition entry array.
+    pub const fn entries_crc(&self) -> u32 {
+        self.entries_crc
+    }
+
+    /// Gets the length of the partition entry array.
+    pub const fn entries_len(&self) -> u64 {
+        self.num_entries as u64 * self.entry_size as u64
+    }
+
+    /// Gets the number of sectors occupied by the partition entry array.
+    pub const fn entries_sectors(&self, sector_len: usize) -> u64 {
+        self.entries_len().div_ceil(sector_len as u64)
+    }
+
+    // compares the fields that must be identical in the primary and backup copies
+    fn matches(&self, other: &Self) -> bool {
+        self.revision == other.revision
+            && self.header_size == other.header_size
+            && self.first_usable_lba == other.first_usable_lba
+            && self.last_usable_lba == other.last_usable_lba
+            && self.disk_guid == other.disk_guid
+            && self.num_entries == other.num_entries
+            && self.entry_size == other.entry_size
+            && self.entries_crc == other.entries_crc
+            && self.alternate_lba == other.my_lba
+            && other.alternate_lba == self.my_lba
+    }
+}
+
+/// Represents one of the two GPT copies.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GptCopy {
+    /// Primary copy at LBA 1.
+    Primary,
+    /// Backup copy at the last LBA.
+    Backup,
+}
+
+impl fmt::Display for GptCopy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Primary => write!(f, "primary"),
+            Self::Backup => write!(f, "backup"),
+        }
+    }
+}
+
+/// Represents the status of a GPT header and its partition entry array.
+#[derive(Clone, Copy, Debug)]
+pub enum GptStatus {
+    /// The header and entry CRC32s match.
+    Ok,
+    /// No GPT signature at the header LBA.
+    Missing,
+    /// The header is malformed, or its CRC32 does not match.
+    InvalidHeader(Error),
+    /// The partition entry array CRC32 does not match the header.
+    EntriesCrc { actual: u32, expected: u32 },
+    /// Reading from the disk failed.
+    ReadError(Error),
+}
+
+impl GptStatus {
+    /// Gets whether the GPT copy is intact.
+    pub const fn is_ok(&self) -> bool {
+        matches!(self, Self::Ok)
+    }
+}
+
+impl fmt::Display for GptStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ok => write!(f, "ok"),
+            Self::Missing => write!(f, "missing"),
+            Self::InvalidHeader(err) => write!(f, "invalid header: {err}"),
+            Self::EntriesCrc { actual, expected } => write!(
+                f,
+                "partition entries CRC mismatch: {actual:#010x}, expected: {expected:#010x}"
+            ),
+            Self::ReadError(err) => write!(f, "read error: {err}"),
+        }
+    }
+}
+
+/// Represents the status of the protective MBR.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PmbrStatus {
+    /// The MBR has a protective (`0xee`) partition record.
+    Ok,
+    /// The MBR boot signature is missing.
+    Missing,
+    /// The MBR has no protective partition record.
+    NotProtective,
+}
+
+impl fmt::Display for PmbrStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ok => write!(f, "ok"),
+            Self::Missing => write!(f, "missing boot signature"),
+            Self::NotProtective => write!(f, "no protective partition record"),
+        }
+    }
+}
+
+/// Represents the result of checking one GPT copy.
+#[derive(Clone, Copy, Debug)]
+pub struct GptCopyCheck {
+    /// LBA of the checked header.
+    pub lba: u64,
+    /// Status of the header and entry array.
+    pub status: GptStatus,
+    /// Parsed header, when the header CRC32 matches.
+    pub header: Option<GptHeader>,
+}
+
+/// Represents the result of [GptDisk::check].
+#[derive(Clone, Copy, Debug)]
+pub struct GptReport {
+    /// Status of the protective MBR.
+    pub pmbr: PmbrStatus,
+    /// Primary GPT copy.
+    pub primary: GptCopyCheck,
+    /// Backup GPT copy.
+    pub backup: GptCopyCheck,
+    /// Whether both copies are intact, and describe the same partition table.
+    pub copies_match: bool,
+    /// Logical sector length used for the check.
+    pub sector_len: usize,
+    /// Backup SPL address stored by the JH7110 `--fix-imghdr` workaround, when present.
+    pub jh7110_fix: Option<u32>,
+}
+
+impl GptReport {
+    /// Gets whether the protective MBR and both GPT copies are intact and consistent.
+    pub const fn is_ok(&self) -> bool {
+        matches!(self.pmbr, PmbrStatus::Ok)
+            && self.primary.status.is_ok()
+            && self.backup.status.is_ok()
+            && self.copies_match
+    }
+
+    /// Gets the result of checking a GPT copy.
+    pub const fn copy(&self, copy: GptCopy) -> &GptCopyCheck {
+        match copy {
+            GptCopy::Primary => &self.primary,
+            GptCopy::Backup => &self.backup,
+        }
+    }
+}
+
+/// Validates and repairs the GUID Partition Table of a disk image.
+///
+/// The JH7110 `--fix-imghdr` workaround stores foreign values at [JH7110_FIX_BOFS_OFFSET] (MBR
+/// boot code) and [JH7110_FIX_CRCS_OFFSET] (reserved tail of the primary header sector). Neither
+/// location is covered by a GPT checksum, so they are ignored when checking, and preserved when
+/// repairing.
+///
+/// The logical sector length defaults to [DEF_SECTOR_LEN], use [gpt_sector_len] to detect it, e.g.
+/// for 4Kn eMMC images.
+///
+/// Example repairing a damaged primary header from the backup copy:
+///
+/// ```
+/// # use spl_tool::{DiskIo, GptCopy, GptDisk};
+/// # fn gpt_disk() -> Vec<u8> {
+/// #     let mut disk = vec![0u8; 128 * 512];
+/// #     disk[446 + 4] = 0xee;
+/// #     disk[510..512].copy_from_slice(&[0x55, 0xaa]);
+/// #     let hdr = |my: u64, alt: u64, entries: u64| {
+/// #         let mut h = [0u8; 92];
+/// #         h[..8].copy_from_slice(b"EFI PART");
+/// #         h[8..12].copy_from_slice(&0x1_0000u32.to_le_bytes());
+/// #         h[12..16].copy_from_slice(&92u32.to_le_bytes());
+/// #         h[24..32].copy_from_slice(&my.to_le_bytes());
+/// #         h[32..40].copy_from_slice(&alt.to_le_bytes());
+/// #         h[40..48].copy_from_slice(&34u64.to_le_bytes());
+/// #         h[48..56].copy_from_slice(&94u64.to_le_bytes());
+/// #         h[72..80].copy_from_slice(&entries.to_le_bytes());
+/// #         h[80..84].copy_from_slice(&128u32.to_le_bytes());
+/// #         h[84..88].copy_from_slice(&128u32.to_le_bytes());
+/// #         h[88..92].copy_from_slice(&spl_tool::crc32_ieee(&[0u8; 128 * 128]).to_le_bytes());
+/// #         let crc = spl_tool::crc32_ieee(&h);
+/// #         h[16..20].copy_from_slice(&crc.to_le_bytes());
+/// #         h
+/// #     };
+/// #     disk[512..604].copy_from_slice(&hdr(1, 127, 2));
+/// #     disk[127 * 512..127 * 512 + 92].copy_from_slice(&hdr(127, 1, 95));
+/// #     disk
+/// # }
+/// let mut disk = gpt_disk();
+/// // damage the primary header
+/// disk[512 + 40] ^= 0xff;
+///
+/// let mut gpt = GptDisk::new(disk.as_mut_slice());
+/// assert!(!gpt.check().is_ok());
+///
+/// assert!(matches!(gpt.repair(None), Ok(Some(GptCopy::Primary))));
+/// assert!(gpt.check().is_ok());
+/// ```
+pub struct GptDisk<D> {
+    io: D,
+    sector_len: usize,
+}
+
+impl<D: DiskIo> GptDisk<D> {
+    /// Creates a new [GptDisk] with [DEF_SECTOR_LEN] sectors.
+    pub fn new(io: D) -> Self {
+        Self {
+            io,
+            sector_len: DEF_SECTOR_LEN,
+        }
+    }
+
+    /// Gets the logical sector length.
+    pub const fn sector_len(&self) -> usize {
+        self.sector_len
+    }
+
+    /// Sets the logical sector length, unsupported lengths fall back to [DEF_SECTOR_LEN].
+    pub fn set_sector_len(&mut self, val: usize) {
+        self.sector_len = if is_valid_sector_len(val) {
+            val
+        } else {
+            DEF_SECTOR_LEN
+        };
+    }
+
+    /// Builder function that sets the logical sector length.
+    pub fn with_sector_len(mut self, val: usize) -> Self {
+        self.set_sector_len(val);
+        self
+    }
+
+    /// Gets a mutable reference to the underlying disk.
+    pub fn io(&mut self) -> &mut D {
+        &mut self.io
+    }
+
+    /// Consumes the [GptDisk], and returns the underlying disk.
+    pub fn into_inner(self) -> D {
+        self.io
+    }
+
+    /// Gets the last LBA of the disk.
+    pub fn last_lba(&self) -> u64 {
+        (self.io.disk_len() / self.sector_len as u64).saturating_sub(1)
+    }
+
+    /// Checks the protective MBR, both GPT copies, and whether the copies match.
+    pub fn check(&mut self) -> GptReport {
+        let mut mbr = [0u8; MBR_LEN];
+        let pmbr = match self.io.read_at(0, mbr.as_mut()) {
+            Ok(()) => pmbr_status(mbr.as_ref()),
+            Err(_) => PmbrStatus::Missing,
+        };
+
+        let primary = self.check_copy(1);
+
+        let backup_lba = match primary.header {
+            Some(hdr) if hdr.alternate_lba() > 1 && hdr.alternate_lba() <= self.last_lba() => {
+                hdr.alternate_lba()
+            }
+            _ => self.last_lba(),
+        };
+        let backup = self.check_copy(backup_lba);
+
+        let copies_match = match (primary.status, primary.header, backup.status, backup.header) {
+            (GptStatus::Ok, Some(p), GptStatus::Ok, Some(b)) => p.matches(&b),
+            _ => false,
+        };
+
+        GptReport {
+            pmbr,
+            primary,
+            backup,
+            copies_match,
+            sector_len: self.sector_len,
+            jh7110_fix: self.jh7110_fix(mbr.as_ref()),
+        }
+    }
+
+    /// Rebuilds one GPT copy from the other.
+    ///
+    /// With `source`, the other copy is rebuilt from it. Otherwise, the primary copy is used when
+    /// intact, then the backup copy. The partition entry array is copied before the header is
+    /// written, and the rest of the primary header sector (including [JH7110_FIX_CRCS_OFFSET]) is
+    /// preserved.
+    ///
+    /// Returns the rebuilt copy, or `None` when both copies are already intact and consistent.
+    pub fn repair(&mut self, source: Option<GptCopy>) -> Result<Option<GptCopy>> {
+        let report = self.check();
+
+        let src = match source {
+            Some(copy) => copy,
+            None if report.primary.status.is_ok() => GptCopy::Primary,
+            None if report.backup.status.is_ok() => GptCopy::Backup,
+            None => return Err(Error::InvalidGpt),
+        };
+
+        let src_check = report.copy(src);
+        let src_hdr = match (src_check.status, src_check.header) {
+            (GptStatus::Ok, Some(hdr)) => hdr,
+            _ => return Err(Error::InvalidGpt),
+        };
+
+        if report.copies_match {
+            return Ok(None);
+        }
+
+        let entries_sectors = src_hdr.entries_sectors(self.sector_len);
+        let (dst, dst_hdr) = match src {
+            GptCopy::Primary => {
+                let lba = match src_hdr.alternate_lba() {
+                    lba if lba > src_hdr.last_usable_lba() && lba <= self.last_lba() => lba,
+                    _ => self.last_lba(),
+                };
+                let mut hdr = src_hdr;
+                hdr.my_lba = lba;
+                hdr.alternate_lba = 1;
+                hdr.entries_lba = lba.saturating_sub(entries_sectors);
+                (GptCopy::Backup, hdr)
+            }
+            GptCopy::Backup => {
+                let mut hdr = src_hdr;
+                hdr.my_lba = 1;
+                hdr.alternate_lba = src_hdr.my_lba();
+                hdr.entries_lba = 2;
+                (GptCopy::Primary, hdr)
+            }
+        };
+
+        let entries_end = dst_hdr.entries_lba.saturating_add(entries_sectors);
+        let overlaps = match dst {
+            GptCopy::Primary => entries_end > dst_hdr.first_usable_lba,
+            GptCopy::Backup => dst_hdr.entries_lba <= dst_hdr.last_usable_lba,
+        };
+        if overlaps {
+            return Err(Error::InvalidGpt);
+        }
+
+        self.copy_entries(&src_hdr, &dst_hdr)?;
+
+        let offset = dst_hdr.my_lba * self.sector_len as u64;
+        let mut buf = [0u8; MAX_SECTOR_LEN];
+        let sector = &mut buf[..self.sector_len];
+        if dst == GptCopy::Primary {
+            // keep the reserved tail of the sector, e.g. the JH7110 `--fix-imghdr` CRC
+            self.io.read_at(offset, sector)?;
+        }
+
+        dst_hdr.write_bytes(sector)?;
+        self.io.write_at(offset, sector)?;
+
+        Ok(Some(dst))
+    }
+
+    fn check_copy(&mut self, lba: u64) -> GptCopyCheck {
+        let mut res = GptCopyCheck {
+            lba,
+            status: GptStatus::Ok,
+            header: None,
+        };
+
+        let mut buf = [0u8; MAX_SECTOR_LEN];
+        let sector = &mut buf[..self.sector_len];
+        if let Err(err) = self.io.read_at(lba * self.sector_len as u64, sector) {
+            res.status = GptStatus::ReadError(err);
+            return res;
+        }
+
+        if sector[..8] != GPT_SIGNATURE {
+            res.status = GptStatus::Missing;
+            return res;
+        }
+
+        let hdr = match GptHeader::parse(sector) {
+            Ok(hdr) => hdr,
+            Err(err) => {
+                res.status = GptStatus::InvalidHeader(err);
+                return res;
+            }
+        };
+        res.header = Some(hdr);
+
+        if hdr.my_lba() != lba {
+            res.status = GptStatus::InvalidHeader(Error::InvalidGpt);
+            return res;
+        }
+
+        res.status = match self.entries_crc(&hdr) {
+            Ok(crc) if crc == hdr.entries_crc() => GptStatus::Ok,
+            Ok(crc) => GptStatus::EntriesCrc {
+                actual: crc,
+                expected: hdr.entries_crc(),
+            },
+            Err(err) => GptStatus::ReadError(err),
+        };
+
+        res
+    }
+
+    fn entries_crc(&mut self, hdr: &GptHeader) -> Result<u32> {
+        let mut buf = [0u8; MAX_SECTOR_LEN];
+        let mut crc = !0u32;
+        let start = self.entries_offset(hdr).ok_or(Error::DiskRead(u64::MAX))?;
+        let len = hdr.entries_len();
+        let mut pos = 0u64;
+
+        while pos < len {
+            let chunk_len = core::cmp::min(len - pos, MAX_SECTOR_LEN as u64) as usize;
+            let offset = start.checked_add(pos).ok_or(Error::DiskRead(start))?;
+            self.io.read_at(offset, &mut buf[..chunk_len])?;
+            crc = crc32_ieee_update(crc, &buf[..chunk_len]);
+            pos += chunk_len as u64;
+        }
+
+        Ok(!crc)
+    }
+
+    fn copy_entries(&mut self, src: &GptHeader, dst: &GptHeader) -> Result<()> {
+        let mut buf = [0u8; MAX_SECTOR_LEN];
+        let sector = &mut buf[..self.sector_len];
+        let src_start = self.entries_offset(src).ok_or(Error::DiskRead(u64::MAX))?;
+        let dst_start = self.entries_offset(dst).ok_or(Error::DiskWrite(u64::MAX))?;
+        let len = src.entries_sectors(self.sector_len) * self.sector_len as u64;
+        let mut pos = 0u64;
+
+        while pos < len {
+            let src_offset = src_start
+                .checked_add(pos)
+                .ok_or(Error::DiskRead(src_start))?;
+            let dst_offset = dst_start
+                .checked_add(pos)
+                .ok_or(Error::DiskWrite(dst_start))?;
+            self.io.read_at(src_offset, sector)?;
+            self.io.write_at(dst_offset, sector)?;
+            pos += self.sector_len as u64;
+        }
+
+        Ok(())
+    }
+
+    // byte offset of the partition entry array, `None` when it overflows
+    fn entries_offset(&self, hdr: &GptHeader) -> Option<u64> {
+        hdr.entries_lba().checked_mul(self.sector_len as u64)
+    }
+
+    fn jh7110_fix(&mut self, mbr: &[u8]) -> Option<u32> {
+        let mut crcs = [0u8; 4];
+        self.io
+            .read_at(JH7110_FIX_CRCS_OFFSET as u64, crcs.as_mut())
+            .ok()?;
+
+        (u32::from_le_bytes(crcs) == CRC_FAILED).then(|| {
+            let idx = JH7110_FIX_BOFS_OFFSET;
+            u32::from_le_bytes([mbr[idx], mbr[idx + 1], mbr[idx + 2], mbr[idx + 3]])
+        })
+    }
+}
+
+fn pmbr_status(mbr: &[u8]) -> PmbrStatus {
+    if mbr[510..512] != MBR_SIGNATURE {
+        return PmbrStatus::Missing;
+    }
+
+    // the boot code (including the JH7110 `bofs` at 0x4) is not checked
+    let protective = (0..4).any(|idx| mbr[MBR_PART_OFFSET + idx * 16 + 4] == MBR_TYPE_PROTECTIVE);
+
+    if protective {
+        PmbrStatus::Ok
+    } else {
+        PmbrStatus::NotProtective
+    }
+}
+
+// CRC32 of the header, with the CRC field treated as zero
+fn header_crc32(header: &[u8]) -> u32 {
+    let crc = crc32_ieee_update(!0, &header[..16]);
+    let crc = crc32_ieee_update(crc, [0u8; 4].as_ref());
+    !crc32_ieee_update(crc, &header[20..])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::crc32_ieee;
+
+    const DISK_GUID: [u8; 16] = [0x5a; 16];
+    const NUM_ENTRIES: u32 = 128;
+
+    // disk with a protective MBR, one partition, and both GPT copies
+    fn gpt_disk(sector_len: usize, sectors: u64) -> Vec<u8> {
+        let mut disk = std::vec![0u8; sectors as usize * sector_len];
+        disk[MBR_PART_OFFSET + 4] = MBR_TYPE_PROTECTIVE;
+        disk[510..512].copy_from_slice(MBR_SIGNATURE.as_ref());
+
+        let entries_len = NUM_ENTRIES as usize * GPT_ENTRY_LEN;
+        let entries_sectors = entries_len.div_ceil(sector_len) as u64;
+        let last_lba = sectors - 1;
+
+        let mut entries = std::vec![0u8; entries_len];
+        // type GUID, partition GUID, first and last LBA
+        entries[..32].fill(0x11);
+        entries[32..40].copy_from_slice((2 + entries_sectors).to_le_bytes().as_ref());
+        entries[40..48].copy_from_slice((last_lba - entries_sectors - 1).to_le_bytes().as_ref());
+
+        let primary = GptHeader {
+            revision: 0x1_0000,
+            header_size: GPT_HEADER_LEN as u32,
+            header_crc: 0,
+            my_lba: 1,
+            alternate_lba: last_lba,
+            first_usable_lba: 2 + entries_sectors,
+            last_usable_lba: last_lba - entries_sectors - 1,
+            disk_guid: DISK_GUID,
+            entries_lba: 2,
+            num_entries: NUM_ENTRIES,
+            entry_size: GPT_ENTRY_LEN as u32,
+            entries_crc: crc32_ieee(entries.as_ref()),
+        };
+        let backup = GptHeader {
+            my_lba: last_lba,
+            alternate_lba: 1,
+            entries_lba: last_lba - entries_sectors,
+            ..primary
+        };
+
+        for hdr in [primary, backup] {
+            let offset = hdr.my_lba as usize * sector_len;
+            hdr.write_bytes(&mut disk[offset..offset + sector_len])
+                .unwrap();
+
+            let offset = hdr.entries_lba as usize * sector_len;
+            disk[offset..offset + entries_len].copy_from_slice(entries.as_ref());
+        }
+
+        disk
+    }
+
+    fn backup_offset(disk: &[u8], sector_len: usize) -> usize {
+        disk.len() - sector_len
+    }
+
+    #[test]
+    fn test_check_ok() {
+        for sector_len in SECTOR_LENS {
+            let mut disk = gpt_disk(sector_len, 128);
+
+            assert_eq!(gpt_sector_len(disk.as_mut_slice()), Some(sector_len));
+
+            let mut gpt = GptDisk::new(disk.as_mut_slice()).with_sector_len(sector_len);
+            let report = gpt.check();
+
+            assert!(report.is_ok(), "{sector_len}: {report:?}");
+            assert_eq!(report.sector_len, sector_len);
+            assert_eq!(report.primary.lba, 1);
+            assert_eq!(report.backup.lba, 127);
+            assert_eq!(report.jh7110_fix, None);
+            assert_eq!(report.primary.header.unwrap().disk_guid(), DISK_GUID);
+            assert!(matches!(gpt.repair(None), Ok(None)));
+        }
+
+        // the wrong sector length finds no GPT
+        let mut disk = gpt_disk(4096, 128);
+        let mut gpt = GptDisk::new(disk.as_mut_slice());
+        let report = gpt.check();
+        assert!(matches!(report.primary.status, GptStatus::Missing));
+        assert!(!report.is_ok());
+
+        assert_eq!(gpt_sector_len([0u8; 8192].as_mut_slice()), None);
+    }
+
+    #[test]
+    fn test_sector_len() {
+        for len in SECTOR_LENS {
+            assert!(is_valid_sector_len(len));
+        }
+        for len in [0, 256, 768, 8192] {
+            assert!(!is_valid_sector_len(len));
+            let gpt = GptDisk::new([0u8; 0].as_mut_slice()).with_sector_len(len);
+            assert_eq!(gpt.sector_len(), DEF_SECTOR_LEN);
+        }
+    }
+
+    #[test]
+    fn test_repair_primary() {
+        for sector_len in [512, 4096] {
+            let good = gpt_disk(sector_len, 128);
+            let mut disk = good.clone();
+
+            // JH7110 `--fix-imghdr` values outside of the checksummed ranges
+            disk[JH7110_FIX_BOFS_OFFSET..JH7110_FIX_BOFS_OFFSET + 4]
+                .copy_from_slice(0x20_0000u32.to_le_bytes().as_ref());
+            disk[JH7110_FIX_CRCS_OFFSET..JH7110_FIX_CRCS_OFFSET + 4]
+                .copy_from_slice(CRC_FAILED.to_le_bytes().as_ref());
+            let fixed = disk.clone();
+
+            // damage the primary header and entries
+            disk[sector_len + 40] ^= 0xff;
+            disk[2 * sector_len] ^= 0xff;
+
+            let mut gpt = GptDisk::new(disk.as_mut_slice()).with_sector_len(sector_len);
+            let report = gpt.check();
+            assert!(matches!(
+                report.primary.status,
+                GptStatus::InvalidHeader(Error::InvalidCrc(_))
+            ));
+            assert!(report.backup.status.is_ok());
+            assert_eq!(report.jh7110_fix, Some(0x20_0000));
+
+            assert!(matches!(gpt.repair(None), Ok(Some(GptCopy::Primary))));
+            assert!(gpt.check().is_ok());
+            // the workaround values survive the repair
+            assert_eq!(disk, fixed);
+        }
+    }
+
+    #[test]
+    fn test_repair_backup() {
+        let good = gpt_disk(512, 128);
+        let mut disk = good.clone();
+        let backup = backup_offset(disk.as_ref(), 512);
+
+        // damage the backup entries
+        disk[backup - 512 * 32] ^= 0xff;
+
+        let mut gpt = GptDisk::new(disk.as_mut_slice());
+        let report = gpt.check();
+        assert!(report.primary.status.is_ok());
+        assert!(matches!(report.backup.status, GptStatus::EntriesCrc { .. }));
+        assert!(!report.copies_match);
+
+        assert!(matches!(gpt.repair(None), Ok(Some(GptCopy::Backup))));
+        assert_eq!(disk, good);
+
+        // missing backup header, e.g. after `dd` to a larger disk
+        let mut disk = good.clone();
+        disk.extend_from_slice([0u8; 512 * 64].as_ref());
+        let mut gpt = GptDisk::new(disk.as_mut_slice());
+        assert!(gpt.check().is_ok());
+        disk[backup..backup + 512].fill(0);
+
+        let mut gpt = GptDisk::new(disk.as_mut_slice());
+        assert!(matches!(gpt.check().backup.status, GptStatus::Missing));
+        assert!(matches!(gpt.repair(None), Ok(Some(GptCopy::Backup))));
+        assert!(gpt.check().is_ok());
+    }
+
+    #[test]
+    fn test_repair_mismatch() {
+        let mut disk = gpt_disk(512, 128);
+        let backup = backup_offset(disk.as_ref(), 512);
+
+        // a valid backup header describing another disk
+        let mut hdr = GptHeader::parse(&disk[backup..]).unwrap();
+        hdr.disk_guid = [0xa5; 16];
+        hdr.write_bytes(&mut disk[backup..]).unwrap();
+
+        let mut gpt = GptDisk::new(disk.as_mut_slice());
+        let report = gpt.check();
+        assert!(report.primary.status.is_ok());
+        assert!(report.backup.status.is_ok());
+        assert!(!report.copies_match);
+        assert!(!report.is_ok());
+
+        // rebuild the primary copy from the backup, on request
+        assert!(matches!(
+            gpt.repair(Some(GptCopy::Backup)),
+            Ok(Some(GptCopy::Primary))
+        ));
+        let report = gpt.check();
+        assert!(report.is_ok());
+        assert_eq!(report.primary.header.unwrap().disk_guid(), [0xa5; 16]);
+    }
+
+    #[test]
+    fn test_repair_invalid() {
+        let good = gpt_disk(512, 128);
+
+        // both copies damaged
+        let mut disk = good.clone();
+        let backup = backup_offset(disk.as_ref(), 512);
+        disk[512 + 40] ^= 0xff;
+        disk[backup + 40] ^= 0xff;
+        let mut gpt = GptDisk::new(disk.as_mut_slice());
+        assert!(matches!(gpt.repair(None), Err(Error::InvalidGpt)));
+
+        // explicitly requested source copy is damaged
+        let mut disk = good.clone();
+        disk[backup + 40] ^= 0xff;
+        let mut gpt = GptDisk::new(disk.as_mut_slice());
+        assert!(matches!(
+            gpt.repair(Some(GptCopy::Backup)),
+            Err(Error::InvalidGpt)
+        ));
+
+        // rebuilt entries would overlap the first usable LBA
+        let mut disk = good.clone();
+        let mut hdr = GptHeader::parse(&disk[backup..]).unwrap();
+        hdr.first_usable_lba = 3;
+        hdr.write_bytes(&mut disk[backup..]).unwrap();
+        disk[512 + 40] ^= 0xff;
+        let mut gpt = GptDisk::new(disk.as_mut_slice());
+        assert!(matches!(gpt.repair(None), Err(Error::InvalidGpt)));
+    }
+
+    #[test]
+    fn test_pmbr() {
+        let good = gpt_disk(512, 128);
+
+        let mut disk = good.clone();
+        disk[511] = 0;
+        assert_eq!(
+            GptDisk::new(disk.as_mut_slice()).check().pmbr,
+            PmbrStatus::Missing
+        );
+
+        let mut disk = good.clone();
+        disk[MBR_PART_OFFSET + 4] = 0x83;
+        let report = GptDisk::new(disk.as_mut_slice()).check();
+        assert_eq!(report.pmbr, PmbrStatus::NotProtective);
+        assert!(!report.is_ok());
+
+        // the boot code is not checked
+        let mut disk = good.clone();
+        disk[..MBR_PART_OFFSET].fill(0x90);
+        assert!(GptDisk::new(disk.as_mut_slice()).check().is_ok());
+    }
+
+    #[test]
+    fn test_header_parse_invalid() {
+        let disk = gpt_disk(512, 128);
+        let sector = &disk[512..1024];
+        let hdr = GptHeader::parse(sector).unwrap();
+
+        assert!(matches!(
+            GptHeader::parse(&sector[..GPT_HEADER_LEN - 1]),
+            Err(Error::InvalidGpt)
+        ));
+
+        let mut bad = sector.to_vec();
+        bad[0] = b'e';
+        assert!(matches!(
+            GptHeader::parse(bad.as_ref()),
+            Err(Error::InvalidGpt)
+        ));
+
+        // header size smaller than the minimum, or larger than the sector
+        for size in [GPT_HEADER_LEN as u32 - 4, 513] {
+            let mut bad = sector.to_vec();
+            bad[12..16].copy_from_slice(size.to_le_bytes().as_ref());
+            assert!(matches!(
+                GptHeader::parse(bad.as_ref()),
+                Err(Error::InvalidGpt)
+            ));
+        }
+
+        let mut bad = sector.to_vec();
+        bad[16] ^= 0xff;
+        assert!(matches!(
+            GptHeader::parse(bad.as_ref()),
+            Err(Error::InvalidCrc(_))
+        ));
+
+        // field checks run on CRC-valid headers
+        for hdr in [
+            GptHeader {
+                entry_size: 64,
+                ..hdr
+            },
+            GptHeader {
+                entry_size: 132,
+                ..hdr
+            },
+            GptHeader {
+                num_entries: u32::MAX,
+                ..hdr
+            },
+            GptHeader {
+                first_usable_lba: hdr.last_usable_lba + 2,
+                ..hdr
+            },
+        ] {
+            let mut bad = sector.to_vec();
+            hdr.write_bytes(bad.as_mut()).unwrap();
+            assert!(matches!(
+                GptHeader::parse(bad.as_ref()),
+                Err(Error::InvalidGpt)
+            ));
+        }
+
+        // bytes past `header_size` are not covered by the CRC
+        let mut tail = sector.to_vec();
+        tail[GPT_HEADER_LEN..].fill(0xff);
+        assert_eq!(GptHeader::parse(tail.as_ref()).unwrap(), hdr);
+    }
+
+    #[test]
+    fn test_check_bad_entries_lba() {
+        let mut disk = gpt_disk(512, 128);
+        let mut hdr = GptHeader::parse(&disk[512..1024]).unwrap();
+
+        // partition entry array past the end of the disk, and with an overflowing offset
+        for lba in [1000, u64::MAX / 2] {
+            hdr.entries_lba = lba;
+            hdr.write_bytes(&mut disk[512..1024]).unwrap();
+
+            let report = GptDisk::new(disk.as_mut_slice()).check();
+            assert!(matches!(report.primary.status, GptStatus::ReadError(_)));
+        }
+    }
+
+    #[test]
+    fn test_jh7110_fix_overlap() {
+        assert!(!jh7110_fix_overlaps_gpt(512, GPT_HEADER_LEN as u32));
+        assert!(!jh7110_fix_overlaps_gpt(4096, GPT_HEADER_LEN as u32));
+        // a header extending into the CRC location
+        assert!(jh7110_fix_overlaps_gpt(512, 200));
+    }
+}
@@ -0,0 +1,555 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use core::fmt;
+
+use super::{Error, Result};
+
+/// Maximum length of the data in a single HEX/SREC record.
+pub const HEX_RECORD_MAX_LEN: usize = 255;
+/// Length of the data written to each HEX/SREC record.
+pub const HEX_LINE_LEN: usize = 16;
+
+// length of the decoded bytes of the longest Intel HEX line: len + addr + type + data + checksum
+const HEX_LINE_MAX_BYTES: usize = 1 + 2 + 1 + HEX_RECORD_MAX_LEN + 1;
+// `S0` header record data written by [HexWriter]
+const SREC_HEADER: &[u8] = b"spl_tool";
+
+/// Represents the supported text formats for flash programmers.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HexFormat {
+    /// Intel HEX (I32HEX) with extended linear address records.
+    IntelHex,
+    /// Motorola S-record with 32-bit address (`S3`) records.
+    Srec,
+}
+
+impl HexFormat {
+    /// Detects the format from the first non-whitespace character of the data.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        let mut iter = data.iter().skip_while(|b| b.is_ascii_whitespace());
+        match (iter.next(), iter.next()) {
+            (Some(b':'), Some(b)) if b.is_ascii_hexdigit() => Some(Self::IntelHex),
+            (Some(b'S'), Some(b)) if b.is_ascii_digit() => Some(Self::Srec),
+            _ => None,
+        }
+    }
+
+    /// Gets the conventional file extension for the format.
+    pub const fn extension(&self) -> &'static str {
+        match self {
+            Self::IntelHex => "hex",
+            Self::Srec => "srec",
+        }
+    }
+}
+
+impl fmt::Display for HexFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IntelHex => write!(f, "Intel HEX"),
+            Self::Srec => write!(f, "Motorola S-record"),
+        }
+    }
+}
+
+/// Represents a data record with an absolute address.
+#[derive(Clone, Copy, Debug)]
+pub struct HexRecord {
+    addr: u32,
+    len: usize,
+    data: [u8; HEX_RECORD_MAX_LEN],
+}
+
+impl HexRecord {
+    /// Gets the absolute address of the record data.
+    pub const fn addr(&self) -> u32 {
+        self.addr
+    }
+
+    /// Gets the record data.
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    /// Gets the absolute end address (exclusive) of the record data.
+    pub const fn end(&self) -> u64 {
+        self.addr as u64 + self.len as u64
+    }
+}
+
+/// Reads the data records of an Intel HEX or Motorola S-record file.
+///
+/// Intel HEX extended segment/linear address records are applied to the following data records,
+/// so every [HexRecord] has an absolute address. Reading stops at the end-of-file (Intel HEX) or
+/// termination (S-record) record.
+///
+/// Errors report the 1-based line number with [Error::InvalidHexRecord].
+pub struct HexReader<'a> {
+    lines: core::str::Lines<'a>,
+    line: usize,
+    format: Option<HexFormat>,
+    base: u32,
+    done: bool,
+}
+
+impl<'a> HexReader<'a> {
+    /// Creates a new [HexReader], detecting the format from the first record.
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            lines: text.lines(),
+            line: 0,
+            format: HexFormat::detect(text.as_bytes()),
+            base: 0,
+            done: false,
+        }
+    }
+
+    /// Gets the detected format, if any.
+    pub const fn format(&self) -> Option<HexFormat> {
+        self.format
+    }
+
+    fn ihex_record(&mut self, line: &str) -> Result<Option<HexRecord>> {
+        let mut buf = [0u8; HEX_LINE_MAX_BYTES];
+        let bytes = decode_hex(line.strip_prefix(':'), &mut buf).ok_or(self.err())?;
+
+        let len = *bytes.first().ok_or(self.err())? as usize;
+        if bytes.len() != len + 5 || checksum(bytes) != 0 {
+            return Err(self.err());
+        }
+
+        let addr = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let data = &bytes[4..4 + len];
+
+        match bytes[3] {
+            // data
+            0x00 => Ok(Some(record(self.base.wrapping_add(addr), data))),
+            // end-of-file
+            0x01 => {
+                self.done = true;
+                Ok(None)
+            }
+            // extended segment address
+            0x02 if len == 2 => {
+                self.base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4;
+                Ok(None)
+            }
+            // extended linear address
+            0x04 if len == 2 => {
+                self.base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16;
+                Ok(None)
+            }
+            // start segment/linear address
+            0x03 | 0x05 => Ok(None),
+            _ => Err(self.err()),
+        }
+    }
+
+    fn srec_record(&mut self, line: &str) -> Result<Option<HexRecord>> {
+        let mut buf = [0u8; HEX_LINE_MAX_BYTES];
+        let rec_type = line.as_bytes().get(1).copied().ok_or(self.err())?;
+        let bytes = decode_hex(line.get(2..), &mut buf).ok_or(self.err())?;
+
+        let addr_len = match rec_type {
+            b'0' | b'1' | b'5' | b'9' => 2,
+            b'2' | b'6' | b'8' => 3,
+            b'3' | b'7' => 4,
+            _ => return Err(self.err()),
+        };
+
+        let count = *bytes.first().ok_or(self.err())? as usize;
+        if bytes.len() != count + 1 || count < addr_len + 1 || checksum(bytes) != 0xff {
+            return Err(self.err());
+        }
+
+        let addr = bytes[1..1 + addr_len]
+            .iter()
+            .fold(0u32, |acc, &b| (acc << 8) | b as u32);
+        let data = &bytes[1 + addr_len..count];
+
+        match rec_type {
+            b'1' | b'2' | b'3' => Ok(Some(record(addr, data))),
+            b'7' | b'8' | b'9' => {
+                self.done = true;
+                Ok(None)
+            }
+            // header and record count
+            _ => Ok(None),
+        }
+    }
+
+    fn err(&self) -> Error {
+        Error::InvalidHexRecord(self.line)
+    }
+}
+
+impl Iterator for HexReader<'_> {
+    type Item = Result<HexRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let line = self.lines.next()?.trim();
+            self.line += 1;
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let res = match self.format {
+                Some(HexFormat::IntelHex) => self.ihex_record(line),
+                Some(HexFormat::Srec) => self.srec_record(line),
+                None => Err(self.err()),
+            };
+
+            match res {
+                Ok(Some(rec)) => return Some(Ok(rec)),
+                Ok(None) => (),
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Writes data at absolute addresses as Intel HEX or Motorola S-record text.
+///
+/// Call [HexWriter::finish] after the last [HexWriter::write] to emit the end-of-file or
+/// termination record.
+pub struct HexWriter<W: fmt::Write> {
+    inner: W,
+    format: HexFormat,
+    upper_addr: Option<u16>,
+    started: bool,
+}
+
+impl<W: fmt::Write> HexWriter<W> {
+    /// Creates a new [HexWriter].
+    pub const fn new(inner: W, format: HexFormat) -> Self {
+        Self {
+            inner,
+            format,
+            upper_addr: None,
+            started: false,
+        }
+    }
+
+    /// Gets the output format.
+    pub const fn format(&self) -> HexFormat {
+        self.format
+    }
+
+    /// Writes data records for `data` starting at the absolute address `addr`.
+    ///
+    /// Returns an error if the data extends past the 32-bit address space.
+    pub fn write(&mut self, addr: u32, data: &[u8]) -> fmt::Result {
+        if addr as u64 + data.len() as u64 > u32::MAX as u64 + 1 {
+            return Err(fmt::Error);
+        }
+
+        self.start()?;
+
+        let mut pos = 0usize;
+        while pos < data.len() {
+            let cur = addr + pos as u32;
+            // Intel HEX data records must not cross a 64 KiB boundary
+            let max_len = match self.format {
+                HexFormat::IntelHex => 0x1_0000 - (cur & 0xffff) as usize,
+                HexFormat::Srec => HEX_LINE_LEN,
+            };
+            let len = HEX_LINE_LEN.min(max_len).min(data.len() - pos);
+            let chunk = &data[pos..pos + len];
+
+            match self.format {
+                HexFormat::IntelHex => {
+                    let upper = (cur >> 16) as u16;
+                    if self.upper_addr != Some(upper) {
+                        self.ihex_line(0, 0x04, &upper.to_be_bytes())?;
+                        self.upper_addr = Some(upper);
+                    }
+                    self.ihex_line(cur as u16, 0x00, chunk)?;
+                }
+                HexFormat::Srec => self.srec_line(b'3', &cur.to_be_bytes(), chunk)?,
+            }
+
+            pos += len;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the end-of-file or termination record, and returns the inner writer.
+    pub fn finish(mut self) -> core::result::Result<W, fmt::Error> {
+        self.start()?;
+
+        match self.format {
+            HexFormat::IntelHex => self.ihex_line(0, 0x01, &[])?,
+            HexFormat::Srec => self.srec_line(b'7', &[0u8; 4], &[])?,
+        }
+
+        Ok(self.inner)
+    }
+
+    fn start(&mut self) -> fmt::Result {
+        if !self.started {
+            self.started = true;
+            if self.format == HexFormat::Srec {
+                self.srec_line(b'0', &[0u8; 2], SREC_HEADER)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn ihex_line(&mut self, addr: u16, rec_type: u8, data: &[u8]) -> fmt::Result {
+        let [addr_hi, addr_lo] = addr.to_be_bytes();
+        let head = [data.len() as u8, addr_hi, addr_lo, rec_type];
+        let sum = checksum(&head).wrapping_add(checksum(data));
+
+        write!(self.inner, ":")?;
+        write_hex(&mut self.inner, &head)?;
+        write_hex(&mut self.inner, data)?;
+        writeln!(self.inner, "{:02X}", sum.wrapping_neg())
+    }
+
+    fn srec_line(&mut self, rec_type: u8, addr: &[u8], data: &[u8]) -> fmt::Result {
+        let count = (addr.len() + data.len() + 1) as u8;
+        let sum = count
+            .wrapping_add(checksum(addr))
+            .wrapping_add(checksum(data));
+
+        write!(self.inner, "S{}{count:02X}", rec_type as char)?;
+        write_hex(&mut self.inner, addr)?;
+        write_hex(&mut self.inner, data)?;
+        writeln!(self.inner, "{:02X}", !sum)
+    }
+}
+
+fn record(addr: u32, data: &[u8]) -> HexRecord {
+    let mut res = HexRecord {
+        addr,
+        len: data.len(),
+        data: [0u8; HEX_RECORD_MAX_LEN],
+    };
+    res.data[..data.len()].copy_from_slice(data);
+    res
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |acc, &b| acc.wrapping_add(b))
+}
+
+fn decode_hex<'b>(text: Option<&str>, buf: &'b mut [u8]) -> Option<&'b [u8]> {
+    let text = text?.as_bytes();
+    if text.len() % 2 != 0 || text.len() / 2 > buf.len() {
+        return None;
+    }
+
+    for (dst, pair) in buf.iter_mut().zip(text.chunks_exact(2)) {
+        let hi = (pair[0] as char).to_digit(16)?;
+        let lo = (pair[1] as char).to_digit(16)?;
+        *dst = (hi << 4 | lo) as u8;
+    }
+
+    Some(&buf[..text.len() / 2])
+}
+
+fn write_hex<W: fmt::Write>(w: &mut W, data: &[u8]) -> fmt::Result {
+    data.iter().try_for_each(|b| write!(w, "{b:02X}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::string::String;
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn read_all(text: &str) -> Result<Vec<(u32, Vec<u8>)>> {
+        HexReader::new(text)
+            .map(|rec| rec.map(|rec| (rec.addr(), rec.data().to_vec())))
+            .collect()
+    }
+
+    fn write_all(format: HexFormat, chunks: &[(u32, &[u8])]) -> String {
+        let mut writer = HexWriter::new(String::new(), format);
+        for (addr, data) in chunks {
+            writer.write(*addr, data).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            HexFormat::detect(b"\r\n  :10000000"),
+            Some(HexFormat::IntelHex)
+        );
+        assert_eq!(HexFormat::detect(b"S00B0000"), Some(HexFormat::Srec));
+        assert_eq!(HexFormat::detect(b":x"), None);
+        assert_eq!(HexFormat::detect(b"SX"), None);
+        assert_eq!(HexFormat::detect(b"\x40\x02\x00\x00"), None);
+        assert_eq!(HexFormat::detect(b""), None);
+    }
+
+    #[test]
+    fn test_read_ihex() {
+        let text =
+            ":0300300002337A1E\n:020000040800F2\n:02000000AABB99\n:00000001FF\n:0100000000FF\n";
+
+        let mut reader = HexReader::new(text);
+        assert_eq!(reader.format(), Some(HexFormat::IntelHex));
+        let rec = reader.next().unwrap().unwrap();
+        assert_eq!(rec.addr(), 0x30);
+        assert_eq!(rec.data(), [0x02, 0x33, 0x7a].as_ref());
+        assert_eq!(rec.end(), 0x33);
+
+        // extended linear address, and records after the end-of-file are ignored
+        let rec = reader.next().unwrap().unwrap();
+        assert_eq!(rec.addr(), 0x0800_0000);
+        assert_eq!(rec.data(), [0xaa, 0xbb].as_ref());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_read_ihex_segment_address() {
+        let recs = read_all(":020000021000EC\n:01001000559A\n:00000001FF\n").unwrap();
+        assert_eq!(recs, [(0x10010, [0x55].to_vec())]);
+    }
+
+    #[test]
+    fn test_read_srec() {
+        let text = "S00600004844521B\nS1130000285F245F2212226A000424290008237C2A\nS9030000FC\n";
+
+        let recs = read_all(text).unwrap();
+        assert_eq!(recs.len(), 1);
+        assert_eq!(recs[0].0, 0);
+        assert_eq!(
+            recs[0].1,
+            [
+                0x28, 0x5f, 0x24, 0x5f, 0x22, 0x12, 0x22, 0x6a, 0x00, 0x04, 0x24, 0x29, 0x00, 0x08,
+                0x23, 0x7c
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_invalid() {
+        // bad checksum on the second line
+        assert!(matches!(
+            read_all(":0100000000FF\n:0100000000FE\n"),
+            Err(Error::InvalidHexRecord(2))
+        ));
+        // odd number of digits
+        assert!(matches!(
+            read_all(":0100000000F"),
+            Err(Error::InvalidHexRecord(1))
+        ));
+        // length byte does not match the record
+        assert!(matches!(
+            read_all(":0200000000FE"),
+            Err(Error::InvalidHexRecord(1))
+        ));
+        // non-hex digit
+        assert!(matches!(
+            read_all(":01000000G0FF"),
+            Err(Error::InvalidHexRecord(1))
+        ));
+        // unknown Intel HEX record type
+        assert!(matches!(
+            read_all(":00000006FA"),
+            Err(Error::InvalidHexRecord(1))
+        ));
+        // extended address record with the wrong length
+        assert!(matches!(
+            read_all(":0100000400FB"),
+            Err(Error::InvalidHexRecord(1))
+        ));
+        // reserved S4 record
+        assert!(matches!(
+            read_all("S4030000FC"),
+            Err(Error::InvalidHexRecord(1))
+        ));
+        // S-record count shorter than the address
+        assert!(matches!(
+            read_all("S30100FE"),
+            Err(Error::InvalidHexRecord(1))
+        ));
+        // truncated lines
+        assert!(matches!(read_all(":"), Err(Error::InvalidHexRecord(1))));
+        assert!(matches!(read_all("S1"), Err(Error::InvalidHexRecord(1))));
+        // unknown format
+        assert!(matches!(
+            read_all("\nhello"),
+            Err(Error::InvalidHexRecord(2))
+        ));
+
+        // a line longer than the longest record
+        let mut long = String::from(":FF000000");
+        long.extend(core::iter::repeat_n('0', 2 * 300));
+        assert!(matches!(
+            read_all(long.as_str()),
+            Err(Error::InvalidHexRecord(1))
+        ));
+
+        // reading stops after the first error
+        let mut reader = HexReader::new(":00\n:0100000000FF\n");
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let data: Vec<u8> = (0..64u8).collect();
+
+        for format in [HexFormat::IntelHex, HexFormat::Srec] {
+            // crosses a 64 KiB boundary, and adds a second region
+            let text = write_all(format, &[(0xfff8, data.as_ref()), (0x20_0000, &data[..3])]);
+
+            let recs = read_all(text.as_str()).unwrap();
+            let mut img = Vec::new();
+            for (addr, rec) in recs.iter() {
+                assert!(rec.len() <= HEX_LINE_LEN);
+                if format == HexFormat::IntelHex {
+                    // data records never cross a 64 KiB boundary
+                    assert_eq!(addr >> 16, (addr + rec.len() as u32 - 1) >> 16);
+                }
+                if *addr < 0x20_0000 {
+                    assert_eq!(*addr as usize, 0xfff8 + img.len());
+                    img.extend_from_slice(rec);
+                }
+            }
+            assert_eq!(img, data);
+            assert_eq!(recs.last().unwrap(), &(0x20_0000, data[..3].to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_write_known() {
+        assert_eq!(
+            write_all(HexFormat::IntelHex, &[(0x30, &[0x02, 0x33, 0x7a])]),
+            ":020000040000FA\n:0300300002337A1E\n:00000001FF\n"
+        );
+        assert_eq!(
+            write_all(HexFormat::Srec, &[]),
+            "S00B000073706C5F746F6F6C88\nS70500000000FA\n"
+        );
+    }
+
+    #[test]
+    fn test_write_overflow() {
+        let mut writer = HexWriter::new(String::new(), HexFormat::IntelHex);
+
+        assert!(writer.write(u32::MAX, &[0, 0]).is_err());
+        assert!(writer.write(u32::MAX, &[0x55]).is_ok());
+
+        let text = writer.finish().unwrap();
+        assert_eq!(
+            read_all(text.as_str()).unwrap(),
+            [(u32::MAX, [0x55].to_vec())]
+        );
+    }
+}
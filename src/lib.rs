@@ -6,10 +6,64 @@
 
 #![no_std]
 
+// Provides the panic handler for the `cdylib`/`staticlib` builds of the C API, the
+// `std::error::Error` and `std::io` integrations, and allocations in unit tests.
+#[cfg(any(feature = "capi", feature = "std", test))]
+extern crate std;
+
+#[cfg(feature = "capi")]
+pub mod capi;
+
 mod crc32;
+mod elf;
 mod error;
+mod fit;
+mod flash_layout;
+mod gpt;
+mod hex_file;
+#[cfg(feature = "embedded-storage")]
+mod nor_flash;
+#[cfg(feature = "std")]
+mod path_error;
+mod sbl_config;
+#[cfg(feature = "secure-boot")]
+mod secure_boot;
+mod sha256;
+mod sparse;
+mod spl_diff;
 mod spl_header;
+mod spl_header_view;
+mod spl_health;
+mod spl_image;
+#[cfg(feature = "std")]
+mod spl_io;
+mod spl_scan;
+mod uboot_env;
+mod uimage;
 
 pub use crc32::*;
+pub use elf::*;
 pub use error::*;
+pub use fit::*;
+pub use flash_layout::*;
+pub use gpt::*;
+pub use hex_file::*;
+#[cfg(feature = "embedded-storage")]
+pub use nor_flash::*;
+#[cfg(feature = "std")]
+pub use path_error::*;
+pub use sbl_config::*;
+#[cfg(feature = "secure-boot")]
+pub use secure_boot::*;
+pub use sha256::*;
+pub use sparse::*;
+pub use spl_diff::*;
 pub use spl_header::*;
+pub use spl_header_view::*;
+pub use spl_health::*;
+pub use spl_image::*;
+#[cfg(feature = "std")]
+pub use spl_io::*;
+pub use spl_scan::*;
+pub use uboot_env::*;
+pub use uimage::*;
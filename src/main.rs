@@ -3,29 +3,84 @@
 #[cfg(feature = "cli")]
 use std::fs;
 #[cfg(feature = "cli")]
-use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "cli")]
+use std::process::ExitCode;
 
 #[cfg(feature = "cli")]
 use clap::Parser;
 
 #[cfg(feature = "cli")]
-use spl_tool::{crc32, crc32_final};
-use spl_tool::{Error, Result};
+mod cli;
+
+#[cfg(feature = "cli")]
+use cli::analyze::{spl_analyze, AnalyzeArgs};
+#[cfg(feature = "cli")]
+use cli::diff::spl_diff;
+#[cfg(feature = "cli")]
+use cli::env::{env_get, env_import, env_set, EnvRegion};
+#[cfg(feature = "cli")]
+use cli::export::{spl_export, ExportArgs};
+#[cfg(feature = "cli")]
+use cli::fix::{spl_fix_image_header, spl_unfix_image};
+#[cfg(feature = "cli")]
+use cli::gpt::{gpt_check, gpt_repair, parse_sector_len, GptSource};
+#[cfg(feature = "cli")]
+use cli::image::write_hex_image;
+#[cfg(feature = "cli")]
+use cli::info::{spl_info, spl_verify};
+#[cfg(feature = "cli")]
+use cli::manifest::Manifest;
+#[cfg(feature = "cli")]
+use cli::output::write_atomic;
 #[cfg(feature = "cli")]
-use spl_tool::{HeaderConf, UbootSplHeader};
+use cli::scan::spl_scan;
 #[cfg(feature = "cli")]
-use spl_tool::{CRC_FAILED, DEF_BACKUP, DEF_SPL_FILE, MAX_SPL_LEN, SPL_HEADER_LEN};
+use cli::script::{spl_script, ScriptArgs};
+#[cfg(all(feature = "cli", feature = "secure-boot"))]
+use cli::sign::{load_signing_key, spl_verify_signature};
+#[cfg(feature = "cli")]
+use cli::update::{spl_flash_update, FlashUpdateArgs};
+#[cfg(feature = "cli")]
+use cli::write::{spl_write, WriteArgs};
+#[cfg(feature = "cli")]
+use cli::{conf_path, parse_num, parse_offset, with_suffix, Result};
+#[cfg(all(feature = "cli", feature = "secure-boot"))]
+use spl_tool::SplSigningKey;
+
+use spl_tool::Error;
+#[cfg(not(feature = "cli"))]
+use spl_tool::Result;
+#[cfg(feature = "cli")]
+use spl_tool::{is_elf, HeaderConf, HexFormat, SblConfig, SplElf, UbootSplHeader};
+#[cfg(feature = "cli")]
+use spl_tool::{spl_check_len, spl_crc32, ErrorContext, IoContext};
+#[cfg(feature = "cli")]
+use spl_tool::{
+    DEF_SCAN_ALIGN, DEF_SPL_FILE, JH7110_SRAM_BASE, JH7110_SRAM_LEN, MAX_SPL_LEN, SPL_HEADER_LEN,
+};
 
 #[derive(clap::Parser, Debug)]
 #[command(author, about, long_about = None)]
 #[cfg(feature = "cli")]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// Create the SPL header
     #[arg(short = 'c', long = "create-splhdr", default_value = "false")]
     create_spl_header: bool,
     /// Fix the IMG header
     #[arg(short = 'i', long = "fix-imghdr", default_value = "false")]
     fix_img_header: bool,
+    /// Logical sector size of the disk image fixed with --fix-imghdr [default: detected from the GPT signature position, or 512]
+    #[arg(long = "sector-size", value_parser = parse_sector_len, requires = "fix_img_header")]
+    sector_size: Option<usize>,
+    /// Only print the byte changes --fix-imghdr would make
+    #[arg(long = "dry-run", default_value = "false", requires = "fix_img_header")]
+    dry_run: bool,
+    /// Sidecar file --fix-imghdr saves the original bytes to [default: <FILE>.fix-imghdr]
+    #[arg(long = "undo-file", requires = "fix_img_header")]
+    undo_file: Option<PathBuf>,
     /// Provide a custom SBL_BAK_OFFSET address, default value: 0x200000
     #[arg(short = 'b', long = "sbl-bak-addr", default_value = "0")]
     sbl_bak_addr: u32,
@@ -34,29 +89,239 @@ struct Args {
     version: u32,
     /// Provide a SPL filename
     #[arg(short = 'f', long = "file")]
-    file: Option<String>,
+    file: Option<PathBuf>,
+    /// Provide a vendor `input_sbl_normal.cfg` file, command-line options take precedence
+    ///
+    /// A relative `SBL_FILE` is resolved against the directory of the config file.
+    #[arg(short = 'g', long = "sbl-cfg")]
+    sbl_cfg: Option<String>,
+    /// Also write the created SPL image as Intel HEX or Motorola S-record
+    #[arg(short = 'x', long = "hex-format", value_enum)]
+    hex_format: Option<OutputFormat>,
+    /// Write both the primary copy at 0 and the backup copy at `bofs` to the HEX/SREC image
+    #[arg(
+        short = 'l',
+        long = "flash-layout",
+        default_value = "false",
+        requires = "hex_format"
+    )]
+    flash_layout: bool,
+    /// Write a JSON manifest with input/output digests of the created SPL image
+    #[arg(short = 'm', long = "manifest")]
+    manifest: Option<String>,
+    /// Sign the created SPL image with a PEM private key (ECDSA P-256 or RSA-2048, experimental)
+    #[cfg(feature = "secure-boot")]
+    #[arg(short = 'k', long = "sign-key")]
+    sign_key: Option<String>,
+    /// Verify the signature of a signed SPL image (experimental)
+    #[cfg(feature = "secure-boot")]
+    #[arg(short = 's', long = "verify-sig", default_value = "false")]
+    verify_sig: bool,
+    /// Provide a trusted PEM public key the SPL image must be signed with
+    #[cfg(feature = "secure-boot")]
+    #[arg(short = 'p', long = "pub-key")]
+    pub_key: Option<String>,
 }
 
-fn main() -> Result<()> {
-    spl_main()
+#[derive(clap::Subcommand, Debug)]
+#[cfg(feature = "cli")]
+enum Command {
+    /// Compare two headered SPL images field by field
+    Diff {
+        /// Old SPL image
+        old: String,
+        /// New SPL image
+        new: String,
+    },
+    /// Print the header fields and status of an SPL image or flash layout (binary, HEX or SREC)
+    Info {
+        /// SPL image or flash layout
+        image: String,
+    },
+    /// Verify the header and CRC32 of an SPL image or flash layout (binary, HEX or SREC)
+    Verify {
+        /// SPL image or flash layout
+        image: String,
+    },
+    /// Read or modify a U-Boot environment blob or flash dump
+    #[command(subcommand)]
+    Env(EnvCommand),
+    /// Search a flash dump or disk image for plausible SPL headers
+    Scan {
+        /// Flash dump or disk image
+        image: String,
+        /// Alignment of the checked offsets
+        #[arg(short = 'a', long = "align", default_value_t = DEF_SCAN_ALIGN as u32, value_parser = parse_num)]
+        align: u32,
+        /// Offset to start scanning from
+        #[arg(short = 's', long = "start", default_value = "0", value_parser = parse_offset)]
+        start: u64,
+    },
+    /// Generate a `boot.scr` script image that flashes the SPL and FIT images to the SPI flash
+    Script(ScriptArgs),
+    /// Check and extract the SPL copies, U-Boot env and FIT image of a raw SPI flash dump
+    Analyze(AnalyzeArgs),
+    /// Replace the SPL copies in an existing flash image, leaving the env and FIT untouched
+    FlashUpdate(FlashUpdateArgs),
+    /// Validate or repair the GPT of a disk image
+    #[command(subcommand)]
+    Gpt(GptCommand),
+    /// Write a disk or SPL image to a block device or file, and verify the written SPL copies
+    Write(WriteArgs),
+    /// Export a disk image as a block map file and/or an Android sparse image
+    Export(ExportArgs),
+    /// Restore the original bytes overwritten by --fix-imghdr from its undo record
+    UnfixImg {
+        /// Image fixed with --fix-imghdr
+        image: PathBuf,
+        /// Undo record saved by --fix-imghdr [default: <IMAGE>.fix-imghdr]
+        #[arg(long = "undo-file")]
+        undo_file: Option<PathBuf>,
+        /// Only print the byte changes that would be made
+        #[arg(long = "dry-run", default_value = "false")]
+        dry_run: bool,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+#[cfg(feature = "cli")]
+enum EnvCommand {
+    /// Print all variables, or the values of the provided variables
+    Get {
+        #[command(flatten)]
+        region: EnvRegion,
+        /// Variable names
+        keys: Vec<String>,
+    },
+    /// Set a variable, or delete it if no value is provided
+    Set {
+        #[command(flatten)]
+        region: EnvRegion,
+        /// Variable name
+        key: String,
+        /// Variable value
+        value: Option<String>,
+    },
+    /// Replace all variables from a `mkenvimage` text file, creating the image if needed
+    Import {
+        #[command(flatten)]
+        region: EnvRegion,
+        /// Text file with one `key=value` per line
+        text: String,
+    },
+}
+
+#[derive(clap::Subcommand, Debug)]
+#[cfg(feature = "cli")]
+enum GptCommand {
+    /// Validate the protective MBR, both GPT copies, and the partition entry CRC32s
+    Check {
+        /// Disk image or block device
+        image: String,
+        /// Logical sector size [default: detected from the GPT signature position, or 512]
+        #[arg(long = "sector-size", value_parser = parse_sector_len)]
+        sector_size: Option<usize>,
+    },
+    /// Rebuild the damaged or mismatched GPT copy from the intact one
+    Repair {
+        /// Disk image or block device
+        image: String,
+        /// Copy to rebuild from [default: primary if intact, otherwise backup]
+        #[arg(long = "from", value_enum)]
+        from: Option<GptSource>,
+        /// Logical sector size [default: detected from the GPT signature position, or 512]
+        #[arg(long = "sector-size", value_parser = parse_sector_len)]
+        sector_size: Option<usize>,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+#[cfg(feature = "cli")]
+enum OutputFormat {
+    /// Intel HEX
+    Ihex,
+    /// Motorola S-record
+    Srec,
+}
+
+#[cfg(feature = "cli")]
+impl From<OutputFormat> for HexFormat {
+    fn from(val: OutputFormat) -> Self {
+        match val {
+            OutputFormat::Ihex => Self::IntelHex,
+            OutputFormat::Srec => Self::Srec,
+        }
+    }
 }
 
 #[cfg(not(feature = "cli"))]
-fn spl_main() -> Result<()> {
+fn main() -> Result<()> {
     println!("The CLI application requires the `cli` feature. Please re-compile with: cargo build --features cli");
     Err(Error::RequiresCliFeature)
 }
 
+#[cfg(feature = "cli")]
+fn main() -> ExitCode {
+    match spl_main() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => ExitCode::from(cli::report(&err)),
+    }
+}
+
 #[cfg(feature = "cli")]
 fn spl_main() -> Result<()> {
     env_logger::init();
 
     let args = Args::parse();
-    let file = match args.file {
-        Some(f) => f,
-        None => {
+
+    if let Some(command) = args.command {
+        return match command {
+            Command::Diff { old, new } => spl_diff(old.as_str(), new.as_str()),
+            Command::Info { image } => spl_info(image.as_str()),
+            Command::Verify { image } => spl_verify(image.as_str()),
+            Command::Env(EnvCommand::Get { region, keys }) => env_get(&region, keys.as_ref()),
+            Command::Env(EnvCommand::Set { region, key, value }) => {
+                env_set(&region, key.as_str(), value.as_deref())
+            }
+            Command::Env(EnvCommand::Import { region, text }) => env_import(&region, text.as_str()),
+            Command::Scan {
+                image,
+                align,
+                start,
+            } => spl_scan(image.as_str(), align as u64, start),
+            Command::Script(script) => spl_script(&script),
+            Command::Analyze(analyze) => spl_analyze(&analyze),
+            Command::FlashUpdate(update) => spl_flash_update(&update),
+            Command::Gpt(GptCommand::Check { image, sector_size }) => {
+                gpt_check(image.as_str(), sector_size)
+            }
+            Command::Gpt(GptCommand::Repair {
+                image,
+                from,
+                sector_size,
+            }) => gpt_repair(image.as_str(), from, sector_size),
+            Command::Write(write) => spl_write(&write),
+            Command::Export(export) => spl_export(&export),
+            Command::UnfixImg {
+                image,
+                undo_file,
+                dry_run,
+            } => spl_unfix_image(&image, undo_file.as_deref(), dry_run),
+        };
+    }
+
+    let cfg_str = match args.sbl_cfg.as_ref() {
+        Some(path) => fs::read_to_string(path).io_context(Error::InvalidConfigFile, path)?,
+        None => String::new(),
+    };
+    let cfg = SblConfig::parse(cfg_str.as_str())?;
+
+    let file = match (args.file, cfg.name()) {
+        (Some(f), _) => f,
+        (None, Some(f)) => sbl_cfg_file(args.sbl_cfg.as_deref().unwrap_or_default(), f),
+        (None, None) => {
             log::debug!("no SPL file provided, trying {DEF_SPL_FILE}");
-            DEF_SPL_FILE.to_owned()
+            PathBuf::from(DEF_SPL_FILE)
         }
     };
 
@@ -65,28 +330,82 @@ fn spl_main() -> Result<()> {
     let version = args.version;
     let bofs = args.sbl_bak_addr;
 
-    let conf = HeaderConf::new()
-        .with_name(file.as_str())
-        .with_vers(version)
-        .with_bofs(bofs)
-        .with_create_header(create_spl_header)
-        .with_fix_image_header(fix_img_header);
+    let mut conf = HeaderConf::new();
+    cfg.apply_conf(&mut conf);
+
+    let mut header = UbootSplHeader::new();
+    cfg.apply_header(&mut header);
+
+    conf.set_path(&file);
+    conf.set_create_header(create_spl_header);
+    conf.set_fix_image_header(fix_img_header);
+
+    if version != 0 {
+        conf.set_vers(version);
+    }
+    if bofs != 0 {
+        conf.set_bofs(bofs);
+    }
+
+    log::info!("Using SPL file: {}", file.display());
+
+    #[cfg(feature = "secure-boot")]
+    let sign_key = args.sign_key.as_deref().map(load_signing_key).transpose()?;
 
-    log::info!("Using SPL file: {file}");
+    let mut manifest = spl_create_header(
+        &conf,
+        header,
+        #[cfg(feature = "secure-boot")]
+        sign_key.as_ref(),
+    )?;
 
-    spl_create_header(&conf)?;
-    spl_fix_image_header(&conf)?;
+    if let (true, Some(format)) = (conf.create_header(), args.hex_format) {
+        let outpath = with_suffix(&file, ".normal.out");
+        let (hex_path, digest) = write_hex_image(&outpath, format.into(), args.flash_layout)?;
+
+        if let Some(manifest) = manifest.as_mut() {
+            manifest.set_hex_output(&hex_path, format.into(), args.flash_layout, digest);
+        }
+    }
+
+    // written last, so the manifest also covers the HEX/SREC flash layout output
+    if let (Some(path), Some(manifest)) = (args.manifest.as_deref(), manifest.as_ref()) {
+        manifest.write(path)?;
+    }
+
+    spl_fix_image_header(
+        &conf,
+        args.sector_size,
+        args.dry_run,
+        args.undo_file.as_deref(),
+    )?;
+
+    #[cfg(feature = "secure-boot")]
+    if args.verify_sig {
+        spl_verify_signature(&file, args.pub_key.as_deref())?;
+    }
 
     Ok(())
 }
 
+// resolves a relative `SBL_FILE` against the directory of the config file
+#[cfg(feature = "cli")]
+fn sbl_cfg_file(cfg_path: &str, name: &str) -> PathBuf {
+    Path::new(cfg_path)
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(name)
+}
+
 #[cfg(feature = "cli")]
-fn spl_create_header(conf: &HeaderConf) -> Result<()> {
+fn spl_create_header(
+    conf: &HeaderConf,
+    mut header: UbootSplHeader,
+    #[cfg(feature = "secure-boot")] sign_key: Option<&SplSigningKey>,
+) -> Result<Option<Manifest>> {
     if !conf.create_header() {
-        Ok(())
+        Ok(None)
     } else {
-        let mut header = UbootSplHeader::new();
-
         if conf.bofs() != 0 {
             header.set_bofs(conf.bofs());
         }
@@ -94,115 +413,167 @@ fn spl_create_header(conf: &HeaderConf) -> Result<()> {
             header.set_vers(conf.vers());
         }
 
+        let resl = header.resl() as usize;
+        if resl < SPL_HEADER_LEN {
+            log::error!("Invalid SPL image offset: {resl:#x}, minimum: {SPL_HEADER_LEN:#x}");
+            return Err(Error::InvalidResl(header.resl()).into());
+        }
+
         let sofs = header.sofs();
         let bofs = header.bofs();
         let vers = header.vers();
-        let name = conf.name();
+        let path = conf_path(conf)?;
+        let name = path.display();
 
         log::info!("ubsplhdr.sofs: {sofs:#x}, ubsplhdr.bofs: {bofs:#x}, ubsplhdr.vers: {vers:#x}, name: {name}");
 
+        let input = fs::read(path).io_context(Error::InvalidSplFile, path)?;
         let mut ubootspl = [0u8; MAX_SPL_LEN];
-        let sz = {
-            // enter limited scope to close file after reading.
-            let mut f = fs::File::open(name).map_err(|err| {
-                log::error!("Error opening SPL image file {name}: {err}");
-                Error::InvalidSplFile
-            })?;
-            f.read(&mut ubootspl).map_err(|err| {
-                log::error!("Error reading from SPL image file {name}: {err}");
-                Error::InvalidSplFile
-            })?
-        };
+        let sz = spl_read_payload(path, input.as_ref(), &mut ubootspl)?;
 
-        if sz >= MAX_SPL_LEN {
-            log::error!("File too large! Please rebuild your SPL with -Os. Maximum allowed size is {MAX_SPL_LEN} bytes.");
-            Err(Error::InvalidSplLen((sz, MAX_SPL_LEN)))
-        } else if sz == 0 {
-            log::error!("Empty SPL file.");
-            Err(Error::InvalidSplLen((sz, MAX_SPL_LEN)))
+        if let Err(err) = spl_check_len(sz) {
+            if sz == 0 {
+                log::error!("Empty SPL file.");
+            } else {
+                log::error!("File too large! Please rebuild your SPL with -Os. Maximum allowed size is {} bytes.", MAX_SPL_LEN - 1);
+            }
+            Err(err.into())
         } else {
             header.set_fsiz(sz as u32);
-            let outpath = format!("{name}.normal.out");
-            let mut out = fs::File::create(outpath.as_str()).map_err(|err| {
-                log::error!("Error creating {outpath} file: {err}");
-                Error::InvalidHeaderFile
-            })?;
+            let outpath = with_suffix(path, ".normal.out");
 
-            let v = crc32(!0, 0x04c1_1db7, &ubootspl[..sz]);
-            header.set_crcs(crc32_final(v));
+            header.set_crcs(spl_crc32(&ubootspl[..sz]));
+
+            #[cfg(feature = "secure-boot")]
+            if let Some(key) = sign_key {
+                header.sign(key, &ubootspl[..sz])?;
+                log::info!("SPL signed with {:?} key.", key.alg());
+            }
+
+            let mut out = Vec::with_capacity(resl + sz);
 
             {
                 // enter limited scope to remove header bytes from stack after writing
                 let header_bytes: [u8; SPL_HEADER_LEN] = header.into();
-
-                out.write_all(header_bytes.as_ref()).map_err(|err| {
-                    log::error!("Error writing SPL header to {outpath} file: {err}");
-                    Error::InvalidHeaderFile
-                })?;
+                out.extend_from_slice(header_bytes.as_ref());
             }
 
-            out.write_all(ubootspl[..sz].as_ref()).map_err(|err| {
-                log::error!("Error writing SPL image to {outpath} file: {err}");
-                Error::InvalidSplFile
-            })?;
+            // pad the gap between the header and a relocated SPL image
+            out.resize(resl, 0);
+            out.extend_from_slice(ubootspl[..sz].as_ref());
 
-            log::info!("SPL written to {outpath} successfully.");
+            // write to a temporary file and rename, so a crash never leaves a partial image
+            write_atomic(&outpath, out.as_slice())
+                .io_context(Error::InvalidHeaderFile, &outpath)?;
 
-            Ok(())
+            log::info!("SPL written to {} successfully.", outpath.display());
+
+            Ok(Some(Manifest::new(
+                path,
+                input.as_ref(),
+                &ubootspl[..sz],
+                &header,
+                &outpath,
+                out.as_ref(),
+            )))
         }
     }
 }
 
+/// Copies the SPL payload from the `input` file into the buffer, flattening ELF input.
+///
+/// Returns the length of the payload, which may exceed the buffer length for raw binaries.
 #[cfg(feature = "cli")]
-fn spl_fix_image_header(conf: &HeaderConf) -> Result<()> {
-    if !conf.fix_image_header() {
-        Ok(())
-    } else {
-        let name = conf.name();
-        let mut img_bytes = [0u8; SPL_HEADER_LEN];
+fn spl_read_payload(path: &Path, input: &[u8], buf: &mut [u8]) -> Result<usize> {
+    let name = path.display();
 
-        let mut file = fs::File::open(name).map_err(|err| {
-            log::error!("Error opening SPL image {name}: {err}");
-            Error::InvalidSplFile
+    if is_elf(input) {
+        let elf = SplElf::parse(input).inspect_err(|err| {
+            log::error!("Error parsing SPL ELF file {name}: {err}");
         })?;
 
-        file.read_exact(&mut img_bytes).map_err(|err| {
-            log::error!("Error reading header from SPL image {name}: {err}");
-            Error::InvalidSplFile
-        })?;
-
-        // From `spl_tool` C implementation:
-        //
-        // When starting with emmc, bootrom will read 0x0 instead of partition 0. (Known issues).
-        // Read GPT PMBR+Header, then write the backup address at 0x4, and write the wrong CRC
-        // check value at 0x290, so that bootrom CRC check fails and jump to the backup address
-        // to load the real SPL.
-        let mut img_header = UbootSplHeader::try_from(img_bytes)?;
-
-        img_header.set_bofs(if conf.bofs() != 0 {
-            conf.bofs()
-        } else {
-            DEF_BACKUP
-        });
-
-        img_header.set_crcs(CRC_FAILED);
+        let range = elf.load_range()?;
+        let entry = elf.entry();
+        log::info!(
+            "Flattening SPL ELF file {name}, load address: {:#x}, entry: {entry:#x}",
+            range.start
+        );
 
-        file.seek(io::SeekFrom::Start(0)).map_err(|err| {
-            log::error!("Error seeking to beginning of SPL image {name}: {err}");
-            Error::InvalidSplFile
+        elf.check_sram().inspect_err(|err| {
+            log::error!("SPL ELF file {name} does not fit the JH7110 SRAM ({JH7110_SRAM_BASE:#x}..{:#x}): {err}", JH7110_SRAM_BASE + JH7110_SRAM_LEN);
         })?;
 
-        {
-            // enter limited scope to remove header bytes from stack after writing
-            let hdr_bytes: [u8; SPL_HEADER_LEN] = img_header.into();
-            file.write_all(&hdr_bytes).map_err(|err| {
-                log::error!("Error writing fixed header back to SPL image {name}: {err}");
-                Error::InvalidSplFile
-            })?;
-        }
+        elf.flatten(buf)
+            .inspect_err(|_| {
+                log::error!("File too large! Please rebuild your SPL with -Os. Maximum allowed size is {} bytes.", MAX_SPL_LEN - 1);
+            })
+            .path_context(path)
+    } else {
+        let len = core::cmp::min(input.len(), buf.len());
+        buf[..len].copy_from_slice(&input[..len]);
 
-        log::info!("IMG {name} fixed header successfully.");
+        Ok(input.len())
+    }
+}
+
+#[cfg(all(test, feature = "cli"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sbl_cfg_file() {
+        assert_eq!(
+            sbl_cfg_file("board/input_sbl_normal.cfg", "u-boot-spl.bin"),
+            Path::new("board/u-boot-spl.bin")
+        );
+        assert_eq!(
+            sbl_cfg_file("input_sbl_normal.cfg", "u-boot-spl.bin"),
+            Path::new("u-boot-spl.bin")
+        );
+        assert_eq!(
+            sbl_cfg_file("board/input_sbl_normal.cfg", "/tmp/u-boot-spl.bin"),
+            Path::new("/tmp/u-boot-spl.bin")
+        );
+    }
 
-        Ok(())
+    #[cfg(unix)]
+    #[test]
+    fn test_create_header_non_utf8_name() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let payload = [0x13u8, 0x00, 0x00, 0x00, 0x6f, 0x00, 0x00, 0x00];
+
+        let dir = std::env::temp_dir().join(format!("spl_tool-non-utf8-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let file = dir.join(OsStr::from_bytes(b"u-boot-spl-\xff.bin"));
+        fs::write(&file, payload).unwrap();
+
+        let name = file.as_os_str().as_bytes();
+        let conf = HeaderConf::new()
+            .with_name_bytes(name)
+            .with_create_header(true);
+        assert_eq!(conf_path(&conf).unwrap(), file.as_path());
+
+        let manifest = spl_create_header(
+            &conf,
+            UbootSplHeader::new(),
+            #[cfg(feature = "secure-boot")]
+            None,
+        )
+        .unwrap();
+        assert!(manifest.is_some());
+
+        // the output keeps the raw file name, with the suffix appended
+        let outpath = dir.join(OsStr::from_bytes(b"u-boot-spl-\xff.bin.normal.out"));
+        assert_eq!(with_suffix(&file, ".normal.out"), outpath);
+
+        let out = fs::read(&outpath).unwrap();
+        let header = UbootSplHeader::try_from(out.as_slice()).unwrap();
+        assert!(header.verify_payload(&out[SPL_HEADER_LEN..]).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
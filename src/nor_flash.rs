@@ -0,0 +1,594 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+
+use super::{
+    spl_health_check, spl_stream_crc, Error, Result, SplHealth, UbootSplHeader, SPL_HEADER_LEN,
+};
+
+/// Length of the stack buffer used to stream data to and from flash.
+///
+/// The `READ_SIZE` and `WRITE_SIZE` of the flash must evenly divide this length.
+pub const FLASH_CHUNK_LEN: usize = 256;
+
+const ZERO_CHUNK: [u8; FLASH_CHUNK_LEN] = [0u8; FLASH_CHUNK_LEN];
+
+/// Updates the primary and backup SPL copies in a [NorFlash].
+///
+/// The headered image (`header`, zero padding up to `resl`, then `payload`) is written to the
+/// backup copy at `bofs` first, read back and verified, then to the primary copy at offset `0`.
+/// A power loss during the update therefore always leaves at least one intact copy.
+///
+/// The `header` must already match the `payload`, see [UbootSplHeader::set_payload].
+///
+/// Example using an in-memory flash:
+///
+/// ```
+/// # use embedded_storage::nor_flash::{ErrorType, NorFlash, ReadNorFlash};
+/// # use spl_tool::{spl_flash_health, spl_update, spl_verify_copy, SplCopyStatus, UbootSplHeader};
+/// # use spl_tool::DEF_BACKUP;
+/// # struct RamFlash([u8; 0x8000]);
+/// # impl ErrorType for RamFlash { type Error = core::convert::Infallible; }
+/// # impl ReadNorFlash for RamFlash {
+/// #     const READ_SIZE: usize = 1;
+/// #     fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+/// #         let o = offset as usize;
+/// #         bytes.copy_from_slice(&self.0[o..o + bytes.len()]);
+/// #         Ok(())
+/// #     }
+/// #     fn capacity(&self) -> usize { self.0.len() }
+/// # }
+/// # impl NorFlash for RamFlash {
+/// #     const WRITE_SIZE: usize = 4;
+/// #     const ERASE_SIZE: usize = 0x1000;
+/// #     fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+/// #         assert_eq!(from as usize % Self::ERASE_SIZE, 0);
+/// #         assert_eq!(to as usize % Self::ERASE_SIZE, 0);
+/// #         self.0[from as usize..to as usize].fill(0xff);
+/// #         Ok(())
+/// #     }
+/// #     fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+/// #         assert_eq!(offset as usize % Self::WRITE_SIZE, 0);
+/// #         assert_eq!(bytes.len() % Self::WRITE_SIZE, 0);
+/// #         for (dst, src) in self.0[offset as usize..].iter_mut().zip(bytes) {
+/// #             // NOR flash can only clear bits
+/// #             *dst &= *src;
+/// #         }
+/// #         Ok(())
+/// #     }
+/// # }
+/// let mut flash = RamFlash([0u8; 0x8000]);
+/// let payload = [0xa5u8; 1001];
+/// let header = UbootSplHeader::new()
+///     .with_bofs(0x4000)
+///     .with_payload(payload.as_ref())
+///     .unwrap();
+///
+/// spl_update(&mut flash, &header, payload.as_ref()).unwrap();
+///
+/// assert!(spl_verify_copy(&mut flash, 0, &header).is_ok());
+/// assert!(spl_verify_copy(&mut flash, 0x4000, &header).is_ok());
+/// assert_eq!(&flash.0[0x400..0x400 + payload.len()], payload.as_ref());
+/// // the remainder of the last write unit is left erased
+/// assert_eq!(&flash.0[0x400 + payload.len()..0x400 + 1004], [0xff; 3].as_ref());
+///
+/// // a corrupted copy fails verification
+/// flash.0[0x4400] = 0;
+/// assert!(spl_verify_copy(&mut flash, 0x4000, &header).is_err());
+///
+/// // the health check reports the broken backup, while the primary is still bootable
+/// let health = spl_flash_health(&mut flash, DEF_BACKUP);
+/// assert!(health.primary.is_ok());
+/// assert!(matches!(health.backup, SplCopyStatus::CrcMismatch { .. }));
+/// assert!(health.is_bootable() && !health.is_ok());
+/// ```
+pub fn spl_update<F: NorFlash>(
+    flash: &mut F,
+    header: &UbootSplHeader,
+    payload: &[u8],
+) -> Result<()> {
+    header.verify_payload(payload)?;
+
+    let resl = header.resl() as usize;
+    if resl < SPL_HEADER_LEN {
+        return Err(Error::InvalidResl(header.resl()));
+    }
+
+    check_chunk_align::<F>()?;
+
+    let bofs = header.bofs();
+    if !(bofs as usize).is_multiple_of(F::ERASE_SIZE) {
+        return Err(Error::InvalidFlashAlign(bofs));
+    }
+
+    // the primary copy must not run into the backup copy
+    let img_len = resl.saturating_add(payload.len());
+    if img_len > bofs as usize {
+        return Err(Error::InvalidSplLen((img_len, bofs as usize)));
+    }
+
+    let end = (bofs as usize).saturating_add(img_len);
+    if end > flash.capacity() {
+        return Err(Error::InvalidSplLen((end, flash.capacity())));
+    }
+
+    spl_write_copy(flash, bofs, header, payload)?;
+    spl_verify_copy(flash, bofs, header)?;
+
+    spl_write_copy(flash, 0, header, payload)?;
+    spl_verify_copy(flash, 0, header)
+}
+
+/// Erases the covered sectors, and writes a headered SPL image to a [NorFlash] at `offset`.
+///
+/// `offset` must be aligned to the `ERASE_SIZE` of the flash.
+pub fn spl_write_copy<F: NorFlash>(
+    flash: &mut F,
+    offset: u32,
+    header: &UbootSplHeader,
+    payload: &[u8],
+) -> Result<()> {
+    check_chunk_align::<F>()?;
+
+    if !(offset as usize).is_multiple_of(F::ERASE_SIZE) {
+        return Err(Error::InvalidFlashAlign(offset));
+    }
+
+    let resl = header.resl() as usize;
+    let img_len = resl.saturating_add(payload.len());
+    let erase_len = img_len
+        .div_ceil(F::ERASE_SIZE)
+        .saturating_mul(F::ERASE_SIZE);
+    let erase_end = (offset as usize).saturating_add(erase_len);
+
+    if erase_end > flash.capacity() || resl < SPL_HEADER_LEN {
+        return Err(Error::InvalidSplLen((erase_end, flash.capacity())));
+    }
+
+    flash
+        .erase(offset, erase_end as u32)
+        .map_err(|_| Error::FlashErase(offset))?;
+
+    let mut writer = FlashWriter::new(flash, offset);
+
+    {
+        // enter limited scope to remove header bytes from stack after writing
+        let header_bytes: [u8; SPL_HEADER_LEN] = header.into();
+        writer.write(header_bytes.as_ref())?;
+    }
+
+    // pad the gap between the header and a relocated SPL image
+    let mut pad_len = resl - SPL_HEADER_LEN;
+    while pad_len > 0 {
+        let len = core::cmp::min(pad_len, FLASH_CHUNK_LEN);
+        writer.write(&ZERO_CHUNK[..len])?;
+        pad_len -= len;
+    }
+
+    writer.write(payload)?;
+    writer.flush()
+}
+
+/// Reads back and verifies the SPL copy in a [ReadNorFlash] at `offset` against the `header`.
+///
+/// Checks that the stored header is identical, and that the CRC32 of the stored payload
+/// matches the `crcs` field.
+pub fn spl_verify_copy<F: ReadNorFlash>(
+    flash: &mut F,
+    offset: u32,
+    header: &UbootSplHeader,
+) -> Result<()> {
+    let mut stored = [0u8; SPL_HEADER_LEN];
+    spl_read(flash, offset, stored.as_mut())?;
+
+    let exp: [u8; SPL_HEADER_LEN] = header.into();
+    if stored != exp {
+        return Err(Error::FlashVerify(offset));
+    }
+
+    let crc = spl_read_crc(
+        flash,
+        offset.saturating_add(header.resl()),
+        header.fsiz() as usize,
+    )?;
+
+    if crc != header.crcs() {
+        Err(Error::InvalidCrc((crc, header.crcs())))
+    } else {
+        Ok(())
+    }
+}
+
+/// Calculates the SPL CRC32 of `len` bytes in a [ReadNorFlash] at `offset`.
+///
+/// The data is streamed through a small stack buffer, so the payload is never buffered in full.
+pub fn spl_read_crc<F: ReadNorFlash>(flash: &mut F, offset: u32, len: usize) -> Result<u32> {
+    spl_stream_crc(
+        &mut |off, buf: &mut [u8]| spl_read(flash, off, buf),
+        offset,
+        len,
+    )
+}
+
+/// Checks the primary and backup SPL copies in a [ReadNorFlash].
+///
+/// See [spl_health_check] for details, `fallback_bofs` is used when the primary header is
+/// unreadable.
+pub fn spl_flash_health<F: ReadNorFlash>(flash: &mut F, fallback_bofs: u32) -> SplHealth {
+    spl_health_check(
+        |off, buf: &mut [u8]| spl_read(flash, off, buf),
+        fallback_bofs,
+    )
+}
+
+/// Reads from a [ReadNorFlash], handling the `READ_SIZE` alignment of the flash.
+pub fn spl_read<F: ReadNorFlash>(flash: &mut F, offset: u32, buf: &mut [u8]) -> Result<()> {
+    for (idx, chunk) in buf.chunks_mut(FLASH_CHUNK_LEN).enumerate() {
+        let chunk_offset = offset.saturating_add((idx * FLASH_CHUNK_LEN) as u32);
+        read_chunk(flash, chunk_offset, chunk)?;
+    }
+
+    Ok(())
+}
+
+fn read_chunk<F: ReadNorFlash>(flash: &mut F, offset: u32, buf: &mut [u8]) -> Result<()> {
+    let read_size = F::READ_SIZE;
+    let start = (offset as usize) / read_size * read_size;
+    let head = offset as usize - start;
+    let aligned_len = (head + buf.len()).div_ceil(read_size) * read_size;
+
+    if head == 0 && aligned_len == buf.len() {
+        flash
+            .read(offset, buf)
+            .map_err(|_| Error::FlashRead(offset))
+    } else if aligned_len > FLASH_CHUNK_LEN * 3 || start + aligned_len > flash.capacity() {
+        Err(Error::InvalidFlashAlign(offset))
+    } else {
+        let mut aligned = [0u8; FLASH_CHUNK_LEN * 3];
+        flash
+            .read(start as u32, &mut aligned[..aligned_len])
+            .map_err(|_| Error::FlashRead(offset))?;
+        buf.copy_from_slice(&aligned[head..head + buf.len()]);
+        Ok(())
+    }
+}
+
+fn check_chunk_align<F: NorFlash>() -> Result<()> {
+    if !FLASH_CHUNK_LEN.is_multiple_of(F::WRITE_SIZE)
+        || !FLASH_CHUNK_LEN.is_multiple_of(F::READ_SIZE)
+    {
+        Err(Error::InvalidFlashAlign(F::WRITE_SIZE as u32))
+    } else {
+        Ok(())
+    }
+}
+
+/// Buffers writes into `WRITE_SIZE` aligned chunks.
+struct FlashWriter<'a, F: NorFlash> {
+    flash: &'a mut F,
+    offset: u32,
+    buf: [u8; FLASH_CHUNK_LEN],
+    len: usize,
+}
+
+impl<'a, F: NorFlash> FlashWriter<'a, F> {
+    fn new(flash: &'a mut F, offset: u32) -> Self {
+        Self {
+            flash,
+            offset,
+            buf: [0xff; FLASH_CHUNK_LEN],
+            len: 0,
+        }
+    }
+
+    fn write(&mut self, mut data: &[u8]) -> Result<()> {
+        while !data.is_empty() {
+            let len = core::cmp::min(FLASH_CHUNK_LEN - self.len, data.len());
+            self.buf[self.len..self.len + len].copy_from_slice(&data[..len]);
+            self.len += len;
+            data = &data[len..];
+
+            if self.len == FLASH_CHUNK_LEN {
+                self.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the buffered data, padding to the `WRITE_SIZE` with erased (`0xff`) bytes.
+    fn flush(&mut self) -> Result<()> {
+        if self.len == 0 {
+            return Ok(());
+        }
+
+        let len = self.len.div_ceil(F::WRITE_SIZE) * F::WRITE_SIZE;
+        self.buf[self.len..len].fill(0xff);
+
+        self.flash
+            .write(self.offset, &self.buf[..len])
+            .map_err(|_| Error::FlashWrite(self.offset))?;
+
+        self.offset = self.offset.saturating_add(len as u32);
+        self.buf.fill(0xff);
+        self.len = 0;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_storage::nor_flash::{ErrorType, NorFlashError, NorFlashErrorKind};
+    use std::{vec, vec::Vec};
+
+    use super::*;
+
+    const CAPACITY: usize = 0x10000;
+    const BOFS: u32 = 0x8000;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Op {
+        Read(u32),
+        Erase(u32, u32),
+        Write(u32),
+    }
+
+    #[derive(Debug)]
+    struct MockError;
+
+    impl NorFlashError for MockError {
+        fn kind(&self) -> NorFlashErrorKind {
+            NorFlashErrorKind::Other
+        }
+    }
+
+    // In-memory NOR flash that records operations, and can fail or corrupt writes.
+    struct MockFlash<const ERASE: usize> {
+        data: Vec<u8>,
+        ops: Vec<Op>,
+        // writes at or above this offset fail
+        fail_write_from: Option<u32>,
+        // writes in this range silently flip a bit
+        corrupt: Option<(u32, u32)>,
+    }
+
+    impl<const ERASE: usize> MockFlash<ERASE> {
+        fn new() -> Self {
+            Self {
+                data: vec![0xff; CAPACITY],
+                ops: Vec::new(),
+                fail_write_from: None,
+                corrupt: None,
+            }
+        }
+
+        fn first_op_at(&self, start: u32, end: u32) -> Option<usize> {
+            self.ops.iter().position(|op| match *op {
+                Op::Erase(off, _) | Op::Write(off) => (start..end).contains(&off),
+                Op::Read(_) => false,
+            })
+        }
+    }
+
+    impl<const ERASE: usize> ErrorType for MockFlash<ERASE> {
+        type Error = MockError;
+    }
+
+    impl<const ERASE: usize> ReadNorFlash for MockFlash<ERASE> {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> core::result::Result<(), MockError> {
+            self.ops.push(Op::Read(offset));
+            let off = offset as usize;
+            bytes.copy_from_slice(&self.data[off..off + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl<const ERASE: usize> NorFlash for MockFlash<ERASE> {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = ERASE;
+
+        fn erase(&mut self, from: u32, to: u32) -> core::result::Result<(), MockError> {
+            assert_eq!(from as usize % ERASE, 0);
+            assert_eq!(to as usize % ERASE, 0);
+
+            self.ops.push(Op::Erase(from, to));
+            self.data[from as usize..to as usize].fill(0xff);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> core::result::Result<(), MockError> {
+            assert_eq!(offset as usize % Self::WRITE_SIZE, 0);
+            assert_eq!(bytes.len() % Self::WRITE_SIZE, 0);
+
+            self.ops.push(Op::Write(offset));
+            if self.fail_write_from.is_some_and(|from| offset >= from) {
+                return Err(MockError);
+            }
+
+            for (dst, src) in self.data[offset as usize..].iter_mut().zip(bytes) {
+                // NOR flash can only clear bits
+                *dst &= *src;
+            }
+
+            if let Some((start, end)) = self.corrupt {
+                if (start..end).contains(&offset) {
+                    self.data[offset as usize] ^= 0x01;
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    fn image(fill: u8, len: usize) -> (UbootSplHeader, Vec<u8>) {
+        let payload = vec![fill; len];
+        let header = UbootSplHeader::new()
+            .with_bofs(BOFS)
+            .with_payload(payload.as_ref())
+            .unwrap();
+        (header, payload)
+    }
+
+    // flash holding an intact old image in both copies
+    fn old_flash<const ERASE: usize>() -> (MockFlash<ERASE>, UbootSplHeader) {
+        let mut flash = MockFlash::<ERASE>::new();
+        let (header, payload) = image(0x11, 0x800);
+
+        spl_write_copy(&mut flash, 0, &header, payload.as_ref()).unwrap();
+        spl_write_copy(&mut flash, BOFS, &header, payload.as_ref()).unwrap();
+        flash.ops.clear();
+
+        (flash, header)
+    }
+
+    #[test]
+    fn test_update_backup_first() {
+        let (mut flash, _) = old_flash::<0x1000>();
+        let (header, payload) = image(0x22, 0x1801);
+
+        spl_update(&mut flash, &header, payload.as_ref()).unwrap();
+
+        // the backup is erased, written and read back before the primary is touched
+        let backup = flash.first_op_at(BOFS, CAPACITY as u32).unwrap();
+        let primary = flash.first_op_at(0, BOFS).unwrap();
+        assert_eq!(flash.ops[backup], Op::Erase(BOFS, BOFS + 0x2000));
+        assert_eq!(flash.ops[primary], Op::Erase(0, 0x2000));
+        assert!(flash.ops[backup..primary]
+            .iter()
+            .any(|op| matches!(op, Op::Read(off) if *off == BOFS)));
+
+        assert!(spl_verify_copy(&mut flash, 0, &header).is_ok());
+        assert!(spl_verify_copy(&mut flash, BOFS, &header).is_ok());
+    }
+
+    #[test]
+    fn test_update_backup_write_failure() {
+        let (mut flash, old_header) = old_flash::<0x1000>();
+        let old_primary = flash.data[..BOFS as usize].to_vec();
+        let (header, payload) = image(0x22, 0x1000);
+
+        flash.fail_write_from = Some(BOFS);
+        assert!(matches!(
+            spl_update(&mut flash, &header, payload.as_ref()),
+            Err(Error::FlashWrite(BOFS))
+        ));
+
+        // the primary copy was never erased or written
+        assert_eq!(flash.first_op_at(0, BOFS), None);
+        assert_eq!(flash.data[..BOFS as usize], old_primary);
+        assert!(spl_verify_copy(&mut flash, 0, &old_header).is_ok());
+    }
+
+    #[test]
+    fn test_update_backup_verify_failure() {
+        let (mut flash, old_header) = old_flash::<0x1000>();
+        let old_primary = flash.data[..BOFS as usize].to_vec();
+        let (header, payload) = image(0x22, 0x1000);
+
+        // corrupt the stored payload of the backup copy
+        flash.corrupt = Some((BOFS + 0x400, BOFS + 0x800));
+        assert!(matches!(
+            spl_update(&mut flash, &header, payload.as_ref()),
+            Err(Error::InvalidCrc(_))
+        ));
+
+        assert_eq!(flash.first_op_at(0, BOFS), None);
+        assert_eq!(flash.data[..BOFS as usize], old_primary);
+        assert!(spl_verify_copy(&mut flash, 0, &old_header).is_ok());
+
+        // a corrupted header fails verification before the payload CRC
+        let (mut flash, _) = old_flash::<0x1000>();
+        flash.corrupt = Some((BOFS, BOFS + 1));
+        assert!(matches!(
+            spl_update(&mut flash, &header, payload.as_ref()),
+            Err(Error::FlashVerify(BOFS))
+        ));
+        assert_eq!(flash.first_op_at(0, BOFS), None);
+    }
+
+    #[test]
+    fn test_update_primary_failure_keeps_backup() {
+        let (mut flash, _) = old_flash::<0x1000>();
+        let (header, payload) = image(0x22, 0x1000);
+
+        // the primary write fails after the new backup is verified
+        flash.corrupt = Some((0x400, 0x800));
+        assert!(spl_update(&mut flash, &header, payload.as_ref()).is_err());
+
+        let health = spl_flash_health(&mut flash, BOFS);
+        assert!(!health.primary.is_ok());
+        assert!(health.backup.is_ok());
+        assert!(health.is_bootable());
+    }
+
+    #[test]
+    fn test_update_unaligned_erase_size() {
+        // 0x8000 is not a multiple of a 0x3000 erase sector
+        let mut flash = MockFlash::<0x3000>::new();
+        let (header, payload) = image(0x22, 0x100);
+
+        assert!(matches!(
+            spl_update(&mut flash, &header, payload.as_ref()),
+            Err(Error::InvalidFlashAlign(BOFS))
+        ));
+        assert!(flash.ops.is_empty());
+
+        // non power-of-two sectors work when `bofs` is aligned
+        let header = header.with_bofs(0x9000);
+        spl_update(&mut flash, &header, payload.as_ref()).unwrap();
+        assert_eq!(flash.ops[0], Op::Erase(0x9000, 0xc000));
+        assert!(spl_verify_copy(&mut flash, 0x9000, &header).is_ok());
+
+        assert!(matches!(
+            spl_write_copy(&mut flash, 0x1000, &header, payload.as_ref()),
+            Err(Error::InvalidFlashAlign(0x1000))
+        ));
+    }
+
+    #[test]
+    fn test_update_bofs_overlap() {
+        let mut flash = MockFlash::<0x1000>::new();
+
+        // the primary image runs into the backup copy
+        let (header, payload) = image(0x22, BOFS as usize - 0x400 + 1);
+        assert!(matches!(
+            spl_update(&mut flash, &header, payload.as_ref()),
+            Err(Error::InvalidSplLen(_))
+        ));
+
+        let header = header.with_bofs(0);
+        assert!(matches!(
+            spl_update(&mut flash, &header, payload.as_ref()),
+            Err(Error::InvalidSplLen(_))
+        ));
+
+        // the backup copy runs past the end of the flash
+        let (header, payload) = image(0x22, 0x1000);
+        let header = header.with_bofs(CAPACITY as u32 - 0x1000);
+        assert!(matches!(
+            spl_update(&mut flash, &header, payload.as_ref()),
+            Err(Error::InvalidSplLen(_))
+        ));
+
+        assert!(flash.ops.is_empty());
+    }
+
+    #[test]
+    fn test_update_mismatched_payload() {
+        let mut flash = MockFlash::<0x1000>::new();
+        let (header, payload) = image(0x22, 0x100);
+
+        assert!(matches!(
+            spl_update(&mut flash, &header, &payload[1..]),
+            Err(Error::InvalidSplLen(_))
+        ));
+        assert!(flash.ops.is_empty());
+    }
+}
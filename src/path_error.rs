@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use core::fmt;
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{Error, ErrorCategory, ErrorDetail};
+
+/// Represents an [Error] with the path of the file it occurred on, and the underlying I/O error.
+///
+/// Available with the `std` feature. The I/O error is returned from
+/// [source](std::error::Error::source), so the full chain is available to error reporters.
+///
+/// Example:
+///
+/// ```rust
+/// use std::error::Error as _;
+///
+/// use spl_tool::{Error, ErrorCategory, IoContext};
+///
+/// let err = std::fs::read("/nonexistent/u-boot-spl.bin")
+///     .io_context(Error::InvalidSplFile, "/nonexistent/u-boot-spl.bin")
+///     .unwrap_err();
+///
+/// assert_eq!(err.category(), ErrorCategory::Io);
+/// assert!(err.to_string().starts_with("/nonexistent/u-boot-spl.bin: "));
+/// assert!(err.source().is_some());
+/// ```
+#[derive(Debug)]
+pub struct PathError {
+    error: Error,
+    path: Option<PathBuf>,
+    source: Option<io::Error>,
+}
+
+impl PathError {
+    /// Creates a new [PathError] without context.
+    pub const fn new(error: Error) -> Self {
+        Self {
+            error,
+            path: None,
+            source: None,
+        }
+    }
+
+    /// Gets the library [Error].
+    pub const fn error(&self) -> Error {
+        self.error
+    }
+
+    /// Gets the path of the file the error occurred on.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Builder function that sets the path of the file the error occurred on.
+    pub fn with_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Gets the underlying I/O error.
+    pub fn io_error(&self) -> Option<&io::Error> {
+        self.source.as_ref()
+    }
+
+    /// Builder function that sets the underlying I/O error.
+    pub fn with_io_error(mut self, err: io::Error) -> Self {
+        self.source = Some(err);
+        self
+    }
+
+    /// Gets the broad [ErrorCategory] of the library [Error].
+    pub const fn category(&self) -> ErrorCategory {
+        self.error.category()
+    }
+
+    /// Gets the structured [ErrorDetail] of the library [Error].
+    pub const fn detail(&self) -> ErrorDetail {
+        self.error.detail()
+    }
+}
+
+impl From<Error> for PathError {
+    fn from(err: Error) -> Self {
+        Self::new(err)
+    }
+}
+
+impl From<PathError> for Error {
+    fn from(err: PathError) -> Self {
+        err.error
+    }
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.path.as_ref() {
+            Some(path) => write!(f, "{}: {}", path.display(), self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl std::error::Error for PathError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self.source.as_ref() {
+            Some(err) => Some(err),
+            None => std::error::Error::source(&self.error),
+        }
+    }
+}
+
+/// Adds the path of the file an [Error] occurred on.
+pub trait ErrorContext<T> {
+    /// Converts the [Error] into a [PathError] with the `path` context.
+    fn path_context<P: AsRef<Path>>(self, path: P) -> Result<T, PathError>;
+}
+
+impl<T> ErrorContext<T> for Result<T, Error> {
+    fn path_context<P: AsRef<Path>>(self, path: P) -> Result<T, PathError> {
+        self.map_err(|err| PathError::new(err).with_path(path))
+    }
+}
+
+impl<T> ErrorContext<T> for Result<T, PathError> {
+    fn path_context<P: AsRef<Path>>(self, path: P) -> Result<T, PathError> {
+        self.map_err(|err| match err.path {
+            Some(_) => err,
+            None => err.with_path(path),
+        })
+    }
+}
+
+/// Converts an I/O error into a [PathError] with the library [Error] and the path context.
+pub trait IoContext<T> {
+    /// Converts the I/O error into a [PathError] for `error` on `path`.
+    fn io_context<P: AsRef<Path>>(self, error: Error, path: P) -> Result<T, PathError>;
+}
+
+impl<T> IoContext<T> for io::Result<T> {
+    fn io_context<P: AsRef<Path>>(self, error: Error, path: P) -> Result<T, PathError> {
+        self.map_err(|err| PathError::new(error).with_path(path).with_io_error(err))
+    }
+}
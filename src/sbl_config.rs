@@ -0,0 +1,295 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use super::{Error, HeaderConf, Result, UbootSplHeader};
+
+/// Default filename of the vendor SBL configuration file.
+pub const DEF_SBL_CFG_FILE: &str = "input_sbl_normal.cfg";
+
+/// Represents the settings parsed from a vendor `input_sbl_normal.cfg` file.
+///
+/// The file is a line-based `KEY=VALUE` list. Blank lines, `#`/`;` comments and `[section]`
+/// headers are ignored. Numeric values are accepted in hexadecimal (`0x` prefix) or decimal.
+///
+/// Supported keys (case-insensitive):
+///
+/// | Key                                  | Setting                         |
+/// |--------------------------------------|---------------------------------|
+/// | `SOFS`, `SBL_HDR_OFFSET`             | [UbootSplHeader::sofs]          |
+/// | `BOFS`, `SBL_BAK_OFFSET`             | [UbootSplHeader::bofs]/[HeaderConf::bofs] |
+/// | `VERS`, `VERSION`, `SBL_VERSION`     | [UbootSplHeader::vers]/[HeaderConf::vers] |
+/// | `RESL`, `SBL_OFFSET`                 | [UbootSplHeader::resl]          |
+/// | `FILE`, `SBL_FILE`, `SPL_FILE`       | [HeaderConf::name]              |
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SblConfig<'a> {
+    sofs: Option<u32>,
+    bofs: Option<u32>,
+    vers: Option<u32>,
+    resl: Option<u32>,
+    name: Option<&'a str>,
+}
+
+impl<'a> SblConfig<'a> {
+    /// Creates a new, empty [SblConfig].
+    pub const fn new() -> Self {
+        Self {
+            sofs: None,
+            bofs: None,
+            vers: None,
+            resl: None,
+            name: None,
+        }
+    }
+
+    /// Parses an `input_sbl_normal.cfg` file.
+    ///
+    /// Unsupported keys are logged as warnings, and otherwise ignored.
+    pub fn parse(cfg: &'a str) -> Result<Self> {
+        Self::parse_with(cfg, |line, key| {
+            log::warn!("unsupported SBL config key on line {line}: {key}");
+        })
+    }
+
+    /// Parses an `input_sbl_normal.cfg` file.
+    ///
+    /// Calls `unsupported` with the line number and key for every unsupported key.
+    pub fn parse_with<F: FnMut(usize, &'a str)>(cfg: &'a str, mut unsupported: F) -> Result<Self> {
+        let mut res = Self::new();
+
+        for (idx, line) in cfg.lines().enumerate() {
+            let line_num = idx.saturating_add(1);
+            let line = strip_comment(line).trim();
+
+            if line.is_empty() || (line.starts_with('[') && line.ends_with(']')) {
+                continue;
+            }
+
+            let (key, val) = line
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim().trim_matches('"')))
+                .ok_or(Error::InvalidConfigLine(line_num))?;
+
+            if key.eq_ignore_ascii_case("SOFS") || key.eq_ignore_ascii_case("SBL_HDR_OFFSET") {
+                res.sofs = Some(parse_u32(val).ok_or(Error::InvalidConfigValue(line_num))?);
+            } else if key.eq_ignore_ascii_case("BOFS") || key.eq_ignore_ascii_case("SBL_BAK_OFFSET")
+            {
+                res.bofs = Some(parse_u32(val).ok_or(Error::InvalidConfigValue(line_num))?);
+            } else if key.eq_ignore_ascii_case("VERS")
+                || key.eq_ignore_ascii_case("VERSION")
+                || key.eq_ignore_ascii_case("SBL_VERSION")
+            {
+                res.vers = Some(parse_u32(val).ok_or(Error::InvalidConfigValue(line_num))?);
+            } else if key.eq_ignore_ascii_case("RESL") || key.eq_ignore_ascii_case("SBL_OFFSET") {
+                res.resl = Some(parse_u32(val).ok_or(Error::InvalidConfigValue(line_num))?);
+            } else if key.eq_ignore_ascii_case("FILE")
+                || key.eq_ignore_ascii_case("SBL_FILE")
+                || key.eq_ignore_ascii_case("SPL_FILE")
+            {
+                res.name = Some(val);
+            } else {
+                unsupported(line_num, key);
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Gets the offset of SPL header, if set.
+    pub const fn sofs(&self) -> Option<u32> {
+        self.sofs
+    }
+
+    /// Gets the `SBL_BAK_OFFSET`, if set.
+    pub const fn bofs(&self) -> Option<u32> {
+        self.bofs
+    }
+
+    /// Gets the version, if set.
+    pub const fn vers(&self) -> Option<u32> {
+        self.vers
+    }
+
+    /// Gets the offset from `HDR` to `SPL_IMAGE`, if set.
+    pub const fn resl(&self) -> Option<u32> {
+        self.resl
+    }
+
+    /// Gets the SPL filename, if set.
+    ///
+    /// A relative filename refers to the directory of the configuration file.
+    pub const fn name(&self) -> Option<&'a str> {
+        self.name
+    }
+
+    /// Applies the configured settings to an [UbootSplHeader].
+    ///
+    /// Settings missing from the configuration file leave the header unchanged.
+    pub fn apply_header(&self, header: &mut UbootSplHeader) {
+        if let Some(sofs) = self.sofs {
+            header.set_sofs(sofs);
+        }
+        if let Some(bofs) = self.bofs {
+            header.set_bofs(bofs);
+        }
+        if let Some(vers) = self.vers {
+            header.set_vers(vers);
+        }
+        if let Some(resl) = self.resl {
+            header.set_resl(resl);
+        }
+    }
+
+    /// Applies the configured settings to a [HeaderConf].
+    ///
+    /// Settings missing from the configuration file leave the configuration unchanged.
+    pub fn apply_conf(&self, conf: &mut HeaderConf<'a>) {
+        if let Some(name) = self.name {
+            conf.set_name(name);
+        }
+        if let Some(bofs) = self.bofs {
+            conf.set_bofs(bofs);
+        }
+        if let Some(vers) = self.vers {
+            conf.set_vers(vers);
+        }
+    }
+}
+
+// Strips a `#` or `;` comment, starting at the line start or after whitespace, outside quotes.
+//
+// Quoted values and paths like `build;v2/u-boot-spl.bin` or `spl#2.bin` are kept intact.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut prev_space = true;
+
+    for (pos, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' | ';' if !quoted && prev_space => return &line[..pos],
+            _ => (),
+        }
+        prev_space = c.is_whitespace();
+    }
+
+    line
+}
+
+// Parses a `u32` config value from a hexadecimal (`0x` prefix) or decimal string.
+pub(crate) fn parse_u32(val: &str) -> Option<u32> {
+    match val.strip_prefix("0x").or_else(|| val.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => val.parse::<u32>().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // layout of the vendor `input_sbl_normal.cfg` shipped with the StarFive `spl_tool`
+    const VENDOR_CFG: &str = r#"
+;===================================================
+; SBL normal boot image configuration
+;===================================================
+[SBL_NORMAL]
+SBL_HDR_OFFSET = 0x240       ; offset of the SPL header
+SBL_BAK_OFFSET = 0x200000    ; offset of the backup SBL from the flash start
+SBL_VERSION    = 0x01010101
+SBL_OFFSET     = 0x400
+SBL_FILE       = "u-boot-spl.bin"
+"#;
+
+    #[test]
+    fn test_parse_vendor_cfg() {
+        let mut unsupported = 0;
+        let cfg = SblConfig::parse_with(VENDOR_CFG, |_, _| unsupported += 1).unwrap();
+
+        assert_eq!(cfg.sofs(), Some(0x240));
+        assert_eq!(cfg.bofs(), Some(0x200000));
+        assert_eq!(cfg.vers(), Some(0x01010101));
+        assert_eq!(cfg.resl(), Some(0x400));
+        assert_eq!(cfg.name(), Some("u-boot-spl.bin"));
+        assert_eq!(unsupported, 0);
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        let cfg = SblConfig::parse("# comment\n  ; indented comment\nBOFS=0x100000 # trailing\n")
+            .unwrap();
+        assert_eq!(cfg.bofs(), Some(0x100000));
+        assert_eq!(cfg.name(), None);
+    }
+
+    #[test]
+    fn test_parse_quoted_comment_chars() {
+        let cfg = SblConfig::parse("SBL_FILE=\"build;v2/u-boot-spl.bin\" ; comment").unwrap();
+        assert_eq!(cfg.name(), Some("build;v2/u-boot-spl.bin"));
+
+        let cfg = SblConfig::parse("SBL_FILE=\"out #2/u-boot-spl.bin\"").unwrap();
+        assert_eq!(cfg.name(), Some("out #2/u-boot-spl.bin"));
+
+        let cfg = SblConfig::parse("FILE=spl#2.bin").unwrap();
+        assert_eq!(cfg.name(), Some("spl#2.bin"));
+    }
+
+    #[test]
+    fn test_parse_case_insensitive_keys() {
+        let cfg = SblConfig::parse("sofs=576\nVersion=0x2").unwrap();
+        assert_eq!(cfg.sofs(), Some(576));
+        assert_eq!(cfg.vers(), Some(2));
+    }
+
+    #[test]
+    fn test_parse_unsupported_key() {
+        let mut keys = [("", 0usize); 1];
+        let mut count = 0;
+        SblConfig::parse_with("\nSBL_UNKNOWN=1", |line, key| {
+            keys[count] = (key, line);
+            count += 1;
+        })
+        .unwrap();
+
+        assert_eq!(count, 1);
+        assert_eq!(keys[0], ("SBL_UNKNOWN", 2));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(matches!(
+            SblConfig::parse("BOFS 0x200000"),
+            Err(Error::InvalidConfigLine(1))
+        ));
+        assert!(matches!(
+            SblConfig::parse("\nBOFS=0xzz"),
+            Err(Error::InvalidConfigValue(2))
+        ));
+        assert!(matches!(
+            SblConfig::parse("VERS=0x100000000"),
+            Err(Error::InvalidConfigValue(1))
+        ));
+    }
+
+    #[test]
+    fn test_apply() {
+        let cfg =
+            SblConfig::parse("SBL_BAK_OFFSET=0x100000\nSBL_VERSION=2\nSBL_FILE=spl.bin").unwrap();
+
+        let mut header = UbootSplHeader::new();
+        cfg.apply_header(&mut header);
+        assert_eq!(header.bofs(), 0x100000);
+        assert_eq!(header.vers(), 2);
+
+        let mut conf = HeaderConf::new();
+        cfg.apply_conf(&mut conf);
+        assert_eq!(conf.bofs(), 0x100000);
+        assert_eq!(conf.name().to_str(), Some("spl.bin"));
+    }
+
+    #[test]
+    fn test_parse_u32() {
+        assert_eq!(parse_u32("0x240"), Some(0x240));
+        assert_eq!(parse_u32("0XFF"), Some(0xff));
+        assert_eq!(parse_u32("576"), Some(576));
+        assert_eq!(parse_u32("-1"), None);
+        assert_eq!(parse_u32(""), None);
+    }
+}
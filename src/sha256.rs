@@ -0,0 +1,220 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+/// Length of a SHA-256 digest in bytes.
+pub const SHA256_LEN: usize = 32;
+
+const BLOCK_LEN: usize = 64;
+
+const H0: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Streaming SHA-256 (FIPS 180-4) hasher.
+///
+/// Useful for verifying image digests in embedded/bare-metal contexts without allocation.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sha256 {
+    state: [u32; 8],
+    block: [u8; BLOCK_LEN],
+    block_len: usize,
+    total_len: u64,
+}
+
+impl Sha256 {
+    /// Creates a new [Sha256] hasher.
+    pub const fn new() -> Self {
+        Self {
+            state: H0,
+            block: [0u8; BLOCK_LEN],
+            block_len: 0,
+            total_len: 0,
+        }
+    }
+
+    /// Adds the provided data to the digest calculation.
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.total_len = self.total_len.wrapping_add(data.len() as u64);
+
+        if self.block_len != 0 {
+            let len = core::cmp::min(BLOCK_LEN - self.block_len, data.len());
+            self.block[self.block_len..self.block_len + len].copy_from_slice(&data[..len]);
+            self.block_len += len;
+            data = &data[len..];
+
+            if self.block_len == BLOCK_LEN {
+                let block = self.block;
+                self.compress(&block);
+                self.block_len = 0;
+            }
+        }
+
+        let mut chunks = data.chunks_exact(BLOCK_LEN);
+        for chunk in chunks.by_ref() {
+            // chunks are guaranteed to be `BLOCK_LEN` long
+            let mut block = [0u8; BLOCK_LEN];
+            block.copy_from_slice(chunk);
+            self.compress(&block);
+        }
+
+        let rem = chunks.remainder();
+        self.block[..rem.len()].copy_from_slice(rem);
+        self.block_len += rem.len();
+    }
+
+    /// Builder function that adds the provided data to the digest calculation.
+    pub fn with_update(mut self, data: &[u8]) -> Self {
+        self.update(data);
+        self
+    }
+
+    /// Performs the final round of the SHA-256 calculation, and returns the digest.
+    pub fn finalize(mut self) -> [u8; SHA256_LEN] {
+        let bit_len = self.total_len.wrapping_mul(8);
+
+        let mut pad = [0u8; BLOCK_LEN * 2];
+        pad[0] = 0x80;
+        let pad_len = if self.block_len < BLOCK_LEN - 8 {
+            BLOCK_LEN - self.block_len
+        } else {
+            BLOCK_LEN * 2 - self.block_len
+        };
+        pad[pad_len - 8..pad_len].copy_from_slice(bit_len.to_be_bytes().as_ref());
+
+        // keep the message length from the data, not the padding
+        let total_len = self.total_len;
+        self.update(&pad[..pad_len]);
+        self.total_len = total_len;
+
+        let mut res = [0u8; SHA256_LEN];
+        for (out, word) in res.chunks_exact_mut(4).zip(self.state.iter()) {
+            out.copy_from_slice(word.to_be_bytes().as_ref());
+        }
+
+        res
+    }
+
+    fn compress(&mut self, block: &[u8; BLOCK_LEN]) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (s, v) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Calculate the SHA-256 digest over the provided data buffer.
+pub fn sha256(data: &[u8]) -> [u8; SHA256_LEN] {
+    Sha256::new().with_update(data).finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest_hex(digest: [u8; SHA256_LEN]) -> [u8; SHA256_LEN * 2] {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+
+        let mut res = [0u8; SHA256_LEN * 2];
+        for (i, b) in digest.iter().enumerate() {
+            res[i * 2] = HEX[(b >> 4) as usize];
+            res[i * 2 + 1] = HEX[(b & 0xf) as usize];
+        }
+        res
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(
+            digest_hex(sha256(b"")).as_ref(),
+            b"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn test_abc() {
+        assert_eq!(
+            digest_hex(sha256(b"abc")).as_ref(),
+            b"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_448_bits() {
+        assert_eq!(
+            digest_hex(sha256(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            ))
+            .as_ref(),
+            b"248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn test_million_a() {
+        // stream in uneven chunks to exercise the partial block handling
+        let chunk = [b'a'; 999];
+        let mut hasher = Sha256::new();
+        for _ in 0..1001 {
+            hasher.update(chunk.as_ref());
+        }
+        hasher.update(chunk[..1].as_ref());
+
+        assert_eq!(
+            digest_hex(hasher.finalize()).as_ref(),
+            b"cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+}
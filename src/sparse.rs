@@ -0,0 +1,334 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use super::{Error, Result};
+
+/// Magic number of an Android sparse image header.
+pub const SPARSE_HEADER_MAGIC: u32 = 0xed26_ff3a;
+/// Major version of the Android sparse image format.
+pub const SPARSE_MAJOR_VERSION: u16 = 1;
+/// Minor version of the Android sparse image format.
+pub const SPARSE_MINOR_VERSION: u16 = 0;
+/// Length of an Android sparse image header.
+pub const SPARSE_HEADER_LEN: usize = 28;
+/// Length of an Android sparse image chunk header.
+pub const SPARSE_CHUNK_HEADER_LEN: usize = 12;
+/// Default block length of bmap files and Android sparse images.
+pub const DEF_SPARSE_BLOCK_LEN: usize = 4096;
+
+/// Represents the type of an Android sparse image chunk.
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SparseChunkType {
+    /// Blocks stored verbatim after the chunk header.
+    Raw = 0xcac1,
+    /// Blocks filled with the 4-byte value stored after the chunk header.
+    Fill = 0xcac2,
+    /// Blocks left untouched on the target.
+    DontCare = 0xcac3,
+    /// CRC32 of the preceding data.
+    Crc32 = 0xcac4,
+}
+
+impl TryFrom<u16> for SparseChunkType {
+    type Error = Error;
+
+    fn try_from(val: u16) -> Result<Self> {
+        match val {
+            0xcac1 => Ok(Self::Raw),
+            0xcac2 => Ok(Self::Fill),
+            0xcac3 => Ok(Self::DontCare),
+            0xcac4 => Ok(Self::Crc32),
+            _ => Err(Error::InvalidSparseImage),
+        }
+    }
+}
+
+impl From<SparseChunkType> for u16 {
+    fn from(val: SparseChunkType) -> Self {
+        val as u16
+    }
+}
+
+/// Represents the 28-byte Android sparse image header, as created by `img2simg`.
+///
+/// All multi-byte fields are little-endian.
+///
+/// | Offset | Field | Size |
+/// |--------|-------|------|
+/// | 0x00 | magic | 4 |
+/// | 0x04 | major_version | 2 |
+/// | 0x06 | minor_version | 2 |
+/// | 0x08 | file_hdr_sz | 2 |
+/// | 0x0a | chunk_hdr_sz | 2 |
+/// | 0x0c | blk_sz | 4 |
+/// | 0x10 | total_blks | 4 |
+/// | 0x14 | total_chunks | 4 |
+/// | 0x18 | image_checksum | 4 |
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SparseHeader {
+    /// Block length, a multiple of 4.
+    pub block_len: u32,
+    /// Number of blocks in the expanded image.
+    pub total_blocks: u32,
+    /// Number of chunks in the sparse image.
+    pub total_chunks: u32,
+    /// CRC32 of the expanded image, `0` when unused.
+    pub image_checksum: u32,
+}
+
+impl SparseHeader {
+    /// Creates a new [SparseHeader].
+    pub const fn new(block_len: u32, total_blocks: u32, total_chunks: u32) -> Self {
+        Self {
+            block_len,
+            total_blocks,
+            total_chunks,
+            image_checksum: 0,
+        }
+    }
+}
+
+impl From<SparseHeader> for [u8; SPARSE_HEADER_LEN] {
+    fn from(val: SparseHeader) -> Self {
+        let mut res = [0u8; SPARSE_HEADER_LEN];
+
+        res[0x00..0x04].copy_from_slice(SPARSE_HEADER_MAGIC.to_le_bytes().as_ref());
+        res[0x04..0x06].copy_from_slice(SPARSE_MAJOR_VERSION.to_le_bytes().as_ref());
+        res[0x06..0x08].copy_from_slice(SPARSE_MINOR_VERSION.to_le_bytes().as_ref());
+        res[0x08..0x0a].copy_from_slice((SPARSE_HEADER_LEN as u16).to_le_bytes().as_ref());
+        res[0x0a..0x0c].copy_from_slice((SPARSE_CHUNK_HEADER_LEN as u16).to_le_bytes().as_ref());
+        res[0x0c..0x10].copy_from_slice(val.block_len.to_le_bytes().as_ref());
+        res[0x10..0x14].copy_from_slice(val.total_blocks.to_le_bytes().as_ref());
+        res[0x14..0x18].copy_from_slice(val.total_chunks.to_le_bytes().as_ref());
+        res[0x18..0x1c].copy_from_slice(val.image_checksum.to_le_bytes().as_ref());
+
+        res
+    }
+}
+
+/// Represents the 12-byte Android sparse image chunk header.
+///
+/// | Offset | Field | Size |
+/// |--------|-------|------|
+/// | 0x00 | chunk_type | 2 |
+/// | 0x02 | reserved | 2 |
+/// | 0x04 | chunk_sz | 4 |
+/// | 0x08 | total_sz | 4 |
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SparseChunkHeader {
+    /// Type of the chunk.
+    pub chunk_type: SparseChunkType,
+    /// Number of blocks in the expanded image covered by the chunk.
+    pub blocks: u32,
+    /// Length of the chunk in the sparse image, including the chunk header.
+    pub total_len: u32,
+}
+
+impl SparseChunkHeader {
+    /// Creates a new [SparseChunkHeader] for `blocks` of `block_len` bytes.
+    ///
+    /// Returns [Error::InvalidSparseImage] when the chunk length overflows.
+    pub fn new(chunk_type: SparseChunkType, blocks: u32, block_len: u32) -> Result<Self> {
+        let data_len = match chunk_type {
+            SparseChunkType::Raw => blocks.checked_mul(block_len),
+            SparseChunkType::Fill | SparseChunkType::Crc32 => Some(4),
+            SparseChunkType::DontCare => Some(0),
+        };
+
+        let total_len = data_len
+            .and_then(|len| len.checked_add(SPARSE_CHUNK_HEADER_LEN as u32))
+            .ok_or(Error::InvalidSparseImage)?;
+
+        Ok(Self {
+            chunk_type,
+            blocks,
+            total_len,
+        })
+    }
+}
+
+impl From<SparseChunkHeader> for [u8; SPARSE_CHUNK_HEADER_LEN] {
+    fn from(val: SparseChunkHeader) -> Self {
+        let mut res = [0u8; SPARSE_CHUNK_HEADER_LEN];
+
+        res[0x00..0x02].copy_from_slice(u16::from(val.chunk_type).to_le_bytes().as_ref());
+        res[0x04..0x08].copy_from_slice(val.blocks.to_le_bytes().as_ref());
+        res[0x08..0x0c].copy_from_slice(val.total_len.to_le_bytes().as_ref());
+
+        res
+    }
+}
+
+/// Represents the contents of an image block, used to build bmap files and sparse images.
+///
+/// Only [BlockKind::Hole] blocks are left out: a block of zero bytes may overwrite stale data on
+/// the target (e.g. unused GPT partition entries), so it is written like any other data.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BlockKind {
+    /// Unallocated block of a sparse image file, not transferred.
+    Hole,
+    /// All zero bytes, written as a zero fill.
+    Zero,
+    /// A repeated 4-byte little-endian value.
+    Fill(u32),
+    /// Any other data.
+    Data,
+}
+
+impl BlockKind {
+    /// Classifies the contents of a block.
+    ///
+    /// Holes are not detected from the block contents, see [BlockKind::Hole].
+    ///
+    /// Blocks with a length that is not a multiple of 4 are either [BlockKind::Zero] or
+    /// [BlockKind::Data].
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use spl_tool::BlockKind;
+    ///
+    /// assert_eq!(BlockKind::classify(&[0u8; 16]), BlockKind::Zero);
+    /// assert_eq!(BlockKind::classify(&[0xffu8; 16]), BlockKind::Fill(0xffff_ffff));
+    /// assert_eq!(BlockKind::classify(b"spl_tool"), BlockKind::Data);
+    /// ```
+    pub fn classify(block: &[u8]) -> Self {
+        if block.iter().all(|&b| b == 0) {
+            return Self::Zero;
+        }
+
+        if block.len() < 4 || !block.len().is_multiple_of(4) {
+            return Self::Data;
+        }
+
+        let (head, rest) = block.split_at(4);
+        if rest.chunks_exact(4).all(|word| word == head) {
+            Self::Fill(u32::from_le_bytes([head[0], head[1], head[2], head[3]]))
+        } else {
+            Self::Data
+        }
+    }
+
+    /// Gets whether the block has to be written to the target.
+    ///
+    /// Example:
+    ///
+    /// ```rust
+    /// use spl_tool::BlockKind;
+    ///
+    /// assert!(!BlockKind::Hole.is_mapped());
+    /// assert!(BlockKind::Zero.is_mapped());
+    /// ```
+    pub const fn is_mapped(&self) -> bool {
+        !matches!(self, Self::Hole)
+    }
+
+    /// Gets the Android sparse image chunk type of the block.
+    pub const fn chunk_type(&self) -> SparseChunkType {
+        match self {
+            Self::Hole => SparseChunkType::DontCare,
+            Self::Zero | Self::Fill(_) => SparseChunkType::Fill,
+            Self::Data => SparseChunkType::Raw,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_bytes() {
+        let bytes: [u8; SPARSE_HEADER_LEN] = SparseHeader::new(4096, 0x1234, 7).into();
+
+        assert_eq!(bytes[0x00..0x04], SPARSE_HEADER_MAGIC.to_le_bytes());
+        assert_eq!(bytes[0x04..0x06], SPARSE_MAJOR_VERSION.to_le_bytes());
+        assert_eq!(bytes[0x06..0x08], SPARSE_MINOR_VERSION.to_le_bytes());
+        assert_eq!(bytes[0x08..0x0a], 28u16.to_le_bytes());
+        assert_eq!(bytes[0x0a..0x0c], 12u16.to_le_bytes());
+        assert_eq!(bytes[0x0c..0x10], 4096u32.to_le_bytes());
+        assert_eq!(bytes[0x10..0x14], 0x1234u32.to_le_bytes());
+        assert_eq!(bytes[0x14..0x18], 7u32.to_le_bytes());
+        assert_eq!(bytes[0x18..0x1c], [0u8; 4]);
+    }
+
+    #[test]
+    fn test_chunk_header() {
+        let hdr = SparseChunkHeader::new(SparseChunkType::Raw, 3, 4096).unwrap();
+        assert_eq!(hdr.total_len, 12 + 3 * 4096);
+
+        let bytes: [u8; SPARSE_CHUNK_HEADER_LEN] = hdr.into();
+        assert_eq!(bytes[0x00..0x02], 0xcac1u16.to_le_bytes());
+        assert_eq!(bytes[0x02..0x04], [0u8; 2]);
+        assert_eq!(bytes[0x04..0x08], 3u32.to_le_bytes());
+        assert_eq!(bytes[0x08..0x0c], (12u32 + 3 * 4096).to_le_bytes());
+
+        let fill = SparseChunkHeader::new(SparseChunkType::Fill, u32::MAX, 4096).unwrap();
+        assert_eq!(fill.total_len, 16);
+        let dont_care = SparseChunkHeader::new(SparseChunkType::DontCare, u32::MAX, 4096).unwrap();
+        assert_eq!(dont_care.total_len, 12);
+    }
+
+    #[test]
+    fn test_chunk_header_overflow() {
+        let max = (u32::MAX - SPARSE_CHUNK_HEADER_LEN as u32) / 4096;
+
+        assert!(SparseChunkHeader::new(SparseChunkType::Raw, max, 4096).is_ok());
+        assert!(matches!(
+            SparseChunkHeader::new(SparseChunkType::Raw, max + 1, 4096),
+            Err(Error::InvalidSparseImage)
+        ));
+        assert!(matches!(
+            SparseChunkHeader::new(SparseChunkType::Raw, u32::MAX, 4096),
+            Err(Error::InvalidSparseImage)
+        ));
+    }
+
+    #[test]
+    fn test_chunk_type() {
+        for ty in [
+            SparseChunkType::Raw,
+            SparseChunkType::Fill,
+            SparseChunkType::DontCare,
+            SparseChunkType::Crc32,
+        ] {
+            assert_eq!(SparseChunkType::try_from(u16::from(ty)).unwrap(), ty);
+        }
+
+        assert!(matches!(
+            SparseChunkType::try_from(0xcac5),
+            Err(Error::InvalidSparseImage)
+        ));
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(BlockKind::classify(&[]), BlockKind::Zero);
+        assert_eq!(BlockKind::classify(&[0u8; 4095]), BlockKind::Zero);
+        assert_eq!(BlockKind::classify(&[0xffu8; 4095]), BlockKind::Data);
+        assert_eq!(BlockKind::classify(&[0xffu8; 3]), BlockKind::Data);
+        assert_eq!(
+            BlockKind::classify([1u8, 2, 3, 4].repeat(1024).as_ref()),
+            BlockKind::Fill(0x0403_0201)
+        );
+
+        let mut block = [0xa5u8; 4096];
+        block[4095] = 0;
+        assert_eq!(BlockKind::classify(block.as_ref()), BlockKind::Data);
+    }
+
+    #[test]
+    fn test_zero_blocks_mapped() {
+        assert!(!BlockKind::Hole.is_mapped());
+        assert_eq!(BlockKind::Hole.chunk_type(), SparseChunkType::DontCare);
+
+        // zero blocks overwrite stale data on the target, like `img2simg`
+        assert!(BlockKind::Zero.is_mapped());
+        assert_eq!(BlockKind::Zero.chunk_type(), SparseChunkType::Fill);
+        assert_eq!(BlockKind::Fill(1).chunk_type(), SparseChunkType::Fill);
+        assert_eq!(BlockKind::Data.chunk_type(), SparseChunkType::Raw);
+    }
+}
@@ -0,0 +1,388 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use core::{fmt, ops::Range};
+
+use super::{Result, UbootSplHeader};
+
+/// Represents the fields of an [UbootSplHeader].
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SplField {
+    Sofs,
+    Bofs,
+    #[cfg(feature = "secure-boot")]
+    Salg,
+    #[cfg(feature = "secure-boot")]
+    Sexp,
+    #[cfg(feature = "secure-boot")]
+    Khsh,
+    #[cfg(feature = "secure-boot")]
+    Pkey,
+    #[cfg(feature = "secure-boot")]
+    Ssig,
+    Vers,
+    Fsiz,
+    Resl,
+    Crcs,
+}
+
+impl SplField {
+    /// List of all [SplField]s, in header order.
+    #[cfg(not(feature = "secure-boot"))]
+    pub const ALL: &'static [Self] = &[
+        Self::Sofs,
+        Self::Bofs,
+        Self::Vers,
+        Self::Fsiz,
+        Self::Resl,
+        Self::Crcs,
+    ];
+
+    /// List of all [SplField]s, in header order.
+    #[cfg(feature = "secure-boot")]
+    pub const ALL: &'static [Self] = &[
+        Self::Sofs,
+        Self::Bofs,
+        Self::Salg,
+        Self::Sexp,
+        Self::Khsh,
+        Self::Pkey,
+        Self::Ssig,
+        Self::Vers,
+        Self::Fsiz,
+        Self::Resl,
+        Self::Crcs,
+    ];
+
+    /// Gets the byte offset of the field in the serialized header.
+    pub const fn offset(&self) -> usize {
+        match self {
+            Self::Sofs => 0x000,
+            Self::Bofs => 0x004,
+            #[cfg(feature = "secure-boot")]
+            Self::Salg => 0x008,
+            #[cfg(feature = "secure-boot")]
+            Self::Sexp => 0x00c,
+            #[cfg(feature = "secure-boot")]
+            Self::Khsh => 0x020,
+            #[cfg(feature = "secure-boot")]
+            Self::Pkey => 0x040,
+            #[cfg(feature = "secure-boot")]
+            Self::Ssig => 0x140,
+            Self::Vers => 0x284,
+            Self::Fsiz => 0x288,
+            Self::Resl => 0x28c,
+            Self::Crcs => 0x290,
+        }
+    }
+
+    /// Gets the field name.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Sofs => "sofs",
+            Self::Bofs => "bofs",
+            #[cfg(feature = "secure-boot")]
+            Self::Salg => "salg",
+            #[cfg(feature = "secure-boot")]
+            Self::Sexp => "sexp",
+            #[cfg(feature = "secure-boot")]
+            Self::Khsh => "khsh",
+            #[cfg(feature = "secure-boot")]
+            Self::Pkey => "pkey",
+            #[cfg(feature = "secure-boot")]
+            Self::Ssig => "ssig",
+            Self::Vers => "vers",
+            Self::Fsiz => "fsiz",
+            Self::Resl => "resl",
+            Self::Crcs => "crcs",
+        }
+    }
+
+    /// Gets the value of the field from an [UbootSplHeader].
+    pub fn value<'a>(&self, header: &'a UbootSplHeader) -> SplFieldValue<'a> {
+        match self {
+            Self::Sofs => SplFieldValue::Word(header.sofs()),
+            Self::Bofs => SplFieldValue::Word(header.bofs()),
+            #[cfg(feature = "secure-boot")]
+            Self::Salg => SplFieldValue::Word(header.salg()),
+            #[cfg(feature = "secure-boot")]
+            Self::Sexp => SplFieldValue::Word(header.sexp()),
+            #[cfg(feature = "secure-boot")]
+            Self::Khsh => SplFieldValue::Bytes(header.khsh().as_ref()),
+            #[cfg(feature = "secure-boot")]
+            Self::Pkey => SplFieldValue::Bytes(header.pkey().as_ref()),
+            #[cfg(feature = "secure-boot")]
+            Self::Ssig => SplFieldValue::Bytes(header.ssig().as_ref()),
+            Self::Vers => SplFieldValue::Word(header.vers()),
+            Self::Fsiz => SplFieldValue::Word(header.fsiz()),
+            Self::Resl => SplFieldValue::Word(header.resl()),
+            Self::Crcs => SplFieldValue::Word(header.crcs()),
+        }
+    }
+}
+
+impl fmt::Display for SplField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Represents the value of a [SplField].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplFieldValue<'a> {
+    Word(u32),
+    Bytes(&'a [u8]),
+}
+
+impl fmt::Display for SplFieldValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Word(val) => write!(f, "{val:#x}"),
+            Self::Bytes(val) => val.iter().try_for_each(|b| write!(f, "{b:02x}")),
+        }
+    }
+}
+
+/// Represents a differing field between two [UbootSplHeader]s.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SplFieldDiff<'a> {
+    pub field: SplField,
+    pub old: SplFieldValue<'a>,
+    pub new: SplFieldValue<'a>,
+}
+
+/// Represents the differences between two headered SPL images.
+///
+/// Each image is parsed with [UbootSplHeader::try_from], and the payloads are the `fsiz` bytes
+/// at `resl` (truncated to the available data).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SplDiff<'a> {
+    old_header: UbootSplHeader,
+    new_header: UbootSplHeader,
+    old_payload: &'a [u8],
+    new_payload: &'a [u8],
+}
+
+impl<'a> SplDiff<'a> {
+    /// Compares two headered SPL images.
+    pub fn new(old: &'a [u8], new: &'a [u8]) -> Result<Self> {
+        let old_header = UbootSplHeader::try_from(old)?;
+        let new_header = UbootSplHeader::try_from(new)?;
+
+        Ok(Self {
+            old_payload: payload(&old_header, old),
+            new_payload: payload(&new_header, new),
+            old_header,
+            new_header,
+        })
+    }
+
+    /// Gets the header of the old image.
+    pub const fn old_header(&self) -> &UbootSplHeader {
+        &self.old_header
+    }
+
+    /// Gets the header of the new image.
+    pub const fn new_header(&self) -> &UbootSplHeader {
+        &self.new_header
+    }
+
+    /// Gets the payload of the old image.
+    pub const fn old_payload(&self) -> &'a [u8] {
+        self.old_payload
+    }
+
+    /// Gets the payload of the new image.
+    pub const fn new_payload(&self) -> &'a [u8] {
+        self.new_payload
+    }
+
+    /// Gets an iterator over the differing header fields.
+    pub fn fields(&self) -> impl Iterator<Item = SplFieldDiff<'_>> + '_ {
+        SplField::ALL.iter().copied().filter_map(|field| {
+            let old = field.value(&self.old_header);
+            let new = field.value(&self.new_header);
+            (old != new).then_some(SplFieldDiff { field, old, new })
+        })
+    }
+
+    /// Gets the payload size difference in bytes (`new - old`).
+    pub fn size_delta(&self) -> i64 {
+        (self.new_payload.len() as i64).saturating_sub(self.old_payload.len() as i64)
+    }
+
+    /// Gets the offset of the first differing payload byte, if any.
+    pub fn first_diff(&self) -> Option<usize> {
+        self.changed_ranges().next().map(|r| r.start)
+    }
+
+    /// Gets an iterator over the changed payload regions.
+    ///
+    /// Bytes past the end of the shorter payload are reported as a changed region.
+    pub fn changed_ranges(&self) -> SplChangedRanges<'a> {
+        SplChangedRanges {
+            old: self.old_payload,
+            new: self.new_payload,
+            idx: 0,
+        }
+    }
+
+    /// Gets whether the images have identical header fields and payloads.
+    pub fn is_empty(&self) -> bool {
+        self.fields().next().is_none() && self.old_payload == self.new_payload
+    }
+}
+
+/// Iterator over the changed regions of two SPL payloads.
+#[derive(Clone, Debug)]
+pub struct SplChangedRanges<'a> {
+    old: &'a [u8],
+    new: &'a [u8],
+    idx: usize,
+}
+
+impl Iterator for SplChangedRanges<'_> {
+    type Item = Range<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let common = core::cmp::min(self.old.len(), self.new.len());
+        let max = core::cmp::max(self.old.len(), self.new.len());
+
+        while self.idx < common && self.old[self.idx] == self.new[self.idx] {
+            self.idx += 1;
+        }
+
+        if self.idx >= max {
+            return None;
+        }
+
+        let start = self.idx;
+        if start >= common {
+            self.idx = max;
+        } else {
+            while self.idx < common && self.old[self.idx] != self.new[self.idx] {
+                self.idx += 1;
+            }
+            // merge a differing run that reaches the end with the length difference
+            if self.idx == common {
+                self.idx = max;
+            }
+        }
+
+        Some(start..self.idx)
+    }
+}
+
+fn payload<'a>(header: &UbootSplHeader, img: &'a [u8]) -> &'a [u8] {
+    let start = core::cmp::min(header.resl() as usize, img.len());
+    let end = core::cmp::min(start.saturating_add(header.fsiz() as usize), img.len());
+    &img[start..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{spl_image, spl_image_len, Error, DEF_RESL, SPL_HEADER_LEN};
+
+    const OLD: &[u8] = &[0x13, 0x00, 0x00, 0x00, 0x6f, 0x00, 0x00, 0x00];
+    const OLD_IMG: [u8; spl_image_len(OLD.len())] = spl_image(OLD);
+
+    #[test]
+    fn test_identical() {
+        let diff = SplDiff::new(OLD_IMG.as_ref(), OLD_IMG.as_ref()).unwrap();
+
+        assert!(diff.is_empty());
+        assert_eq!(diff.fields().count(), 0);
+        assert_eq!(diff.first_diff(), None);
+        assert_eq!(diff.size_delta(), 0);
+        assert_eq!(diff.old_payload(), OLD);
+    }
+
+    #[test]
+    fn test_changed_payload() {
+        const NEW: &[u8] = &[0x13, 0xff, 0x00, 0x00, 0x6f, 0xff, 0xff, 0x00];
+        const NEW_IMG: [u8; spl_image_len(NEW.len())] = spl_image(NEW);
+
+        let diff = SplDiff::new(OLD_IMG.as_ref(), NEW_IMG.as_ref()).unwrap();
+
+        assert!(!diff.is_empty());
+        assert_eq!(diff.first_diff(), Some(1));
+        assert_eq!(diff.size_delta(), 0);
+
+        let mut ranges = diff.changed_ranges();
+        assert_eq!(ranges.next(), Some(1..2));
+        assert_eq!(ranges.next(), Some(5..7));
+        assert_eq!(ranges.next(), None);
+
+        let mut fields = diff.fields();
+        let crcs = fields.next().unwrap();
+        assert_eq!(crcs.field, SplField::Crcs);
+        assert_eq!(crcs.old, SplFieldValue::Word(diff.old_header().crcs()));
+        assert_eq!(crcs.new, SplFieldValue::Word(diff.new_header().crcs()));
+        assert!(fields.next().is_none());
+    }
+
+    #[test]
+    fn test_grown_payload() {
+        const NEW: &[u8] = &[0x13, 0x00, 0x00, 0x00, 0x6f, 0x00, 0x00, 0x01, 0x02, 0x03];
+        const NEW_IMG: [u8; spl_image_len(NEW.len())] = spl_image(NEW);
+
+        let diff = SplDiff::new(OLD_IMG.as_ref(), NEW_IMG.as_ref()).unwrap();
+
+        assert_eq!(diff.size_delta(), 2);
+        // the differing last byte is merged with the appended bytes
+        assert!(diff.changed_ranges().eq(core::iter::once(7..10)));
+        assert!(diff.fields().any(|f| f.field == SplField::Fsiz));
+
+        let diff = SplDiff::new(NEW_IMG.as_ref(), OLD_IMG.as_ref()).unwrap();
+        assert_eq!(diff.size_delta(), -2);
+        assert!(diff.changed_ranges().eq(core::iter::once(7..10)));
+    }
+
+    #[test]
+    fn test_appended_payload() {
+        const NEW: &[u8] = &[0x13, 0x00, 0x00, 0x00, 0x6f, 0x00, 0x00, 0x00, 0x01];
+        const NEW_IMG: [u8; spl_image_len(NEW.len())] = spl_image(NEW);
+
+        let diff = SplDiff::new(OLD_IMG.as_ref(), NEW_IMG.as_ref()).unwrap();
+
+        assert!(diff.changed_ranges().eq(core::iter::once(8..9)));
+        assert_eq!(diff.first_diff(), Some(8));
+    }
+
+    #[test]
+    fn test_truncated_image() {
+        // payload truncated to the available data
+        let img = &OLD_IMG[..DEF_RESL as usize + 4];
+        let diff = SplDiff::new(OLD_IMG.as_ref(), img).unwrap();
+
+        assert_eq!(diff.new_payload(), &OLD[..4]);
+        assert_eq!(diff.size_delta(), -4);
+        assert!(diff.changed_ranges().eq(core::iter::once(4..8)));
+
+        // header only, the payload is empty
+        let diff = SplDiff::new(OLD_IMG.as_ref(), &OLD_IMG[..SPL_HEADER_LEN]).unwrap();
+        assert!(diff.new_payload().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        assert!(matches!(
+            SplDiff::new(OLD_IMG.as_ref(), &OLD_IMG[..SPL_HEADER_LEN - 1]),
+            Err(Error::InvalidHeaderLen(_))
+        ));
+    }
+
+    #[test]
+    fn test_field_offsets() {
+        let header = UbootSplHeader::new().with_bofs(0x12345678);
+        let bytes = header.to_bytes();
+
+        for field in SplField::ALL {
+            if let SplFieldValue::Word(val) = field.value(&header) {
+                let off = field.offset();
+                assert_eq!(&bytes[off..off + 4], val.to_le_bytes().as_ref(), "{field}");
+            }
+        }
+    }
+}
@@ -1,8 +1,8 @@
 // SPDX-License-Identifier: GPL-2.0+
 
-use core::{cmp, mem};
+use core::{fmt, mem};
 
-use super::{Error, Result};
+use super::{spl_crc32, Error, Result};
 
 /// Default value of the offset of SPL header: `64+256+256 = 0x240`
 pub const DEF_SOFS: u32 = 0x240;
@@ -10,41 +10,50 @@ pub const DEF_SOFS: u32 = 0x240;
 pub const DEF_VERS: u32 = 0x01010101;
 /// Default value of `SBL_BAK_OFFSET`.
 pub const DEF_BACKUP: u32 = 0x200000;
+/// Default offset of the U-Boot FIT image in the VisionFive2 SPI flash.
+pub const DEF_FIT_OFFSET: u32 = 0x100000;
 /// Default value for the offset from `HDR` to `SPL_IMAGE`.
 pub const DEF_RESL: u32 = 0x400;
 /// Default filename of the U-Boot SPL binary.
 pub const DEF_SPL_FILE: &str = "u-boot-spl.bin";
-/// Maximum path length: defined in `linux/limits.h`.
-pub const PATH_MAX: usize = 4096;
+/// Default filename of the U-Boot FIT image.
+pub const DEF_FIT_FILE: &str = "u-boot.itb";
 /// Value indicating a failed CRC32 calculation/check.
 pub const CRC_FAILED: u32 = 0x5a5a5a5a;
 /// Default size for an U-Boot SPL header.
 ///
 /// Currently set to `1 KiB` (including reserved padding).
 pub const SPL_HEADER_LEN: usize = 1024;
-/// Maximum supported length of an U-Boot SPL image.
+/// Upper bound (exclusive) of the length of an U-Boot SPL image.
+///
+/// See [spl_check_len] for the check used on creation and validation.
 pub const MAX_SPL_LEN: usize = 180048;
 
-const PATH_ZERO_BYTES: [u8; PATH_MAX] = [0u8; PATH_MAX];
-const RES_PAD2_LEN: usize = 636;
-const RES_PAD3_LEN: usize = 364;
+// length of the reserved padding after the `bofs` field
+pub(crate) const RES_PAD2_LEN: usize = 636;
+// length of the reserved padding after the `crcs` field
+pub(crate) const RES_PAD3_LEN: usize = 364;
 
 /// Represents the U-Boot header for the SPL binary.
 ///
 /// All `u32` end up little endian in output header.
+///
+/// Reserved regions are kept verbatim, so parsing and serializing a header does not clobber
+/// foreign data (e.g. a GPT). The experimental `secure-boot` feature models a signed header in
+/// the reserved region after `bofs`.
 #[repr(C)]
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct UbootSplHeader {
     sofs: u32,
     bofs: u32,
-    // reserved offset padding buffer
-    zro2: [u8; RES_PAD2_LEN],
+    // reserved offset padding buffer, `RES_PAD2_LEN` spelled out for the C header
+    zro2: [u8; 636],
     vers: u32,
     fsiz: u32,
     resl: u32,
     crcs: u32,
-    // reserved offset padding buffer
-    zro3: [u8; RES_PAD3_LEN],
+    // reserved offset padding buffer, `RES_PAD3_LEN` spelled out for the C header
+    zro3: [u8; 364],
 }
 
 impl UbootSplHeader {
@@ -53,12 +62,12 @@ impl UbootSplHeader {
         Self {
             sofs: DEF_SOFS,
             bofs: DEF_BACKUP,
-            zro2: [0; 636],
+            zro2: [0; RES_PAD2_LEN],
             vers: DEF_VERS,
             fsiz: 0,
             resl: DEF_RESL,
             crcs: 0,
-            zro3: [0; 364],
+            zro3: [0; RES_PAD3_LEN],
         }
     }
 
@@ -67,6 +76,17 @@ impl UbootSplHeader {
         self.sofs
     }
 
+    /// Sets the offset of SPL header.
+    pub const fn set_sofs(&mut self, val: u32) {
+        self.sofs = val;
+    }
+
+    /// Builder function that sets the offset of SPL header.
+    pub const fn with_sofs(mut self, val: u32) -> Self {
+        self.set_sofs(val);
+        self
+    }
+
     /// Gets the `SBL_BAK_OFFSET`:
     ///
     /// Offset of backup SBL from Flash info start from `input_sbl_normal.cfg`
@@ -77,30 +97,41 @@ impl UbootSplHeader {
     /// Sets the `SBL_BAK_OFFSET`:
     ///
     /// Offset of backup SBL from Flash info start from `input_sbl_normal.cfg`
-    pub fn set_bofs(&mut self, val: u32) {
+    pub const fn set_bofs(&mut self, val: u32) {
         self.bofs = val;
     }
 
     /// Builder function that sets the `SBL_BAK_OFFSET`:
     ///
     /// Offset of backup SBL from Flash info start from `input_sbl_normal.cfg`
-    pub fn with_bofs(mut self, val: u32) -> Self {
+    pub const fn with_bofs(mut self, val: u32) -> Self {
         self.set_bofs(val);
         self
     }
 
+    // reserved region after `bofs`, holds the experimental secure-boot fields
+    #[cfg(feature = "secure-boot")]
+    pub(crate) const fn reserved(&self) -> &[u8; RES_PAD2_LEN] {
+        &self.zro2
+    }
+
+    #[cfg(feature = "secure-boot")]
+    pub(crate) const fn reserved_mut(&mut self) -> &mut [u8; RES_PAD2_LEN] {
+        &mut self.zro2
+    }
+
     /// Gets the version: `0x01010101` by default
     pub const fn vers(&self) -> u32 {
         self.vers
     }
 
     /// Sets the version.
-    pub fn set_vers(&mut self, val: u32) {
+    pub const fn set_vers(&mut self, val: u32) {
         self.vers = val;
     }
 
     /// Builder function that sets the version.
-    pub fn with_vers(mut self, val: u32) -> Self {
+    pub const fn with_vers(mut self, val: u32) -> Self {
         self.set_vers(val);
         self
     }
@@ -111,12 +142,12 @@ impl UbootSplHeader {
     }
 
     /// Sets the `u-boot-spl.bin` size in bytes.
-    pub fn set_fsiz(&mut self, val: u32) {
+    pub const fn set_fsiz(&mut self, val: u32) {
         self.fsiz = val;
     }
 
     /// Builder function that sets the `u-boot-spl.bin` size in bytes.
-    pub fn with_fsiz(mut self, val: u32) -> Self {
+    pub const fn with_fsiz(mut self, val: u32) -> Self {
         self.set_fsiz(val);
         self
     }
@@ -129,12 +160,12 @@ impl UbootSplHeader {
     }
 
     /// Sets the offset from `HDR` to `SPL_IMAGE`.
-    pub fn set_resl(&mut self, val: u32) {
+    pub const fn set_resl(&mut self, val: u32) {
         self.resl = val;
     }
 
     /// Builder function that sets the offset from `HDR` to `SPL_IMAGE`.
-    pub fn with_resl(mut self, val: u32) -> Self {
+    pub const fn with_resl(mut self, val: u32) -> Self {
         self.set_resl(val);
         self
     }
@@ -145,52 +176,182 @@ impl UbootSplHeader {
     }
 
     /// Sets the CRC32 of `u-boot-spl.bin`.
-    pub fn set_crcs(&mut self, val: u32) {
+    pub const fn set_crcs(&mut self, val: u32) {
         self.crcs = val;
     }
 
     /// Builder function that Sets the CRC32 of `u-boot-spl.bin`.
-    pub fn with_crcs(mut self, val: u32) -> Self {
+    pub const fn with_crcs(mut self, val: u32) -> Self {
         self.set_crcs(val);
         self
     }
-}
 
-impl From<&UbootSplHeader> for [u8; SPL_HEADER_LEN] {
-    fn from(val: &UbootSplHeader) -> Self {
-        const WORD_LEN: usize = mem::size_of::<u32>();
+    /// Sets the size and CRC32 fields from the `u-boot-spl.bin` payload.
+    ///
+    /// The payload length is checked with [spl_check_len].
+    pub const fn set_payload(&mut self, payload: &[u8]) -> Result<()> {
+        let len = payload.len();
+
+        match spl_check_len(len) {
+            Ok(()) => {
+                self.set_fsiz(len as u32);
+                self.set_crcs(spl_crc32(payload));
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Builder function that sets the size and CRC32 fields from the `u-boot-spl.bin` payload.
+    pub const fn with_payload(mut self, payload: &[u8]) -> Result<Self> {
+        match self.set_payload(payload) {
+            Ok(()) => Ok(self),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Validates that the header fields are plausible for an U-Boot SPL image.
+    ///
+    /// Checks that `sofs` is [DEF_SOFS], `resl` is between [SPL_HEADER_LEN] and [MAX_SPL_LEN],
+    /// and `fsiz` passes [spl_check_len], the same bound used when creating a header.
+    pub fn validate(&self) -> Result<()> {
+        let resl = self.resl as usize;
+        let fsiz = self.fsiz as usize;
+
+        if self.sofs != DEF_SOFS {
+            Err(Error::InvalidSofs(self.sofs))
+        } else if !(SPL_HEADER_LEN..=MAX_SPL_LEN).contains(&resl) {
+            Err(Error::InvalidResl(self.resl))
+        } else {
+            spl_check_len(fsiz)
+        }
+    }
+
+    /// Verifies the size and CRC32 fields against the `u-boot-spl.bin` payload.
+    pub fn verify_payload(&self, payload: &[u8]) -> Result<()> {
+        let fsiz = self.fsiz as usize;
 
+        if payload.len() != fsiz {
+            Err(Error::InvalidSplLen((payload.len(), fsiz)))
+        } else {
+            let crc = spl_crc32(payload);
+            if crc != self.crcs {
+                Err(Error::InvalidCrc((crc, self.crcs)))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    /// Serializes the header to its little-endian byte representation.
+    ///
+    /// Usable in `const` context, e.g. to embed a header at compile time.
+    pub const fn to_bytes(&self) -> [u8; SPL_HEADER_LEN] {
         let mut res = [0u8; SPL_HEADER_LEN];
         let mut idx = 0usize;
 
         // serialize SOFS field to buffer
-        res[idx..idx.saturating_add(WORD_LEN)].copy_from_slice(val.sofs.to_le_bytes().as_ref());
-        idx = idx.saturating_add(WORD_LEN);
+        idx = copy_bytes(&mut res, idx, self.sofs.to_le_bytes().as_slice());
 
         // serialize BOFS field to buffer
-        res[idx..idx.saturating_add(WORD_LEN)].copy_from_slice(val.bofs.to_le_bytes().as_ref());
-        idx = idx.saturating_add(WORD_LEN);
+        idx = copy_bytes(&mut res, idx, self.bofs.to_le_bytes().as_slice());
 
-        // skip `zro2` reserved padding
-        idx = idx.saturating_add(RES_PAD2_LEN);
+        // serialize `zro2` reserved padding
+        idx = copy_bytes(&mut res, idx, self.zro2.as_slice());
 
         // serialize VERS field to buffer
-        res[idx..idx.saturating_add(WORD_LEN)].copy_from_slice(val.vers.to_le_bytes().as_ref());
-        idx = idx.saturating_add(WORD_LEN);
+        idx = copy_bytes(&mut res, idx, self.vers.to_le_bytes().as_slice());
 
         // serialize FSIZ field to buffer
-        res[idx..idx.saturating_add(WORD_LEN)].copy_from_slice(val.fsiz.to_le_bytes().as_ref());
-        idx = idx.saturating_add(WORD_LEN);
+        idx = copy_bytes(&mut res, idx, self.fsiz.to_le_bytes().as_slice());
 
         // serialize RESL field to buffer
-        res[idx..idx.saturating_add(WORD_LEN)].copy_from_slice(val.resl.to_le_bytes().as_ref());
-        idx = idx.saturating_add(WORD_LEN);
+        idx = copy_bytes(&mut res, idx, self.resl.to_le_bytes().as_slice());
 
         // serialize CRCS field to buffer
-        res[idx..idx.saturating_add(WORD_LEN)].copy_from_slice(val.crcs.to_le_bytes().as_ref());
+        idx = copy_bytes(&mut res, idx, self.crcs.to_le_bytes().as_slice());
+
+        // serialize `zro3` reserved padding
+        copy_bytes(&mut res, idx, self.zro3.as_slice());
 
         res
     }
+
+    /// Deserializes the header from its little-endian byte representation.
+    ///
+    /// Unlike [TryFrom], cannot fail, and is usable in `const` context. Reserved regions are kept
+    /// verbatim, so parsing and serializing a header does not clobber foreign data.
+    pub const fn from_bytes(val: &[u8; SPL_HEADER_LEN]) -> Self {
+        // deserialize SOFS field from buffer
+        let (sofs, idx) = read_word(val, 0);
+
+        // deserialize BOFS field from buffer
+        let (bofs, idx) = read_word(val, idx);
+
+        // deserialize `zro2` reserved padding
+        // Keep the contents verbatim, e.g. the protective MBR of a disk image.
+        let (zro2, idx) = read_bytes(val, idx);
+
+        // deserialize VERS field from buffer
+        let (vers, idx) = read_word(val, idx);
+
+        // deserialize FSIZ field from buffer
+        let (fsiz, idx) = read_word(val, idx);
+
+        // deserialize RESL field from buffer
+        let (resl, idx) = read_word(val, idx);
+
+        // deserialize CRCS field from buffer
+        let (crcs, idx) = read_word(val, idx);
+
+        // deserialize `zro3` reserved padding
+        // Keep the contents verbatim, e.g. the GPT header of a disk image.
+        let (zro3, _) = read_bytes(val, idx);
+
+        Self {
+            sofs,
+            bofs,
+            zro2,
+            vers,
+            fsiz,
+            resl,
+            crcs,
+            zro3,
+        }
+    }
+}
+
+/// Reads a little-endian word from `src` at `idx`, returning it and the index after the word.
+const fn read_word(src: &[u8; SPL_HEADER_LEN], idx: usize) -> (u32, usize) {
+    let word = u32::from_le_bytes([src[idx], src[idx + 1], src[idx + 2], src[idx + 3]]);
+    (word, idx + mem::size_of::<u32>())
+}
+
+/// Reads `N` bytes from `src` at `idx`, returning them and the index after the read bytes.
+const fn read_bytes<const N: usize>(src: &[u8; SPL_HEADER_LEN], idx: usize) -> ([u8; N], usize) {
+    let mut res = [0u8; N];
+    let mut i = 0usize;
+    while i < N {
+        res[i] = src[idx + i];
+        i += 1;
+    }
+    (res, idx + N)
+}
+
+/// Copies `src` into `dst` at `idx`, returning the index after the copied bytes.
+const fn copy_bytes(dst: &mut [u8; SPL_HEADER_LEN], idx: usize, src: &[u8]) -> usize {
+    let mut i = 0usize;
+    while i < src.len() {
+        dst[idx + i] = src[i];
+        i += 1;
+    }
+    idx + src.len()
+}
+
+impl From<&UbootSplHeader> for [u8; SPL_HEADER_LEN] {
+    fn from(val: &UbootSplHeader) -> Self {
+        val.to_bytes()
+    }
 }
 
 impl From<UbootSplHeader> for [u8; SPL_HEADER_LEN] {
@@ -203,56 +364,9 @@ impl TryFrom<&[u8]> for UbootSplHeader {
     type Error = Error;
 
     fn try_from(val: &[u8]) -> Result<Self> {
-        const WORD_LEN: usize = mem::size_of::<u32>();
-
-        if val.len() < SPL_HEADER_LEN {
-            Err(Error::InvalidHeaderLen((val.len(), SPL_HEADER_LEN)))
-        } else {
-            let mut idx = 0usize;
-
-            // deserialize SOFS field from buffer
-            let sofs = u32::from_le_bytes(val[idx..idx.saturating_add(WORD_LEN)].try_into()?);
-            idx = idx.saturating_add(WORD_LEN);
-
-            // deserialize BOFS field from buffer
-            let bofs = u32::from_le_bytes(val[idx..idx.saturating_add(WORD_LEN)].try_into()?);
-            idx = idx.saturating_add(WORD_LEN);
-
-            // skip deserializing `zro2` reserved padding
-            // It doesn't matter what is in this field, so ignore it.
-            let zro2 = [0u8; RES_PAD2_LEN];
-            idx = idx.saturating_add(RES_PAD2_LEN);
-
-            // deserialize VERS field from buffer
-            let vers = u32::from_le_bytes(val[idx..idx.saturating_add(WORD_LEN)].try_into()?);
-            idx = idx.saturating_add(WORD_LEN);
-
-            // deserialize FSIZ field from buffer
-            let fsiz = u32::from_le_bytes(val[idx..idx.saturating_add(WORD_LEN)].try_into()?);
-            idx = idx.saturating_add(WORD_LEN);
-
-            // deserialize RESL field from buffer
-            let resl = u32::from_le_bytes(val[idx..idx.saturating_add(WORD_LEN)].try_into()?);
-            idx = idx.saturating_add(WORD_LEN);
-
-            // deserialize CRCS field from buffer
-            let crcs = u32::from_le_bytes(val[idx..idx.saturating_add(WORD_LEN)].try_into()?);
-
-            // skip deserializing `zro3` reserved padding
-            // It doesn't matter what is in this field, so ignore it.
-            let zro3 = [0u8; RES_PAD3_LEN];
-
-            Ok(Self {
-                sofs,
-                bofs,
-                zro2,
-                vers,
-                fsiz,
-                resl,
-                crcs,
-                zro3,
-            })
-        }
+        val.first_chunk::<SPL_HEADER_LEN>()
+            .map(Self::from_bytes)
+            .ok_or(Error::InvalidHeaderLen((val.len(), SPL_HEADER_LEN)))
     }
 }
 
@@ -272,28 +386,146 @@ impl<const N: usize> TryFrom<[u8; N]> for UbootSplHeader {
     }
 }
 
+/// Checks that an `u-boot-spl.bin` payload length is non-zero and below [MAX_SPL_LEN].
+///
+/// The single bound for SPL payloads: used when creating a header, and when validating a
+/// parsed one.
+pub const fn spl_check_len(len: usize) -> Result<()> {
+    if len == 0 || len >= MAX_SPL_LEN {
+        Err(Error::InvalidSplLen((len, MAX_SPL_LEN)))
+    } else {
+        Ok(())
+    }
+}
+
 impl Default for UbootSplHeader {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Represents the path of the SPL image in a [HeaderConf], borrowed from the caller.
+///
+/// Paths are kept verbatim, so non-UTF-8 filenames are neither truncated nor dropped.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplPath<'a> {
+    /// UTF-8 path, e.g. from an `input_sbl_normal.cfg` file.
+    Str(&'a str),
+    /// Raw path bytes, e.g. a non-UTF-8 Unix filename on a `no_std` target.
+    Bytes(&'a [u8]),
+    /// Platform path, available with the `std` feature.
+    #[cfg(feature = "std")]
+    Path(&'a std::path::Path),
+}
+
+impl<'a> SplPath<'a> {
+    /// Gets the path as a string, if it is valid UTF-8.
+    pub fn to_str(&self) -> Option<&'a str> {
+        match *self {
+            Self::Str(val) => Some(val),
+            Self::Bytes(val) => core::str::from_utf8(val).ok(),
+            #[cfg(feature = "std")]
+            Self::Path(val) => val.to_str(),
+        }
+    }
+
+    /// Gets the raw bytes of the path.
+    ///
+    /// Platform paths are returned in their platform-specific encoding, see
+    /// `OsStr::as_encoded_bytes`.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        match *self {
+            Self::Str(val) => val.as_bytes(),
+            Self::Bytes(val) => val,
+            #[cfg(feature = "std")]
+            Self::Path(val) => val.as_os_str().as_encoded_bytes(),
+        }
+    }
+
+    /// Gets whether the path is empty.
+    pub fn is_empty(&self) -> bool {
+        self.as_bytes().is_empty()
+    }
+
+    /// Gets the path as a platform [Path](std::path::Path).
+    ///
+    /// Raw bytes are converted as-is on Unix, and must be valid UTF-8 on other platforms.
+    #[cfg(feature = "std")]
+    pub fn to_path(&self) -> Option<&'a std::path::Path> {
+        use std::path::Path;
+
+        match *self {
+            Self::Str(val) => Some(Path::new(val)),
+            #[cfg(unix)]
+            Self::Bytes(val) => {
+                use std::os::unix::ffi::OsStrExt;
+                Some(Path::new(std::ffi::OsStr::from_bytes(val)))
+            }
+            #[cfg(not(unix))]
+            Self::Bytes(val) => core::str::from_utf8(val).ok().map(Path::new),
+            Self::Path(val) => Some(val),
+        }
+    }
+}
+
+impl Default for SplPath<'_> {
+    fn default() -> Self {
+        Self::Str("")
+    }
+}
+
+impl fmt::Display for SplPath<'_> {
+    /// Displays the path, replacing invalid UTF-8 sequences with `U+FFFD`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Str(val) => f.write_str(val),
+            Self::Bytes(val) => val.utf8_chunks().try_for_each(|chunk| {
+                f.write_str(chunk.valid())?;
+                if chunk.invalid().is_empty() {
+                    Ok(())
+                } else {
+                    f.write_str("\u{FFFD}")
+                }
+            }),
+            #[cfg(feature = "std")]
+            Self::Path(val) => write!(f, "{}", val.display()),
+        }
+    }
+}
+
 /// Represents configuration arguments for SPL header generation.
-#[repr(C)]
+///
+/// The SPL image path is borrowed, so the configuration stays small and `Copy` on `no_std`
+/// targets.
+///
+/// Example:
+///
+/// ```rust
+/// use spl_tool::{HeaderConf, SplPath};
+///
+/// let conf = HeaderConf::new().with_name("u-boot-spl.bin");
+/// assert_eq!(conf.name().to_str(), Some("u-boot-spl.bin"));
+///
+/// // non-UTF-8 filenames are kept verbatim
+/// let conf = HeaderConf::new().with_name_bytes(b"spl-\xff.bin");
+/// assert_eq!(conf.name(), SplPath::Bytes(b"spl-\xff.bin"));
+/// assert_eq!(conf.name().to_str(), None);
+/// ```
 #[derive(Clone, Copy, Debug, PartialEq)]
-pub struct HeaderConf {
-    name: [u8; PATH_MAX],
+pub struct HeaderConf<'a> {
+    name: SplPath<'a>,
     vers: u32,
     bofs: u32,
     create_header: bool,
     fix_image_header: bool,
 }
 
-impl HeaderConf {
+impl<'a> HeaderConf<'a> {
     /// Creates a new [HeaderConf].
     pub const fn new() -> Self {
         Self {
-            name: [0u8; PATH_MAX],
+            name: SplPath::Str(""),
             vers: DEF_VERS,
             bofs: DEF_BACKUP,
             create_header: false,
@@ -301,32 +533,54 @@ impl HeaderConf {
         }
     }
 
-    /// Gets the header name as a string.
-    pub fn name(&self) -> &str {
-        core::str::from_utf8(self.name[..self.name_len()].as_ref()).unwrap_or("")
-    }
-
-    fn name_len(&self) -> usize {
+    /// Gets the path of the SPL image.
+    pub const fn name(&self) -> SplPath<'a> {
         self.name
-            .iter()
-            .position(|&b| b == 0)
-            .unwrap_or(self.name.len())
     }
 
-    /// Sets the header name from a string.
-    pub fn set_name(&mut self, val: &str) {
-        let val_bytes = val.as_bytes();
-        let len = cmp::min(PATH_MAX - 1, val_bytes.len());
-        self.name[..len].copy_from_slice(val_bytes[..len].as_ref());
-        self.name[len..].copy_from_slice(PATH_ZERO_BYTES[len..].as_ref());
+    /// Sets the path of the SPL image from a string.
+    pub fn set_name(&mut self, val: &'a str) {
+        self.name = SplPath::Str(val);
     }
 
-    /// Sets the header name from a string.
-    pub fn with_name(mut self, val: &str) -> Self {
+    /// Builder function that sets the path of the SPL image from a string.
+    pub fn with_name(mut self, val: &'a str) -> Self {
         self.set_name(val);
         self
     }
 
+    /// Sets the path of the SPL image from raw bytes, e.g. a non-UTF-8 filename.
+    pub fn set_name_bytes(&mut self, val: &'a [u8]) {
+        self.name = SplPath::Bytes(val);
+    }
+
+    /// Builder function that sets the path of the SPL image from raw bytes.
+    pub fn with_name_bytes(mut self, val: &'a [u8]) -> Self {
+        self.set_name_bytes(val);
+        self
+    }
+
+    /// Gets the path of the SPL image as a platform [Path](std::path::Path).
+    ///
+    /// See [SplPath::to_path].
+    #[cfg(feature = "std")]
+    pub fn path(&self) -> Option<&'a std::path::Path> {
+        self.name.to_path()
+    }
+
+    /// Sets the path of the SPL image from a platform path.
+    #[cfg(feature = "std")]
+    pub fn set_path<P: AsRef<std::path::Path> + ?Sized>(&mut self, val: &'a P) {
+        self.name = SplPath::Path(val.as_ref());
+    }
+
+    /// Builder function that sets the path of the SPL image from a platform path.
+    #[cfg(feature = "std")]
+    pub fn with_path<P: AsRef<std::path::Path> + ?Sized>(mut self, val: &'a P) -> Self {
+        self.set_path(val);
+        self
+    }
+
     /// Gets the version.
     pub const fn vers(&self) -> u32 {
         self.vers
@@ -398,8 +652,167 @@ impl HeaderConf {
     }
 }
 
-impl Default for HeaderConf {
+impl Default for HeaderConf<'_> {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::vec;
+
+    use super::*;
+    use crate::SplField;
+
+    #[test]
+    fn test_spl_len_bound() {
+        assert!(matches!(
+            spl_check_len(0),
+            Err(Error::InvalidSplLen((0, MAX_SPL_LEN)))
+        ));
+        assert!(spl_check_len(1).is_ok());
+        assert!(spl_check_len(MAX_SPL_LEN - 1).is_ok());
+        assert!(spl_check_len(MAX_SPL_LEN).is_err());
+
+        // creation and validation agree on the bound
+        let payload = vec![0u8; MAX_SPL_LEN];
+        assert!(UbootSplHeader::new()
+            .with_payload(&payload[..MAX_SPL_LEN - 1])
+            .is_ok());
+        assert!(UbootSplHeader::new()
+            .with_payload(payload.as_ref())
+            .is_err());
+
+        let header = UbootSplHeader::new().with_fsiz(MAX_SPL_LEN as u32 - 1);
+        assert!(header.validate().is_ok());
+        assert!(header.with_fsiz(MAX_SPL_LEN as u32).validate().is_err());
+        assert!(header.with_fsiz(0).validate().is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let header = UbootSplHeader::new().with_fsiz(0x1000);
+        assert!(header.validate().is_ok());
+
+        assert!(matches!(
+            header.with_sofs(0).validate(),
+            Err(Error::InvalidSofs(0))
+        ));
+        assert!(matches!(
+            header.with_resl(SPL_HEADER_LEN as u32 - 1).validate(),
+            Err(Error::InvalidResl(0x3ff))
+        ));
+        assert!(matches!(
+            header.with_resl(MAX_SPL_LEN as u32 + 1).validate(),
+            Err(Error::InvalidResl(_))
+        ));
+
+        let detail = header.with_resl(0).validate().unwrap_err().detail();
+        assert_eq!(detail.field, Some(SplField::Resl));
+        assert_eq!(detail.actual, Some(0));
+        assert_eq!(detail.expected, None);
+        assert_eq!(detail.min, Some(SPL_HEADER_LEN as u64));
+        assert_eq!(detail.max, Some(MAX_SPL_LEN as u64));
+    }
+
+    #[test]
+    fn test_crc_detail() {
+        let payload = [0x13u8, 0x00, 0x00, 0x00];
+        let header = UbootSplHeader::new()
+            .with_payload(payload.as_ref())
+            .unwrap()
+            .with_crcs(0);
+
+        // the CRC check is shared with other formats, so it names no SPL header field
+        let detail = header
+            .verify_payload(payload.as_ref())
+            .unwrap_err()
+            .detail();
+        assert_eq!(detail.field, None);
+        assert_eq!(detail.actual, Some(spl_crc32(payload.as_ref()) as u64));
+        assert_eq!(detail.expected, Some(0));
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let mut bytes = UbootSplHeader::new()
+            .with_bofs(0x100000)
+            .with_fsiz(0x1234)
+            .with_crcs(0xdeadbeef)
+            .to_bytes();
+        // reserved regions are kept verbatim
+        bytes[0x10] = 0x55;
+        bytes[0x3ff] = 0xaa;
+
+        let header = UbootSplHeader::try_from(bytes).unwrap();
+        assert_eq!(header.bofs(), 0x100000);
+        assert_eq!(header.fsiz(), 0x1234);
+        assert_eq!(header.crcs(), 0xdeadbeef);
+        assert_eq!(header.to_bytes(), bytes);
+
+        assert!(UbootSplHeader::try_from(&bytes[..SPL_HEADER_LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn test_verify_payload() {
+        let payload = [0x13u8, 0x00, 0x00, 0x00];
+        let header = UbootSplHeader::new()
+            .with_payload(payload.as_ref())
+            .unwrap();
+
+        assert!(header.verify_payload(payload.as_ref()).is_ok());
+        assert!(matches!(
+            header.verify_payload(&payload[..3]),
+            Err(Error::InvalidSplLen((3, 4)))
+        ));
+        assert!(matches!(
+            header.verify_payload([0x13u8, 0x00, 0x00, 0x01].as_ref()),
+            Err(Error::InvalidCrc(_))
+        ));
+    }
+
+    #[test]
+    fn test_spl_path_display() {
+        assert_eq!(std::format!("{}", SplPath::Str("spl.bin")), "spl.bin");
+        assert_eq!(
+            std::format!("{}", SplPath::Bytes(b"spl-\xff.bin")),
+            "spl-\u{FFFD}.bin"
+        );
+        // one replacement per invalid sequence, including a truncated one at the end
+        assert_eq!(
+            std::format!("{}", SplPath::Bytes(b"\xff\xfea\xe2\x82")),
+            "\u{FFFD}\u{FFFD}a\u{FFFD}"
+        );
+
+        let path = SplPath::Bytes(b"spl-\xff.bin");
+        assert_eq!(path.to_str(), None);
+        assert_eq!(path.as_bytes(), b"spl-\xff.bin");
+        assert!(!path.is_empty());
+        assert!(SplPath::default().is_empty());
+    }
+
+    #[cfg(all(feature = "std", unix))]
+    #[test]
+    fn test_spl_path_to_path() {
+        use std::os::unix::ffi::OsStrExt;
+        use std::path::Path;
+
+        let name = b"/tmp/spl-\xff.bin";
+        let path = SplPath::Bytes(name).to_path().unwrap();
+        assert_eq!(path.as_os_str().as_bytes(), name);
+        assert_eq!(path.file_name().unwrap().as_bytes(), b"spl-\xff.bin");
+
+        let conf = HeaderConf::new().with_name_bytes(name);
+        assert_eq!(conf.path(), Some(path));
+
+        let conf = HeaderConf::new().with_path(path);
+        assert_eq!(conf.name().as_bytes(), name);
+        assert_eq!(conf.path(), Some(path));
+
+        assert_eq!(
+            SplPath::Str("spl.bin").to_path(),
+            Some(Path::new("spl.bin"))
+        );
+    }
+}
@@ -0,0 +1,496 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use super::{Error, Result, SplField, UbootSplHeader, SPL_HEADER_LEN};
+#[cfg(feature = "secure-boot")]
+use super::{SPL_KEY_HASH_LEN, SPL_PUB_KEY_LEN, SPL_SIGNATURE_LEN};
+
+/// Represents a zero-copy view of a serialized [UbootSplHeader].
+///
+/// Fields are read in little-endian directly from the [SplField::offset] in the buffer, so the
+/// header is never copied into a [UbootSplHeader]. Useful on targets with little RAM, e.g. to
+/// inspect an SPL image in memory-mapped flash.
+///
+/// Example:
+///
+/// ```rust
+/// use spl_tool::{SplHeaderRef, UbootSplHeader, DEF_SOFS};
+///
+/// let bytes = UbootSplHeader::new().with_fsiz(0x1000).to_bytes();
+/// let hdr = SplHeaderRef::new(bytes.as_ref()).unwrap();
+///
+/// assert_eq!(hdr.sofs(), DEF_SOFS);
+/// assert_eq!(hdr.fsiz(), 0x1000);
+/// assert_eq!(UbootSplHeader::from(hdr), UbootSplHeader::try_from(bytes).unwrap());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SplHeaderRef<'a>(&'a [u8; SPL_HEADER_LEN]);
+
+impl<'a> SplHeaderRef<'a> {
+    /// Creates a new [SplHeaderRef] over the first [SPL_HEADER_LEN] bytes of `buf`.
+    ///
+    /// Returns [Error::InvalidHeaderLen] when `buf` is shorter than the header.
+    pub const fn new(buf: &'a [u8]) -> Result<Self> {
+        match buf.first_chunk::<SPL_HEADER_LEN>() {
+            Some(hdr) => Ok(Self(hdr)),
+            None => Err(Error::InvalidHeaderLen((buf.len(), SPL_HEADER_LEN))),
+        }
+    }
+
+    /// Gets the [SPL_HEADER_LEN] bytes of the header.
+    pub const fn as_bytes(&self) -> &'a [u8; SPL_HEADER_LEN] {
+        self.0
+    }
+
+    /// Gets the offset of SPL header: 64+256+256 = 0x240
+    pub const fn sofs(&self) -> u32 {
+        read_word(self.0, SplField::Sofs)
+    }
+
+    /// Gets the offset of the backup SPL image from the start of the flash.
+    pub const fn bofs(&self) -> u32 {
+        read_word(self.0, SplField::Bofs)
+    }
+
+    /// Gets the raw signature algorithm field (experimental).
+    #[cfg(feature = "secure-boot")]
+    pub const fn salg(&self) -> u32 {
+        read_word(self.0, SplField::Salg)
+    }
+
+    /// Gets the RSA public exponent field.
+    #[cfg(feature = "secure-boot")]
+    pub const fn sexp(&self) -> u32 {
+        read_word(self.0, SplField::Sexp)
+    }
+
+    /// Gets the SHA-256 hash of the public key field.
+    #[cfg(feature = "secure-boot")]
+    pub const fn khsh(&self) -> &'a [u8; SPL_KEY_HASH_LEN] {
+        read_array(self.0, SplField::Khsh)
+    }
+
+    /// Gets the public key field.
+    #[cfg(feature = "secure-boot")]
+    pub const fn pkey(&self) -> &'a [u8; SPL_PUB_KEY_LEN] {
+        read_array(self.0, SplField::Pkey)
+    }
+
+    /// Gets the signature field.
+    #[cfg(feature = "secure-boot")]
+    pub const fn ssig(&self) -> &'a [u8; SPL_SIGNATURE_LEN] {
+        read_array(self.0, SplField::Ssig)
+    }
+
+    /// Gets whether the header holds a signature (experimental).
+    #[cfg(feature = "secure-boot")]
+    pub const fn is_signed(&self) -> bool {
+        self.salg() != 0
+    }
+
+    /// Gets the version.
+    pub const fn vers(&self) -> u32 {
+        read_word(self.0, SplField::Vers)
+    }
+
+    /// Gets the size of the `u-boot-spl.bin` payload.
+    pub const fn fsiz(&self) -> u32 {
+        read_word(self.0, SplField::Fsiz)
+    }
+
+    /// Gets the offset from `HDR` to `SPL_IMAGE`.
+    pub const fn resl(&self) -> u32 {
+        read_word(self.0, SplField::Resl)
+    }
+
+    /// Gets the CRC32 of the `u-boot-spl.bin` payload.
+    pub const fn crcs(&self) -> u32 {
+        read_word(self.0, SplField::Crcs)
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for SplHeaderRef<'a> {
+    type Error = Error;
+
+    fn try_from(val: &'a [u8]) -> Result<Self> {
+        Self::new(val)
+    }
+}
+
+impl From<SplHeaderRef<'_>> for UbootSplHeader {
+    fn from(val: SplHeaderRef<'_>) -> Self {
+        Self::from_bytes(val.as_bytes())
+    }
+}
+
+/// Represents a mutable zero-copy view of a serialized [UbootSplHeader].
+///
+/// Fields are written in little-endian directly at the [SplField::offset] in the buffer, so a
+/// header can be edited in place, leaving the reserved regions untouched.
+///
+/// Example:
+///
+/// ```rust
+/// use spl_tool::{spl_crc32, SplHeaderMut, UbootSplHeader, SPL_HEADER_LEN};
+///
+/// let payload = [0x13, 0x00, 0x00, 0x00];
+/// let mut img = [0u8; SPL_HEADER_LEN];
+///
+/// let mut hdr = SplHeaderMut::new(img.as_mut()).unwrap();
+/// hdr.set_header(&UbootSplHeader::new());
+/// hdr.set_fsiz(payload.len() as u32);
+/// hdr.set_crcs(spl_crc32(payload.as_ref()));
+///
+/// let header = UbootSplHeader::try_from(img).unwrap();
+/// assert!(header.verify_payload(payload.as_ref()).is_ok());
+/// ```
+#[derive(Debug, PartialEq)]
+pub struct SplHeaderMut<'a>(&'a mut [u8; SPL_HEADER_LEN]);
+
+impl<'a> SplHeaderMut<'a> {
+    /// Creates a new [SplHeaderMut] over the first [SPL_HEADER_LEN] bytes of `buf`.
+    ///
+    /// Returns [Error::InvalidHeaderLen] when `buf` is shorter than the header.
+    pub const fn new(buf: &'a mut [u8]) -> Result<Self> {
+        let len = buf.len();
+        match buf.first_chunk_mut::<SPL_HEADER_LEN>() {
+            Some(hdr) => Ok(Self(hdr)),
+            None => Err(Error::InvalidHeaderLen((len, SPL_HEADER_LEN))),
+        }
+    }
+
+    /// Gets a read-only [SplHeaderRef] of the header.
+    pub const fn header_ref(&self) -> SplHeaderRef<'_> {
+        SplHeaderRef(self.0)
+    }
+
+    /// Gets the [SPL_HEADER_LEN] bytes of the header.
+    pub const fn as_bytes(&self) -> &[u8; SPL_HEADER_LEN] {
+        self.0
+    }
+
+    /// Gets the offset of SPL header: 64+256+256 = 0x240
+    pub const fn sofs(&self) -> u32 {
+        self.header_ref().sofs()
+    }
+
+    /// Sets the offset of SPL header.
+    pub const fn set_sofs(&mut self, val: u32) {
+        write_bytes(self.0, SplField::Sofs, val.to_le_bytes().as_slice());
+    }
+
+    /// Gets the offset of the backup SPL image from the start of the flash.
+    pub const fn bofs(&self) -> u32 {
+        self.header_ref().bofs()
+    }
+
+    /// Sets the offset of the backup SPL image from the start of the flash.
+    pub const fn set_bofs(&mut self, val: u32) {
+        write_bytes(self.0, SplField::Bofs, val.to_le_bytes().as_slice());
+    }
+
+    /// Gets the raw signature algorithm field (experimental).
+    #[cfg(feature = "secure-boot")]
+    pub const fn salg(&self) -> u32 {
+        self.header_ref().salg()
+    }
+
+    /// Sets the raw signature algorithm field.
+    #[cfg(feature = "secure-boot")]
+    pub const fn set_salg(&mut self, val: u32) {
+        write_bytes(self.0, SplField::Salg, val.to_le_bytes().as_slice());
+    }
+
+    /// Gets the RSA public exponent field.
+    #[cfg(feature = "secure-boot")]
+    pub const fn sexp(&self) -> u32 {
+        self.header_ref().sexp()
+    }
+
+    /// Sets the RSA public exponent field.
+    #[cfg(feature = "secure-boot")]
+    pub const fn set_sexp(&mut self, val: u32) {
+        write_bytes(self.0, SplField::Sexp, val.to_le_bytes().as_slice());
+    }
+
+    /// Gets the SHA-256 hash of the public key field.
+    #[cfg(feature = "secure-boot")]
+    pub const fn khsh(&self) -> &[u8; SPL_KEY_HASH_LEN] {
+        read_array(self.0, SplField::Khsh)
+    }
+
+    /// Sets the SHA-256 hash of the public key field.
+    #[cfg(feature = "secure-boot")]
+    pub const fn set_khsh(&mut self, val: &[u8; SPL_KEY_HASH_LEN]) {
+        write_bytes(self.0, SplField::Khsh, val.as_slice());
+    }
+
+    /// Gets the public key field.
+    #[cfg(feature = "secure-boot")]
+    pub const fn pkey(&self) -> &[u8; SPL_PUB_KEY_LEN] {
+        read_array(self.0, SplField::Pkey)
+    }
+
+    /// Sets the public key field.
+    #[cfg(feature = "secure-boot")]
+    pub const fn set_pkey(&mut self, val: &[u8; SPL_PUB_KEY_LEN]) {
+        write_bytes(self.0, SplField::Pkey, val.as_slice());
+    }
+
+    /// Gets the signature field.
+    #[cfg(feature = "secure-boot")]
+    pub const fn ssig(&self) -> &[u8; SPL_SIGNATURE_LEN] {
+        read_array(self.0, SplField::Ssig)
+    }
+
+    /// Sets the signature field.
+    #[cfg(feature = "secure-boot")]
+    pub const fn set_ssig(&mut self, val: &[u8; SPL_SIGNATURE_LEN]) {
+        write_bytes(self.0, SplField::Ssig, val.as_slice());
+    }
+
+    /// Gets the version.
+    pub const fn vers(&self) -> u32 {
+        self.header_ref().vers()
+    }
+
+    /// Sets the version.
+    pub const fn set_vers(&mut self, val: u32) {
+        write_bytes(self.0, SplField::Vers, val.to_le_bytes().as_slice());
+    }
+
+    /// Gets the size of the `u-boot-spl.bin` payload.
+    pub const fn fsiz(&self) -> u32 {
+        self.header_ref().fsiz()
+    }
+
+    /// Sets the size of the `u-boot-spl.bin` payload.
+    pub const fn set_fsiz(&mut self, val: u32) {
+        write_bytes(self.0, SplField::Fsiz, val.to_le_bytes().as_slice());
+    }
+
+    /// Gets the offset from `HDR` to `SPL_IMAGE`.
+    pub const fn resl(&self) -> u32 {
+        self.header_ref().resl()
+    }
+
+    /// Sets the offset from `HDR` to `SPL_IMAGE`.
+    pub const fn set_resl(&mut self, val: u32) {
+        write_bytes(self.0, SplField::Resl, val.to_le_bytes().as_slice());
+    }
+
+    /// Gets the CRC32 of the `u-boot-spl.bin` payload.
+    pub const fn crcs(&self) -> u32 {
+        self.header_ref().crcs()
+    }
+
+    /// Sets the CRC32 of the `u-boot-spl.bin` payload.
+    pub const fn set_crcs(&mut self, val: u32) {
+        write_bytes(self.0, SplField::Crcs, val.to_le_bytes().as_slice());
+    }
+
+    /// Overwrites the header with a serialized [UbootSplHeader], including its reserved regions.
+    pub const fn set_header(&mut self, header: &UbootSplHeader) {
+        *self.0 = header.to_bytes();
+    }
+}
+
+impl<'a> TryFrom<&'a mut [u8]> for SplHeaderMut<'a> {
+    type Error = Error;
+
+    fn try_from(val: &'a mut [u8]) -> Result<Self> {
+        Self::new(val)
+    }
+}
+
+impl From<&SplHeaderMut<'_>> for UbootSplHeader {
+    fn from(val: &SplHeaderMut<'_>) -> Self {
+        val.header_ref().into()
+    }
+}
+
+impl From<SplHeaderMut<'_>> for UbootSplHeader {
+    fn from(val: SplHeaderMut<'_>) -> Self {
+        (&val).into()
+    }
+}
+
+const fn read_word(buf: &[u8; SPL_HEADER_LEN], field: SplField) -> u32 {
+    let off = field.offset();
+    u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+
+// the fields always fit in the header
+#[cfg(feature = "secure-boot")]
+const fn read_array<const N: usize>(buf: &[u8; SPL_HEADER_LEN], field: SplField) -> &[u8; N] {
+    match buf.split_at(field.offset()).1.first_chunk::<N>() {
+        Some(val) => val,
+        None => panic!("SPL header field is outside of the header"),
+    }
+}
+
+const fn write_bytes(buf: &mut [u8; SPL_HEADER_LEN], field: SplField, val: &[u8]) {
+    let off = field.offset();
+    let mut i = 0usize;
+    while i < val.len() {
+        buf[off + i] = val[i];
+        i += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SplFieldValue;
+
+    fn header() -> UbootSplHeader {
+        let header = UbootSplHeader::new()
+            .with_bofs(0x1122_3344)
+            .with_vers(0x0101_0102)
+            .with_fsiz(0x1000)
+            .with_resl(0x800)
+            .with_crcs(0xdead_beef);
+
+        #[cfg(feature = "secure-boot")]
+        let header = header
+            .with_salg(0x5566_7788)
+            .with_sexp(0x10001)
+            .with_khsh([0x11; SPL_KEY_HASH_LEN])
+            .with_pkey([0x22; SPL_PUB_KEY_LEN])
+            .with_ssig([0x33; SPL_SIGNATURE_LEN]);
+
+        header
+    }
+
+    fn get<'a>(hdr: &SplHeaderRef<'a>, field: SplField) -> SplFieldValue<'a> {
+        match field {
+            SplField::Sofs => SplFieldValue::Word(hdr.sofs()),
+            SplField::Bofs => SplFieldValue::Word(hdr.bofs()),
+            #[cfg(feature = "secure-boot")]
+            SplField::Salg => SplFieldValue::Word(hdr.salg()),
+            #[cfg(feature = "secure-boot")]
+            SplField::Sexp => SplFieldValue::Word(hdr.sexp()),
+            #[cfg(feature = "secure-boot")]
+            SplField::Khsh => SplFieldValue::Bytes(hdr.khsh().as_ref()),
+            #[cfg(feature = "secure-boot")]
+            SplField::Pkey => SplFieldValue::Bytes(hdr.pkey().as_ref()),
+            #[cfg(feature = "secure-boot")]
+            SplField::Ssig => SplFieldValue::Bytes(hdr.ssig().as_ref()),
+            SplField::Vers => SplFieldValue::Word(hdr.vers()),
+            SplField::Fsiz => SplFieldValue::Word(hdr.fsiz()),
+            SplField::Resl => SplFieldValue::Word(hdr.resl()),
+            SplField::Crcs => SplFieldValue::Word(hdr.crcs()),
+        }
+    }
+
+    fn set(hdr: &mut SplHeaderMut<'_>, field: SplField, val: SplFieldValue<'_>) {
+        match (field, val) {
+            (SplField::Sofs, SplFieldValue::Word(val)) => hdr.set_sofs(val),
+            (SplField::Bofs, SplFieldValue::Word(val)) => hdr.set_bofs(val),
+            #[cfg(feature = "secure-boot")]
+            (SplField::Salg, SplFieldValue::Word(val)) => hdr.set_salg(val),
+            #[cfg(feature = "secure-boot")]
+            (SplField::Sexp, SplFieldValue::Word(val)) => hdr.set_sexp(val),
+            #[cfg(feature = "secure-boot")]
+            (SplField::Khsh, SplFieldValue::Bytes(val)) => hdr.set_khsh(val.try_into().unwrap()),
+            #[cfg(feature = "secure-boot")]
+            (SplField::Pkey, SplFieldValue::Bytes(val)) => hdr.set_pkey(val.try_into().unwrap()),
+            #[cfg(feature = "secure-boot")]
+            (SplField::Ssig, SplFieldValue::Bytes(val)) => hdr.set_ssig(val.try_into().unwrap()),
+            (SplField::Vers, SplFieldValue::Word(val)) => hdr.set_vers(val),
+            (SplField::Fsiz, SplFieldValue::Word(val)) => hdr.set_fsiz(val),
+            (SplField::Resl, SplFieldValue::Word(val)) => hdr.set_resl(val),
+            (SplField::Crcs, SplFieldValue::Word(val)) => hdr.set_crcs(val),
+            (field, val) => panic!("{field}: unexpected value {val:?}"),
+        }
+    }
+
+    const fn value_len(val: SplFieldValue<'_>) -> usize {
+        match val {
+            SplFieldValue::Word(_) => 4,
+            SplFieldValue::Bytes(val) => val.len(),
+        }
+    }
+
+    #[test]
+    fn test_getters() {
+        let header = header();
+        let bytes = header.to_bytes();
+        let hdr = SplHeaderRef::new(bytes.as_ref()).unwrap();
+
+        for &field in SplField::ALL {
+            assert_eq!(get(&hdr, field), field.value(&header), "{field}");
+        }
+
+        let mut buf = bytes;
+        let hdr = SplHeaderMut::new(buf.as_mut()).unwrap();
+        for &field in SplField::ALL {
+            assert_eq!(
+                get(&hdr.header_ref(), field),
+                field.value(&header),
+                "{field}"
+            );
+        }
+
+        assert_eq!(UbootSplHeader::from(hdr), header);
+    }
+
+    #[test]
+    fn test_setters() {
+        let header = header();
+        let bytes = header.to_bytes();
+
+        for &field in SplField::ALL {
+            let val = field.value(&header);
+            let range = field.offset()..field.offset() + value_len(val);
+
+            let mut buf = [0u8; SPL_HEADER_LEN];
+            set(&mut SplHeaderMut::new(buf.as_mut()).unwrap(), field, val);
+
+            // only the bytes of the field are written
+            assert_eq!(&buf[range.clone()], &bytes[range.clone()], "{field}");
+            assert!(
+                buf.iter()
+                    .enumerate()
+                    .all(|(idx, &b)| range.contains(&idx) || b == 0),
+                "{field}"
+            );
+        }
+
+        // setting every field rebuilds the serialized header
+        let mut buf = [0u8; SPL_HEADER_LEN];
+        let mut hdr = SplHeaderMut::new(buf.as_mut()).unwrap();
+        for &field in SplField::ALL {
+            set(&mut hdr, field, field.value(&header));
+        }
+        assert_eq!(buf, bytes);
+    }
+
+    #[test]
+    fn test_set_header_keeps_trailing_data() {
+        let header = header();
+        let mut buf = [0xaau8; SPL_HEADER_LEN + 4];
+
+        SplHeaderMut::new(buf.as_mut()).unwrap().set_header(&header);
+
+        assert_eq!(&buf[..SPL_HEADER_LEN], header.to_bytes().as_ref());
+        assert_eq!(&buf[SPL_HEADER_LEN..], [0xaa; 4].as_ref());
+    }
+
+    #[test]
+    fn test_short_buffer() {
+        let mut buf = [0u8; SPL_HEADER_LEN - 1];
+
+        assert!(matches!(
+            SplHeaderRef::new(buf.as_ref()),
+            Err(Error::InvalidHeaderLen((len, SPL_HEADER_LEN))) if len == SPL_HEADER_LEN - 1
+        ));
+        assert!(matches!(
+            SplHeaderMut::new(buf.as_mut()),
+            Err(Error::InvalidHeaderLen((len, SPL_HEADER_LEN))) if len == SPL_HEADER_LEN - 1
+        ));
+        assert!(matches!(
+            SplHeaderMut::new(&mut []),
+            Err(Error::InvalidHeaderLen((0, SPL_HEADER_LEN)))
+        ));
+    }
+}
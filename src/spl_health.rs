@@ -0,0 +1,346 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use core::fmt;
+
+use super::{crc32, crc32_final, Error, Result, UbootSplHeader, SPL_CRC32_POLY, SPL_HEADER_LEN};
+
+/// Length of the stack buffer used to stream the SPL payload for the CRC32 check.
+pub const HEALTH_CHUNK_LEN: usize = 256;
+
+/// Represents the status of a single SPL copy in flash.
+#[derive(Clone, Copy, Debug)]
+pub enum SplCopyStatus {
+    /// The header is valid, and the payload CRC32 matches.
+    Ok,
+    /// The header region is erased (all `0xff`).
+    Blank,
+    /// The header fields are implausible.
+    InvalidHeader(Error),
+    /// The payload CRC32 does not match the header `crcs` field.
+    CrcMismatch { actual: u32, expected: u32 },
+    /// Reading from flash failed.
+    ReadError(Error),
+}
+
+impl SplCopyStatus {
+    /// Gets whether the SPL copy is intact.
+    pub const fn is_ok(&self) -> bool {
+        matches!(self, Self::Ok)
+    }
+}
+
+impl fmt::Display for SplCopyStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ok => write!(f, "ok"),
+            Self::Blank => write!(f, "blank"),
+            Self::InvalidHeader(err) => write!(f, "invalid header: {err}"),
+            Self::CrcMismatch { actual, expected } => {
+                write!(
+                    f,
+                    "CRC mismatch: {actual:#010x}, expected: {expected:#010x}"
+                )
+            }
+            Self::ReadError(err) => write!(f, "read error: {err}"),
+        }
+    }
+}
+
+/// Represents the status of the primary and backup SPL copies in flash.
+#[derive(Clone, Copy, Debug)]
+pub struct SplHealth {
+    /// Offset of the backup copy that was checked.
+    pub bofs: u32,
+    /// Status of the primary copy at offset `0`.
+    pub primary: SplCopyStatus,
+    /// Status of the backup copy at `bofs`.
+    pub backup: SplCopyStatus,
+}
+
+impl SplHealth {
+    /// Gets whether both SPL copies are intact.
+    pub const fn is_ok(&self) -> bool {
+        self.primary.is_ok() && self.backup.is_ok()
+    }
+
+    /// Gets whether at least one SPL copy is intact, so the board can still boot.
+    pub const fn is_bootable(&self) -> bool {
+        self.primary.is_ok() || self.backup.is_ok()
+    }
+}
+
+/// Checks the primary and backup SPL copies using a read callback.
+///
+/// `read` is called with an offset and a buffer to fill. The backup offset is taken from the
+/// primary header when it is valid, and `fallback_bofs` otherwise.
+///
+/// Each payload is streamed through the CRC32 in [HEALTH_CHUNK_LEN] chunks, so it is never
+/// buffered in full.
+pub fn spl_health_check<R>(mut read: R, fallback_bofs: u32) -> SplHealth
+where
+    R: FnMut(u32, &mut [u8]) -> Result<()>,
+{
+    let (primary, primary_header) = spl_copy_status(&mut read, 0);
+
+    let bofs = match (primary, primary_header) {
+        (SplCopyStatus::Ok | SplCopyStatus::CrcMismatch { .. }, Some(hdr)) => hdr.bofs(),
+        _ => fallback_bofs,
+    };
+
+    let (backup, _) = spl_copy_status(&mut read, bofs);
+
+    SplHealth {
+        bofs,
+        primary,
+        backup,
+    }
+}
+
+/// Checks the SPL copy at `offset` using a read callback.
+///
+/// Returns the status, and the parsed header if it was plausible.
+pub fn spl_copy_status<R>(read: &mut R, offset: u32) -> (SplCopyStatus, Option<UbootSplHeader>)
+where
+    R: FnMut(u32, &mut [u8]) -> Result<()>,
+{
+    let mut hdr_bytes = [0u8; SPL_HEADER_LEN];
+    if let Err(err) = read(offset, hdr_bytes.as_mut()) {
+        return (SplCopyStatus::ReadError(err), None);
+    }
+
+    if hdr_bytes.iter().all(|&b| b == 0xff) {
+        return (SplCopyStatus::Blank, None);
+    }
+
+    let header = match UbootSplHeader::try_from(hdr_bytes).and_then(|h| h.validate().map(|_| h)) {
+        Ok(h) => h,
+        Err(err) => return (SplCopyStatus::InvalidHeader(err), None),
+    };
+
+    let payload_offset = offset.saturating_add(header.resl());
+    let status = match spl_stream_crc(read, payload_offset, header.fsiz() as usize) {
+        Ok(crc) if crc == header.crcs() => SplCopyStatus::Ok,
+        Ok(crc) => SplCopyStatus::CrcMismatch {
+            actual: crc,
+            expected: header.crcs(),
+        },
+        Err(err) => SplCopyStatus::ReadError(err),
+    };
+
+    (status, Some(header))
+}
+
+/// Calculates the SPL CRC32 of `len` bytes at `offset` using a read callback.
+pub fn spl_stream_crc<R>(read: &mut R, offset: u32, len: usize) -> Result<u32>
+where
+    R: FnMut(u32, &mut [u8]) -> Result<()>,
+{
+    let mut buf = [0u8; HEALTH_CHUNK_LEN];
+    let mut crc = !0u32;
+    let mut pos = 0usize;
+
+    while pos < len {
+        let chunk_len = core::cmp::min(len - pos, HEALTH_CHUNK_LEN);
+
+        read(offset.saturating_add(pos as u32), &mut buf[..chunk_len])?;
+        crc = crc32(crc, SPL_CRC32_POLY, &buf[..chunk_len]);
+
+        pos += chunk_len;
+    }
+
+    Ok(crc32_final(crc))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::spl_crc32;
+
+    const BOFS: u32 = 0x1000;
+    const FALLBACK_BOFS: u32 = 0x2000;
+    const FLASH_LEN: usize = 0x3000;
+    // spans three CRC chunks, the last one partial
+    const PAYLOAD_LEN: usize = HEALTH_CHUNK_LEN * 2 + 3;
+
+    fn payload() -> Vec<u8> {
+        (0..PAYLOAD_LEN).map(|i| (i * 7) as u8).collect()
+    }
+
+    fn write_copy(flash: &mut [u8], offset: u32, bofs: u32) -> UbootSplHeader {
+        let payload = payload();
+        let header = UbootSplHeader::new()
+            .with_bofs(bofs)
+            .with_payload(payload.as_ref())
+            .unwrap();
+
+        let start = offset as usize;
+        let resl = start + header.resl() as usize;
+        flash[start..start + SPL_HEADER_LEN].copy_from_slice(header.to_bytes().as_ref());
+        flash[resl..resl + payload.len()].copy_from_slice(payload.as_ref());
+
+        header
+    }
+
+    // erased flash with the primary and backup copies
+    fn flash() -> Vec<u8> {
+        let mut res = std::vec![0xffu8; FLASH_LEN];
+        write_copy(res.as_mut(), 0, BOFS);
+        write_copy(res.as_mut(), BOFS, BOFS);
+        res
+    }
+
+    fn reader(flash: &[u8]) -> impl FnMut(u32, &mut [u8]) -> Result<()> + '_ {
+        |pos, buf| {
+            let start = pos as usize;
+            let src = flash
+                .get(start..start + buf.len())
+                .ok_or(Error::FlashRead(pos))?;
+            buf.copy_from_slice(src);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_ok() {
+        let flash = flash();
+        let health = spl_health_check(reader(flash.as_ref()), FALLBACK_BOFS);
+
+        assert_eq!(health.bofs, BOFS);
+        assert!(health.primary.is_ok());
+        assert!(health.backup.is_ok());
+        assert!(health.is_ok());
+    }
+
+    #[test]
+    fn test_stream_chunks() {
+        let flash = flash();
+        let mut reads = Vec::new();
+        let mut read = reader(flash.as_ref());
+        let mut record = |pos: u32, buf: &mut [u8]| {
+            reads.push((pos, buf.len()));
+            read(pos, buf)
+        };
+
+        let (status, header) = spl_copy_status(&mut record, BOFS);
+        assert!(status.is_ok());
+
+        let resl = BOFS + header.unwrap().resl();
+        let chunk = HEALTH_CHUNK_LEN as u32;
+        assert_eq!(
+            reads,
+            [
+                (BOFS, SPL_HEADER_LEN),
+                (resl, HEALTH_CHUNK_LEN),
+                (resl + chunk, HEALTH_CHUNK_LEN),
+                (resl + chunk * 2, 3),
+            ]
+        );
+        assert_eq!(
+            spl_stream_crc(&mut reader(flash.as_ref()), resl, PAYLOAD_LEN).unwrap(),
+            spl_crc32(payload().as_ref())
+        );
+    }
+
+    #[test]
+    fn test_blank() {
+        let flash = std::vec![0xffu8; FLASH_LEN];
+        let health = spl_health_check(reader(flash.as_ref()), FALLBACK_BOFS);
+
+        assert_eq!(health.bofs, FALLBACK_BOFS);
+        assert!(matches!(health.primary, SplCopyStatus::Blank));
+        assert!(matches!(health.backup, SplCopyStatus::Blank));
+        assert!(!health.is_bootable());
+    }
+
+    #[test]
+    fn test_crc_mismatch() {
+        let mut flash = flash();
+        let header = UbootSplHeader::try_from(flash.as_slice()).unwrap();
+
+        // the last byte, in the partial chunk
+        let last = header.resl() as usize + PAYLOAD_LEN - 1;
+        flash[last] ^= 1;
+
+        let mut payload = payload();
+        payload[PAYLOAD_LEN - 1] ^= 1;
+
+        let health = spl_health_check(reader(flash.as_ref()), FALLBACK_BOFS);
+
+        // the backup offset is still taken from the primary header
+        assert_eq!(health.bofs, BOFS);
+        assert!(matches!(
+            health.primary,
+            SplCopyStatus::CrcMismatch { actual, expected }
+                if actual == spl_crc32(payload.as_ref()) && expected == header.crcs()
+        ));
+        assert!(health.backup.is_ok());
+        assert!(!health.is_ok());
+        assert!(health.is_bootable());
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let mut flash = flash();
+        flash[..4].copy_from_slice(0x100u32.to_le_bytes().as_ref());
+
+        let health = spl_health_check(reader(flash.as_ref()), FALLBACK_BOFS);
+
+        assert_eq!(health.bofs, FALLBACK_BOFS);
+        assert!(matches!(
+            health.primary,
+            SplCopyStatus::InvalidHeader(Error::InvalidSofs(0x100))
+        ));
+        assert!(matches!(health.backup, SplCopyStatus::Blank));
+    }
+
+    #[test]
+    fn test_backup_at_bofs() {
+        // the primary points to a backup copy at the fallback offset
+        let mut flash = std::vec![0xffu8; FLASH_LEN];
+        write_copy(flash.as_mut(), 0, FALLBACK_BOFS);
+        write_copy(flash.as_mut(), FALLBACK_BOFS, FALLBACK_BOFS);
+
+        let health = spl_health_check(reader(flash.as_ref()), BOFS);
+
+        assert_eq!(health.bofs, FALLBACK_BOFS);
+        assert!(health.is_ok());
+
+        // without a valid primary, the backup is looked up at the fallback offset
+        flash[..SPL_HEADER_LEN].fill(0xff);
+        let health = spl_health_check(reader(flash.as_ref()), FALLBACK_BOFS);
+
+        assert!(matches!(health.primary, SplCopyStatus::Blank));
+        assert!(health.backup.is_ok());
+        assert!(health.is_bootable());
+    }
+
+    #[test]
+    fn test_read_error() {
+        let flash = flash();
+
+        // the backup payload is cut off
+        let len = BOFS as usize + SPL_HEADER_LEN + HEALTH_CHUNK_LEN;
+        let health = spl_health_check(reader(&flash[..len]), FALLBACK_BOFS);
+
+        assert!(health.primary.is_ok());
+        assert!(matches!(
+            health.backup,
+            SplCopyStatus::ReadError(Error::FlashRead(pos)) if pos == BOFS + (SPL_HEADER_LEN + HEALTH_CHUNK_LEN) as u32
+        ));
+
+        // the header read fails
+        let health = spl_health_check(reader(&flash[..SPL_HEADER_LEN - 1]), FALLBACK_BOFS);
+
+        assert_eq!(health.bofs, FALLBACK_BOFS);
+        assert!(matches!(
+            health.primary,
+            SplCopyStatus::ReadError(Error::FlashRead(0))
+        ));
+        assert!(matches!(
+            health.backup,
+            SplCopyStatus::ReadError(Error::FlashRead(FALLBACK_BOFS))
+        ));
+    }
+}
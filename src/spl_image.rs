@@ -0,0 +1,78 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use super::{UbootSplHeader, DEF_RESL, SPL_HEADER_LEN};
+
+/// Gets the length of a headered SPL image for a payload of `payload_len` bytes.
+///
+/// Uses the default offset from `HDR` to `SPL_IMAGE` ([DEF_RESL]).
+pub const fn spl_image_len(payload_len: usize) -> usize {
+    DEF_RESL as usize + payload_len
+}
+
+/// Creates a headered SPL image from the `u-boot-spl.bin` payload, using the default header.
+///
+/// Usable in `const` context, so a broken image is a compile error instead of a boot failure:
+///
+/// ```
+/// use spl_tool::{spl_image, spl_image_len, UbootSplHeader, SPL_HEADER_LEN};
+///
+/// const PAYLOAD: &[u8] = &[0x13, 0x00, 0x00, 0x00]; // e.g. include_bytes!("first-stage.bin")
+/// const IMAGE: [u8; spl_image_len(PAYLOAD.len())] = spl_image(PAYLOAD);
+///
+/// let header = UbootSplHeader::try_from(IMAGE).unwrap();
+/// assert!(header.verify_payload(&IMAGE[SPL_HEADER_LEN..]).is_ok());
+/// ```
+///
+/// An image length that does not fit the payload fails to compile:
+///
+/// ```compile_fail
+/// use spl_tool::spl_image;
+///
+/// const IMAGE: [u8; 16] = spl_image(&[0x13, 0x00, 0x00, 0x00]);
+/// ```
+pub const fn spl_image<const N: usize>(payload: &[u8]) -> [u8; N] {
+    spl_image_with_header(UbootSplHeader::new(), payload)
+}
+
+/// Creates a headered SPL image from a custom header and the `u-boot-spl.bin` payload.
+///
+/// The `fsiz` and `crcs` fields are set from the payload, and the payload is placed at `resl`.
+///
+/// # Panics
+///
+/// Panics (or fails to compile in `const` context) if the payload length is invalid, `resl` is
+/// shorter than the header, or `N` is not `resl + payload.len()`.
+pub const fn spl_image_with_header<const N: usize>(
+    header: UbootSplHeader,
+    payload: &[u8],
+) -> [u8; N] {
+    let header = match header.with_payload(payload) {
+        Ok(hdr) => hdr,
+        Err(_) => panic!("invalid SPL payload length"),
+    };
+
+    let resl = header.resl() as usize;
+    if resl < SPL_HEADER_LEN {
+        panic!("invalid SPL image offset, must not overlap the header");
+    }
+    if N != resl + payload.len() {
+        panic!("invalid SPL image length, must be `resl + payload.len()`");
+    }
+
+    let mut res = [0u8; N];
+
+    let hdr_bytes = header.to_bytes();
+    let mut i = 0usize;
+    while i < SPL_HEADER_LEN {
+        res[i] = hdr_bytes[i];
+        i += 1;
+    }
+
+    let mut i = 0usize;
+    while i < payload.len() {
+        res[resl + i] = payload[i];
+        i += 1;
+    }
+
+    res
+}
@@ -0,0 +1,221 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::vec::Vec;
+
+use super::{Error, Result, UbootSplHeader, SPL_HEADER_LEN};
+
+impl UbootSplHeader {
+    /// Reads a [UbootSplHeader] from the next [SPL_HEADER_LEN] bytes of a reader.
+    ///
+    /// The header fields are not validated, see [validate](Self::validate).
+    ///
+    /// Available with the `std` feature.
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut buf = [0u8; SPL_HEADER_LEN];
+        reader.read_exact(buf.as_mut())?;
+
+        Self::try_from(buf).map_err(invalid_data)
+    }
+
+    /// Writes the [SPL_HEADER_LEN] bytes of the [UbootSplHeader] to a writer.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(self.to_bytes().as_ref())
+    }
+
+    /// Reads a [UbootSplHeader] at `offset` of a reader, e.g. the backup copy at `bofs`.
+    pub fn read_at<R: Read + Seek>(mut reader: R, offset: u64) -> io::Result<Self> {
+        reader.seek(SeekFrom::Start(offset))?;
+        Self::read_from(reader)
+    }
+
+    /// Writes the [UbootSplHeader] at `offset` of a writer.
+    ///
+    /// Only the [SPL_HEADER_LEN] bytes of the header are written, the payload is left untouched.
+    pub fn write_at<W: Write + Seek>(&self, mut writer: W, offset: u64) -> io::Result<()> {
+        writer.seek(SeekFrom::Start(offset))?;
+        self.write_to(writer)
+    }
+}
+
+/// Represents a headered SPL image, loaded into memory.
+///
+/// Available with the `std` feature.
+///
+/// Example:
+///
+/// ```rust
+/// use spl_tool::{spl_image, spl_image_len, SplImage};
+///
+/// const PAYLOAD: &[u8] = &[0x13, 0x00, 0x00, 0x00];
+/// const IMAGE: [u8; spl_image_len(PAYLOAD.len())] = spl_image(PAYLOAD);
+///
+/// let img = SplImage::from_reader(IMAGE.as_ref()).unwrap();
+///
+/// assert_eq!(img.payload(), PAYLOAD);
+/// assert!(img.verify().is_ok());
+///
+/// let mut out = Vec::new();
+/// img.write_to(&mut out).unwrap();
+/// assert_eq!(out, IMAGE);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct SplImage {
+    header: UbootSplHeader,
+    payload: Vec<u8>,
+}
+
+impl SplImage {
+    /// Creates a new [SplImage] from a header and the `u-boot-spl.bin` payload.
+    ///
+    /// The `fsiz` and `crcs` fields are set from the payload, and the header is validated.
+    pub fn new(header: UbootSplHeader, payload: Vec<u8>) -> Result<Self> {
+        let header = header.with_payload(payload.as_ref())?;
+        header.validate()?;
+
+        Ok(Self { header, payload })
+    }
+
+    /// Loads an [SplImage] from a reader positioned at the start of the header.
+    ///
+    /// The header is validated before reading `fsiz` payload bytes at `resl`, so a corrupt
+    /// header never causes a large allocation. The CRC32 is not checked, see
+    /// [verify](Self::verify).
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<Self> {
+        let header = UbootSplHeader::read_from(&mut reader)?;
+        header.validate().map_err(invalid_data)?;
+
+        // skip the padding between the header and the payload
+        let pad = (header.resl() as usize - SPL_HEADER_LEN) as u64;
+        let skipped = io::copy(&mut (&mut reader).take(pad), &mut io::sink())?;
+        if skipped != pad {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let mut payload = std::vec![0u8; header.fsiz() as usize];
+        reader.read_exact(payload.as_mut())?;
+
+        Ok(Self { header, payload })
+    }
+
+    /// Gets the [UbootSplHeader].
+    pub const fn header(&self) -> &UbootSplHeader {
+        &self.header
+    }
+
+    /// Gets the `u-boot-spl.bin` payload.
+    pub fn payload(&self) -> &[u8] {
+        self.payload.as_ref()
+    }
+
+    /// Converts the [SplImage] into its header and payload.
+    pub fn into_parts(self) -> (UbootSplHeader, Vec<u8>) {
+        (self.header, self.payload)
+    }
+
+    /// Verifies the size and CRC32 fields of the header against the payload.
+    pub fn verify(&self) -> Result<()> {
+        self.header.verify_payload(self.payload.as_ref())
+    }
+
+    /// Writes the header, the zero padding up to `resl`, and the payload to a writer.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.header.write_to(&mut writer)?;
+
+        let pad = (self.header.resl() as usize - SPL_HEADER_LEN) as u64;
+        io::copy(&mut io::repeat(0).take(pad), &mut writer)?;
+
+        writer.write_all(self.payload.as_ref())
+    }
+}
+
+// header errors are reported as invalid data, with the library [Error] as the source
+fn invalid_data(err: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::vec;
+
+    use super::*;
+
+    const PAYLOAD: &[u8] = &[0x13, 0x00, 0x00, 0x00, 0x6f, 0x00, 0x00, 0x00];
+    const RESL: u32 = 0x800;
+
+    // header, zero padding up to `resl`, and the payload
+    fn image(resl: u32) -> Vec<u8> {
+        let header = UbootSplHeader::new().with_resl(resl);
+        let img = SplImage::new(header, PAYLOAD.to_vec()).unwrap();
+
+        let mut res = Vec::new();
+        img.write_to(&mut res).unwrap();
+        res
+    }
+
+    #[test]
+    fn test_read_write_at_offset() {
+        let header = UbootSplHeader::new().with_bofs(0x1000).with_fsiz(0x10);
+        let mut cursor = Cursor::new(vec![0xaau8; 0x1000 + SPL_HEADER_LEN + 4]);
+
+        header.write_at(&mut cursor, 0x1000).unwrap();
+
+        let buf = cursor.get_ref();
+        assert!(buf[..0x1000].iter().all(|&b| b == 0xaa));
+        assert_eq!(
+            &buf[0x1000..0x1000 + SPL_HEADER_LEN],
+            header.to_bytes().as_ref()
+        );
+        assert_eq!(&buf[0x1000 + SPL_HEADER_LEN..], [0xaa; 4].as_ref());
+
+        assert_eq!(
+            UbootSplHeader::read_at(&mut cursor, 0x1000).unwrap(),
+            header
+        );
+        assert_eq!(cursor.position(), 0x1000 + SPL_HEADER_LEN as u64);
+
+        let err = UbootSplHeader::read_at(&mut cursor, 0x1005).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_from_reader_skips_padding() {
+        let mut buf = image(RESL);
+        assert_eq!(buf.len(), RESL as usize + PAYLOAD.len());
+
+        // the padding is skipped, not parsed
+        buf[SPL_HEADER_LEN..RESL as usize].fill(0xff);
+
+        let img = SplImage::from_reader(buf.as_slice()).unwrap();
+        assert_eq!(img.header().resl(), RESL);
+        assert_eq!(img.payload(), PAYLOAD);
+        assert!(img.verify().is_ok());
+    }
+
+    #[test]
+    fn test_from_reader_truncated() {
+        let buf = image(RESL);
+
+        // in the padding, and in the payload
+        for len in [SPL_HEADER_LEN + 1, RESL as usize, buf.len() - 1] {
+            let err = SplImage::from_reader(&buf[..len]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "{len}");
+        }
+
+        let err = SplImage::from_reader(&buf[..SPL_HEADER_LEN - 1]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_from_reader_invalid_header() {
+        let mut buf = image(SPL_HEADER_LEN as u32);
+        buf[..4].copy_from_slice(0x100u32.to_le_bytes().as_ref());
+
+        let err = SplImage::from_reader(buf.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let source = err.get_ref().and_then(|e| e.downcast_ref::<Error>());
+        assert!(matches!(source, Some(Error::InvalidSofs(0x100))), "{err}");
+    }
+}
@@ -0,0 +1,296 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use super::{
+    crc32, crc32_final, Error, Result, SplCopyStatus, UbootSplHeader, DEF_SOFS, HEALTH_CHUNK_LEN,
+    SPL_CRC32_POLY, SPL_HEADER_LEN,
+};
+
+/// Default alignment of the offsets checked for SPL headers (one disk sector).
+pub const DEF_SCAN_ALIGN: u64 = 512;
+
+/// Represents a plausible SPL header found by [SplScanner].
+#[derive(Clone, Copy, Debug)]
+pub struct SplScanHit {
+    /// Offset of the header in the scanned data.
+    pub offset: u64,
+    /// Parsed header fields.
+    pub header: UbootSplHeader,
+    /// Status of the payload CRC32 check, [SplCopyStatus::Ok] when it matches `crcs`.
+    pub status: SplCopyStatus,
+}
+
+/// Iterator that searches arbitrary data for plausible SPL headers, using a read callback.
+///
+/// `read` is called with an offset and a buffer to fill, so large files can be scanned with
+/// chunked or memory-mapped reads. At each aligned offset, the `sofs` field is checked first, and
+/// the full header is only read and validated ([UbootSplHeader::validate]) when it matches
+/// [DEF_SOFS].
+///
+/// Errors from `read` are yielded, and scanning continues at the next aligned offset.
+pub struct SplScanner<R> {
+    read: R,
+    len: u64,
+    align: u64,
+    pos: u64,
+}
+
+impl<R> SplScanner<R>
+where
+    R: FnMut(u64, &mut [u8]) -> Result<()>,
+{
+    /// Creates a new [SplScanner] over `len` bytes of data.
+    pub fn new(read: R, len: u64) -> Self {
+        Self {
+            read,
+            len,
+            align: DEF_SCAN_ALIGN,
+            pos: 0,
+        }
+    }
+
+    /// Gets the alignment of the checked offsets.
+    pub const fn align(&self) -> u64 {
+        self.align
+    }
+
+    /// Sets the alignment of the checked offsets, a zero alignment is treated as `1`.
+    pub fn set_align(&mut self, val: u64) {
+        self.align = val.max(1);
+    }
+
+    /// Builder function that sets the alignment of the checked offsets.
+    pub fn with_align(mut self, val: u64) -> Self {
+        self.set_align(val);
+        self
+    }
+
+    /// Gets the offset of the next check.
+    pub const fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Sets the offset to continue scanning from, rounded up to the alignment.
+    pub fn set_position(&mut self, val: u64) {
+        self.pos = val.div_ceil(self.align).saturating_mul(self.align);
+    }
+
+    /// Builder function that sets the offset to start scanning from.
+    pub fn with_position(mut self, val: u64) -> Self {
+        self.set_position(val);
+        self
+    }
+
+    fn check(&mut self, offset: u64) -> Result<Option<SplScanHit>> {
+        let mut sofs = [0u8; 4];
+        (self.read)(offset, sofs.as_mut())?;
+
+        if u32::from_le_bytes(sofs) != DEF_SOFS {
+            return Ok(None);
+        }
+
+        let mut hdr_bytes = [0u8; SPL_HEADER_LEN];
+        (self.read)(offset, hdr_bytes.as_mut())?;
+
+        let header = match UbootSplHeader::try_from(hdr_bytes) {
+            Ok(hdr) if hdr.validate().is_ok() => hdr,
+            _ => return Ok(None),
+        };
+
+        let payload_offset = offset + header.resl() as u64;
+        let payload_len = header.fsiz() as u64;
+
+        let status = if payload_offset + payload_len > self.len {
+            SplCopyStatus::ReadError(Error::InvalidSplLen((
+                (self.len.saturating_sub(payload_offset)) as usize,
+                payload_len as usize,
+            )))
+        } else {
+            match stream_crc(&mut self.read, payload_offset, payload_len) {
+                Ok(crc) if crc == header.crcs() => SplCopyStatus::Ok,
+                Ok(crc) => SplCopyStatus::CrcMismatch {
+                    actual: crc,
+                    expected: header.crcs(),
+                },
+                Err(err) => SplCopyStatus::ReadError(err),
+            }
+        };
+
+        Ok(Some(SplScanHit {
+            offset,
+            header,
+            status,
+        }))
+    }
+}
+
+impl<R> Iterator for SplScanner<R>
+where
+    R: FnMut(u64, &mut [u8]) -> Result<()>,
+{
+    type Item = Result<SplScanHit>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos.saturating_add(SPL_HEADER_LEN as u64) <= self.len {
+            let offset = self.pos;
+            self.pos = self.pos.saturating_add(self.align);
+
+            match self.check(offset) {
+                Ok(Some(hit)) => return Some(Ok(hit)),
+                Ok(None) => (),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+
+        None
+    }
+}
+
+fn stream_crc<R>(read: &mut R, offset: u64, len: u64) -> Result<u32>
+where
+    R: FnMut(u64, &mut [u8]) -> Result<()>,
+{
+    let mut buf = [0u8; HEALTH_CHUNK_LEN];
+    let mut crc = !0u32;
+    let mut pos = 0u64;
+
+    while pos < len {
+        let chunk_len = core::cmp::min(len - pos, HEALTH_CHUNK_LEN as u64) as usize;
+
+        read(offset + pos, &mut buf[..chunk_len])?;
+        crc = crc32(crc, SPL_CRC32_POLY, &buf[..chunk_len]);
+
+        pos += chunk_len as u64;
+    }
+
+    Ok(crc32_final(crc))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::{spl_image, spl_image_len, DEF_RESL};
+
+    const PAYLOAD: &[u8] = &[0x13, 0x00, 0x00, 0x00, 0x6f, 0x00, 0x00, 0x00];
+    const IMG: [u8; spl_image_len(PAYLOAD.len())] = spl_image(PAYLOAD);
+
+    fn disk(offsets: &[usize], len: usize) -> Vec<u8> {
+        let mut res = std::vec![0u8; len];
+        for &offset in offsets {
+            res[offset..offset + IMG.len()].copy_from_slice(IMG.as_ref());
+        }
+        res
+    }
+
+    fn scan(data: &[u8]) -> SplScanner<impl FnMut(u64, &mut [u8]) -> Result<()> + '_> {
+        SplScanner::new(
+            |offset, buf: &mut [u8]| {
+                let offset = offset as usize;
+                buf.copy_from_slice(
+                    data.get(offset..offset + buf.len())
+                        .ok_or(Error::InvalidSplLen((data.len(), offset + buf.len())))?,
+                );
+                Ok(())
+            },
+            data.len() as u64,
+        )
+    }
+
+    #[test]
+    fn test_scan() {
+        let data = disk(&[0x200, 0x1000], 0x2000);
+        let hits: Vec<_> = scan(data.as_ref()).collect::<Result<_>>().unwrap();
+
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].offset, 0x200);
+        assert_eq!(hits[1].offset, 0x1000);
+        for hit in hits.iter() {
+            assert!(matches!(hit.status, SplCopyStatus::Ok));
+            assert_eq!(hit.header.fsiz() as usize, PAYLOAD.len());
+        }
+    }
+
+    #[test]
+    fn test_scan_align_position() {
+        let data = disk(&[0x100, 0x400], 0x1000);
+
+        // the header at 0x100 is not sector-aligned
+        assert_eq!(scan(data.as_ref()).count(), 1);
+
+        let offsets: Vec<_> = scan(data.as_ref())
+            .with_align(0x100)
+            .map(|hit| hit.unwrap().offset)
+            .collect();
+        assert_eq!(offsets, [0x100, 0x400]);
+
+        // the start position is rounded up to the alignment
+        let scanner = scan(data.as_ref()).with_align(0x100).with_position(0x101);
+        assert_eq!(scanner.position(), 0x200);
+        assert_eq!(
+            scanner.map(|hit| hit.unwrap().offset).collect::<Vec<_>>(),
+            [0x400]
+        );
+
+        assert_eq!(scan(data.as_ref()).with_align(0).align(), 1);
+    }
+
+    #[test]
+    fn test_scan_bad_payload() {
+        let mut data = disk(&[0, 0x800], 0x1000);
+        data[DEF_RESL as usize] ^= 0xff;
+
+        let hits: Vec<_> = scan(data.as_ref()).map(|hit| hit.unwrap()).collect();
+        assert_eq!(hits.len(), 2);
+        assert!(matches!(
+            hits[0].status,
+            SplCopyStatus::CrcMismatch { expected, .. } if expected == hits[1].header.crcs()
+        ));
+        assert!(matches!(hits[1].status, SplCopyStatus::Ok));
+
+        // the payload extends past the end of the data
+        let data = &data[..0x800 + DEF_RESL as usize + 4];
+        let hits: Vec<_> = scan(data).map(|hit| hit.unwrap()).collect();
+        assert!(matches!(
+            hits[1].status,
+            SplCopyStatus::ReadError(Error::InvalidSplLen((4, 8)))
+        ));
+    }
+
+    #[test]
+    fn test_scan_invalid_header() {
+        let mut data = std::vec![0u8; 0x800];
+        // a matching `sofs` alone is not a hit
+        data[0x200..0x204].copy_from_slice(DEF_SOFS.to_le_bytes().as_ref());
+        // and neither is a header failing validation (empty payload)
+        data[0x400..0x400 + SPL_HEADER_LEN]
+            .copy_from_slice(UbootSplHeader::new().to_bytes().as_ref());
+
+        assert_eq!(scan(data.as_ref()).count(), 0);
+        // too short for a header
+        assert_eq!(scan(&IMG[..SPL_HEADER_LEN - 1]).count(), 0);
+    }
+
+    #[test]
+    fn test_scan_read_errors() {
+        let data = disk(&[0x400], 0x1000);
+        let scanner = SplScanner::new(
+            |offset, buf: &mut [u8]| {
+                if offset < 0x400 {
+                    return Err(Error::DiskRead(offset));
+                }
+                let offset = offset as usize;
+                buf.copy_from_slice(&data[offset..offset + buf.len()]);
+                Ok(())
+            },
+            data.len() as u64,
+        );
+
+        // errors are yielded, and scanning continues at the next aligned offset
+        let res: Vec<_> = scanner.collect();
+        assert_eq!(res.len(), 3);
+        assert!(matches!(res[0], Err(Error::DiskRead(0))));
+        assert!(matches!(res[1], Err(Error::DiskRead(0x200))));
+        assert!(matches!(&res[2], Ok(hit) if hit.offset == 0x400));
+    }
+}
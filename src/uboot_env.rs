@@ -0,0 +1,381 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use super::{crc32_ieee, Error, Result};
+
+/// Default offset of the U-Boot environment in the VisionFive2 SPI flash.
+pub const DEF_ENV_OFFSET: u32 = 0xf_0000;
+/// Default size of the U-Boot environment region in the VisionFive2 SPI flash.
+pub const DEF_ENV_SIZE: usize = 0x1_0000;
+/// Length of the CRC32 at the start of a U-Boot environment blob.
+pub const ENV_CRC_LEN: usize = 4;
+/// Flag byte of the active copy of a redundant U-Boot environment.
+pub const ENV_REDUND_ACTIVE: u8 = 1;
+/// Default byte used to pad the data area, the erased flash value also used by `mkenvimage`.
+pub const ENV_PAD_BYTE: u8 = 0xff;
+
+/// Gets the offset of the variable data in a U-Boot environment blob.
+///
+/// Redundant environments have a flag byte between the CRC32 and the data.
+pub const fn env_data_offset(redundant: bool) -> usize {
+    if redundant {
+        ENV_CRC_LEN + 1
+    } else {
+        ENV_CRC_LEN
+    }
+}
+
+/// Represents a parsed U-Boot environment blob, as generated by `mkenvimage`.
+///
+/// The blob layout is a little-endian CRC32 of the data area, an optional redundant-env flag
+/// byte, then `key=value` entries terminated by `\0`, with an empty entry after the last one.
+#[derive(Clone, Copy, Debug)]
+pub struct UbootEnv<'a> {
+    crc: u32,
+    flags: Option<u8>,
+    data: &'a [u8],
+}
+
+impl<'a> UbootEnv<'a> {
+    /// Parses a U-Boot environment blob, and verifies its CRC32.
+    ///
+    /// The blob length must be the size of the environment region.
+    pub fn parse(blob: &'a [u8], redundant: bool) -> Result<Self> {
+        let data_offset = env_data_offset(redundant);
+        if blob.len() <= data_offset {
+            return Err(Error::InvalidEnvSize((data_offset + 1, blob.len())));
+        }
+
+        let crc = u32::from_le_bytes(blob[..ENV_CRC_LEN].try_into()?);
+        let flags = redundant.then_some(blob[ENV_CRC_LEN]);
+        let data = &blob[data_offset..];
+
+        let calc = crc32_ieee(data);
+        if calc != crc {
+            Err(Error::InvalidCrc((calc, crc)))
+        } else {
+            Ok(Self { crc, flags, data })
+        }
+    }
+
+    /// Gets the CRC32 of the data area.
+    pub const fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// Gets the redundant-env flag byte, if the environment is redundant.
+    pub const fn flags(&self) -> Option<u8> {
+        self.flags
+    }
+
+    /// Gets the data area, including padding.
+    pub const fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Gets an iterator over the `key=value` entries.
+    pub fn vars(&self) -> UbootEnvVars<'a> {
+        UbootEnvVars {
+            data: self.data,
+            idx: 0,
+        }
+    }
+
+    /// Gets the value of the variable `key`.
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.vars()
+            .filter_map(|var| var.ok())
+            .find_map(|(k, v)| (k == key).then_some(v))
+    }
+}
+
+/// Iterator over the `key=value` entries of a U-Boot environment.
+///
+/// Entries that are not UTF-8, or have no `=`, are reported as [Error::InvalidEnvVar] with their
+/// 0-based index.
+#[derive(Clone, Debug)]
+pub struct UbootEnvVars<'a> {
+    data: &'a [u8],
+    idx: usize,
+}
+
+impl<'a> Iterator for UbootEnvVars<'a> {
+    type Item = Result<(&'a str, &'a str)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let len = self.data.iter().position(|&b| b == 0)?;
+        if len == 0 {
+            return None;
+        }
+
+        let entry = &self.data[..len];
+        self.data = &self.data[len + 1..];
+
+        let idx = self.idx;
+        self.idx += 1;
+
+        Some(
+            core::str::from_utf8(entry)
+                .ok()
+                .and_then(|e| e.split_once('='))
+                .ok_or(Error::InvalidEnvVar(idx)),
+        )
+    }
+}
+
+/// Iterator over the `key=value` lines of a `mkenvimage` text file.
+///
+/// Empty lines, and lines starting with `#`, are skipped. Invalid lines are reported as
+/// [Error::InvalidEnvVar] with their 1-based line number.
+#[derive(Clone, Debug)]
+pub struct UbootEnvText<'a> {
+    lines: core::str::Lines<'a>,
+    line: usize,
+}
+
+impl<'a> UbootEnvText<'a> {
+    /// Creates a new [UbootEnvText] iterator.
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            lines: text.lines(),
+            line: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for UbootEnvText<'a> {
+    type Item = Result<(&'a str, &'a str)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = self.lines.next()?;
+            self.line += 1;
+
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            return Some(match line.split_once('=') {
+                Some((key, val)) if is_valid_key(key) && !val.contains('\0') => Ok((key, val)),
+                _ => Err(Error::InvalidEnvVar(self.line)),
+            });
+        }
+    }
+}
+
+/// Creates a U-Boot environment blob from `key=value` pairs, like `mkenvimage`.
+///
+/// The whole `blob` is used as the environment region: the data area after the terminating empty
+/// entry is filled with the `pad` byte (`mkenvimage -p`, [ENV_PAD_BYTE] by default), and the
+/// CRC32 covers the data area including padding. Provide `flags` to create a redundant
+/// environment.
+///
+/// Returns the length of the variable data, including the terminating empty entry.
+pub fn uboot_env_create<'v, I>(
+    blob: &mut [u8],
+    flags: Option<u8>,
+    pad: u8,
+    vars: I,
+) -> Result<usize>
+where
+    I: IntoIterator<Item = (&'v str, &'v str)>,
+{
+    let data_offset = env_data_offset(flags.is_some());
+    if blob.len() <= data_offset {
+        return Err(Error::InvalidEnvSize((data_offset + 1, blob.len())));
+    }
+
+    let size = blob.len();
+    let data = &mut blob[data_offset..];
+    let mut pos = 0usize;
+
+    for (idx, (key, val)) in vars.into_iter().enumerate() {
+        if !is_valid_key(key) || val.contains('\0') {
+            return Err(Error::InvalidEnvVar(idx));
+        }
+
+        // `key=value\0`, and room for the terminating empty entry
+        let end = pos + key.len() + val.len() + 2;
+        if end + 1 > data.len() {
+            return Err(Error::InvalidEnvSize((data_offset + end + 1, size)));
+        }
+
+        data[pos..pos + key.len()].copy_from_slice(key.as_bytes());
+        pos += key.len();
+        data[pos] = b'=';
+        pos += 1;
+        data[pos..pos + val.len()].copy_from_slice(val.as_bytes());
+        pos += val.len();
+        data[pos] = 0;
+        pos += 1;
+    }
+
+    // terminate with an empty entry, and pad the rest of the region
+    data[pos] = 0;
+    let len = pos + 1;
+    data[len..].fill(pad);
+
+    let crc = crc32_ieee(data);
+    blob[..ENV_CRC_LEN].copy_from_slice(crc.to_le_bytes().as_ref());
+    if let Some(flags) = flags {
+        blob[ENV_CRC_LEN] = flags;
+    }
+
+    Ok(len)
+}
+
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && !key.contains(['=', '\0'])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn create(size: usize, flags: Option<u8>, vars: &[(&str, &str)]) -> Vec<u8> {
+        let mut blob = std::vec![0u8; size];
+        uboot_env_create(&mut blob, flags, ENV_PAD_BYTE, vars.iter().copied()).unwrap();
+        blob
+    }
+
+    #[test]
+    fn test_create_layout() {
+        let mut blob = [0u8; 16];
+        let len =
+            uboot_env_create(blob.as_mut(), None, ENV_PAD_BYTE, [("a", "1"), ("bc", "")]).unwrap();
+
+        // `key=value\0` entries, the terminating empty entry, then `mkenvimage` padding
+        assert_eq!(len, 9);
+        assert_eq!(&blob[ENV_CRC_LEN..ENV_CRC_LEN + len], b"a=1\0bc=\0\0");
+        assert_eq!(&blob[ENV_CRC_LEN + len..], [0xff; 3].as_ref());
+        assert_eq!(
+            u32::from_le_bytes(blob[..ENV_CRC_LEN].try_into().unwrap()),
+            crc32_ieee(&blob[ENV_CRC_LEN..])
+        );
+
+        uboot_env_create(blob.as_mut(), None, 0, [("a", "1"), ("bc", "")]).unwrap();
+        assert_eq!(&blob[ENV_CRC_LEN + len..], [0u8; 3].as_ref());
+    }
+
+    #[test]
+    fn test_parse() {
+        let blob = create(
+            0x100,
+            None,
+            &[("bootdelay", "2"), ("bootcmd", "run a=b; boot")],
+        );
+        let env = UbootEnv::parse(blob.as_ref(), false).unwrap();
+
+        assert_eq!(env.flags(), None);
+        assert_eq!(env.data().len(), 0x100 - ENV_CRC_LEN);
+        assert_eq!(env.get("bootdelay"), Some("2"));
+        // only the first `=` separates the key
+        assert_eq!(env.get("bootcmd"), Some("run a=b; boot"));
+        assert_eq!(env.get("boot"), None);
+        assert_eq!(env.vars().count(), 2);
+    }
+
+    #[test]
+    fn test_parse_redundant() {
+        let blob = create(0x40, Some(ENV_REDUND_ACTIVE), &[("a", "1")]);
+        let env = UbootEnv::parse(blob.as_ref(), true).unwrap();
+
+        assert_eq!(env.flags(), Some(ENV_REDUND_ACTIVE));
+        assert_eq!(env.get("a"), Some("1"));
+
+        // the flag byte is not covered by the CRC32
+        assert!(UbootEnv::parse(blob.as_ref(), false).is_err());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let mut blob = create(0x40, None, &[("a", "1")]);
+
+        assert!(matches!(
+            UbootEnv::parse(&blob[..ENV_CRC_LEN], false),
+            Err(Error::InvalidEnvSize((5, 4)))
+        ));
+        assert!(matches!(
+            UbootEnv::parse(&blob[..ENV_CRC_LEN + 1], true),
+            Err(Error::InvalidEnvSize((6, 5)))
+        ));
+
+        blob[ENV_CRC_LEN] = b'b';
+        assert!(matches!(
+            UbootEnv::parse(blob.as_ref(), false),
+            Err(Error::InvalidCrc(_))
+        ));
+    }
+
+    #[test]
+    fn test_vars_invalid() {
+        let vars = UbootEnvVars {
+            data: b"a=1\0noequals\0\xff=x\0\0",
+            idx: 0,
+        };
+        let res: Vec<_> = vars.collect();
+
+        assert_eq!(res.len(), 3);
+        assert!(matches!(res[0], Ok(("a", "1"))));
+        assert!(matches!(res[1], Err(Error::InvalidEnvVar(1))));
+        assert!(matches!(res[2], Err(Error::InvalidEnvVar(2))));
+
+        // unterminated data ends the iteration
+        let mut vars = UbootEnvVars {
+            data: b"a=1",
+            idx: 0,
+        };
+        assert!(vars.next().is_none());
+    }
+
+    #[test]
+    fn test_create_invalid() {
+        let mut blob = [0u8; 16];
+
+        assert!(matches!(
+            uboot_env_create(&mut blob[..4], None, ENV_PAD_BYTE, []),
+            Err(Error::InvalidEnvSize((5, 4)))
+        ));
+        assert!(matches!(
+            uboot_env_create(blob.as_mut(), None, ENV_PAD_BYTE, [("a", "1"), ("", "2")]),
+            Err(Error::InvalidEnvVar(1))
+        ));
+        assert!(matches!(
+            uboot_env_create(blob.as_mut(), None, ENV_PAD_BYTE, [("a=b", "1")]),
+            Err(Error::InvalidEnvVar(0))
+        ));
+        assert!(matches!(
+            uboot_env_create(blob.as_mut(), None, ENV_PAD_BYTE, [("a", "1\0")]),
+            Err(Error::InvalidEnvVar(0))
+        ));
+
+        // `k=01234567\0` and the terminating entry fill all 12 data bytes
+        assert!(uboot_env_create(blob.as_mut(), None, ENV_PAD_BYTE, [("k", "01234567")]).is_ok());
+        assert!(matches!(
+            uboot_env_create(blob.as_mut(), None, ENV_PAD_BYTE, [("k", "012345678")]),
+            Err(Error::InvalidEnvSize((17, 16)))
+        ));
+    }
+
+    #[test]
+    fn test_text() {
+        let text = "# comment\n\nbootdelay=2\nbootcmd=run distro_bootcmd # not a comment\nempty=\n";
+        let vars: Vec<_> = UbootEnvText::new(text).collect::<Result<_>>().unwrap();
+
+        assert_eq!(
+            vars,
+            [
+                ("bootdelay", "2"),
+                ("bootcmd", "run distro_bootcmd # not a comment"),
+                ("empty", ""),
+            ]
+        );
+
+        let mut vars = UbootEnvText::new("a=1\n\nnoequals\n=2\n");
+        assert!(matches!(vars.next(), Some(Ok(("a", "1")))));
+        assert!(matches!(vars.next(), Some(Err(Error::InvalidEnvVar(3)))));
+        assert!(matches!(vars.next(), Some(Err(Error::InvalidEnvVar(4)))));
+        assert!(vars.next().is_none());
+    }
+}
@@ -0,0 +1,650 @@
+// SPDX-License-Identifier: GPL-2.0+
+
+use super::{crc32_ieee, Error, Result};
+
+/// Magic number of a legacy U-Boot image (uImage) header.
+pub const UIMAGE_MAGIC: u32 = 0x2705_1956;
+/// Length of a legacy uImage header.
+pub const UIMAGE_HEADER_LEN: usize = 64;
+/// Length of the image name in a legacy uImage header.
+pub const UIMAGE_NAME_LEN: usize = 32;
+/// Operating system ID for Linux (`IH_OS_LINUX`).
+pub const UIMAGE_OS_LINUX: u8 = 5;
+/// Operating system ID for U-Boot firmware (`IH_OS_U_BOOT`).
+pub const UIMAGE_OS_U_BOOT: u8 = 17;
+/// Architecture ID for RISC-V (`IH_ARCH_RISCV`).
+pub const UIMAGE_ARCH_RISCV: u8 = 26;
+
+/// Represents the legacy uImage type (`IH_TYPE_*`).
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UimageType {
+    Standalone = 1,
+    Kernel = 2,
+    Ramdisk = 3,
+    Multi = 4,
+    Firmware = 5,
+    Script = 6,
+}
+
+impl TryFrom<u8> for UimageType {
+    type Error = Error;
+
+    fn try_from(val: u8) -> Result<Self> {
+        match val {
+            1 => Ok(Self::Standalone),
+            2 => Ok(Self::Kernel),
+            3 => Ok(Self::Ramdisk),
+            4 => Ok(Self::Multi),
+            5 => Ok(Self::Firmware),
+            6 => Ok(Self::Script),
+            _ => Err(Error::InvalidUimageType(val)),
+        }
+    }
+}
+
+impl From<UimageType> for u8 {
+    fn from(val: UimageType) -> Self {
+        val as u8
+    }
+}
+
+/// Represents the legacy uImage compression type (`IH_COMP_*`).
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UimageComp {
+    None = 0,
+    Gzip = 1,
+    Bzip2 = 2,
+    Lzma = 3,
+    Lzo = 4,
+    Lz4 = 5,
+    Zstd = 6,
+}
+
+impl TryFrom<u8> for UimageComp {
+    type Error = Error;
+
+    fn try_from(val: u8) -> Result<Self> {
+        match val {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Gzip),
+            2 => Ok(Self::Bzip2),
+            3 => Ok(Self::Lzma),
+            4 => Ok(Self::Lzo),
+            5 => Ok(Self::Lz4),
+            6 => Ok(Self::Zstd),
+            _ => Err(Error::InvalidUimageComp(val)),
+        }
+    }
+}
+
+impl From<UimageComp> for u8 {
+    fn from(val: UimageComp) -> Self {
+        val as u8
+    }
+}
+
+/// Represents the 64-byte legacy U-Boot image (uImage) header, as created by `mkimage`.
+///
+/// All multi-byte fields are big-endian.
+///
+/// | Offset | Field | Size |
+/// |--------|-------|------|
+/// | 0x00 | magic | 4 |
+/// | 0x04 | hcrc | 4 |
+/// | 0x08 | time | 4 |
+/// | 0x0c | size | 4 |
+/// | 0x10 | load | 4 |
+/// | 0x14 | ep | 4 |
+/// | 0x18 | dcrc | 4 |
+/// | 0x1c | os | 1 |
+/// | 0x1d | arch | 1 |
+/// | 0x1e | type | 1 |
+/// | 0x1f | comp | 1 |
+/// | 0x20 | name | 32 |
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UimageHeader {
+    magic: u32,
+    hcrc: u32,
+    time: u32,
+    size: u32,
+    load: u32,
+    ep: u32,
+    dcrc: u32,
+    os: u8,
+    arch: u8,
+    image_type: u8,
+    comp: u8,
+    name: [u8; UIMAGE_NAME_LEN],
+}
+
+impl UimageHeader {
+    /// Creates a new [UimageHeader] for an uncompressed RISC-V Linux script.
+    pub const fn new() -> Self {
+        Self {
+            magic: UIMAGE_MAGIC,
+            hcrc: 0,
+            time: 0,
+            size: 0,
+            load: 0,
+            ep: 0,
+            dcrc: 0,
+            os: UIMAGE_OS_LINUX,
+            arch: UIMAGE_ARCH_RISCV,
+            image_type: UimageType::Script as u8,
+            comp: UimageComp::None as u8,
+            name: [0u8; UIMAGE_NAME_LEN],
+        }
+    }
+
+    /// Gets the magic number.
+    pub const fn magic(&self) -> u32 {
+        self.magic
+    }
+
+    /// Gets the header CRC32, calculated with the `hcrc` field set to zero.
+    pub const fn hcrc(&self) -> u32 {
+        self.hcrc
+    }
+
+    /// Gets the image creation timestamp.
+    pub const fn time(&self) -> u32 {
+        self.time
+    }
+
+    /// Sets the image creation timestamp.
+    pub fn set_time(&mut self, val: u32) {
+        self.time = val;
+    }
+
+    /// Builder function that sets the image creation timestamp.
+    pub fn with_time(mut self, val: u32) -> Self {
+        self.set_time(val);
+        self
+    }
+
+    /// Gets the image data size.
+    pub const fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Gets the data load address.
+    pub const fn load(&self) -> u32 {
+        self.load
+    }
+
+    /// Sets the data load address.
+    pub fn set_load(&mut self, val: u32) {
+        self.load = val;
+    }
+
+    /// Builder function that sets the data load address.
+    pub fn with_load(mut self, val: u32) -> Self {
+        self.set_load(val);
+        self
+    }
+
+    /// Gets the entry point address.
+    pub const fn ep(&self) -> u32 {
+        self.ep
+    }
+
+    /// Sets the entry point address.
+    pub fn set_ep(&mut self, val: u32) {
+        self.ep = val;
+    }
+
+    /// Builder function that sets the entry point address.
+    pub fn with_ep(mut self, val: u32) -> Self {
+        self.set_ep(val);
+        self
+    }
+
+    /// Gets the image data CRC32.
+    pub const fn dcrc(&self) -> u32 {
+        self.dcrc
+    }
+
+    /// Gets the operating system ID.
+    pub const fn os(&self) -> u8 {
+        self.os
+    }
+
+    /// Sets the operating system ID.
+    pub fn set_os(&mut self, val: u8) {
+        self.os = val;
+    }
+
+    /// Builder function that sets the operating system ID.
+    pub fn with_os(mut self, val: u8) -> Self {
+        self.set_os(val);
+        self
+    }
+
+    /// Gets the CPU architecture ID.
+    pub const fn arch(&self) -> u8 {
+        self.arch
+    }
+
+    /// Sets the CPU architecture ID.
+    pub fn set_arch(&mut self, val: u8) {
+        self.arch = val;
+    }
+
+    /// Builder function that sets the CPU architecture ID.
+    pub fn with_arch(mut self, val: u8) -> Self {
+        self.set_arch(val);
+        self
+    }
+
+    /// Gets the image type.
+    pub fn image_type(&self) -> Result<UimageType> {
+        self.image_type.try_into()
+    }
+
+    /// Sets the image type.
+    pub fn set_image_type(&mut self, val: UimageType) {
+        self.image_type = val.into();
+    }
+
+    /// Builder function that sets the image type.
+    pub fn with_image_type(mut self, val: UimageType) -> Self {
+        self.set_image_type(val);
+        self
+    }
+
+    /// Gets the compression type.
+    pub fn comp(&self) -> Result<UimageComp> {
+        self.comp.try_into()
+    }
+
+    /// Sets the compression type.
+    pub fn set_comp(&mut self, val: UimageComp) {
+        self.comp = val.into();
+    }
+
+    /// Builder function that sets the compression type.
+    pub fn with_comp(mut self, val: UimageComp) -> Self {
+        self.set_comp(val);
+        self
+    }
+
+    /// Gets the image name, up to the first NUL byte.
+    pub fn name(&self) -> &[u8] {
+        let len = self
+            .name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(UIMAGE_NAME_LEN);
+        &self.name[..len]
+    }
+
+    /// Sets the image name, truncated to [UIMAGE_NAME_LEN] bytes.
+    pub fn set_name(&mut self, val: &str) {
+        let len = core::cmp::min(val.len(), UIMAGE_NAME_LEN);
+        self.name = [0u8; UIMAGE_NAME_LEN];
+        self.name[..len].copy_from_slice(&val.as_bytes()[..len]);
+    }
+
+    /// Builder function that sets the image name.
+    pub fn with_name(mut self, val: &str) -> Self {
+        self.set_name(val);
+        self
+    }
+
+    /// Sets the `size` and `dcrc` fields from the image data.
+    pub fn set_data(&mut self, data: &[u8]) -> Result<()> {
+        let size = u32::try_from(data.len())
+            .map_err(|_| Error::InvalidUimageLen((data.len(), u32::MAX as usize)))?;
+
+        self.size = size;
+        self.dcrc = crc32_ieee(data);
+
+        Ok(())
+    }
+
+    /// Builder function that sets the `size` and `dcrc` fields from the image data.
+    pub fn with_data(mut self, data: &[u8]) -> Result<Self> {
+        self.set_data(data)?;
+        Ok(self)
+    }
+
+    /// Verifies the size and CRC32 of the image data against the header.
+    pub fn verify_data(&self, data: &[u8]) -> Result<()> {
+        let size = self.size as usize;
+        let data = data
+            .get(..size)
+            .ok_or(Error::InvalidUimageLen((data.len(), size)))?;
+
+        let crc = crc32_ieee(data);
+        if crc != self.dcrc {
+            Err(Error::InvalidCrc((crc, self.dcrc)))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Serializes the header, calculating the header CRC32.
+    pub fn to_bytes(&self) -> [u8; UIMAGE_HEADER_LEN] {
+        let mut res = self.serialize_with_hcrc(0);
+        let hcrc = crc32_ieee(res.as_ref());
+        res[4..8].copy_from_slice(hcrc.to_be_bytes().as_ref());
+        res
+    }
+
+    fn serialize_with_hcrc(&self, hcrc: u32) -> [u8; UIMAGE_HEADER_LEN] {
+        let mut res = [0u8; UIMAGE_HEADER_LEN];
+        let words = [
+            self.magic, hcrc, self.time, self.size, self.load, self.ep, self.dcrc,
+        ];
+
+        for (dst, word) in res.chunks_exact_mut(4).zip(words) {
+            dst.copy_from_slice(word.to_be_bytes().as_ref());
+        }
+
+        res[0x1c] = self.os;
+        res[0x1d] = self.arch;
+        res[0x1e] = self.image_type;
+        res[0x1f] = self.comp;
+        res[0x20..].copy_from_slice(self.name.as_ref());
+
+        res
+    }
+}
+
+impl Default for UimageHeader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TryFrom<&[u8]> for UimageHeader {
+    type Error = Error;
+
+    /// Parses a legacy uImage header, and verifies the magic number and header CRC32.
+    fn try_from(val: &[u8]) -> Result<Self> {
+        let buf: &[u8; UIMAGE_HEADER_LEN] = val
+            .get(..UIMAGE_HEADER_LEN)
+            .ok_or(Error::InvalidHeaderLen((val.len(), UIMAGE_HEADER_LEN)))?
+            .try_into()?;
+
+        let word =
+            |idx: usize| u32::from_be_bytes([buf[idx], buf[idx + 1], buf[idx + 2], buf[idx + 3]]);
+
+        let magic = word(0x00);
+        if magic != UIMAGE_MAGIC {
+            return Err(Error::InvalidUimageMagic(magic));
+        }
+
+        let res = Self {
+            magic,
+            hcrc: word(0x04),
+            time: word(0x08),
+            size: word(0x0c),
+            load: word(0x10),
+            ep: word(0x14),
+            dcrc: word(0x18),
+            os: buf[0x1c],
+            arch: buf[0x1d],
+            image_type: buf[0x1e],
+            comp: buf[0x1f],
+            name: buf[0x20..].try_into()?,
+        };
+
+        let hcrc = crc32_ieee(res.serialize_with_hcrc(0).as_ref());
+        if hcrc != res.hcrc {
+            Err(Error::InvalidCrc((hcrc, res.hcrc)))
+        } else {
+            Ok(res)
+        }
+    }
+}
+
+impl From<&UimageHeader> for [u8; UIMAGE_HEADER_LEN] {
+    fn from(val: &UimageHeader) -> Self {
+        val.to_bytes()
+    }
+}
+
+impl From<UimageHeader> for [u8; UIMAGE_HEADER_LEN] {
+    fn from(val: UimageHeader) -> Self {
+        (&val).into()
+    }
+}
+
+/// Gets the length of a script uImage, including the header, for a script of `script_len` bytes.
+///
+/// Script data starts with a big-endian length list terminated by zero, like multi-file images.
+pub const fn uimage_script_len(script_len: usize) -> usize {
+    UIMAGE_HEADER_LEN + 8 + script_len
+}
+
+/// Creates a script uImage (e.g. `boot.scr`), like `mkimage -T script`.
+///
+/// The image type is set to [UimageType::Script], and the `size`/`dcrc` fields are calculated.
+///
+/// Returns the length of the image written to `out`.
+pub fn uimage_script_create(header: UimageHeader, script: &[u8], out: &mut [u8]) -> Result<usize> {
+    let len = uimage_script_len(script.len());
+    if out.len() < len {
+        return Err(Error::InvalidHeaderLen((out.len(), len)));
+    }
+
+    let script_len = u32::try_from(script.len())
+        .map_err(|_| Error::InvalidUimageLen((script.len(), u32::MAX as usize)))?;
+
+    let data = &mut out[UIMAGE_HEADER_LEN..len];
+    data[..4].copy_from_slice(script_len.to_be_bytes().as_ref());
+    data[4..8].fill(0);
+    data[8..].copy_from_slice(script);
+
+    let header = header.with_image_type(UimageType::Script).with_data(data)?;
+    out[..UIMAGE_HEADER_LEN].copy_from_slice(header.to_bytes().as_ref());
+
+    Ok(len)
+}
+
+/// Gets the script from a script uImage, verifying the header and data CRC32.
+pub fn uimage_script(img: &[u8]) -> Result<&[u8]> {
+    let header = UimageHeader::try_from(img)?;
+
+    let ty = header.image_type()?;
+    if ty != UimageType::Script {
+        return Err(Error::InvalidUimageType(ty.into()));
+    }
+
+    let data = &img[UIMAGE_HEADER_LEN..];
+    header.verify_data(data)?;
+    let data = &data[..header.size() as usize];
+
+    // the script is the first file after the zero-terminated length list
+    let word = |idx: usize| {
+        data.get(idx..idx + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .ok_or(Error::InvalidUimageLen((data.len(), idx + 4)))
+    };
+
+    let script_len = word(0)?;
+    let mut start = 4usize;
+    while word(start)? != 0 {
+        start += 4;
+    }
+    start += 4;
+
+    start
+        .checked_add(script_len)
+        .and_then(|end| data.get(start..end))
+        .ok_or(Error::InvalidUimageLen((
+            data.len(),
+            start.saturating_add(script_len),
+        )))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn script_image(script: &[u8]) -> Vec<u8> {
+        let mut img = std::vec![0u8; uimage_script_len(script.len())];
+        let header = UimageHeader::new().with_time(0x6500_0000).with_name("test");
+        let len = uimage_script_create(header, script, img.as_mut()).unwrap();
+        assert_eq!(len, img.len());
+        img
+    }
+
+    #[test]
+    fn test_header_roundtrip() {
+        let header = UimageHeader::new()
+            .with_time(1)
+            .with_load(0x4020_0000)
+            .with_ep(0x4020_0000)
+            .with_os(UIMAGE_OS_U_BOOT)
+            .with_image_type(UimageType::Firmware)
+            .with_comp(UimageComp::Gzip)
+            .with_name("a name longer than thirty-two bytes")
+            .with_data(b"data")
+            .unwrap();
+
+        let bytes = header.to_bytes();
+        assert_eq!(&bytes[..4], UIMAGE_MAGIC.to_be_bytes().as_ref());
+        assert_eq!(&bytes[0x0c..0x10], 4u32.to_be_bytes().as_ref());
+
+        let parsed = UimageHeader::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(
+            parsed,
+            UimageHeader {
+                hcrc: parsed.hcrc,
+                ..header
+            }
+        );
+        assert_eq!(parsed.name(), b"a name longer than thirty-two by");
+        assert_eq!(parsed.image_type().unwrap(), UimageType::Firmware);
+        assert_eq!(parsed.comp().unwrap(), UimageComp::Gzip);
+        assert!(parsed.verify_data(b"data and trailing bytes").is_ok());
+        assert!(matches!(
+            parsed.verify_data(b"dat"),
+            Err(Error::InvalidUimageLen((3, 4)))
+        ));
+        assert!(matches!(
+            parsed.verify_data(b"DATA"),
+            Err(Error::InvalidCrc(_))
+        ));
+    }
+
+    #[test]
+    fn test_header_invalid() {
+        let mut bytes = UimageHeader::new().to_bytes();
+
+        assert!(matches!(
+            UimageHeader::try_from(&bytes[..UIMAGE_HEADER_LEN - 1]),
+            Err(Error::InvalidHeaderLen((63, 64)))
+        ));
+
+        bytes[0x1e] = 0xff;
+        assert!(matches!(
+            UimageHeader::try_from(bytes.as_ref()),
+            Err(Error::InvalidCrc(_))
+        ));
+
+        bytes[0] = 0;
+        assert!(matches!(
+            UimageHeader::try_from(bytes.as_ref()),
+            Err(Error::InvalidUimageMagic(0x0005_1956))
+        ));
+
+        let header = UimageHeader {
+            image_type: 0,
+            comp: 7,
+            ..UimageHeader::new()
+        };
+        assert!(matches!(
+            header.image_type(),
+            Err(Error::InvalidUimageType(0))
+        ));
+        assert!(matches!(header.comp(), Err(Error::InvalidUimageComp(7))));
+    }
+
+    #[test]
+    fn test_script_roundtrip() {
+        let script = b"echo hello\n";
+        let img = script_image(script);
+
+        assert_eq!(img.len(), UIMAGE_HEADER_LEN + 8 + script.len());
+        assert_eq!(uimage_script(img.as_ref()).unwrap(), script);
+        // trailing data after the image is ignored
+        let mut padded = img.clone();
+        padded.extend_from_slice(&[0xff; 16]);
+        assert_eq!(uimage_script(padded.as_ref()).unwrap(), script);
+
+        let mut out = [0u8; UIMAGE_HEADER_LEN + 8];
+        assert!(matches!(
+            uimage_script_create(UimageHeader::new(), script, out.as_mut()),
+            Err(Error::InvalidHeaderLen((72, 83)))
+        ));
+    }
+
+    #[test]
+    fn test_script_invalid() {
+        let img = script_image(b"echo hello\n");
+
+        // truncated data
+        assert!(matches!(
+            uimage_script(&img[..img.len() - 1]),
+            Err(Error::InvalidUimageLen(_))
+        ));
+
+        // not a script image
+        let header = UimageHeader::try_from(img.as_ref())
+            .unwrap()
+            .with_image_type(UimageType::Kernel);
+        let mut kernel = img.clone();
+        kernel[..UIMAGE_HEADER_LEN].copy_from_slice(header.to_bytes().as_ref());
+        assert!(matches!(
+            uimage_script(kernel.as_ref()),
+            Err(Error::InvalidUimageType(2))
+        ));
+    }
+
+    #[test]
+    fn test_script_invalid_lengths() {
+        // rebuilds the header over arbitrary (CRC-valid) script data
+        let image = |data: &[u8]| {
+            let header = UimageHeader::new().with_data(data).unwrap();
+            let mut img = header.to_bytes().to_vec();
+            img.extend_from_slice(data);
+            img
+        };
+
+        // script length past the end of the data
+        let img = image(&[0, 0, 0, 0xff, 0, 0, 0, 0, b'a']);
+        assert!(matches!(
+            uimage_script(img.as_ref()),
+            Err(Error::InvalidUimageLen((9, 263)))
+        ));
+
+        // maximum script length
+        let img = image(&[0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0]);
+        assert!(matches!(
+            uimage_script(img.as_ref()),
+            Err(Error::InvalidUimageLen(_))
+        ));
+
+        // unterminated length list
+        let img = image(&[0, 0, 0, 1, 0, 0, 0, 1]);
+        assert!(matches!(
+            uimage_script(img.as_ref()),
+            Err(Error::InvalidUimageLen((8, 12)))
+        ));
+
+        // empty data
+        let img = image(&[]);
+        assert!(matches!(
+            uimage_script(img.as_ref()),
+            Err(Error::InvalidUimageLen((0, 4)))
+        ));
+    }
+}